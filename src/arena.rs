@@ -0,0 +1,114 @@
+use std::num::NonZeroU32;
+use std::ops::{Index, IndexMut};
+
+/// Contiguous storage for the nodes of a QuadTree.
+///
+/// The root lives at index zero, and the four sub-nodes created by each
+/// subdivision are pushed side by side, so a node links to its sub-nodes with
+/// a single [`QuadId`] rather than owning a boxed array. Nodes are never
+/// removed, so indices stay valid for the life of the tree.
+#[derive(Debug)]
+pub struct Arena<N> {
+    nodes: Vec<N>,
+}
+
+/// Index of the first of four contiguous sub-nodes in an [`Arena`]. The root
+/// is never a sub-node, so the index is never zero, which keeps an
+/// `Option<QuadId>` link to four bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuadId(NonZeroU32);
+
+impl QuadId {
+    /// Index of the sub-node at `offset` from the first, in [`crate::SubNode`]
+    /// order.
+    pub(crate) fn sub_node(self, offset: usize) -> usize {
+        self.0.get() as usize + offset
+    }
+}
+
+impl<N> Arena<N> {
+    /// Create an arena holding only the root.
+    pub(crate) fn new(root: N) -> Self {
+        Self { nodes: vec![root] }
+    }
+
+    pub(crate) fn root(&self) -> &N {
+        &self.nodes[0]
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Iterate over every node in the order they were created.
+    pub(crate) fn iter(&self) -> std::slice::Iter<'_, N> {
+        self.nodes.iter()
+    }
+
+    /// Get the four sub-nodes starting at `quad`.
+    pub(crate) fn quad(&self, quad: QuadId) -> &[N; 4] {
+        let first = quad.sub_node(0);
+        self.nodes[first..first + 4]
+            .try_into()
+            .expect("Unreachable, sub-nodes are always pushed in fours.")
+    }
+
+    /// Push four new sub-nodes, returning the link to them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the arena would exceed `u32::MAX` nodes.
+    pub(crate) fn push_quad(&mut self, quad: [N; 4]) -> QuadId {
+        let first = u32::try_from(self.nodes.len())
+            .ok()
+            .and_then(NonZeroU32::new)
+            .expect("QuadTree node arena overflowed u32 indices");
+
+        self.nodes.extend(quad);
+        QuadId(first)
+    }
+}
+
+impl<N> Index<usize> for Arena<N> {
+    type Output = N;
+
+    fn index(&self, index: usize) -> &N {
+        &self.nodes[index]
+    }
+}
+
+impl<N> IndexMut<usize> for Arena<N> {
+    fn index_mut(&mut self, index: usize) -> &mut N {
+        &mut self.nodes[index]
+    }
+}
+
+/// Storage for the data held directly by a node. With the `smallvec` feature,
+/// up to [`INLINE_CHILDREN`] data are stored inline in the node itself, so a
+/// typical leaf needs no allocation of its own and its data sit next to its
+/// bounds in the arena.
+#[cfg(not(feature = "smallvec"))]
+pub(crate) type Children<D> = Vec<D>;
+
+/// Storage for the data held directly by a node. With the `smallvec` feature,
+/// up to [`INLINE_CHILDREN`] data are stored inline in the node itself, so a
+/// typical leaf needs no allocation of its own and its data sit next to its
+/// bounds in the arena.
+#[cfg(feature = "smallvec")]
+pub(crate) type Children<D> = smallvec::SmallVec<[D; INLINE_CHILDREN]>;
+
+/// Number of data stored inline in each node with the `smallvec` feature,
+/// matching [`crate::DEFAULT_MAX_CHILDREN`]. Leaves holding more spill to the
+/// heap as a `Vec` would.
+#[cfg(feature = "smallvec")]
+pub(crate) const INLINE_CHILDREN: usize = crate::DEFAULT_MAX_CHILDREN;
+
+/// Heap memory owned by a node's child storage, excluding spare capacity.
+pub(crate) fn children_heap_bytes<D>(children: &Children<D>) -> usize {
+    #[cfg(feature = "smallvec")]
+    if !children.spilled() {
+        return 0;
+    }
+
+    size_of_val(children.as_slice())
+}
@@ -0,0 +1,259 @@
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+
+use geo::{GeoNum, Rect};
+
+use crate::arena::Arena;
+use crate::iter::DatumIter;
+use crate::node::{Branch, Node, SubNode};
+
+/// Read-only cursor onto a single node of a QuadTree.
+///
+/// Obtained from the `root` method on each QuadTree implementation, this is
+/// the supported way to walk the tree structure directly, for example to
+/// build tiles, render the tree, or aggregate data by node. Cursors are cheap
+/// to copy and borrow the tree for their lifetime.
+///
+/// Each QuadTree names its cursor with an alias, such as [`crate::PointNodeRef`],
+/// so cursors can be passed to and returned from functions:
+///
+/// ```
+/// use geo::{Point, Rect, coord};
+/// use quadtree::{AsPoint, CalcMethod, PointNodeRef, PointQuadTree, QuadTree};
+///
+/// // Count the data in each leaf, recursing through the sub-nodes
+/// fn leaf_sizes<D: AsPoint>(node: PointNodeRef<'_, D, f64>, sizes: &mut Vec<usize>) {
+///     match node.sub_nodes() {
+///         Some(sub_nodes) => sub_nodes.into_iter().for_each(|n| leaf_sizes(n, sizes)),
+///         None => sizes.push(node.children().count()),
+///     }
+/// }
+///
+/// let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 4.0, y: 4.0});
+/// let mut qt = PointQuadTree::new(bounds, CalcMethod::Euclidean, 2, 2);
+/// for (x, y) in [(0.5, 0.5), (1.5, 0.5), (0.5, 1.5), (3.5, 3.5)] {
+///     qt.insert(Point::new(x, y)).unwrap();
+/// }
+///
+/// let mut sizes = vec![];
+/// leaf_sizes(qt.root(), &mut sizes);
+/// assert_eq!(sizes.iter().sum::<usize>(), 4);
+/// assert_eq!(sizes.len(), 7);
+/// ```
+pub struct NodeRef<'a, N, D, T>
+where
+    N: Node<D, T>,
+    T: GeoNum,
+{
+    arena: &'a Arena<N>,
+    node: &'a N,
+    _types: PhantomData<(&'a D, T)>,
+}
+
+impl<N, D, T> Clone for NodeRef<'_, N, D, T>
+where
+    N: Node<D, T>,
+    T: GeoNum,
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<N, D, T> Copy for NodeRef<'_, N, D, T>
+where
+    N: Node<D, T>,
+    T: GeoNum,
+{
+}
+
+impl<'a, N, D, T> NodeRef<'a, N, D, T>
+where
+    N: Node<D, T>,
+    T: GeoNum,
+{
+    /// Create a cursor onto the root of the tree held in the arena.
+    pub(crate) fn new(arena: &'a Arena<N>) -> Self {
+        Self::at(arena, arena.root())
+    }
+
+    fn at(arena: &'a Arena<N>, node: &'a N) -> Self {
+        Self {
+            arena,
+            node,
+            _types: PhantomData,
+        }
+    }
+
+    // The node itself, for trees whose nodes carry more than the trait shows
+    pub(crate) fn node(&self) -> &'a N {
+        self.node
+    }
+
+    /// The bounding rect of this node.
+    pub fn bounds(&self) -> &'a Rect<T> {
+        self.node.bounds()
+    }
+
+    /// The depth of this node, with the root at depth zero.
+    pub fn depth(&self) -> u8 {
+        self.node.depth()
+    }
+
+    /// Whether this node is a leaf, i.e. has no sub-nodes.
+    pub fn is_leaf(&self) -> bool {
+        self.node.quad().is_none()
+    }
+
+    /// Iterate over the data held directly by this node. As with the rest of
+    /// the crate, this includes any stuck children.
+    pub fn children(&self) -> DatumIter<'a, N, D, T> {
+        self.node.children()
+    }
+
+    /// Iterate over only the stuck children held by this node. Empty for
+    /// QuadTree types without the concept.
+    pub fn stuck_children(&self) -> DatumIter<'a, N, D, T> {
+        self.node.stuck_children()
+    }
+
+    /// Indices of the data this node references in a multi-cell
+    /// [`crate::BoundsQuadTree`], or that are split at the antimeridian in a
+    /// Spherical one, which are resolved with
+    /// [`crate::BoundsQuadTree::datum`]. Empty for other QuadTree types.
+    pub fn shared(&self) -> &'a [usize] {
+        self.node.shared()
+    }
+
+    /// Iterate over all data held by this node and its descendants in
+    /// preorder.
+    pub fn descendants(&self) -> DatumIter<'a, N, D, T> {
+        self.node.descendants(self.arena)
+    }
+
+    /// Return a cursor onto the requested sub-node, or `None` for leaves.
+    pub fn sub_node(&self, sub_node: SubNode) -> Option<Self> {
+        self.node
+            .nodes(self.arena)
+            .map(|nodes| Self::at(self.arena, &nodes[sub_node as usize]))
+    }
+
+    /// Return cursors onto all four sub-nodes in [`SubNode`] order, or `None`
+    /// for leaves.
+    pub fn sub_nodes(&self) -> Option<[Self; 4]> {
+        self.node
+            .nodes(self.arena)
+            .map(|nodes| [0, 1, 2, 3].map(|i| Self::at(self.arena, &nodes[i])))
+    }
+
+    /// Iterate over this node and all nodes beneath it in preorder, visiting
+    /// sub-nodes in [`SubNode`] order.
+    pub fn preorder(&self) -> PreorderIter<'a, N, D, T> {
+        PreorderIter { stack: vec![*self] }
+    }
+
+    /// Iterate over this node and all nodes beneath it breadth first, so
+    /// shallower nodes are always visited before deeper ones.
+    pub fn bfs(&self) -> BfsIter<'a, N, D, T> {
+        BfsIter {
+            queue: VecDeque::from([*self]),
+        }
+    }
+
+    /// Iterate over the leaves beneath this node in preorder.
+    pub fn leaves(&self) -> LeafIter<'a, N, D, T> {
+        LeafIter {
+            iter: self.preorder(),
+        }
+    }
+}
+
+impl<'a, N, D, T> Branch<'a, D> for NodeRef<'a, N, D, T>
+where
+    N: Node<D, T>,
+    T: GeoNum,
+{
+    fn data(self) -> impl Iterator<Item = &'a D> {
+        self.children()
+    }
+
+    fn branches(self) -> impl Iterator<Item = Self> {
+        self.sub_nodes().into_iter().flatten()
+    }
+}
+
+/// Iterator over [`NodeRef`]s in preorder. See [`NodeRef::preorder`].
+pub struct PreorderIter<'a, N, D, T>
+where
+    N: Node<D, T>,
+    T: GeoNum,
+{
+    stack: Vec<NodeRef<'a, N, D, T>>,
+}
+
+impl<'a, N, D, T> Iterator for PreorderIter<'a, N, D, T>
+where
+    N: Node<D, T>,
+    T: GeoNum,
+{
+    type Item = NodeRef<'a, N, D, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+
+        // Push in reverse so the first sub-node is visited next
+        if let Some(sub_nodes) = node.sub_nodes() {
+            self.stack.extend(sub_nodes.into_iter().rev());
+        }
+
+        Some(node)
+    }
+}
+
+/// Iterator over [`NodeRef`]s in breadth first order. See [`NodeRef::bfs`].
+pub struct BfsIter<'a, N, D, T>
+where
+    N: Node<D, T>,
+    T: GeoNum,
+{
+    queue: VecDeque<NodeRef<'a, N, D, T>>,
+}
+
+impl<'a, N, D, T> Iterator for BfsIter<'a, N, D, T>
+where
+    N: Node<D, T>,
+    T: GeoNum,
+{
+    type Item = NodeRef<'a, N, D, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.queue.pop_front()?;
+
+        if let Some(sub_nodes) = node.sub_nodes() {
+            self.queue.extend(sub_nodes);
+        }
+
+        Some(node)
+    }
+}
+
+/// Iterator over leaf [`NodeRef`]s in preorder. See [`NodeRef::leaves`].
+pub struct LeafIter<'a, N, D, T>
+where
+    N: Node<D, T>,
+    T: GeoNum,
+{
+    iter: PreorderIter<'a, N, D, T>,
+}
+
+impl<'a, N, D, T> Iterator for LeafIter<'a, N, D, T>
+where
+    N: Node<D, T>,
+    T: GeoNum,
+{
+    type Item = NodeRef<'a, N, D, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.find(|node| node.is_leaf())
+    }
+}
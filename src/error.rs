@@ -14,6 +14,7 @@ pub enum Error {
     CannotCastInfinity,
     CalcMethodNotSet,
     UnsupportedGeometry,
+    InvalidBuffer,
 }
 
 impl Display for Error {
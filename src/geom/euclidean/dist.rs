@@ -20,12 +20,12 @@ where
     T: QtFloat,
 {
     fn dist_euclidean(&self, rhs: &GeometryRef<T>) -> T {
-        match rhs {
-            &GeometryRef::Point(d) => Euclidean::distance(self, d),
-            &GeometryRef::Line(d) => Euclidean::distance(self, d),
-            &GeometryRef::LineString(d) => Euclidean::distance(self, d),
-            &GeometryRef::Polygon(d) => Euclidean::distance(self, d),
-            &GeometryRef::Rect(d) => Euclidean::distance(self, &d.to_polygon()),
+        match *rhs {
+            GeometryRef::Point(d) => Euclidean::distance(self, d),
+            GeometryRef::Line(d) => Euclidean::distance(self, d),
+            GeometryRef::LineString(d) => Euclidean::distance(self, d),
+            GeometryRef::Polygon(d) => Euclidean::distance(self, d),
+            GeometryRef::Rect(d) => Euclidean::distance(self, &d.to_polygon()),
         }
     }
 }
@@ -44,12 +44,12 @@ where
     T: QtFloat,
 {
     fn dist_euclidean(&self, rhs: &GeometryRef<T>) -> T {
-        match rhs {
-            &GeometryRef::Point(d) => Euclidean::distance(self, d),
-            &GeometryRef::Line(d) => Euclidean::distance(self, d),
-            &GeometryRef::LineString(d) => Euclidean::distance(self, d),
-            &GeometryRef::Polygon(d) => Euclidean::distance(self, d),
-            &GeometryRef::Rect(d) => Euclidean::distance(self, &d.to_polygon()),
+        match *rhs {
+            GeometryRef::Point(d) => Euclidean::distance(self, d),
+            GeometryRef::Line(d) => Euclidean::distance(self, d),
+            GeometryRef::LineString(d) => Euclidean::distance(self, d),
+            GeometryRef::Polygon(d) => Euclidean::distance(self, d),
+            GeometryRef::Rect(d) => Euclidean::distance(self, &d.to_polygon()),
         }
     }
 }
@@ -68,12 +68,12 @@ where
     T: QtFloat,
 {
     fn dist_euclidean(&self, rhs: &GeometryRef<T>) -> T {
-        match rhs {
-            &GeometryRef::Point(d) => Euclidean::distance(self, d),
-            &GeometryRef::Line(d) => Euclidean::distance(self, d),
-            &GeometryRef::LineString(d) => Euclidean::distance(self, d),
-            &GeometryRef::Polygon(d) => Euclidean::distance(self, d),
-            &GeometryRef::Rect(d) => Euclidean::distance(self, &d.to_polygon()),
+        match *rhs {
+            GeometryRef::Point(d) => Euclidean::distance(self, d),
+            GeometryRef::Line(d) => Euclidean::distance(self, d),
+            GeometryRef::LineString(d) => Euclidean::distance(self, d),
+            GeometryRef::Polygon(d) => Euclidean::distance(self, d),
+            GeometryRef::Rect(d) => Euclidean::distance(self, &d.to_polygon()),
         }
     }
 }
@@ -92,12 +92,12 @@ where
     T: QtFloat,
 {
     fn dist_euclidean(&self, rhs: &GeometryRef<T>) -> T {
-        match rhs {
-            &GeometryRef::Point(d) => Euclidean::distance(self, d),
-            &GeometryRef::Line(d) => Euclidean::distance(self, d),
-            &GeometryRef::LineString(d) => Euclidean::distance(self, d),
-            &GeometryRef::Polygon(d) => Euclidean::distance(self, d),
-            &GeometryRef::Rect(d) => Euclidean::distance(self, &d.to_polygon()),
+        match *rhs {
+            GeometryRef::Point(d) => Euclidean::distance(self, d),
+            GeometryRef::Line(d) => Euclidean::distance(self, d),
+            GeometryRef::LineString(d) => Euclidean::distance(self, d),
+            GeometryRef::Polygon(d) => Euclidean::distance(self, d),
+            GeometryRef::Rect(d) => Euclidean::distance(self, &d.to_polygon()),
         }
     }
 }
@@ -116,12 +116,12 @@ where
     T: QtFloat,
 {
     fn dist_euclidean(&self, rhs: &GeometryRef<T>) -> T {
-        match rhs {
-            &GeometryRef::Point(d) => Euclidean::distance(&self.to_polygon(), d),
-            &GeometryRef::Line(d) => Euclidean::distance(&self.to_polygon(), d),
-            &GeometryRef::LineString(d) => Euclidean::distance(&self.to_polygon(), d),
-            &GeometryRef::Polygon(d) => Euclidean::distance(&self.to_polygon(), d),
-            &GeometryRef::Rect(d) => dist_rect_rect(self, d),
+        match *rhs {
+            GeometryRef::Point(d) => Euclidean::distance(&self.to_polygon(), d),
+            GeometryRef::Line(d) => Euclidean::distance(&self.to_polygon(), d),
+            GeometryRef::LineString(d) => Euclidean::distance(&self.to_polygon(), d),
+            GeometryRef::Polygon(d) => Euclidean::distance(&self.to_polygon(), d),
+            GeometryRef::Rect(d) => dist_rect_rect(self, d),
         }
     }
 }
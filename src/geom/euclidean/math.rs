@@ -1,6 +1,9 @@
-use geo::{Distance, Euclidean, Rect, coord};
+use geo::{Coord, Distance, Euclidean, Intersects, Line, LineString, Point, Rect, coord};
 
-use crate::geom::QtFloat;
+use crate::{
+    GeometryRef,
+    geom::{QtFloat, Snap, line_fraction},
+};
 
 /// Calculate the euclidean distance between two [`Rect`]'s.
 pub fn dist_rect_rect<T>(r1: &Rect<T>, r2: &Rect<T>) -> T
@@ -35,7 +38,253 @@ where
     }
 }
 
+/// Calculate the maximum euclidean distance between a [`Point`] and any point
+/// in a [`Rect`], which is the distance to its farthest corner.
+pub fn max_dist_pt_rect<T>(pt: &Point<T>, rect: &Rect<T>) -> T
+where
+    T: QtFloat,
+{
+    let dx = (pt.x() - rect.min().x)
+        .abs()
+        .max((pt.x() - rect.max().x).abs());
+    let dy = (pt.y() - rect.min().y)
+        .abs()
+        .max((pt.y() - rect.max().y).abs());
+
+    dx.hypot(dy)
+}
+
+/// Calculate the maximum euclidean distance between any point in `r1` and
+/// any point in `r2`, which is the distance between their farthest corners.
+pub fn max_dist_rect_rect<T>(r1: &Rect<T>, r2: &Rect<T>) -> T
+where
+    T: QtFloat,
+{
+    let dx = (r1.max().x - r2.min().x)
+        .abs()
+        .max((r2.max().x - r1.min().x).abs());
+    let dy = (r1.max().y - r2.min().y)
+        .abs()
+        .max((r2.max().y - r1.min().y).abs());
+
+    dx.hypot(dy)
+}
+
+/// Snap a [`Point`] onto the closest point of a [`Line`] segment.
+pub fn snap_pt_line<T>(pt: &Point<T>, line: &Line<T>) -> Snap<T>
+where
+    T: QtFloat,
+{
+    let fraction = line_fraction(pt, line);
+    let point = Point::from(line.start + line.delta() * fraction);
+
+    Snap {
+        point,
+        segment: 0,
+        fraction,
+        distance: Euclidean::distance(pt, &point),
+    }
+}
+
+/// Snap a [`Point`] onto the closest point of a [`LineString`], taking the
+/// first segment on ties. Returns `None` if the linestring has no segments.
+pub fn snap_pt_linestring<T>(pt: &Point<T>, linestring: &LineString<T>) -> Option<Snap<T>>
+where
+    T: QtFloat,
+{
+    Snap::closest(linestring, |line| snap_pt_line(pt, line))
+}
+
+/// Find where a [`Line`] segment first enters a [`Rect`] using the slab
+/// method, as a fraction of the way along the segment. Returns zero if the
+/// segment starts inside the rect, and `None` if it misses it.
+pub fn line_rect_entry<T>(line: &Line<T>, rect: &Rect<T>) -> Option<T>
+where
+    T: QtFloat,
+{
+    let (start, delta) = (line.start, line.delta());
+    let (mut t_min, mut t_max) = (T::zero(), T::one());
+
+    // Clip the segment's parameter range to each pair of parallel edges
+    for (a, d, min, max) in [
+        (start.x, delta.x, rect.min().x, rect.max().x),
+        (start.y, delta.y, rect.min().y, rect.max().y),
+    ] {
+        if d == T::zero() {
+            if a < min || a > max {
+                return None;
+            }
+        } else {
+            let (t1, t2) = ((min - a) / d, (max - a) / d);
+            t_min = t_min.max(t1.min(t2));
+            t_max = t_max.min(t1.max(t2));
+        }
+    }
+
+    (t_min <= t_max).then_some(t_min)
+}
+
+/// Find where a [`Line`] segment first touches a geometry, as a fraction of
+/// the way along the segment. Returns zero if the segment starts inside or on
+/// the geometry, and `None` if it misses it.
+pub fn line_geom_entry<T>(line: &Line<T>, geom: &GeometryRef<T>) -> Option<T>
+where
+    T: QtFloat,
+{
+    let first = |lines: &mut dyn Iterator<Item = Line<T>>| {
+        lines
+            .filter_map(|l| line_line_entry(line, &l))
+            .min_by(|a, b| a.partial_cmp(b).expect("Unreachable, NaN excluded."))
+    };
+
+    match *geom {
+        GeometryRef::Point(pt) => line_line_entry(line, &Line::new(pt.0, pt.0)),
+        GeometryRef::Line(l) => line_line_entry(line, l),
+        GeometryRef::LineString(ls) => first(&mut ls.lines()),
+        GeometryRef::Polygon(poly) => {
+            if poly.intersects(&line.start) {
+                return Some(T::zero());
+            }
+            first(
+                &mut poly
+                    .exterior()
+                    .lines()
+                    .chain(poly.interiors().iter().flat_map(|r| r.lines())),
+            )
+        }
+        GeometryRef::Rect(rect) => line_rect_entry(line, rect),
+    }
+}
+
+// Where `line` first touches `other`, as a fraction of the way along `line`,
+// including where the two are collinear and overlap
+fn line_line_entry<T>(line: &Line<T>, other: &Line<T>) -> Option<T>
+where
+    T: QtFloat,
+{
+    let cross = |a: Coord<T>, b: Coord<T>| a.x * b.y - a.y * b.x;
+    let dot = |a: Coord<T>, b: Coord<T>| a.x * b.x + a.y * b.y;
+    let unit = |t: T| t >= T::zero() && t <= T::one();
+
+    let (r, s) = (line.delta(), other.delta());
+    let qp = other.start - line.start;
+    let denom = cross(r, s);
+
+    if denom != T::zero() {
+        // Proper crossing or touching
+        let (t, u) = (cross(qp, s) / denom, cross(qp, r) / denom);
+        return (unit(t) && unit(u)).then_some(t);
+    }
+
+    if cross(qp, r) != T::zero() {
+        // Parallel and apart
+        return None;
+    }
+
+    let rr = dot(r, r);
+    if rr == T::zero() {
+        // The line is a point, which only touches if it lies on the other
+        return other.intersects(&line.start).then_some(T::zero());
+    }
+
+    // Collinear, so find the overlap of the other's span along the line
+    let t0 = dot(qp, r) / rr;
+    let t1 = t0 + dot(s, r) / rr;
+    let (lo, hi) = (t0.min(t1).max(T::zero()), t0.max(t1).min(T::one()));
+
+    (lo <= hi).then_some(lo)
+}
+
 #[cfg(test)]
 mod tests {
     // TODO: Write tests for rect-rect dist
+    use geo::{Polygon, line_string};
+
+    use super::*;
+
+    #[test]
+    fn line_geom_entry_finds_first_touch_along_the_line() {
+        let ray = Line::new(coord! {x: 0.0, y: 0.0}, coord! {x: 10.0, y: 0.0});
+
+        // Crossing, collinear and missing lines
+        let cross = Line::new(coord! {x: 3.0, y: -1.0}, coord! {x: 3.0, y: 1.0});
+        let along = Line::new(coord! {x: 6.0, y: 0.0}, coord! {x: 8.0, y: 0.0});
+        let miss = Line::new(coord! {x: 3.0, y: 1.0}, coord! {x: 4.0, y: 1.0});
+        assert_eq!(line_geom_entry(&ray, &GeometryRef::Line(&cross)), Some(0.3));
+        assert_eq!(line_geom_entry(&ray, &GeometryRef::Line(&along)), Some(0.6));
+        assert_eq!(line_geom_entry(&ray, &GeometryRef::Line(&miss)), None);
+
+        let pt = Point::new(5.0, 0.0);
+        assert_eq!(line_geom_entry(&ray, &GeometryRef::Point(&pt)), Some(0.5));
+
+        let ls: LineString = line_string![(x: 9.0, y: 1.0), (x: 7.0, y: -1.0), (x: 2.0, y: -1.0)];
+        assert_eq!(
+            line_geom_entry(&ray, &GeometryRef::LineString(&ls)),
+            Some(0.8)
+        );
+
+        // Starting in a polygon's hole only touches at the hole's edge
+        let poly = Polygon::new(
+            line_string![(x: -2.0, y: -2.0), (x: 2.0, y: -2.0), (x: 2.0, y: 2.0), (x: -2.0, y: 2.0)],
+            vec![
+                line_string![(x: -1.0, y: -1.0), (x: 1.0, y: -1.0), (x: 1.0, y: 1.0), (x: -1.0, y: 1.0)],
+            ],
+        );
+        assert_eq!(
+            line_geom_entry(&ray, &GeometryRef::Polygon(&poly)),
+            Some(0.1)
+        );
+        let inside = Line::new(coord! {x: 1.5, y: 0.0}, coord! {x: 10.0, y: 0.0});
+        assert_eq!(
+            line_geom_entry(&inside, &GeometryRef::Polygon(&poly)),
+            Some(0.0)
+        );
+
+        let rect = Rect::new(coord! {x: 4.0, y: -1.0}, coord! {x: 12.0, y: 1.0});
+        assert_eq!(line_rect_entry(&ray, &rect), Some(0.4));
+    }
+
+    #[test]
+    fn closest_points_are_the_distance_apart_for_every_geometry_pair() {
+        use crate::{AsGeom, CalcMethod, DistEuclidean};
+
+        let pt = Point::new(5.0, 5.0);
+        let line = Line::new(coord! {x: 0.0, y: 6.0}, coord! {x: 3.0, y: 9.0});
+        let ls: LineString = line_string![(x: 8.0, y: 0.0), (x: 9.0, y: 3.0), (x: 12.0, y: 3.0)];
+        let poly = Polygon::new(
+            line_string![(x: 0.0, y: 0.0), (x: 4.0, y: 0.0), (x: 4.0, y: 4.0), (x: 0.0, y: 4.0)],
+            vec![
+                line_string![(x: 1.0, y: 1.0), (x: 3.0, y: 1.0), (x: 3.0, y: 3.0), (x: 1.0, y: 3.0)],
+            ],
+        );
+        let rect = Rect::new(coord! {x: 6.0, y: 6.0}, coord! {x: 9.0, y: 8.0});
+        // Touching others, inside a hole, and inside an area
+        let crossing = Line::new(coord! {x: 2.0, y: 5.0}, coord! {x: 7.0, y: 7.0});
+        let in_hole = Point::new(2.0, 2.0);
+        let in_rect = Rect::new(coord! {x: 7.0, y: 7.0}, coord! {x: 8.0, y: 7.5});
+
+        let geoms = [
+            pt.as_geom(),
+            line.as_geom(),
+            ls.as_geom(),
+            poly.as_geom(),
+            rect.as_geom(),
+            crossing.as_geom(),
+            in_hole.as_geom(),
+            in_rect.as_geom(),
+        ];
+        for g1 in &geoms {
+            for g2 in &geoms {
+                let (p1, p2) = g1
+                    .into_calc(CalcMethod::Euclidean)
+                    .closest_points(g2)
+                    .unwrap();
+                let d = g1.dist_euclidean(g2);
+
+                assert!((Euclidean::distance(&p1, &p2) - d).abs() < 1e-12);
+                assert!(g1.dist_euclidean(&p1.as_geom()) < 1e-12);
+                assert!(g2.dist_euclidean(&p2.as_geom()) < 1e-12);
+            }
+        }
+    }
 }
@@ -0,0 +1,58 @@
+use crate::{AsGeom, Error, Geometry, GeometryRef};
+use geo::GeoFloat;
+
+use super::math::dist_pt_geom;
+
+/// Trait applied to geometries to calculate the geodesic distance between
+/// them on the WGS84 ellipsoid.
+///
+/// Distances are only available where one of the geometries is a point, and
+/// return [`Error::UnsupportedGeometry`] otherwise. See
+/// [`super::math::dist_pt_geom`].
+///
+/// This trait should not need to be used outside the crate as it is abstracted
+/// through the [`CalcMethod`](crate::CalcMethod) enum.
+pub trait DistGeodesic<T, Rhs = Self> {
+    fn dist_geodesic(&self, rhs: &Rhs) -> Result<T, Error>;
+}
+
+impl<T> DistGeodesic<T, GeometryRef<'_, T>> for GeometryRef<'_, T>
+where
+    T: GeoFloat,
+{
+    fn dist_geodesic(&self, rhs: &GeometryRef<T>) -> Result<T, Error> {
+        match (self, rhs) {
+            (GeometryRef::Point(pt), geom) | (geom, GeometryRef::Point(pt)) => {
+                dist_pt_geom(pt, geom)
+            }
+            _ => Err(Error::UnsupportedGeometry),
+        }
+    }
+}
+
+impl<T> DistGeodesic<T, Geometry<T>> for GeometryRef<'_, T>
+where
+    T: GeoFloat,
+{
+    fn dist_geodesic(&self, rhs: &Geometry<T>) -> Result<T, Error> {
+        self.dist_geodesic(&rhs.as_geom())
+    }
+}
+
+impl<T> DistGeodesic<T, GeometryRef<'_, T>> for Geometry<T>
+where
+    T: GeoFloat,
+{
+    fn dist_geodesic(&self, rhs: &GeometryRef<T>) -> Result<T, Error> {
+        self.as_geom().dist_geodesic(rhs)
+    }
+}
+
+impl<T> DistGeodesic<T, Geometry<T>> for Geometry<T>
+where
+    T: GeoFloat,
+{
+    fn dist_geodesic(&self, rhs: &Geometry<T>) -> Result<T, Error> {
+        self.as_geom().dist_geodesic(&rhs.as_geom())
+    }
+}
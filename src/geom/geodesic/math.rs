@@ -0,0 +1,373 @@
+use geo::{Bearing, Destination, Distance, GeoFloat, Geodesic, Line, LineString, Point, Polygon};
+
+use super::{WGS84_A, WGS84_F};
+use crate::geom::{Snap, spherical};
+use crate::{Error, GeometryRef};
+
+// Fraction of a segment either side of the closest point on the sphere that
+// is first searched for the closest point on the ellipsoid, widened as needed
+const BRACKET: f64 = 0.05;
+
+// Golden section steps, enough to shrink the bracket below a millimeter on
+// the longest segments
+const STEPS: usize = 50;
+
+/// Calculate the geodesic distance between two [`Point`]'s on the WGS84
+/// ellipsoid, using Karney's algorithm.
+///
+/// Inputs are longitude and latitude in radians, as for Spherical, and the
+/// output is in meters.
+pub fn dist_pt_pt<T>(p1: &Point<T>, p2: &Point<T>) -> T
+where
+    T: GeoFloat,
+{
+    from_f64(Geodesic::distance(to_deg(p1), to_deg(p2)))
+}
+
+/// Snap a [`Point`] onto the closest point of a [`Line`], taken as the
+/// geodesic between its end points on the WGS84 ellipsoid.
+///
+/// Geodesics run close to great circles, so the closest point on the sphere,
+/// see [`spherical::math::snap_pt_line`], is refined by a golden section
+/// search along the geodesic around it, over a bracket widened until it holds
+/// the minimum, and checked against the end points. The fraction is of the
+/// geodesic's length. Inputs are in radians, and the distance is in meters.
+pub fn snap_pt_line<T>(pt: &Point<T>, line: &Line<T>) -> Snap<T>
+where
+    T: GeoFloat,
+{
+    let p = to_deg(pt);
+    let start = to_deg(&line.start.into());
+    let end = to_deg(&line.end.into());
+    let length = Geodesic::distance(start, end);
+    let bearing = Geodesic::bearing(start, end);
+
+    let at = |t: f64| Geodesic::destination(start, bearing, t * length);
+    let dist = |t: f64| Geodesic::distance(p, at(t));
+
+    // Widen the bracket on each side until the distance has risen at its end,
+    // or it reaches the end of the line, so the minimum is held within it
+    let t0 = to_f64(spherical::math::snap_pt_line(pt, line).fraction);
+    let d0 = dist(t0);
+    let widen = |step: f64| {
+        let mut width = BRACKET;
+        while (0.0..=1.0).contains(&(t0 + step * width)) && dist(t0 + step * width) <= d0 {
+            width *= 2.0;
+        }
+        (t0 + step * width).clamp(0.0, 1.0)
+    };
+    let (mut lo, mut hi) = (widen(-1.0), widen(1.0));
+    let ratio = (5f64.sqrt() - 1.0) / 2.0;
+    for _ in 0..STEPS {
+        let (a, b) = (hi - ratio * (hi - lo), lo + ratio * (hi - lo));
+        if dist(a) < dist(b) {
+            hi = b;
+        } else {
+            lo = a;
+        }
+    }
+
+    let (fraction, distance) = [0.0, (lo + hi) / 2.0, 1.0]
+        .map(|t| (t, dist(t)))
+        .into_iter()
+        .fold((0.0, f64::INFINITY), |best, cur| {
+            if cur.1 < best.1 { cur } else { best }
+        });
+
+    Snap {
+        point: from_deg(at(fraction)),
+        segment: 0,
+        fraction: from_f64(fraction),
+        distance: from_f64(distance),
+    }
+}
+
+/// Snap a [`Point`] onto the closest point of a [`LineString`] on the WGS84
+/// ellipsoid, taking the first segment on ties. Returns `None` if the
+/// linestring has no segments.
+pub fn snap_pt_linestring<T>(pt: &Point<T>, linestring: &LineString<T>) -> Option<Snap<T>>
+where
+    T: GeoFloat,
+{
+    Snap::closest(linestring, |line| snap_pt_line(pt, line))
+}
+
+/// Calculate the geodesic distance between a [`Point`] and a geometry on the
+/// WGS84 ellipsoid, as the distance to its closest point, see
+/// [`closest_pt`]. Inputs are in radians and the output is in meters.
+pub fn dist_pt_geom<T>(pt: &Point<T>, geom: &GeometryRef<T>) -> Result<T, Error>
+where
+    T: GeoFloat,
+{
+    match geom {
+        GeometryRef::Point(p) => Ok(dist_pt_pt(pt, p)),
+        _ => snap_pt_geom(pt, geom).map(|(_, d)| d),
+    }
+}
+
+/// Find the closest point of a geometry to a [`Point`] on the WGS84
+/// ellipsoid, which is the point itself where it lies inside a [`Polygon`].
+///
+/// Lines and rings are taken as geodesics between their coordinates, while
+/// polygon containment is tested in longitude and latitude, as for
+/// Spherical. Errors with [`Error::Empty`] for a geometry without any
+/// segments, and [`Error::UnsupportedGeometry`] for a [`geo::Rect`], whose
+/// edges along parallels are not geodesics.
+pub fn closest_pt<T>(pt: &Point<T>, geom: &GeometryRef<T>) -> Result<Point<T>, Error>
+where
+    T: GeoFloat,
+{
+    snap_pt_geom(pt, geom).map(|(p, _)| p)
+}
+
+/// Lower bound in meters on the geodesic distance between points whose
+/// great circle distance, treating their latitudes as on a sphere, is
+/// `angle` radians.
+///
+/// That angle is the one between the surface normals at the two points, and
+/// along any path on the ellipsoid the normal turns no faster than the
+/// distance travelled over the smallest radius of curvature, which is the
+/// meridian's at the equator.
+pub fn dist_lower_bound<T>(angle: T) -> T
+where
+    T: GeoFloat,
+{
+    from_f64(to_f64(angle) * min_radius())
+}
+
+/// As [`dist_lower_bound`], but where `angle` is measured to geodesic
+/// segments taken as the great circle arcs through their ends, so either
+/// side may be a line or polygon. An allowance of twice the flattening
+/// covers the geodesics bowing away from those arcs, so the bound is zero
+/// within about 42 km.
+pub fn dist_lower_bound_segments<T>(angle: T) -> T
+where
+    T: GeoFloat,
+{
+    from_f64((to_f64(angle) - 2.0 * WGS84_F).max(0.0) * min_radius())
+}
+
+/// Upper bound in meters on the geodesic distance between points whose
+/// great circle distance is `angle` radians, the counterpart to
+/// [`dist_lower_bound`] using the largest radius of curvature, which is at
+/// the poles.
+pub fn dist_upper_bound<T>(angle: T) -> T
+where
+    T: GeoFloat,
+{
+    let max_radius = WGS84_A / (1.0 - WGS84_F);
+    let max = (to_f64(angle) + 2.0 * WGS84_F) * max_radius;
+
+    // No two points are further apart than half the equator
+    from_f64(max.min(std::f64::consts::PI * WGS84_A))
+}
+
+// The closest point on the geometry and its distance
+fn snap_pt_geom<T>(pt: &Point<T>, geom: &GeometryRef<T>) -> Result<(Point<T>, T), Error>
+where
+    T: GeoFloat,
+{
+    let closest = |snaps: &mut dyn Iterator<Item = Snap<T>>| {
+        snaps
+            .min_by(|a, b| {
+                a.distance
+                    .partial_cmp(&b.distance)
+                    .expect("Unreachable, NaN distances not produced.")
+            })
+            .map(|snap| (snap.point, snap.distance))
+            .ok_or(Error::Empty)
+    };
+
+    match *geom {
+        GeometryRef::Point(p) => Ok((*p, dist_pt_pt(pt, p))),
+        GeometryRef::Line(line) => closest(&mut std::iter::once(snap_pt_line(pt, line))),
+        GeometryRef::LineString(ls) => closest(&mut snap_pt_linestring(pt, ls).into_iter()),
+        GeometryRef::Polygon(poly) => snap_pt_poly(pt, poly, closest),
+        GeometryRef::Rect(_) => Err(Error::UnsupportedGeometry),
+    }
+}
+
+fn snap_pt_poly<T>(
+    pt: &Point<T>,
+    poly: &Polygon<T>,
+    closest: impl Fn(&mut dyn Iterator<Item = Snap<T>>) -> Result<(Point<T>, T), Error>,
+) -> Result<(Point<T>, T), Error>
+where
+    T: GeoFloat,
+{
+    // The spherical distance is only zero inside the polygon, and handles
+    // polygons crossing the antimeridian
+    if poly.exterior().0.is_empty() {
+        return Err(Error::Empty);
+    }
+    if spherical::math::dist_pt_poly(pt, poly) == T::zero() {
+        return Ok((*pt, T::zero()));
+    }
+
+    closest(
+        &mut std::iter::once(poly.exterior())
+            .chain(poly.interiors())
+            .filter_map(|ring| snap_pt_linestring(pt, ring)),
+    )
+}
+
+// Radius of curvature of the meridian at the equator, the smallest anywhere
+fn min_radius() -> f64 {
+    WGS84_A * (1.0 - WGS84_F).powi(2)
+}
+
+fn to_f64<T>(x: T) -> f64
+where
+    T: GeoFloat,
+{
+    x.to_f64().expect("Unreachable, floats cast to f64.")
+}
+
+fn from_f64<T>(x: f64) -> T
+where
+    T: GeoFloat,
+{
+    T::from(x).expect("Unreachable, f64 casts to floats.")
+}
+
+// geo's geodesic algorithms work in degrees
+fn to_deg<T>(pt: &Point<T>) -> Point<f64>
+where
+    T: GeoFloat,
+{
+    Point::new(to_f64(pt.x()).to_degrees(), to_f64(pt.y()).to_degrees())
+}
+
+fn from_deg<T>(pt: Point<f64>) -> Point<T>
+where
+    T: GeoFloat,
+{
+    Point::new(from_f64(pt.x().to_radians()), from_f64(pt.y().to_radians()))
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use geo::{InterpolatePoint, coord, line_string};
+
+    use super::*;
+
+    fn deg(x: f64, y: f64) -> Point {
+        Point::new(x, y).to_radians()
+    }
+
+    #[test]
+    fn points_are_measured_on_the_ellipsoid() {
+        let new_york = deg(-74.006, 40.7128);
+        let london = deg(-0.1278, 51.5074);
+        assert_abs_diff_eq!(dist_pt_pt(&new_york, &london), 5585234.0, epsilon = 1.0);
+
+        // A degree of latitude is shorter at the equator than at the poles
+        let equator = dist_pt_pt(&deg(0.0, 0.0), &deg(0.0, 1.0));
+        let polar = dist_pt_pt(&deg(0.0, 89.0), &deg(0.0, 90.0));
+        assert_abs_diff_eq!(equator, 110574.4, epsilon = 1.0);
+        assert_abs_diff_eq!(polar, 111693.9, epsilon = 1.0);
+    }
+
+    #[test]
+    fn lines_and_polygons_are_measured_to_their_closest_point() {
+        let line = Line::new(deg(-10.0, 40.0).0, deg(30.0, 60.0).0);
+        let pt = deg(5.0, 60.0);
+        let snap = snap_pt_line(&pt, &line);
+
+        // No point along the geodesic is closer than the snapped one
+        let (start, end) = (to_deg(&line.start.into()), to_deg(&line.end.into()));
+        for i in 0..=1000 {
+            let p = Geodesic::point_at_ratio_between(start, end, i as f64 / 1000.0);
+            assert!(Geodesic::distance(to_deg(&pt), p) >= snap.distance - 1e-3);
+        }
+        assert_abs_diff_eq!(dist_pt_pt(&pt, &snap.point), snap.distance, epsilon = 1e-3);
+
+        // Beyond the end of the line
+        let past = deg(40.0, 62.0);
+        assert_eq!(snap_pt_line(&past, &line).fraction, 1.0);
+        assert_abs_diff_eq!(
+            snap_pt_line(&past, &line).distance,
+            dist_pt_pt(&past, &line.end.into())
+        );
+
+        let poly = Polygon::new(
+            line_string![
+                (x: 0.0, y: 0.0),
+                (x: 0.2, y: 0.0),
+                (x: 0.2, y: 0.2),
+                (x: 0.0, y: 0.2),
+            ],
+            vec![],
+        );
+        let inside = Point::new(0.1, 0.1);
+        let outside = Point::new(0.3, 0.1);
+        let geom = GeometryRef::Polygon(&poly);
+        assert_eq!(dist_pt_geom(&inside, &geom), Ok(0.0));
+        assert_eq!(closest_pt(&inside, &geom), Ok(inside));
+        let edge = Line::new(coord! {x: 0.2, y: 0.0}, coord! {x: 0.2, y: 0.2});
+        assert_eq!(
+            dist_pt_geom(&outside, &geom),
+            Ok(snap_pt_line(&outside, &edge).distance)
+        );
+
+        let rect = geo::Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 1.0, y: 1.0});
+        assert_eq!(
+            dist_pt_geom(&outside, &GeometryRef::Rect(&rect)),
+            Err(Error::UnsupportedGeometry)
+        );
+    }
+
+    #[test]
+    fn long_lines_at_high_latitudes_snap_to_their_closest_point() {
+        // Thousands of km long, where the geodesic strays furthest from the
+        // great circle, with points on either side, far off and near the ends
+        let line = Line::new(deg(-80.0, 70.0).0, deg(70.0, 78.0).0);
+        let (start, end) = (to_deg(&line.start.into()), to_deg(&line.end.into()));
+        let samples = (0..=20000)
+            .map(|i| Geodesic::point_at_ratio_between(start, end, i as f64 / 20000.0))
+            .collect::<Vec<_>>();
+
+        for pt in [
+            deg(0.0, 89.0),
+            deg(0.0, 60.0),
+            deg(-70.0, 85.0),
+            deg(60.0, 50.0),
+            deg(-100.0, 60.0),
+            deg(100.0, 80.0),
+            deg(-5.0, 20.0),
+            deg(180.0, 70.0),
+        ] {
+            let snap = snap_pt_line(&pt, &line);
+            let brute = samples
+                .iter()
+                .map(|s| Geodesic::distance(to_deg(&pt), *s))
+                .fold(f64::INFINITY, f64::min);
+
+            // Samples are at most a few hundred meters apart
+            assert!(snap.distance <= brute + 1e-3, "{pt:?}");
+            assert!(snap.distance >= brute - 100.0, "{pt:?}");
+            assert_abs_diff_eq!(dist_pt_pt(&pt, &snap.point), snap.distance, epsilon = 1e-3);
+        }
+    }
+
+    #[test]
+    fn angle_bounds_hold_the_geodesic_distance() {
+        for (p1, p2) in [
+            (deg(0.0, 0.0), deg(0.0, 10.0)),
+            (deg(0.0, 0.0), deg(0.0, 0.01)),
+            (deg(0.0, 80.0), deg(0.0, 90.0)),
+            (deg(0.0, 0.0), deg(90.0, 0.0)),
+            (deg(-170.0, -45.0), deg(170.0, 45.0)),
+            (deg(0.0, 0.0), deg(179.5, 0.0)),
+        ] {
+            let angle = spherical::math::dist_pt_pt(&p1, &p2);
+            let d = dist_pt_pt(&p1, &p2);
+
+            assert!(dist_lower_bound(angle) <= d);
+            assert!(dist_lower_bound(angle) > 0.99 * d);
+            assert!(dist_lower_bound_segments(angle) <= dist_lower_bound(angle));
+            assert!(dist_upper_bound(angle) >= d);
+        }
+    }
+}
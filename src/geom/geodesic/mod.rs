@@ -0,0 +1,8 @@
+pub mod dist;
+pub mod math;
+
+/// Equatorial radius of the WGS84 ellipsoid in meters.
+pub const WGS84_A: f64 = 6378137.0;
+
+/// Flattening of the WGS84 ellipsoid.
+pub const WGS84_F: f64 = 1.0 / 298.257223563;
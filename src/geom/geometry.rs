@@ -1,4 +1,5 @@
-use geo::{BoundingRect, GeoNum, Line, LineString, Point, Polygon, Rect};
+use geo::coordinate_position::CoordinatePosition;
+use geo::{BoundingRect, Coord, GeoNum, Line, LineString, Point, Polygon, Rect};
 
 use crate::Error;
 
@@ -36,7 +37,7 @@ impl<T> AsGeom<T> for GeometryRef<'_, T>
 where
     T: GeoNum,
 {
-    fn as_geom(&self) -> GeometryRef<T> {
+    fn as_geom(&self) -> GeometryRef<'_, T> {
         *self
     }
 }
@@ -45,8 +46,12 @@ impl<'a, T> GeometryRef<'a, T>
 where
     T: GeoNum,
 {
-    pub fn into_calc(self, method: CalcMethod) -> GeomCalc<'a, T> {
-        GeomCalc { geom: self, method }
+    pub fn into_calc(self, method: CalcMethod<T>) -> GeomCalc<'a, T> {
+        GeomCalc {
+            geom: self,
+            method,
+            point_data: false,
+        }
     }
 }
 
@@ -67,6 +72,39 @@ where
     }
 }
 
+// Delegate to the wrapped geometry so containment can be tested directly.
+impl<T> CoordinatePosition for GeometryRef<'_, T>
+where
+    T: GeoNum,
+{
+    type Scalar = T;
+
+    fn calculate_coordinate_position(
+        &self,
+        coord: &Coord<T>,
+        is_inside: &mut bool,
+        boundary_count: &mut usize,
+    ) {
+        match self {
+            GeometryRef::Point(d) => {
+                d.calculate_coordinate_position(coord, is_inside, boundary_count)
+            }
+            GeometryRef::Line(d) => {
+                d.calculate_coordinate_position(coord, is_inside, boundary_count)
+            }
+            GeometryRef::LineString(d) => {
+                d.calculate_coordinate_position(coord, is_inside, boundary_count)
+            }
+            GeometryRef::Polygon(d) => {
+                d.calculate_coordinate_position(coord, is_inside, boundary_count)
+            }
+            GeometryRef::Rect(d) => {
+                d.calculate_coordinate_position(coord, is_inside, boundary_count)
+            }
+        }
+    }
+}
+
 //
 // -------------------- Geometry (owned data)  -------------------- //
 //
@@ -96,7 +134,7 @@ impl<T> AsGeom<T> for Geometry<T>
 where
     T: GeoNum,
 {
-    fn as_geom(&self) -> GeometryRef<T> {
+    fn as_geom(&self) -> GeometryRef<'_, T> {
         match self {
             Self::Point(d) => GeometryRef::Point(d),
             Self::Line(d) => GeometryRef::Line(d),
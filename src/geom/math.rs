@@ -1,4 +1,8 @@
-use geo::{GeoNum, Point, Rect};
+use geo::line_intersection::{LineIntersection, line_intersection};
+use geo::{Coord, GeoFloat, GeoNum, Intersects, Line, LineString, Point, Polygon, Rect};
+
+use super::{ClosestPoints, Snap};
+use crate::GeometryRef;
 
 /// Determine whether a [`Point`] in contained within or sits on the boundary of
 /// a [`Rect`].
@@ -36,3 +40,219 @@ where
         && r1.min().y <= r2.min().y
         && r1.max().y >= r2.max().y
 }
+
+/// Fraction of the way along a [`Line`] of the point on it closest to `pt` in
+/// the plane, clamped to the segment. Zero for a zero-length line.
+pub(crate) fn line_fraction<T>(pt: &Point<T>, line: &Line<T>) -> T
+where
+    T: GeoFloat,
+{
+    let (a, b) = (pt.0 - line.start, line.delta());
+    let len_sq = b.x * b.x + b.y * b.y;
+
+    if len_sq == T::zero() {
+        return T::zero();
+    }
+
+    ((a.x * b.x + a.y * b.y) / len_sq)
+        .max(T::zero())
+        .min(T::one())
+}
+
+/// Find the closest pair of points between two geometries, the first on `g1`
+/// and the second on `g2`, or `None` if either has no coordinates.
+///
+/// `snap` projects a point onto a segment in the coordinate system in use.
+/// Geometries touch if their segments cross or one lies inside the other's
+/// area, otherwise the closest pair includes an end point of one segment
+/// snapped onto another.
+pub(crate) fn closest_points<T>(
+    g1: &GeometryRef<T>,
+    g2: &GeometryRef<T>,
+    snap: impl Fn(&Point<T>, &Line<T>) -> Snap<T>,
+) -> Option<ClosestPoints<T>>
+where
+    T: GeoFloat,
+{
+    let (s1, s2) = (segments(g1), segments(g2));
+    let (c1, c2) = (s1.first()?.start, s2.first()?.start);
+
+    // A geometry that starts inside the other's area either lies within it,
+    // or crosses its boundary, both of which touch
+    if covers(g2, c1) {
+        return Some((c1.into(), c1.into()));
+    }
+    if covers(g1, c2) {
+        return Some((c2.into(), c2.into()));
+    }
+
+    let mut best: Option<(Point<T>, Point<T>, T)> = None;
+    for l1 in &s1 {
+        for l2 in &s2 {
+            match line_intersection(*l1, *l2) {
+                Some(LineIntersection::SinglePoint { intersection, .. }) => {
+                    return Some((intersection.into(), intersection.into()));
+                }
+                Some(LineIntersection::Collinear { intersection }) => {
+                    return Some((intersection.start.into(), intersection.start.into()));
+                }
+                None => {}
+            }
+
+            let candidates = [
+                (l1.start_point(), l2, false),
+                (l1.end_point(), l2, false),
+                (l2.start_point(), l1, true),
+                (l2.end_point(), l1, true),
+            ];
+            for (pt, line, flip) in candidates {
+                let snapped = snap(&pt, line);
+                if best.is_none_or(|(_, _, d)| snapped.distance < d) {
+                    best = Some(match flip {
+                        false => (pt, snapped.point, snapped.distance),
+                        true => (snapped.point, pt, snapped.distance),
+                    });
+                }
+            }
+        }
+    }
+
+    best.map(|(p1, p2, _)| (p1, p2))
+}
+
+// Break a geometry into its segments, with points and single coordinate
+// linestrings as zero-length segments
+fn segments<T>(geom: &GeometryRef<T>) -> Vec<Line<T>>
+where
+    T: GeoFloat,
+{
+    let rings = |ls: &LineString<T>| match ls.0.as_slice() {
+        [c] => vec![Line::new(*c, *c)],
+        _ => ls.lines().collect(),
+    };
+
+    match *geom {
+        GeometryRef::Point(pt) => vec![Line::new(pt.0, pt.0)],
+        GeometryRef::Line(line) => vec![*line],
+        GeometryRef::LineString(ls) => rings(ls),
+        GeometryRef::Polygon(poly) => std::iter::once(poly.exterior())
+            .chain(poly.interiors())
+            .flat_map(rings)
+            .collect(),
+        GeometryRef::Rect(rect) => rect.to_lines().to_vec(),
+    }
+}
+
+// Whether a coordinate lies in the area of a polygon or rect, including its
+// boundary. Always false for geometries without an area
+fn covers<T>(geom: &GeometryRef<T>, coord: Coord<T>) -> bool
+where
+    T: GeoFloat,
+{
+    match *geom {
+        GeometryRef::Polygon(poly) => poly.intersects(&coord),
+        GeometryRef::Rect(rect) => rect.intersects(&coord),
+        _ => false,
+    }
+}
+
+/// Area of the overlap between two rectangles, zero if they are disjoint.
+pub(crate) fn rect_overlap_area<T>(r1: &Rect<T>, r2: &Rect<T>) -> T
+where
+    T: GeoFloat,
+{
+    let w = r1.max().x.min(r2.max().x) - r1.min().x.max(r2.min().x);
+    let h = r1.max().y.min(r2.max().y) - r1.min().y.max(r2.min().y);
+
+    w.max(T::zero()) * h.max(T::zero())
+}
+
+/// Area of the part of a [`Polygon`] that falls inside a [`Rect`].
+///
+/// Each ring is clipped to the rectangle using Sutherland-Hodgman, which is
+/// exact for area even on concave rings, with holes subtracted from the
+/// clipped exterior.
+pub(crate) fn polygon_area_in_rect<T>(polygon: &Polygon<T>, rect: &Rect<T>) -> T
+where
+    T: GeoFloat,
+{
+    let holes = polygon
+        .interiors()
+        .iter()
+        .fold(T::zero(), |acc, ring| acc + ring_area_in_rect(ring, rect));
+
+    (ring_area_in_rect(polygon.exterior(), rect) - holes).max(T::zero())
+}
+
+// Clip a single ring to the rect and measure it with the shoelace formula
+fn ring_area_in_rect<T>(ring: &LineString<T>, rect: &Rect<T>) -> T
+where
+    T: GeoFloat,
+{
+    let mut pts = ring.coords().copied().collect::<Vec<_>>();
+    // Drop the closing coordinate, as clipping treats the ring as closed
+    if pts.len() > 1 && pts.first() == pts.last() {
+        pts.pop();
+    }
+
+    let (min, max) = (rect.min(), rect.max());
+    let at_x = |k: T| {
+        move |a: Coord<T>, b: Coord<T>| {
+            let t = (k - a.x) / (b.x - a.x);
+            Coord {
+                x: k,
+                y: a.y + t * (b.y - a.y),
+            }
+        }
+    };
+    let at_y = |k: T| {
+        move |a: Coord<T>, b: Coord<T>| {
+            let t = (k - a.y) / (b.y - a.y);
+            Coord {
+                x: a.x + t * (b.x - a.x),
+                y: k,
+            }
+        }
+    };
+
+    pts = clip_half_plane(pts, |c| c.x >= min.x, at_x(min.x));
+    pts = clip_half_plane(pts, |c| c.x <= max.x, at_x(max.x));
+    pts = clip_half_plane(pts, |c| c.y >= min.y, at_y(min.y));
+    pts = clip_half_plane(pts, |c| c.y <= max.y, at_y(max.y));
+
+    let two = T::one() + T::one();
+    let twice_area = (0..pts.len()).fold(T::zero(), |acc, i| {
+        let (a, b) = (pts[i], pts[(i + 1) % pts.len()]);
+        acc + a.x * b.y - b.x * a.y
+    });
+
+    twice_area.abs() / two
+}
+
+// One Sutherland-Hodgman pass, keeping the part of the ring on the inside of
+// a single edge. `cross` is only called on segments that straddle the edge
+fn clip_half_plane<T>(
+    pts: Vec<Coord<T>>,
+    inside: impl Fn(&Coord<T>) -> bool,
+    cross: impl Fn(Coord<T>, Coord<T>) -> Coord<T>,
+) -> Vec<Coord<T>>
+where
+    T: GeoFloat,
+{
+    let mut out = Vec::with_capacity(pts.len() + 4);
+
+    for (i, cur) in pts.iter().enumerate() {
+        let prev = pts[(i + pts.len() - 1) % pts.len()];
+        match (inside(&prev), inside(cur)) {
+            (true, true) => out.push(*cur),
+            (true, false) => out.push(cross(prev, *cur)),
+            (false, true) => {
+                out.push(cross(prev, *cur));
+                out.push(*cur);
+            }
+            (false, false) => {}
+        }
+    }
+
+    out
+}
@@ -0,0 +1,153 @@
+use std::sync::Arc;
+
+use geo::{Coord, GeoFloat, GeoNum, MapCoords, Point, Rect};
+
+use super::{ClosestPoints, Snap};
+use crate::{AsGeom, Error, Geometry, GeometryRef};
+
+/// Trait for user-defined distance metrics, which plug into any QuadTree
+/// through [`crate::CalcMethod::Custom`].
+///
+/// `dist_geom` measures the distance between two geometries, and `dist_bbox`
+/// gives a lower bound on the distance from a geometry to anything inside a
+/// node's bounding box. The bound must be admissible, never more than the
+/// distance to any geometry inside the box, for searches to stay exact. The
+/// smaller it is the less of the tree is pruned, so zero is always valid, but
+/// slow. It should also be zero for a geometry inside the box, as the search
+/// methods use that to check the comparator is in bounds.
+///
+/// The remaining methods are optional. [`Metric::max_dist_bbox`] enables
+/// [`crate::QuadTreeSearch::sorted_desc`] and
+/// [`crate::QuadTreeSearch::farthest`], which otherwise return nothing, and
+/// the closest point methods enable the `_with_points` and `snap` searches,
+/// which otherwise error with [`Error::UnsupportedGeometry`].
+///
+/// Metrics are shared through an [`std::sync::Arc`], so one built at runtime can be
+/// handed to several trees:
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use geo::{Point, Rect, coord};
+/// use quadtree::{CalcMethod, Error, GeometryRef, Metric, PointQuadTree, QuadTree, QuadTreeSearch};
+///
+/// // Euclidean distances with east-west travel twice as costly as north-south
+/// #[derive(Debug)]
+/// struct Anisotropic;
+///
+/// impl Metric<f64> for Anisotropic {
+///     fn dist_geom(&self, g1: &GeometryRef<f64>, g2: &GeometryRef<f64>) -> Result<f64, Error> {
+///         match (g1, g2) {
+///             (GeometryRef::Point(p1), GeometryRef::Point(p2)) => {
+///                 Ok((2.0 * (p1.x() - p2.x())).hypot(p1.y() - p2.y()))
+///             }
+///             _ => Err(Error::UnsupportedGeometry),
+///         }
+///     }
+///
+///     fn dist_bbox(&self, geom: &GeometryRef<f64>, bbox: &Rect<f64>) -> Result<f64, Error> {
+///         let GeometryRef::Point(pt) = geom else {
+///             return Err(Error::UnsupportedGeometry);
+///         };
+///         let gap = |x: f64, min: f64, max: f64| (min - x).max(x - max).max(0.0);
+///         let dx = gap(pt.x(), bbox.min().x, bbox.max().x);
+///         let dy = gap(pt.y(), bbox.min().y, bbox.max().y);
+///
+///         Ok((2.0 * dx).hypot(dy))
+///     }
+/// }
+///
+/// let bounds = Rect::new(coord!(x: 0.0, y: 0.0), coord!(x: 10.0, y: 10.0));
+/// let mut qt = PointQuadTree::from_bounds(bounds, CalcMethod::Custom(Arc::new(Anisotropic)));
+/// qt.insert(Point::new(3.0, 5.0)).unwrap();
+/// qt.insert(Point::new(5.0, 8.0)).unwrap();
+///
+/// assert_eq!(qt.find(&Point::new(5.0, 5.0)).unwrap(), (&Point::new(5.0, 8.0), 3.0));
+/// ```
+pub trait Metric<T>: std::fmt::Debug + Send + Sync
+where
+    T: GeoNum,
+{
+    /// Calculate the distance between two geometries.
+    fn dist_geom(&self, g1: &GeometryRef<T>, g2: &GeometryRef<T>) -> Result<T, Error>;
+
+    /// Calculate an admissible lower bound on the distance between a
+    /// geometry and anything inside the passed bounding box.
+    fn dist_bbox(&self, geom: &GeometryRef<T>, bbox: &Rect<T>) -> Result<T, Error>;
+
+    /// Calculate an upper bound on the distance between a geometry and
+    /// anything inside the passed bounding box.
+    fn max_dist_bbox(&self, _geom: &GeometryRef<T>, _bbox: &Rect<T>) -> Result<T, Error> {
+        Err(Error::UnsupportedGeometry)
+    }
+
+    /// Find the closest pair of points between two geometries, the first on
+    /// `g1` and the second on `g2`, see [`crate::GeomCalc::closest_points`].
+    fn closest_points(
+        &self,
+        _g1: &GeometryRef<T>,
+        _g2: &GeometryRef<T>,
+    ) -> Result<ClosestPoints<T>, Error> {
+        Err(Error::UnsupportedGeometry)
+    }
+
+    /// Snap a point onto a line or linestring, see [`crate::GeomCalc::snap`].
+    fn snap(&self, _pt: &Point<T>, _geom: &GeometryRef<T>) -> Result<Snap<T>, Error> {
+        Err(Error::UnsupportedGeometry)
+    }
+}
+
+/// Adapter measuring geometries in any float type with an `f64` metric, by
+/// converting them to `f64` on the way in and the distances back on the way
+/// out. Used by [`crate::IntQuadTree`], which searches in the comparator's
+/// float type. Only the distance methods are forwarded.
+#[derive(Debug)]
+pub(crate) struct Cast(pub(crate) Arc<dyn Metric<f64>>);
+
+impl<F> Metric<F> for Cast
+where
+    F: GeoFloat,
+{
+    fn dist_geom(&self, g1: &GeometryRef<F>, g2: &GeometryRef<F>) -> Result<F, Error> {
+        let d = self
+            .0
+            .dist_geom(&to_f64(g1)?.as_geom(), &to_f64(g2)?.as_geom())?;
+        F::from(d).ok_or(Error::InvalidDistance)
+    }
+
+    fn dist_bbox(&self, geom: &GeometryRef<F>, bbox: &Rect<F>) -> Result<F, Error> {
+        let bbox = bbox.try_map_coords(coord_to_f64)?;
+        let d = self.0.dist_bbox(&to_f64(geom)?.as_geom(), &bbox)?;
+        F::from(d).ok_or(Error::InvalidDistance)
+    }
+
+    fn max_dist_bbox(&self, geom: &GeometryRef<F>, bbox: &Rect<F>) -> Result<F, Error> {
+        let bbox = bbox.try_map_coords(coord_to_f64)?;
+        let d = self.0.max_dist_bbox(&to_f64(geom)?.as_geom(), &bbox)?;
+        F::from(d).ok_or(Error::InvalidDistance)
+    }
+}
+
+fn coord_to_f64<F>(c: Coord<F>) -> Result<Coord<f64>, Error>
+where
+    F: GeoFloat,
+{
+    let cast = |x: F| x.to_f64().ok_or(Error::InvalidDistance);
+    Ok(Coord {
+        x: cast(c.x)?,
+        y: cast(c.y)?,
+    })
+}
+
+fn to_f64<F>(geom: &GeometryRef<F>) -> Result<Geometry<f64>, Error>
+where
+    F: GeoFloat,
+{
+    Ok(match geom {
+        GeometryRef::Point(g) => Geometry::Point(g.try_map_coords(coord_to_f64)?),
+        GeometryRef::Line(g) => Geometry::Line(g.try_map_coords(coord_to_f64)?),
+        GeometryRef::LineString(g) => Geometry::LineString(g.try_map_coords(coord_to_f64)?),
+        GeometryRef::Polygon(g) => Geometry::Polygon(g.try_map_coords(coord_to_f64)?),
+        GeometryRef::Rect(g) => Geometry::Rect(g.try_map_coords(coord_to_f64)?),
+    })
+}
@@ -0,0 +1,50 @@
+use crate::{AsGeom, Geometry, GeometryRef};
+use geo::GeoFloat;
+
+use super::math::dist_geom;
+
+/// Trait applied to geometries to calculate the Minkowski distance of order
+/// `p` between them, which covers the Manhattan (`p` of one) and Chebyshev
+/// (infinite `p`) distances.
+///
+/// This trait should not need to be used outside the crate as it is abstracted
+/// through the [`CalcMethod`](crate::CalcMethod) enum.
+pub trait DistMinkowski<T, Rhs = Self> {
+    fn dist_minkowski(&self, rhs: &Rhs, p: T) -> T;
+}
+
+impl<T> DistMinkowski<T, GeometryRef<'_, T>> for GeometryRef<'_, T>
+where
+    T: GeoFloat,
+{
+    fn dist_minkowski(&self, rhs: &GeometryRef<T>, p: T) -> T {
+        dist_geom(self, rhs, p)
+    }
+}
+
+impl<T> DistMinkowski<T, Geometry<T>> for GeometryRef<'_, T>
+where
+    T: GeoFloat,
+{
+    fn dist_minkowski(&self, rhs: &Geometry<T>, p: T) -> T {
+        dist_geom(self, &rhs.as_geom(), p)
+    }
+}
+
+impl<T> DistMinkowski<T, GeometryRef<'_, T>> for Geometry<T>
+where
+    T: GeoFloat,
+{
+    fn dist_minkowski(&self, rhs: &GeometryRef<T>, p: T) -> T {
+        dist_geom(&self.as_geom(), rhs, p)
+    }
+}
+
+impl<T> DistMinkowski<T, Geometry<T>> for Geometry<T>
+where
+    T: GeoFloat,
+{
+    fn dist_minkowski(&self, rhs: &Geometry<T>, p: T) -> T {
+        dist_geom(&self.as_geom(), &rhs.as_geom(), p)
+    }
+}
@@ -0,0 +1,275 @@
+use geo::{Coord, GeoFloat, Line, LineString, Point, Rect};
+
+use crate::GeometryRef;
+use crate::geom::{self, ClosestPoints, Snap, line_fraction};
+
+// Golden section steps along a segment, enough to reach the limit of f64
+const STEPS: usize = 80;
+
+/// Calculate the Minkowski norm of order `p` of the vector `delta`, which is
+/// the Manhattan norm for `p` of one, Euclidean for two, and Chebyshev for an
+/// infinite `p`.
+///
+/// `p` should be at least one, below which this is not a norm.
+pub fn norm<T>(delta: Coord<T>, p: T) -> T
+where
+    T: GeoFloat,
+{
+    let (dx, dy) = (delta.x.abs(), delta.y.abs());
+    let two = T::one() + T::one();
+
+    if p == T::one() {
+        dx + dy
+    } else if p == two {
+        dx.hypot(dy)
+    } else if p.is_infinite() {
+        dx.max(dy)
+    } else {
+        // Scale by the larger part so the powers cannot overflow
+        let max = dx.max(dy);
+        match max == T::zero() {
+            true => T::zero(),
+            false => max * ((dx / max).powf(p) + (dy / max).powf(p)).powf(p.recip()),
+        }
+    }
+}
+
+/// Calculate the Minkowski distance of order `p` between two [`Point`]'s.
+pub fn dist_pt_pt<T>(p1: &Point<T>, p2: &Point<T>, p: T) -> T
+where
+    T: GeoFloat,
+{
+    norm(p1.0 - p2.0, p)
+}
+
+/// Snap a [`Point`] onto the closest point of a [`Line`] segment under the
+/// Minkowski distance of order `p`.
+///
+/// For Manhattan and Chebyshev distances the distance along the segment is
+/// piecewise linear, so the closest point is one of the end points or where
+/// the offset to the point changes direction, and is found exactly. Other
+/// orders refine the Euclidean closest point by a golden section search, as
+/// the distance along the segment is convex.
+pub fn snap_pt_line<T>(pt: &Point<T>, line: &Line<T>, p: T) -> Snap<T>
+where
+    T: GeoFloat,
+{
+    let (offset, delta) = (pt.0 - line.start, line.delta());
+    let dist = |t: T| norm(offset - delta * t, p);
+    let unit = |t: &T| t.is_finite() && *t >= T::zero() && *t <= T::one();
+
+    // Where each part of the offset, or their difference or sum, is zero
+    let mut candidates = vec![
+        T::zero(),
+        T::one(),
+        line_fraction(pt, line),
+        offset.x / delta.x,
+        offset.y / delta.y,
+        (offset.x - offset.y) / (delta.x - delta.y),
+        (offset.x + offset.y) / (delta.x + delta.y),
+    ];
+
+    if p != T::one() && !p.is_infinite() {
+        let ratio = (T::from(5.0).expect("Unreachable, floats cast.").sqrt() - T::one())
+            / (T::one() + T::one());
+        let (mut lo, mut hi) = (T::zero(), T::one());
+        for _ in 0..STEPS {
+            let (a, b) = (hi - ratio * (hi - lo), lo + ratio * (hi - lo));
+            if dist(a) < dist(b) {
+                hi = b;
+            } else {
+                lo = a;
+            }
+        }
+        candidates.push((lo + hi) / (T::one() + T::one()));
+    }
+
+    let (fraction, distance) = candidates
+        .into_iter()
+        .filter(unit)
+        .map(|t| (t, dist(t)))
+        .fold((T::zero(), T::infinity()), |best, cur| {
+            if cur.1 < best.1 { cur } else { best }
+        });
+
+    Snap {
+        point: Point::from(line.start + delta * fraction),
+        segment: 0,
+        fraction,
+        distance,
+    }
+}
+
+/// Snap a [`Point`] onto the closest point of a [`LineString`] under the
+/// Minkowski distance of order `p`, taking the first segment on ties. Returns
+/// `None` if the linestring has no segments.
+pub fn snap_pt_linestring<T>(pt: &Point<T>, linestring: &LineString<T>, p: T) -> Option<Snap<T>>
+where
+    T: GeoFloat,
+{
+    Snap::closest(linestring, |line| snap_pt_line(pt, line, p))
+}
+
+/// Find the closest pair of points between two geometries under the
+/// Minkowski distance of order `p`, or `None` if either has no coordinates.
+///
+/// As with the Euclidean distance, where two segments do not cross the
+/// closest pair includes an end point of one of them, which holds for any
+/// norm.
+pub fn closest_points<T>(g1: &GeometryRef<T>, g2: &GeometryRef<T>, p: T) -> Option<ClosestPoints<T>>
+where
+    T: GeoFloat,
+{
+    geom::closest_points(g1, g2, |pt, line| snap_pt_line(pt, line, p))
+}
+
+/// Calculate the Minkowski distance of order `p` between two geometries,
+/// zero where they touch. Infinite if either has no coordinates.
+pub fn dist_geom<T>(g1: &GeometryRef<T>, g2: &GeometryRef<T>, p: T) -> T
+where
+    T: GeoFloat,
+{
+    match (g1, g2) {
+        (GeometryRef::Point(p1), GeometryRef::Point(p2)) => dist_pt_pt(p1, p2, p),
+        (GeometryRef::Point(pt), GeometryRef::Rect(rect))
+        | (GeometryRef::Rect(rect), GeometryRef::Point(pt)) => dist_pt_rect(pt, rect, p),
+        _ => closest_points(g1, g2, p).map_or(T::infinity(), |(p1, p2)| dist_pt_pt(&p1, &p2, p)),
+    }
+}
+
+/// Calculate the Minkowski distance of order `p` between a [`Point`] and a
+/// [`Rect`], measured to the nearest point of the rect, which for any order
+/// clamps the point into it.
+pub fn dist_pt_rect<T>(pt: &Point<T>, rect: &Rect<T>, p: T) -> T
+where
+    T: GeoFloat,
+{
+    let gap = |x: T, min: T, max: T| (min - x).max(x - max).max(T::zero());
+
+    norm(
+        Coord {
+            x: gap(pt.x(), rect.min().x, rect.max().x),
+            y: gap(pt.y(), rect.min().y, rect.max().y),
+        },
+        p,
+    )
+}
+
+/// Calculate the maximum Minkowski distance of order `p` between any point
+/// in `r1` and any point in `r2`, which is between their farthest corners.
+pub fn max_dist_rect_rect<T>(r1: &Rect<T>, r2: &Rect<T>, p: T) -> T
+where
+    T: GeoFloat,
+{
+    let span = |min1: T, max1: T, min2: T, max2: T| (max1 - min2).abs().max((max2 - min1).abs());
+
+    norm(
+        Coord {
+            x: span(r1.min().x, r1.max().x, r2.min().x, r2.max().x),
+            y: span(r1.min().y, r1.max().y, r2.min().y, r2.max().y),
+        },
+        p,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use geo::{BoundingRect, Distance, Euclidean, Polygon, coord, line_string};
+
+    use super::*;
+    use crate::AsGeom;
+
+    #[test]
+    fn norms_cover_manhattan_euclidean_and_chebyshev() {
+        let delta = coord! {x: 3.0, y: -4.0};
+
+        assert_eq!(norm(delta, 1.0), 7.0);
+        assert_eq!(norm(delta, 2.0), 5.0);
+        assert_eq!(norm(delta, f64::INFINITY), 4.0);
+        assert_abs_diff_eq!(norm(delta, 3.0), 91f64.cbrt(), epsilon = 1e-12);
+        assert_eq!(norm(coord! {x: 0.0, y: 0.0}, 3.0), 0.0);
+    }
+
+    #[test]
+    fn snaps_find_the_closest_point_along_the_line() {
+        let line = Line::new(coord! {x: 0.0, y: 0.0}, coord! {x: 4.0, y: 1.0});
+        let samples = (0..=4000).map(|i| line.start + line.delta() * (i as f64 / 4000.0));
+
+        for pt in [
+            Point::new(1.0, 3.0),
+            Point::new(-1.0, 0.5),
+            Point::new(5.0, -2.0),
+        ] {
+            for p in [1.0, 1.5, 2.0, 3.0, f64::INFINITY] {
+                let snap = snap_pt_line(&pt, &line, p);
+                let sampled = samples
+                    .clone()
+                    .map(|c| norm(pt.0 - c, p))
+                    .fold(f64::INFINITY, f64::min);
+
+                assert!(snap.distance <= sampled + 1e-12);
+                assert!(snap.distance > sampled - 1e-3);
+                assert_abs_diff_eq!(dist_pt_pt(&pt, &snap.point, p), snap.distance);
+            }
+        }
+
+        // Exact for piecewise linear norms
+        let pt = Point::new(1.0, 3.0);
+        assert_eq!(snap_pt_line(&pt, &line, 1.0).distance, 2.75);
+        assert_abs_diff_eq!(snap_pt_line(&pt, &line, f64::INFINITY).distance, 2.2);
+    }
+
+    #[test]
+    fn geometry_distances_are_zero_when_touching_and_agree_with_euclidean() {
+        let poly = Polygon::new(
+            line_string![(x: 0.0, y: 0.0), (x: 4.0, y: 0.0), (x: 4.0, y: 4.0), (x: 0.0, y: 4.0)],
+            vec![],
+        );
+        let rect = Rect::new(coord! {x: 6.0, y: 5.0}, coord! {x: 8.0, y: 9.0});
+        let inside = Point::new(1.0, 1.0);
+        let line = Line::new(coord! {x: 5.0, y: 0.0}, coord! {x: 9.0, y: 2.0});
+        let ls: LineString = line_string![(x: -3.0, y: 6.0), (x: -1.0, y: 7.0)];
+
+        let geoms = [
+            poly.as_geom(),
+            rect.as_geom(),
+            inside.as_geom(),
+            line.as_geom(),
+            ls.as_geom(),
+        ];
+        for g1 in &geoms {
+            for g2 in &geoms {
+                let euclidean = g1.into_calc(crate::CalcMethod::Euclidean).dist_geom(g2);
+                assert_abs_diff_eq!(dist_geom(g1, g2, 2.0), euclidean.unwrap(), epsilon = 1e-12);
+
+                let (l1, l2, linf) = (
+                    dist_geom(g1, g2, 1.0),
+                    dist_geom(g1, g2, 2.0),
+                    dist_geom(g1, g2, f64::INFINITY),
+                );
+                assert!(linf <= l2 + 1e-12 && l2 <= l1 + 1e-12);
+            }
+        }
+
+        assert_eq!(dist_geom(&poly.as_geom(), &inside.as_geom(), 1.0), 0.0);
+        assert_eq!(dist_pt_rect(&Point::new(3.0, 2.0), &rect, 1.0), 6.0);
+        assert_eq!(dist_geom(&poly.as_geom(), &rect.as_geom(), 1.0), 3.0);
+        assert_eq!(
+            dist_geom(&poly.as_geom(), &rect.as_geom(), f64::INFINITY),
+            2.0
+        );
+        assert_eq!(
+            Euclidean::distance(&Point::new(3.0, 4.0), &Point::new(0.0, 0.0)),
+            dist_pt_pt(&Point::new(3.0, 4.0), &Point::new(0.0, 0.0), 2.0)
+        );
+        assert_eq!(
+            max_dist_rect_rect(
+                &poly.exterior().bounding_rect().unwrap(),
+                &rect,
+                f64::INFINITY
+            ),
+            9.0
+        );
+    }
+}
@@ -0,0 +1,2 @@
+pub mod dist;
+pub mod math;
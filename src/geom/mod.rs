@@ -1,15 +1,24 @@
 // Module declarations
 pub mod euclidean;
+pub mod geodesic;
 pub mod geometry;
 pub mod math;
+pub mod metric;
+pub mod minkowski;
 pub mod spherical;
 
-use geo::{GeoFloat, GeoNum, Line, LineString, Point, Polygon, Rect};
-use num_traits::{FloatConst, Signed};
+use std::sync::Arc;
+
+use geo::{BoundingRect, CoordNum, GeoFloat, GeoNum, Line, LineString, Point, Polygon, Rect};
+use num_traits::{FloatConst, PrimInt, Signed};
 use rstar::RTreeNum;
 
 pub use euclidean::dist::DistEuclidean;
+pub use geodesic::dist::DistGeodesic;
 pub(crate) use math::*;
+use metric::Cast;
+pub use metric::Metric;
+pub use minkowski::dist::DistMinkowski;
 pub use spherical::dist::DistHaversine;
 
 use crate::{Error, GeometryRef};
@@ -21,6 +30,17 @@ pub trait QtFloat: GeoFloat + Signed + FloatConst + RTreeNum {}
 impl QtFloat for f32 {}
 impl QtFloat for f64 {}
 
+/// Wrapper trait to simplify bounds for integer coordinates in an [`crate::IntQuadTree`]. Comes
+/// implemented for the native integer types up to 32 bits wide, signed and unsigned.
+pub trait QtInt: CoordNum + PrimInt {}
+
+impl QtInt for u8 {}
+impl QtInt for u16 {}
+impl QtInt for u32 {}
+impl QtInt for i8 {}
+impl QtInt for i16 {}
+impl QtInt for i32 {}
+
 /// Set of valid calculation methods with which to instantiate a QuadTree implementation. This set
 /// of flags, which is merged with [`GeomCalc`] in the QuadTrees drives the selection of the
 /// distance algorithm.
@@ -30,26 +50,82 @@ impl QtFloat for f64 {}
 /// - **Euclidean:** Uses standard planar euclidean geometry.
 /// - **Spherical:** Uses the haversine formula for great circle distances (useful for geographic
 ///   applications)
+/// - **Geodesic:** Uses Karney's algorithm for geodesic distances on the WGS84 ellipsoid, for
+///   survey-grade geographic applications. Only distances involving a point are supported, and
+///   [`geo::Rect`] data are not, as their edges along parallels are not geodesics.
+/// - **Manhattan:** Uses planar L1 distances, the sum of the distances along each axis, as on a
+///   grid of streets or warehouse aisles.
+/// - **Chebyshev:** Uses planar L∞ distances, the larger of the distances along each axis.
+/// - **Minkowski:** Uses planar Minkowski distances of the passed order `p`, which must be at
+///   least one. An order of one is Manhattan, two is Euclidean and infinity is Chebyshev.
+/// - **Custom:** Uses a user-defined [`Metric`], such as a travel time approximation, for
+///   applications the built in methods do not cover.
 ///
 /// Euclidean will always output distances in the same units as the inputs, whereas Spherical
 /// requires radian inputs and always produces radian outputs. To get distances in length units,
-/// multiply by the sphere's diameter.
-#[derive(Debug, Clone, Copy)]
-pub enum CalcMethod {
+/// multiply by the sphere's diameter. Geodesic also takes radian inputs, laid out as for
+/// Spherical, but produces meters. The other planar metrics behave as Euclidean.
+///
+/// The numeric type `T` is only needed by [`CalcMethod::Custom`], and is otherwise inferred from
+/// the QuadTree.
+#[derive(Debug, Clone)]
+pub enum CalcMethod<T = f64>
+where
+    T: GeoNum,
+{
     None,
     Euclidean,
     Spherical,
+    Geodesic,
+    Manhattan,
+    Chebyshev,
+    Minkowski(f64),
+    Custom(Arc<dyn Metric<T>>),
+}
+
+impl<T> CalcMethod<T>
+where
+    T: GeoNum,
+{
+    // Whether coordinates are longitude and latitude, wrapping at the
+    // antimeridian
+    pub(crate) fn is_geographic(&self) -> bool {
+        matches!(self, CalcMethod::Spherical | CalcMethod::Geodesic)
+    }
+}
+
+impl CalcMethod {
+    // Carry a method over to another float type, measuring custom metrics in
+    // f64 by converting geometries on the way in
+    pub(crate) fn cast<F>(&self) -> CalcMethod<F>
+    where
+        F: GeoFloat,
+    {
+        match self {
+            CalcMethod::None => CalcMethod::None,
+            CalcMethod::Euclidean => CalcMethod::Euclidean,
+            CalcMethod::Spherical => CalcMethod::Spherical,
+            CalcMethod::Geodesic => CalcMethod::Geodesic,
+            CalcMethod::Manhattan => CalcMethod::Manhattan,
+            CalcMethod::Chebyshev => CalcMethod::Chebyshev,
+            CalcMethod::Minkowski(p) => CalcMethod::Minkowski(*p),
+            CalcMethod::Custom(metric) => CalcMethod::Custom(Arc::new(Cast(metric.clone()))),
+        }
+    }
 }
 
 /// Struct that applies a specific distance algorithm to the reference. Provides `dist_geom` and
 /// `dist_bbox` methods to calculate distances between an arbitrary geometry and a rectangular
 /// bounding box respectively.
+#[derive(Debug, Clone)]
 pub struct GeomCalc<'a, T>
 where
     T: GeoNum,
 {
     geom: GeometryRef<'a, T>,
-    method: CalcMethod,
+    method: CalcMethod<T>,
+    // Set by trees only holding points, which Geodesic bounds more tightly
+    point_data: bool,
 }
 
 impl<T> GeomCalc<'_, T>
@@ -59,22 +135,224 @@ where
     /// Calculate the distance between the contained geometry and another arbitrary geometry `geom`
     /// useing the coordinate system contained in the [`GeomCalc`] struct.
     pub fn dist_geom(&self, geom: &GeometryRef<T>) -> Result<T, crate::Error> {
-        match self.method {
+        match &self.method {
             CalcMethod::None => Err(Error::CalcMethodNotSet),
             CalcMethod::Euclidean => Ok(self.geom.dist_euclidean(geom)),
             CalcMethod::Spherical => self.geom.dist_haversine(geom),
+            CalcMethod::Geodesic => self.geom.dist_geodesic(geom),
+            CalcMethod::Manhattan | CalcMethod::Chebyshev | CalcMethod::Minkowski(_) => {
+                Ok(self.geom.dist_minkowski(geom, self.order()?))
+            }
+            CalcMethod::Custom(metric) => metric.dist_geom(&self.geom, geom),
         }
     }
 
     /// Calculate the distance between the contained geometry and the passed [`Rect`] bounding box
     /// using the coordinate system contained in the [`GeomCalc`] struct.
+    ///
+    /// For Spherical, the bounding box is first grown to cover the great circle arcs between any
+    /// of its points, see [`spherical::math::arc_bounds`], so lines held beneath it are never
+    /// closer than the box. Geodesic scales the Spherical distance to a lower bound in meters, see
+    /// [`geodesic::math::dist_lower_bound_segments`], which is only tightened to
+    /// [`geodesic::math::dist_lower_bound`] when searching a point tree with a point.
     pub fn dist_bbox(&self, bbox: &Rect<T>) -> Result<T, crate::Error> {
-        match self.method {
+        let arc_dist = || spherical::math::arc_bounds(bbox).dist_haversine(&self.geom);
+
+        match &self.method {
             CalcMethod::None => Err(Error::CalcMethodNotSet),
             CalcMethod::Euclidean => Ok(bbox.dist_euclidean(&self.geom)),
-            CalcMethod::Spherical => bbox.dist_haversine(&self.geom),
+            CalcMethod::Spherical => arc_dist(),
+            CalcMethod::Geodesic => match (self.point_data, self.geom) {
+                (true, GeometryRef::Point(_)) => Ok(geodesic::math::dist_lower_bound(arc_dist()?)),
+                _ => Ok(geodesic::math::dist_lower_bound_segments(arc_dist()?)),
+            },
+            CalcMethod::Manhattan | CalcMethod::Chebyshev | CalcMethod::Minkowski(_) => Ok(
+                minkowski::math::dist_geom(&GeometryRef::Rect(bbox), &self.geom, self.order()?),
+            ),
+            CalcMethod::Custom(metric) => metric.dist_bbox(&self.geom, bbox),
+        }
+    }
+
+    /// Calculate an upper bound on the distance between the contained geometry and anything
+    /// inside the passed [`Rect`] bounding box, using the coordinate system contained in the
+    /// [`GeomCalc`] struct. Exact for points, while other geometries are bounded by their own
+    /// bounding box, split at the antimeridian for Spherical. Geodesic scales the Spherical bound
+    /// to meters, see [`geodesic::math::dist_upper_bound`].
+    pub fn max_dist_bbox(&self, bbox: &Rect<T>) -> Result<T, crate::Error> {
+        let rect = match self.geom {
+            GeometryRef::Point(pt) => Rect::new(pt.0, pt.0),
+            geom => geom.bounding_rect().ok_or(Error::CannotMakeBbox)?,
+        };
+        let arc_max_dist = || {
+            let bbox = spherical::math::arc_bounds(bbox);
+            let max_dist = |rect: &Rect<T>| spherical::math::max_dist_rect_rect(rect, &bbox);
+            match spherical::math::antimeridian_bounds(&self.geom) {
+                Some([east, west]) => max_dist(&east).max(max_dist(&west)),
+                None => max_dist(&rect),
+            }
+        };
+
+        match &self.method {
+            CalcMethod::None => Err(Error::CalcMethodNotSet),
+            CalcMethod::Euclidean => Ok(euclidean::math::max_dist_rect_rect(&rect, bbox)),
+            CalcMethod::Spherical => Ok(arc_max_dist()),
+            CalcMethod::Geodesic => Ok(geodesic::math::dist_upper_bound(arc_max_dist())),
+            CalcMethod::Manhattan | CalcMethod::Chebyshev | CalcMethod::Minkowski(_) => Ok(
+                minkowski::math::max_dist_rect_rect(&rect, bbox, self.order()?),
+            ),
+            CalcMethod::Custom(metric) => metric.max_dist_bbox(&self.geom, bbox),
+        }
+    }
+
+    /// Calculate a lower bound on the distance between the contained geometry and `geom` from
+    /// the bounding box of `geom`, as a cheap check before [`GeomCalc::dist_geom`]. For
+    /// Spherical and Geodesic, a geometry crossing the antimeridian is bounded by the parts either
+    /// side of it, see [`spherical::math::antimeridian_bounds`].
+    pub(crate) fn dist_bounds(&self, geom: &GeometryRef<T>) -> Result<T, crate::Error> {
+        let parts = match self.method.is_geographic() {
+            true => spherical::math::antimeridian_bounds(geom),
+            false => None,
+        };
+
+        match parts {
+            Some([east, west]) => Ok(self.dist_bbox(&east)?.min(self.dist_bbox(&west)?)),
+            None => self.dist_bbox(&geom.bounding_rect().ok_or(Error::CannotMakeBbox)?),
         }
     }
+
+    /// Find the closest pair of points between the contained geometry and another arbitrary
+    /// geometry `geom`, using the coordinate system contained in the [`GeomCalc`] struct. The
+    /// first point lies on the contained geometry and the second on `geom`, and both are the same
+    /// point where the geometries touch. Errors with [`Error::Empty`] if either geometry has no
+    /// coordinates.
+    pub fn closest_points(&self, geom: &GeometryRef<T>) -> Result<ClosestPoints<T>, Error> {
+        match &self.method {
+            CalcMethod::None => Err(Error::CalcMethodNotSet),
+            CalcMethod::Euclidean => {
+                closest_points(&self.geom, geom, euclidean::math::snap_pt_line).ok_or(Error::Empty)
+            }
+            CalcMethod::Spherical => {
+                spherical::math::closest_points(&self.geom, geom).ok_or(Error::Empty)
+            }
+            CalcMethod::Geodesic => match (self.geom, *geom) {
+                (GeometryRef::Point(pt), geom) => Ok((*pt, geodesic::math::closest_pt(pt, &geom)?)),
+                (geom, GeometryRef::Point(pt)) => Ok((geodesic::math::closest_pt(pt, &geom)?, *pt)),
+                _ => Err(Error::UnsupportedGeometry),
+            },
+            CalcMethod::Manhattan | CalcMethod::Chebyshev | CalcMethod::Minkowski(_) => {
+                minkowski::math::closest_points(&self.geom, geom, self.order()?).ok_or(Error::Empty)
+            }
+            CalcMethod::Custom(metric) => metric.closest_points(&self.geom, geom),
+        }
+    }
+
+    /// Snap the contained geometry, which must be a [`Point`], onto the passed [`Line`] or
+    /// [`LineString`], finding the closest point on it using the coordinate system contained in
+    /// the [`GeomCalc`] struct. Errors with [`Error::UnsupportedGeometry`] for other geometries,
+    /// and [`Error::Empty`] for a linestring without any segments.
+    pub fn snap(&self, geom: &GeometryRef<T>) -> Result<Snap<T>, crate::Error> {
+        let GeometryRef::Point(pt) = self.geom else {
+            return Err(Error::UnsupportedGeometry);
+        };
+
+        match (&self.method, *geom) {
+            (CalcMethod::None, _) => Err(Error::CalcMethodNotSet),
+            (CalcMethod::Euclidean, GeometryRef::Line(line)) => {
+                Ok(euclidean::math::snap_pt_line(pt, line))
+            }
+            (CalcMethod::Euclidean, GeometryRef::LineString(ls)) => {
+                euclidean::math::snap_pt_linestring(pt, ls).ok_or(Error::Empty)
+            }
+            (CalcMethod::Spherical, GeometryRef::Line(line)) => {
+                Ok(spherical::math::snap_pt_line(pt, line))
+            }
+            (CalcMethod::Spherical, GeometryRef::LineString(ls)) => {
+                spherical::math::snap_pt_linestring(pt, ls).ok_or(Error::Empty)
+            }
+            (CalcMethod::Geodesic, GeometryRef::Line(line)) => {
+                Ok(geodesic::math::snap_pt_line(pt, line))
+            }
+            (CalcMethod::Geodesic, GeometryRef::LineString(ls)) => {
+                geodesic::math::snap_pt_linestring(pt, ls).ok_or(Error::Empty)
+            }
+            (
+                CalcMethod::Manhattan | CalcMethod::Chebyshev | CalcMethod::Minkowski(_),
+                GeometryRef::Line(line),
+            ) => Ok(minkowski::math::snap_pt_line(pt, line, self.order()?)),
+            (
+                CalcMethod::Manhattan | CalcMethod::Chebyshev | CalcMethod::Minkowski(_),
+                GeometryRef::LineString(ls),
+            ) => minkowski::math::snap_pt_linestring(pt, ls, self.order()?).ok_or(Error::Empty),
+            (CalcMethod::Custom(metric), geom) => metric.snap(pt, &geom),
+            _ => Err(Error::UnsupportedGeometry),
+        }
+    }
+
+    // Mark the data searched as all points, for trees that only hold points
+    pub(crate) fn over_points(self) -> Self {
+        Self {
+            point_data: true,
+            ..self
+        }
+    }
+
+    // The order of the Minkowski distance for the planar metrics other than
+    // Euclidean, erroring where it would not be a metric
+    fn order(&self) -> Result<T, Error> {
+        match &self.method {
+            CalcMethod::Manhattan => Ok(T::one()),
+            CalcMethod::Chebyshev => Ok(T::infinity()),
+            CalcMethod::Minkowski(p) if *p >= 1.0 => T::from(*p).ok_or(Error::InvalidDistance),
+            _ => Err(Error::InvalidDistance),
+        }
+    }
+}
+
+/// The closest pair of points between two geometries, as returned by
+/// [`GeomCalc::closest_points`], in the same order as the geometries.
+pub type ClosestPoints<T> = (Point<T>, Point<T>);
+
+/// Where a [`Point`] snaps onto a [`Line`] or [`LineString`], as returned by
+/// [`GeomCalc::snap`] and [`crate::QuadTreeSearch::snap`].
+///
+/// `segment` is the index of the closest segment, always zero for a [`Line`],
+/// and `fraction` is how far along that segment the closest point lies, from
+/// zero at its start to one at its end. The distance is in the units of the
+/// [`CalcMethod`] used, so radians for Spherical and meters for Geodesic.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Snap<T>
+where
+    T: GeoNum,
+{
+    pub point: Point<T>,
+    pub segment: usize,
+    pub fraction: T,
+    pub distance: T,
+}
+
+impl<T> Snap<T>
+where
+    T: GeoFloat,
+{
+    // Snap onto each segment of a linestring in turn, keeping the first
+    // closest, or None if there are no segments
+    pub(crate) fn closest(
+        linestring: &LineString<T>,
+        snap_line: impl Fn(&Line<T>) -> Snap<T>,
+    ) -> Option<Self> {
+        linestring
+            .lines()
+            .enumerate()
+            .map(|(segment, line)| Snap {
+                segment,
+                ..snap_line(&line)
+            })
+            .min_by(|a, b| {
+                a.distance
+                    .partial_cmp(&b.distance)
+                    .expect("Unreachable, NaN distances not produced.")
+            })
+    }
 }
 
 /// The main constraint for QuadTree input data. Both inserted items and test comparators must
@@ -91,9 +369,9 @@ where
 {
     /// Convert this geometry/datum to a [`GeometryRef`]. This is used in QuadTree implementations
     /// to provide poymorphic distance calculations.
-    fn as_geom(&self) -> GeometryRef<T>;
+    fn as_geom(&self) -> GeometryRef<'_, T>;
 
-    fn with_calc(&self, method: CalcMethod) -> GeomCalc<'_, T> {
+    fn with_calc(&self, method: CalcMethod<T>) -> GeomCalc<'_, T> {
         self.as_geom().into_calc(method)
     }
 }
@@ -102,7 +380,7 @@ impl<T> AsGeom<T> for Point<T>
 where
     T: GeoNum,
 {
-    fn as_geom(&self) -> GeometryRef<T> {
+    fn as_geom(&self) -> GeometryRef<'_, T> {
         GeometryRef::Point(self)
     }
 }
@@ -111,7 +389,7 @@ impl<T> AsGeom<T> for Line<T>
 where
     T: GeoNum,
 {
-    fn as_geom(&self) -> GeometryRef<T> {
+    fn as_geom(&self) -> GeometryRef<'_, T> {
         GeometryRef::Line(self)
     }
 }
@@ -120,7 +398,7 @@ impl<T> AsGeom<T> for LineString<T>
 where
     T: GeoNum,
 {
-    fn as_geom(&self) -> GeometryRef<T> {
+    fn as_geom(&self) -> GeometryRef<'_, T> {
         GeometryRef::LineString(self)
     }
 }
@@ -129,7 +407,7 @@ impl<T> AsGeom<T> for Polygon<T>
 where
     T: GeoNum,
 {
-    fn as_geom(&self) -> GeometryRef<T> {
+    fn as_geom(&self) -> GeometryRef<'_, T> {
         GeometryRef::Polygon(self)
     }
 }
@@ -138,7 +416,7 @@ impl<T> AsGeom<T> for Rect<T>
 where
     T: GeoNum,
 {
-    fn as_geom(&self) -> GeometryRef<T> {
+    fn as_geom(&self) -> GeometryRef<'_, T> {
         GeometryRef::Rect(self)
     }
 }
@@ -47,10 +47,10 @@ where
     fn dist_haversine(&self, rhs: &GeometryRef<T>) -> Result<T, Error> {
         match rhs {
             GeometryRef::Point(d) => Ok(dist_pt_line(d, self)),
-            GeometryRef::Line(_) => Err(Error::InvalidDistance),
-            GeometryRef::LineString(_) => Err(Error::InvalidDistance),
-            GeometryRef::Polygon(_) => Err(Error::InvalidDistance),
-            GeometryRef::Rect(_) => Err(Error::InvalidDistance),
+            GeometryRef::Line(d) => Ok(dist_line_line(self, d)),
+            GeometryRef::LineString(d) => Ok(dist_line_linestring(self, d)),
+            GeometryRef::Polygon(d) => Ok(dist_line_poly(self, d)),
+            GeometryRef::Rect(d) => Ok(dist_line_rect(self, d)),
         }
     }
 }
@@ -70,11 +70,11 @@ where
 {
     fn dist_haversine(&self, rhs: &GeometryRef<T>) -> Result<T, Error> {
         match rhs {
-            GeometryRef::Point(_) => Err(Error::InvalidDistance),
-            GeometryRef::Line(_) => Err(Error::InvalidDistance),
-            GeometryRef::LineString(_) => Err(Error::InvalidDistance),
-            GeometryRef::Polygon(_) => Err(Error::InvalidDistance),
-            GeometryRef::Rect(_) => Err(Error::InvalidDistance),
+            GeometryRef::Point(d) => Ok(dist_pt_linestring(d, self)),
+            GeometryRef::Line(d) => Ok(dist_line_linestring(d, self)),
+            GeometryRef::LineString(d) => Ok(dist_linestring_linestring(self, d)),
+            GeometryRef::Polygon(d) => Ok(dist_linestring_poly(self, d)),
+            GeometryRef::Rect(d) => Ok(dist_linestring_rect(self, d)),
         }
     }
 }
@@ -95,10 +95,10 @@ where
     fn dist_haversine(&self, rhs: &GeometryRef<T>) -> Result<T, Error> {
         match rhs {
             GeometryRef::Point(p) => Ok(dist_pt_poly(p, self)),
-            GeometryRef::Line(_) => Err(Error::InvalidDistance),
-            GeometryRef::LineString(_) => Err(Error::InvalidDistance),
-            GeometryRef::Polygon(_) => Err(Error::InvalidDistance),
-            GeometryRef::Rect(_) => Err(Error::InvalidDistance),
+            GeometryRef::Line(d) => Ok(dist_line_poly(d, self)),
+            GeometryRef::LineString(d) => Ok(dist_linestring_poly(d, self)),
+            GeometryRef::Polygon(d) => Ok(dist_poly_poly(self, d)),
+            GeometryRef::Rect(d) => Ok(dist_poly_rect(self, d)),
         }
     }
 }
@@ -119,9 +119,9 @@ where
     fn dist_haversine(&self, rhs: &GeometryRef<T>) -> Result<T, Error> {
         match rhs {
             GeometryRef::Point(d) => Ok(dist_pt_rect(d, self)),
-            GeometryRef::Line(_) => Err(Error::InvalidDistance),
-            GeometryRef::LineString(_) => Err(Error::InvalidDistance),
-            GeometryRef::Polygon(_) => Err(Error::InvalidDistance),
+            GeometryRef::Line(d) => Ok(dist_line_rect(d, self)),
+            GeometryRef::LineString(d) => Ok(dist_linestring_rect(d, self)),
+            GeometryRef::Polygon(d) => Ok(dist_poly_rect(d, self)),
             GeometryRef::Rect(d) => Ok(dist_rect_rect(self, d)),
         }
     }
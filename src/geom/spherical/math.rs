@@ -1,8 +1,12 @@
-use geo::{GeoFloat, Intersects, Line, LineString, Point, Polygon, Rect, coord};
+use geo::{
+    BoundingRect, Coord, GeoFloat, GeoNum, Intersects, Line, LineString, Point, Polygon, Rect,
+    coord,
+};
 use std::f64::consts::PI;
 use std::ops::{Add, Sub};
 
-use crate::geom::pt_in_rect;
+use crate::geom::{self, ClosestPoints, Snap};
+use crate::{AsGeom, Geometry, GeometryRef};
 
 /// Helper macro for making points
 macro_rules! p {
@@ -101,46 +105,91 @@ where
 /// Calculate the great circle distance between a [`Point`] and a [`Line`]
 /// using the Haversine formula.
 ///
+/// The line is taken as the shorter great circle arc between its end points,
+/// so this is the cross-track distance where the point lies alongside the
+/// arc, and the distance to the closer end point otherwise.
+///
 /// Inputs and outputs are in radians. Convert radians to a linear distance by
 /// multiplying by the sphere's radius.
-///
-/// Adapted from [TurfJS](https://github.com/Turfjs/turf/blob/master/packages/turf-point-to-line-distance/index.ts).
 pub fn dist_pt_line<T>(pt: &Point<T>, line: &Line<T>) -> T
 where
     T: GeoFloat,
 {
-    // Projection logic is identical to the euclidean case,
-    // but distance calc is different
-    let (x, y) = pt.x_y();
-    let (x1, y1) = line.start_point().x_y();
-    let (x2, y2) = line.end_point().x_y();
-
-    let (a, b, c, d) = (x - x1, y - y1, x2 - x1, y2 - y1);
-
-    let dot = a * c + b * d;
-    let len_sq = c * c + d * d;
+    snap_pt_line(pt, line).distance
+}
 
-    // Wrap in an `if` to account for a zero line length
-    // Just has to be <0 to work so we pick distance to p1
-    let param = if len_sq == T::zero() {
-        -T::one()
-    } else {
-        dot / len_sq
+/// Snap a [`Point`] onto the closest point of a [`Line`], taken as the
+/// shorter great circle arc between its end points, with the distance between
+/// them calculated using the Haversine formula.
+///
+/// The closest point is the foot of the perpendicular great circle through
+/// the point where that falls on the arc, and the closer end point otherwise.
+/// The fraction is the along-track distance to the closest point over the
+/// length of the arc. Inputs and outputs are in radians.
+pub fn snap_pt_line<T>(pt: &Point<T>, line: &Line<T>) -> Snap<T>
+where
+    T: GeoFloat,
+{
+    let (a, b, p) = (
+        to_vec(&line.start_point()),
+        to_vec(&line.end_point()),
+        to_vec(pt),
+    );
+    let snap_to = |point: Point<T>, fraction: T| Snap {
+        point,
+        segment: 0,
+        fraction,
+        distance: dist_pt_pt(pt, &point),
+    };
+    let nearer_end = || {
+        let (to_a, to_b) = (
+            dist_pt_pt(pt, &line.start_point()),
+            dist_pt_pt(pt, &line.end_point()),
+        );
+        match to_a <= to_b {
+            true => snap_to(line.start_point(), T::zero()),
+            false => snap_to(line.end_point(), T::one()),
+        }
     };
 
-    if param < T::zero() {
-        // Closest to start point, so reduces to pt-pt
-        dist_pt_pt(pt, &line.start_point())
-    } else if param > T::one() {
-        // Closest to end point, so pt-pt again
-        dist_pt_pt(pt, &line.end_point())
+    // Normal to the arc's great circle, which is undefined for zero length
+    // and antipodal end points
+    let n = cross(a, b);
+    let n_sq = dot(n, n);
+    if n_sq == T::zero() {
+        return nearer_end();
+    }
+    let n_len = n_sq.sqrt();
+    let arc = n_len.atan2(dot(a, b));
+
+    // Foot of the perpendicular, undefined when the point is a pole of the
+    // great circle, where the whole circle is equidistant
+    let c = scale(n, dot(p, n) / n_sq);
+    let c = [p[0] - c[0], p[1] - c[1], p[2] - c[2]];
+    if dot(c, c) == T::zero() {
+        return snap_to(line.start_point(), T::zero());
+    }
+
+    // Signed along-track angle from the start of the arc to the foot
+    let along = (dot(cross(a, c), n) / n_len).atan2(dot(a, c));
+    if along >= T::zero() && along <= arc {
+        snap_to(from_vec(c), along / arc)
     } else {
-        // Here we project onto the segment
-        let projected = p!(x1 + param * c, y1 + param * d);
-        dist_pt_pt(pt, &projected)
+        nearer_end()
     }
 }
 
+/// Snap a [`Point`] onto the closest point of a [`LineString`], taking the
+/// first segment on ties. Returns `None` if the linestring has no segments.
+///
+/// Inputs and outputs are in radians.
+pub fn snap_pt_linestring<T>(pt: &Point<T>, linestring: &LineString<T>) -> Option<Snap<T>>
+where
+    T: GeoFloat,
+{
+    Snap::closest(linestring, |line| snap_pt_line(pt, line))
+}
+
 /// Calculate the great circle distance between a [`Point`] and a [`LineString`]
 /// using the HAversine formula.
 ///
@@ -167,57 +216,54 @@ where
 /// Calculate the great circle distance between a [`Point`] and a [`Rect`]
 /// using the Haversine formula.
 ///
+/// The rect is the region between two meridians and two parallels. Directly
+/// above or below it the distance is the difference in latitude, otherwise
+/// the closest point lies on the nearer meridian, which is a great circle.
+/// Longitudes wrap, so a point just east of the antimeridian is close to a
+/// rect just west of it.
+///
 /// Inputs and outputs are in radians. Convert radians to a linear distance by
 /// multiplying by the sphere's radius.
 pub fn dist_pt_rect<T>(pt: &Point<T>, rect: &Rect<T>) -> T
 where
     T: GeoFloat,
 {
+    let pi = T::from(PI).unwrap();
+    let two_pi = pi + pi;
     let (x, y) = pt.x_y();
 
-    // Return early if the point is inside the rect
-    // Can't use contains here, see notes on pt_in_rect
-    if pt_in_rect(rect, pt) {
-        return T::zero();
-    }
-
-    // Take the "left" edge whenever the point is to the left of the rect and
-    // the right edge whenever its to the right of the rect. This works even
-    // when diagonal to these edges as in these cases the closest point is the
-    // corner, which is shared with the other candidate line.
-    let line = if x < rect.min().x {
-        // smallest x-value
-        l!(rect.min().x, rect.min().y, rect.min().x, rect.max().y)
-    } else if x > rect.max().x {
-        // largest x-value
-        l!(rect.max().x, rect.min().y, rect.max().x, rect.max().y)
-    } else if y < rect.min().y {
-        // smallest y-value
-        l!(rect.min().x, rect.min().y, rect.max().x, rect.min().y)
+    if [x, x - two_pi, x + two_pi]
+        .into_iter()
+        .any(|x| x >= rect.min().x && x <= rect.max().x)
+    {
+        // Same meridian, so the latitude delta maps directly to radians, and
+        // is zero inside the rect
+        return (rect.min().y - y).max(y - rect.max().y).max(T::zero());
+    }
+
+    // Lng::sub deals with wrapping when picking the nearer meridian
+    let delta = |edge: T| f64::from(Lng::from(edge) - Lng::from(x)).abs();
+    let edge = if delta(rect.min().x) <= delta(rect.max().x) {
+        rect.min().x
     } else {
-        // largest y-value as final case
-        l!(rect.min().x, rect.max().y, rect.max().x, rect.max().y)
+        rect.max().x
     };
 
-    dist_pt_line(pt, &line)
+    dist_pt_line(pt, &l!(edge, rect.min().y, edge, rect.max().y))
 }
 
 // Calculate Spherical bounds distances.
-// Note that the antimeridian is a problem that is not easy to solve, see:
-// https://macwright.com/2016/09/26/the-180th-meridian.html. All calcs
-// in this module assume that no shape can cross 180 deg lng. Everything
-// must be a separate shape in a composite.
+// Rects never cross the antimeridian, as a Rect always has its min x below
+// its max x. Other geometries may, with a segment taking the shorter way
+// round, so they are unwrapped before any planar tests, see unwrap_geom.
 
 /// Calculate the great circle distance between two [`Rect`]'s using the
 /// Haversine formula.
 ///
+/// Rects either side of the antimeridian are measured across it.
+///
 /// Inputs and outputs are in radians. Convert radians to a linear distance by
 /// multiplying by the sphere's radius.
-///
-/// Note that the antimeridian is a problem that is not easy to solve, see:
-/// https://macwright.com/2016/09/26/the-180th-meridian.html. All calcs
-/// in this module assume that no shape can cross 180 deg lng. Everything
-/// must be a separate shape in a composite.
 pub fn dist_rect_rect<T>(r1: &Rect<T>, r2: &Rect<T>) -> T
 where
     T: GeoFloat,
@@ -238,33 +284,10 @@ where
 
             if d1 < d2 { d1 } else { d2 }
         }
-        // If y (lat) overlaps, then find the point of overlap with the
-        // maximum abs value of lat (closest to the poles) and calc
-        // distance for this lat and the respective lngs
-        (false, true) => {
-            // Point of max overlap is the min of the bounds maxes
-            let min_lat = r1.min().y.max(r2.min().y);
-            let max_lat = r1.max().y.min(r2.max().y);
-            let lat = if min_lat.abs() > max_lat.abs() {
-                min_lat
-            } else {
-                max_lat
-            };
-
-            // Easiest way to adjust for lng wrapping is to take the pair
-            // with the min lng delta, because Lng::sub deals with wrapping
-            let delta_xa = f64::from(Lng::from(r1.max().x) - Lng::from(r2.min().x)).abs();
-            let delta_xi = f64::from(Lng::from(r1.min().x) - Lng::from(r2.max().x)).abs();
-
-            if delta_xa < delta_xi {
-                dist_pt_pt(&p!(r1.max().x, lat), &p!(r2.min().x, lat))
-            } else {
-                dist_pt_pt(&p!(r1.min().x, lat), &p!(r2.max().x, lat))
-            }
-        }
-        // When neither overlaps, take the distance from the closest
-        // corners, accounting for wrapping lngs
-        (false, false) => {
+        // Otherwise the closest points lie on the facing meridians, which
+        // are great circle arcs that do not cross, so one of them is an end
+        // point of an arc
+        (false, _) => {
             // Easiest way to adjust for lng wrapping is to take the pair
             // with the min lng delta, because Lng::sub deals with wrapping
             let delta_xa = f64::from(Lng::from(r1.max().x) - Lng::from(r2.min().x)).abs();
@@ -275,19 +298,98 @@ where
             } else {
                 (r1.min().x, r2.max().x)
             };
-            let (y1, y2) = if r1.max().y < r2.min().y {
-                (r1.max().y, r2.min().y)
+            let e1 = l!(x1, r1.min().y, x1, r1.max().y);
+            let e2 = l!(x2, r2.min().y, x2, r2.max().y);
+
+            [
+                dist_pt_line(&e1.start_point(), &e2),
+                dist_pt_line(&e1.end_point(), &e2),
+                dist_pt_line(&e2.start_point(), &e1),
+                dist_pt_line(&e2.end_point(), &e1),
+            ]
+            .into_iter()
+            .fold(T::infinity(), T::min)
+        }
+    }
+}
+
+/// Calculate the maximum great circle distance between a [`Point`] and any
+/// point in a [`Rect`] using the Haversine formula.
+///
+/// The cosine of the distance is `sin(lat1) sin(lat2) + cos(lat1) cos(lat2)
+/// cos(dlng)`. As latitude cosines are never negative, the longitude in the
+/// rect that is farthest round from the point is farthest at every latitude,
+/// leaving a sinusoid in latitude to minimise over the rect's latitude range.
+///
+/// Inputs and outputs are in radians. Convert radians to a linear distance by
+/// multiplying by the sphere's radius.
+pub fn max_dist_pt_rect<T>(pt: &Point<T>, rect: &Rect<T>) -> T
+where
+    T: GeoFloat,
+{
+    let pi = T::from(PI).unwrap();
+    let (x, y) = pt.x_y();
+
+    // The antipodal longitude if the rect spans it, otherwise whichever edge
+    // is further round, accounting for wrapping
+    let antipode = Lng::from(x + pi).0;
+    let two_pi = pi + pi;
+    let lng = [antipode, antipode - two_pi, antipode + two_pi]
+        .into_iter()
+        .find(|lng| *lng >= rect.min().x && *lng <= rect.max().x)
+        .unwrap_or_else(|| {
+            let delta = |edge: T| f64::from(Lng::from(edge) - Lng::from(x)).abs();
+            if delta(rect.min().x) > delta(rect.max().x) {
+                rect.min().x
             } else {
-                (r1.min().y, r2.max().y)
-            };
+                rect.max().x
+            }
+        });
+
+    // Minimise a sin(lat) + b cos(lat), checking the edges and the trough of
+    // the sinusoid if it falls inside the latitude range
+    let (a, b) = (y.sin(), y.cos() * (lng - x).cos());
+    let cos_dist = |lat: T| a * lat.sin() + b * lat.cos();
+    let peak = a.atan2(b);
+    let trough = if peak > T::zero() {
+        peak - pi
+    } else {
+        peak + pi
+    };
 
-            dist_pt_pt(&p!(x1, y1), &p!(x2, y2))
+    let mut lat = rect.min().y;
+    for candidate in [rect.max().y, trough] {
+        if candidate >= rect.min().y
+            && candidate <= rect.max().y
+            && cos_dist(candidate) < cos_dist(lat)
+        {
+            lat = candidate;
         }
     }
+
+    dist_pt_pt(pt, &p!(lng, lat))
+}
+
+/// Calculate an upper bound on the great circle distance between any point
+/// in `r1` and any point in `r2` using the Haversine formula.
+///
+/// Uses the triangle inequality through the center of `r1`, so is exact when
+/// `r1` is a point, and is capped at the largest possible distance, Pi.
+///
+/// Inputs and outputs are in radians. Convert radians to a linear distance by
+/// multiplying by the sphere's radius.
+pub fn max_dist_rect_rect<T>(r1: &Rect<T>, r2: &Rect<T>) -> T
+where
+    T: GeoFloat,
+{
+    let center = Point::from(r1.center());
+    let bound = max_dist_pt_rect(&center, r2) + max_dist_pt_rect(&center, r1);
+
+    bound.min(T::from(PI).unwrap())
 }
 
 /// Calculate the great circle distance between a [`Point`] and an arbitrary [`Polygon`] using the
-/// Haversine formula.
+/// Haversine formula. The polygon may cross the antimeridian.
 ///
 /// Inputs and outputs are in radians. Convert radians to a linear distance by
 /// multiplying by the sphere's radius.
@@ -295,6 +397,14 @@ pub fn dist_pt_poly<T>(pt: &Point<T>, poly: &Polygon<T>) -> T
 where
     T: GeoFloat,
 {
+    // Containment is tested in the plane, so bring a polygon that crosses the
+    // antimeridian, or lies across it from the point, round to the point
+    let unwrapped = unwrap_geom(&GeometryRef::Polygon(poly), pt.x());
+    let poly = match &unwrapped {
+        Some(Geometry::Polygon(unwrapped)) => unwrapped,
+        _ => poly,
+    };
+
     // Distance is 0 if it intersects anywhere in the polygon
     // Otherwise find the ring with the smallest distance, inside or out
     if poly.intersects(pt) {
@@ -309,6 +419,396 @@ where
     }
 }
 
+/// Calculate the great circle distance between two [`Line`]'s using the
+/// Haversine formula. Zero if the lines cross.
+///
+/// Inputs and outputs are in radians. Convert radians to a linear distance by
+/// multiplying by the sphere's radius.
+pub fn dist_line_line<T>(l1: &Line<T>, l2: &Line<T>) -> T
+where
+    T: GeoFloat,
+{
+    dist_closest(&GeometryRef::Line(l1), &GeometryRef::Line(l2))
+}
+
+/// Calculate the great circle distance between a [`Line`] and a
+/// [`LineString`] using the Haversine formula. Zero if they cross.
+///
+/// Inputs and outputs are in radians. Convert radians to a linear distance by
+/// multiplying by the sphere's radius.
+pub fn dist_line_linestring<T>(line: &Line<T>, linestring: &LineString<T>) -> T
+where
+    T: GeoFloat,
+{
+    dist_closest(
+        &GeometryRef::Line(line),
+        &GeometryRef::LineString(linestring),
+    )
+}
+
+/// Calculate the great circle distance between two [`LineString`]'s using the
+/// Haversine formula. Zero if they cross.
+///
+/// Inputs and outputs are in radians. Convert radians to a linear distance by
+/// multiplying by the sphere's radius.
+pub fn dist_linestring_linestring<T>(ls1: &LineString<T>, ls2: &LineString<T>) -> T
+where
+    T: GeoFloat,
+{
+    dist_closest(&GeometryRef::LineString(ls1), &GeometryRef::LineString(ls2))
+}
+
+/// Calculate the great circle distance between a [`Line`] and a [`Polygon`]
+/// using the Haversine formula. Zero if the line crosses or lies inside the
+/// polygon, but not if it lies inside one of its holes.
+///
+/// Inputs and outputs are in radians. Convert radians to a linear distance by
+/// multiplying by the sphere's radius.
+pub fn dist_line_poly<T>(line: &Line<T>, poly: &Polygon<T>) -> T
+where
+    T: GeoFloat,
+{
+    dist_closest(&GeometryRef::Line(line), &GeometryRef::Polygon(poly))
+}
+
+/// Calculate the great circle distance between a [`LineString`] and a
+/// [`Polygon`] using the Haversine formula. Zero if the linestring crosses or
+/// lies inside the polygon, but not if it lies inside one of its holes.
+///
+/// Inputs and outputs are in radians. Convert radians to a linear distance by
+/// multiplying by the sphere's radius.
+pub fn dist_linestring_poly<T>(linestring: &LineString<T>, poly: &Polygon<T>) -> T
+where
+    T: GeoFloat,
+{
+    dist_closest(
+        &GeometryRef::LineString(linestring),
+        &GeometryRef::Polygon(poly),
+    )
+}
+
+/// Calculate the great circle distance between a [`Line`] and a [`Rect`]
+/// using the Haversine formula. Zero if the line crosses or lies inside the
+/// rect.
+///
+/// Inputs and outputs are in radians. Convert radians to a linear distance by
+/// multiplying by the sphere's radius.
+pub fn dist_line_rect<T>(line: &Line<T>, rect: &Rect<T>) -> T
+where
+    T: GeoFloat,
+{
+    dist_closest(&GeometryRef::Line(line), &GeometryRef::Rect(rect))
+}
+
+/// Calculate the great circle distance between a [`LineString`] and a
+/// [`Rect`] using the Haversine formula. Zero if the linestring crosses or
+/// lies inside the rect.
+///
+/// Inputs and outputs are in radians. Convert radians to a linear distance by
+/// multiplying by the sphere's radius.
+pub fn dist_linestring_rect<T>(linestring: &LineString<T>, rect: &Rect<T>) -> T
+where
+    T: GeoFloat,
+{
+    dist_closest(
+        &GeometryRef::LineString(linestring),
+        &GeometryRef::Rect(rect),
+    )
+}
+
+/// Calculate the great circle distance between two [`Polygon`]'s using the
+/// Haversine formula. Zero if they overlap or one lies inside the other.
+///
+/// Inputs and outputs are in radians. Convert radians to a linear distance by
+/// multiplying by the sphere's radius.
+pub fn dist_poly_poly<T>(p1: &Polygon<T>, p2: &Polygon<T>) -> T
+where
+    T: GeoFloat,
+{
+    dist_closest(&GeometryRef::Polygon(p1), &GeometryRef::Polygon(p2))
+}
+
+/// Calculate the great circle distance between a [`Polygon`] and a [`Rect`]
+/// using the Haversine formula. Zero if they overlap or one lies inside the
+/// other.
+///
+/// Inputs and outputs are in radians. Convert radians to a linear distance by
+/// multiplying by the sphere's radius.
+pub fn dist_poly_rect<T>(poly: &Polygon<T>, rect: &Rect<T>) -> T
+where
+    T: GeoFloat,
+{
+    dist_closest(&GeometryRef::Polygon(poly), &GeometryRef::Rect(rect))
+}
+
+/// Find the closest pair of points between two geometries along great circle
+/// arcs, the first on `g1` and the second on `g2`, or `None` if either has no
+/// coordinates.
+///
+/// Either geometry may cross the antimeridian, or lie across it from the
+/// other. Longitudes are unwrapped to run on continuously from the start of
+/// `g1` before testing for crossings and containment, which holds for
+/// geometries spanning less than half way round the globe. The points
+/// returned are wrapped back into [-Pi, Pi]. Inputs and outputs are in
+/// radians.
+pub fn closest_points<T>(g1: &GeometryRef<T>, g2: &GeometryRef<T>) -> Option<ClosestPoints<T>>
+where
+    T: GeoFloat,
+{
+    let near = start_coord(g1)?.x;
+    let (u1, u2) = (unwrap_geom(g1, near), unwrap_geom(g2, near));
+    let g1 = u1.as_ref().map_or(*g1, AsGeom::as_geom);
+    let g2 = u2.as_ref().map_or(*g2, AsGeom::as_geom);
+
+    let (p1, p2) = geom::closest_points(&g1, &g2, snap_pt_line)?;
+    let pi = T::from(PI).unwrap();
+    let wrap = |pt: Point<T>| match pt.x() {
+        x if x > pi => p!(x - pi - pi, pt.y()),
+        x if x < -pi => p!(x + pi + pi, pt.y()),
+        _ => pt,
+    };
+
+    Some((wrap(p1), wrap(p2)))
+}
+
+/// Split the bounds of a geometry that crosses the antimeridian into the part
+/// east of it, running up to Pi, and the part west of it, from -Pi, or return
+/// `None` if the geometry does not cross.
+///
+/// A geometry crosses where a segment takes the shorter way round, between
+/// longitudes more than Pi apart. Its planar bounding box instead spans the
+/// rest of the globe, so the two parts should be used in its place. A
+/// geometry that wraps all the way round is split at zero. Longitudes are in
+/// radians, and `None` is also returned if the parts cannot be represented in
+/// `T`.
+pub fn antimeridian_bounds<T>(geom: &GeometryRef<T>) -> Option<[Rect<T>; 2]>
+where
+    T: GeoNum,
+{
+    let two_pi = PI + PI;
+    let path: Vec<Coord<T>> = match geom {
+        GeometryRef::Point(_) | GeometryRef::Rect(_) => return None,
+        GeometryRef::Line(line) => vec![line.start, line.end],
+        GeometryRef::LineString(ls) => ls.0.clone(),
+        GeometryRef::Polygon(poly) => poly.exterior().0.clone(),
+    };
+    let bbox = geom.bounding_rect()?;
+
+    // Track the extent of the unwrapped longitudes along the path
+    let mut lngs = path.iter().map(|c| c.x.to_f64());
+    let first = lngs.next()??;
+    let (mut prev, mut lng, mut min, mut max) = (first, first, first, first);
+    for x in lngs {
+        let x = x?;
+        let delta = x - prev;
+        lng += match delta {
+            d if d > PI => d - two_pi,
+            d if d < -PI => d + two_pi,
+            d => d,
+        };
+        prev = x;
+        (min, max) = (min.min(lng), max.max(lng));
+    }
+
+    let (east, west) = match (min < -PI, max > PI) {
+        (false, false) => return None,
+        _ if max - min >= two_pi => (0.0, 0.0),
+        (false, true) => (min, max - two_pi),
+        (true, _) => (min + two_pi, max),
+    };
+    let part = |min_x: f64, max_x: f64| {
+        Some(Rect::new(
+            coord! {x: T::from(min_x)?, y: bbox.min().y},
+            coord! {x: T::from(max_x)?, y: bbox.max().y},
+        ))
+    };
+
+    Some([part(east, PI)?, part(-PI, west)?])
+}
+
+// Distance between the closest pair of points on two geometries, which is
+// infinite if either is empty, as for an empty linestring
+fn dist_closest<T>(g1: &GeometryRef<T>, g2: &GeometryRef<T>) -> T
+where
+    T: GeoFloat,
+{
+    closest_points(g1, g2).map_or(T::infinity(), |(p1, p2)| dist_pt_pt(&p1, &p2))
+}
+
+// Longitude equivalent to `x` within Pi of `near`
+fn unwrap_lng<T>(x: T, near: T) -> T
+where
+    T: GeoFloat,
+{
+    near + Lng::from(x - near).0
+}
+
+// First coordinate of a geometry's path
+fn start_coord<T>(geom: &GeometryRef<T>) -> Option<Coord<T>>
+where
+    T: GeoFloat,
+{
+    match geom {
+        GeometryRef::Point(pt) => Some(pt.0),
+        GeometryRef::Line(line) => Some(line.start),
+        GeometryRef::LineString(ls) => ls.0.first().copied(),
+        GeometryRef::Polygon(poly) => poly.exterior().0.first().copied(),
+        GeometryRef::Rect(rect) => Some(rect.min()),
+    }
+}
+
+// Copy of a geometry with longitudes that run on continuously from near
+// `near`, so planar tests work across the antimeridian, or `None` if the
+// geometry is already continuous there. Rects are moved whole, to wherever
+// is closest to `near`
+fn unwrap_geom<T>(geom: &GeometryRef<T>, near: T) -> Option<Geometry<T>>
+where
+    T: GeoFloat,
+{
+    let pi = T::from(PI).unwrap();
+    let unwrap_ring = |coords: &[Coord<T>], mut lng: T| -> LineString<T> {
+        coords
+            .iter()
+            .map(|c| {
+                lng = unwrap_lng(c.x, lng);
+                coord! {x: lng, y: c.y}
+            })
+            .collect()
+    };
+    let continuous = |coords: &[Coord<T>], mut prev: T| {
+        coords.iter().all(|c| {
+            let step = (c.x - prev).abs() <= pi;
+            prev = c.x;
+            step
+        })
+    };
+
+    match geom {
+        GeometryRef::Rect(rect) => {
+            let shifts = [T::zero(), pi + pi, -(pi + pi)];
+            let gap = |shift: T| (rect.min().x + shift - near).max(near - rect.max().x - shift);
+            let shift = shifts
+                .into_iter()
+                .min_by(|a, b| gap(*a).partial_cmp(&gap(*b)).unwrap())?;
+            (shift != T::zero()).then(|| {
+                Geometry::Rect(Rect::new(
+                    coord! {x: rect.min().x + shift, y: rect.min().y},
+                    coord! {x: rect.max().x + shift, y: rect.max().y},
+                ))
+            })
+        }
+        GeometryRef::Point(pt) => (!continuous(&[pt.0], near))
+            .then(|| Geometry::Point(p!(unwrap_lng(pt.x(), near), pt.y()))),
+        GeometryRef::Line(line) => (!continuous(&[line.start, line.end], near)).then(|| {
+            let ls = unwrap_ring(&[line.start, line.end], near);
+            Geometry::Line(Line::new(ls.0[0], ls.0[1]))
+        }),
+        GeometryRef::LineString(ls) => {
+            (!continuous(&ls.0, near)).then(|| Geometry::LineString(unwrap_ring(&ls.0, near)))
+        }
+        GeometryRef::Polygon(poly) => {
+            // Holes run on from the start of the exterior
+            let exterior = poly.exterior();
+            let start = exterior.0.first().map_or(near, |c| unwrap_lng(c.x, near));
+            let holes_continuous = poly
+                .interiors()
+                .iter()
+                .all(|ring| continuous(&ring.0, start));
+
+            (!continuous(&exterior.0, near) || !holes_continuous).then(|| {
+                let exterior = unwrap_ring(&exterior.0, near);
+                let interiors = poly
+                    .interiors()
+                    .iter()
+                    .map(|ring| unwrap_ring(&ring.0, start))
+                    .collect();
+                Geometry::Polygon(Polygon::new(exterior, interiors))
+            })
+        }
+    }
+}
+
+/// Expand a [`Rect`] so that it covers every great circle arc between two of
+/// its points, as well as the quadrilateral with great circle edges between
+/// its corners.
+///
+/// Arcs bulge towards the poles, by the most for the widest arc, so the
+/// latitude bounds are moved away from the rect until an arc the width of the
+/// rect between them stays outside the original rect. Inputs and outputs are
+/// in radians.
+pub fn arc_bounds<T>(rect: &Rect<T>) -> Rect<T>
+where
+    T: GeoFloat,
+{
+    let two = T::one() + T::one();
+    let half_pi = T::from(PI).unwrap() / two;
+    let cos = (rect.width() / two).cos();
+
+    // Latitude at the ends of an arc peaking at `lat`, and the inverse
+    let pole = |lat: T| match cos > T::zero() {
+        true => (lat.tan() / cos).atan(),
+        false if lat < T::zero() => -half_pi,
+        false => half_pi,
+    };
+    let equator = |lat: T| (lat.tan() * cos.max(T::zero())).atan();
+
+    let min_y = match rect.min().y < T::zero() {
+        true => pole(rect.min().y),
+        false => equator(rect.min().y),
+    };
+    let max_y = match rect.max().y > T::zero() {
+        true => pole(rect.max().y),
+        false => equator(rect.max().y),
+    };
+
+    Rect::new(
+        coord! {x: rect.min().x, y: min_y},
+        coord! {x: rect.max().x, y: max_y},
+    )
+}
+
+// Unit vector for a point on the sphere
+pub(crate) fn to_vec<T>(pt: &Point<T>) -> [T; 3]
+where
+    T: GeoFloat,
+{
+    let (lng, lat) = pt.x_y();
+    [lat.cos() * lng.cos(), lat.cos() * lng.sin(), lat.sin()]
+}
+
+// Point on the sphere in the direction of a non-zero vector
+pub(crate) fn from_vec<T>(v: [T; 3]) -> Point<T>
+where
+    T: GeoFloat,
+{
+    p!(v[1].atan2(v[0]), v[2].atan2(v[0].hypot(v[1])))
+}
+
+fn dot<T>(a: [T; 3], b: [T; 3]) -> T
+where
+    T: GeoFloat,
+{
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross<T>(a: [T; 3], b: [T; 3]) -> [T; 3]
+where
+    T: GeoFloat,
+{
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn scale<T>(a: [T; 3], k: T) -> [T; 3]
+where
+    T: GeoFloat,
+{
+    [a[0] * k, a[1] * k, a[2] * k]
+}
+
 #[cfg(test)]
 mod tests {
     use approx::assert_abs_diff_eq;
@@ -373,19 +873,21 @@ mod tests {
         let b2 = Rect::new(p!(0.1, -0.4), p!(0.3, -0.5));
         assert_abs_diff_eq!(dist_rect_rect(&b1, &b2), 0.3);
 
-        // Test lng greater than, min dist @ 0.4, b2 ends higher
+        // Test lng greater than, closest to b1's corner @ 0.4, b2 ends higher
+        // so the closest point on b2 is poleward of the corner
         let b2 = Rect::new(p!(0.6, 0.2), p!(0.8, 0.6));
-        let d = dist_pt_pt(&p!(0.5, 0.4), &p!(0.6, 0.4));
+        let d = dist_pt_line(&p!(0.5, 0.4), &l!(0.6, 0.2, 0.6, 0.6));
+        assert!(d < dist_pt_pt(&p!(0.5, 0.4), &p!(0.6, 0.4)));
         assert_abs_diff_eq!(dist_rect_rect(&b1, &b2), d);
 
-        // Test lng greater than, min dist @ -0.1, b2 starts lower
+        // Test lng greater than, closest to b1's corner @ -0.1, b2 starts lower
         let b2 = Rect::new(p!(0.7, -0.2), p!(0.8, -0.1));
-        let d = dist_pt_pt(&p!(0.5, -0.1), &p!(0.7, -0.1));
+        let d = dist_pt_line(&p!(0.5, -0.1), &l!(0.7, -0.2, 0.7, -0.1));
         assert_abs_diff_eq!(dist_rect_rect(&b1, &b2), d);
 
-        // Test lng less than - min dist @ 0.3, b1 ends higher
+        // Test lng less than - closest to b2's corner @ 0.3, b1 ends higher
         let b2 = Rect::new(p!(-0.2, 0.0), p!(-0.1, 0.3));
-        let d = dist_pt_pt(&p!(0.1, 0.3), &p!(-0.1, 0.3));
+        let d = dist_pt_line(&p!(-0.1, 0.3), &l!(0.1, -0.1, 0.1, 0.4));
         assert_abs_diff_eq!(dist_rect_rect(&b1, &b2), d);
 
         // Test corner - top left
@@ -415,7 +917,7 @@ mod tests {
 
         // Test overlapping latitude
         let b2 = Rect::new(p!(-3.0, 0.1), p!(-2.9, 0.3));
-        let d = dist_pt_pt(&p!(3.0, 0.3), &p!(-3.0, 0.3));
+        let d = dist_pt_line(&p!(-3.0, 0.3), &l!(3.0, 0.0, 3.0, 0.4));
         assert_abs_diff_eq!(dist_rect_rect(&b1, &b2), d);
 
         // Test corner
@@ -424,6 +926,124 @@ mod tests {
         assert_abs_diff_eq!(dist_rect_rect(&b1, &b2), d);
     }
 
+    #[test]
+    fn pt_rect_dist_measures_to_the_nearer_meridian_across_the_antimeridian() {
+        let rect = Rect::new(p!(-PI, 0.0), p!(-3.0, 0.4));
+
+        // Inside, including on the antimeridian from the other side
+        assert_eq!(dist_pt_rect(&p!(-3.1, 0.2), &rect), 0.0);
+        assert_eq!(dist_pt_rect(&p!(PI, 0.2), &rect), 0.0);
+        assert_abs_diff_eq!(dist_pt_rect(&p!(PI, 0.5), &rect), 0.1);
+
+        // Just east of it, which is closer to -Pi than to -3.0
+        let pt = p!(3.1, 0.2);
+        let d = dist_pt_line(&pt, &l!(-PI, 0.0, -PI, 0.4));
+        assert_abs_diff_eq!(dist_pt_rect(&pt, &rect), d);
+        assert_abs_diff_eq!(d, PI - 3.1, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn geometries_crossing_the_antimeridian_are_split_and_measured_across_it() {
+        let line = l!(3.0, 0.1, -3.0, 0.3);
+        let [east, west] = antimeridian_bounds(&GeometryRef::Line(&line)).unwrap();
+        assert_eq!(east, Rect::new(p!(3.0, 0.1), p!(PI, 0.3)));
+        assert_eq!(west, Rect::new(p!(-PI, 0.1), p!(-3.0, 0.3)));
+        assert!(antimeridian_bounds(&GeometryRef::Line(&l!(-1.0, 0.1, 2.0, 0.3))).is_none());
+
+        // A polygon round the antimeridian contains points either side of it
+        let poly = Polygon::new(
+            LineString::from(vec![(3.0, -0.2), (-3.0, -0.2), (-3.0, 0.2), (3.0, 0.2)]),
+            vec![],
+        );
+        assert!(antimeridian_bounds(&GeometryRef::Polygon(&poly)).is_some());
+        assert_eq!(dist_pt_poly(&p!(3.1, 0.0), &poly), 0.0);
+        assert_eq!(dist_pt_poly(&p!(-3.1, 0.0), &poly), 0.0);
+        assert_abs_diff_eq!(
+            dist_pt_poly(&p!(2.9, 0.0), &poly),
+            dist_pt_line(&p!(2.9, 0.0), &l!(3.0, 0.2, 3.0, -0.2))
+        );
+
+        // Lines crossing each other over the antimeridian touch there, and
+        // the closest points are wrapped back into range
+        let other = l!(-3.1, -0.3, 3.1, 0.5);
+        assert_eq!(dist_line_line(&line, &other), 0.0);
+        assert_eq!(dist_line_poly(&other, &poly), 0.0);
+        let (p1, p2) =
+            closest_points(&GeometryRef::Line(&line), &GeometryRef::Line(&other)).unwrap();
+        assert_abs_diff_eq!(dist_pt_pt(&p1, &p2), 0.0, epsilon = 1e-12);
+        assert!(p1.x().abs() <= PI && p1.x().abs() > 3.0);
+
+        // A rect just above the line, where one of its lower corners or the
+        // line's end is closest
+        let rect = Rect::new(p!(-PI, 0.35), p!(-3.05, 0.5));
+        let closest = [
+            dist_pt_line(&p!(-PI, 0.35), &line),
+            dist_pt_line(&p!(-3.05, 0.35), &line),
+            dist_pt_rect(&p!(-3.0, 0.3), &rect),
+        ]
+        .into_iter()
+        .fold(f64::INFINITY, f64::min);
+        assert_abs_diff_eq!(dist_line_rect(&line, &rect), closest, epsilon = 1e-12);
+        assert!(closest < 0.1);
+    }
+
+    #[test]
+    fn max_dist_pt_rect_matches_sampled_maximum() {
+        // Sample the rect on a fine grid, which can only underestimate, by up
+        // to about half a grid cell
+        let sampled = |pt: &Point, rect: &Rect| {
+            let (min, max) = (rect.min(), rect.max());
+            let mut best = 0.0f64;
+            for i in 0..=200 {
+                for j in 0..=200 {
+                    let x = min.x + (max.x - min.x) * i as f64 / 200.0;
+                    let y = min.y + (max.y - min.y) * j as f64 / 200.0;
+                    best = best.max(dist_pt_pt(pt, &p!(x, y)));
+                }
+            }
+            best
+        };
+
+        let rects = [
+            Rect::new(p!(0.1, 0.2), p!(0.3, 0.5)),
+            // Spans the equator, so the trough can fall inside
+            Rect::new(p!(-0.5, -1.2), p!(0.5, 1.2)),
+            // Spans the antipodal longitude of the points below
+            Rect::new(p!(2.5, -0.3), p!(3.1, 0.3)),
+            Rect::new(p!(-3.1, 0.4), p!(-2.9, 0.6)),
+        ];
+        let pts = [p!(0.0, 0.0), p!(0.2, 0.3), p!(-0.1, 1.3), p!(3.0, -0.5)];
+
+        for rect in &rects {
+            for pt in &pts {
+                let max = max_dist_pt_rect(pt, rect);
+                let sampled = sampled(pt, rect);
+                assert!(max >= sampled - 1e-12);
+                assert_abs_diff_eq!(max, sampled, epsilon = 1e-2);
+            }
+        }
+
+        // The antipode is the farthest anything can be
+        assert_abs_diff_eq!(
+            max_dist_pt_rect(&p!(0.0, 0.0), &Rect::new(p!(3.0, -0.1), p!(PI, 0.1))),
+            PI
+        );
+    }
+
+    #[test]
+    fn max_dist_rect_rect_bounds_corner_distances() {
+        let b1 = Rect::new(p!(0.1, 0.2), p!(0.3, 0.5));
+        let b2 = Rect::new(p!(-0.8, -0.7), p!(-0.6, 0.8));
+        let bound = max_dist_rect_rect(&b1, &b2);
+
+        for c1 in b1.to_polygon().exterior().points() {
+            for c2 in b2.to_polygon().exterior().points() {
+                assert!(dist_pt_pt(&c1, &c2) <= bound);
+            }
+        }
+        assert!(bound <= PI);
+    }
+
     fn test_poly() -> Polygon {
         Polygon::new(
             LineString::from(vec![(0.0, 0.0), (0.0, 0.6), (0.6, 0.6), (0.6, 0.0)]),
@@ -474,4 +1094,148 @@ mod tests {
         println!("{}", dist);
         assert_abs_diff_eq!(dist, test);
     }
+
+    #[test]
+    fn closest_points_are_the_distance_apart_where_distances_exist() {
+        use crate::{AsGeom, CalcMethod, DistHaversine};
+
+        let pt = Point::new(0.5, 0.5);
+        let line = l!(0.0, 0.6, 0.3, 0.9);
+        let poly = test_poly();
+        let r1 = Rect::new(coord! {x: 0.6, y: 0.6}, coord! {x: 0.9, y: 0.8});
+        let r2 = Rect::new(coord! {x: -0.4, y: -0.2}, coord! {x: -0.1, y: 0.2});
+
+        let geoms = [
+            pt.as_geom(),
+            line.as_geom(),
+            poly.as_geom(),
+            r1.as_geom(),
+            r2.as_geom(),
+        ];
+        for g1 in &geoms {
+            for g2 in &geoms {
+                let Ok(d) = g1.dist_haversine(g2) else {
+                    continue;
+                };
+                let (p1, p2) = g1
+                    .into_calc(CalcMethod::Spherical)
+                    .closest_points(g2)
+                    .unwrap();
+
+                assert_abs_diff_eq!(dist_pt_pt(&p1, &p2), d, epsilon = 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn dist_between_segment_geometries_is_zero_only_when_they_touch() {
+        let poly = test_poly();
+        let rect = Rect::new(coord! {x: 0.7, y: 0.0}, coord! {x: 0.9, y: 0.2});
+
+        // Crossing lines, and lines apart measured from the nearest end point
+        let l1 = l!(0.0, 0.0, 0.5, 0.5);
+        let l2 = l!(0.0, 0.5, 0.5, 0.0);
+        let l3 = l!(0.0, 0.8, 0.5, 0.8);
+        assert_eq!(dist_line_line(&l1, &l2), 0.0);
+        assert_abs_diff_eq!(dist_line_line(&l3, &l1), dist_pt_line(&p!(0.5, 0.5), &l3));
+
+        // Inside the polygon, inside its hole, and crossing its boundary
+        let inside = l!(0.05, 0.1, 0.1, 0.5);
+        let in_hole = l!(0.25, 0.3, 0.35, 0.3);
+        assert_eq!(dist_line_poly(&inside, &poly), 0.0);
+        assert_abs_diff_eq!(
+            dist_line_poly(&in_hole, &poly),
+            dist_pt_line(&p!(0.25, 0.3), &l!(0.2, 0.2, 0.2, 0.4))
+        );
+        assert_eq!(
+            dist_line_poly(&l3, &poly),
+            dist_pt_poly(&p!(0.5, 0.8), &poly)
+        );
+        assert_eq!(dist_line_poly(&l1, &poly), 0.0);
+
+        // Here the polygon's bounds are as close to the rect as the polygon,
+        // and empty linestrings are infinitely far away
+        let bbox = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 0.6, y: 0.6});
+        assert_abs_diff_eq!(dist_poly_rect(&poly, &rect), dist_rect_rect(&bbox, &rect));
+        assert_eq!(dist_line_rect(&l!(0.8, -0.1, 0.8, 0.3), &rect), 0.0);
+        let empty = LineString::new(vec![]);
+        assert_eq!(dist_line_linestring(&l1, &empty), f64::INFINITY);
+        assert_eq!(dist_linestring_poly(poly.exterior(), &poly), 0.0);
+    }
+
+    // Points along the great circle arc of a line, by spherical interpolation
+    fn sample_arc(line: &Line) -> Vec<Point> {
+        let (a, b) = (to_vec(&line.start_point()), to_vec(&line.end_point()));
+        let arc = dot(cross(a, b), cross(a, b)).sqrt().atan2(dot(a, b));
+
+        (0..=2000)
+            .map(|i| {
+                let t = arc * i as f64 / 2000.0;
+                let (ka, kb) = ((arc - t).sin() / arc.sin(), t.sin() / arc.sin());
+                from_vec([
+                    a[0] * ka + b[0] * kb,
+                    a[1] * ka + b[1] * kb,
+                    a[2] * ka + b[2] * kb,
+                ])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn dist_pt_line_is_the_closest_approach_to_the_great_circle_arc() {
+        // A long segment at high latitude, whose arc bulges towards the pole
+        let line = l!(-1.0, 1.1, 1.0, 1.1);
+        let arc = sample_arc(&line);
+
+        for pt in [
+            p!(0.0, 1.2),
+            p!(0.0, 1.0),
+            p!(0.5, 1.3),
+            p!(-1.5, 1.0),
+            p!(2.0, 0.2),
+        ] {
+            let sampled = arc
+                .iter()
+                .map(|a| dist_pt_pt(&pt, a))
+                .fold(f64::INFINITY, f64::min);
+            let snap = snap_pt_line(&pt, &line);
+
+            assert_abs_diff_eq!(snap.distance, sampled, epsilon = 1e-6);
+            assert_abs_diff_eq!(dist_pt_pt(&pt, &snap.point), snap.distance);
+        }
+
+        // Directly above the middle of the arc, which is above the end points
+        let snap = snap_pt_line(&p!(0.0, 1.4), &line);
+        assert_abs_diff_eq!(snap.fraction, 0.5, epsilon = 1e-12);
+        assert!(snap.point.y() > 1.1);
+    }
+
+    #[test]
+    fn arc_bounds_cover_arcs_between_corners_and_keep_rect_dists_admissible() {
+        for rect in [
+            Rect::new(p!(-1.0, 0.8), p!(1.0, 1.1)),
+            Rect::new(p!(0.2, -1.2), p!(2.0, -0.3)),
+            Rect::new(p!(-0.5, -0.4), p!(0.5, 0.6)),
+        ] {
+            let bounds = arc_bounds(&rect);
+            let (min, max) = (rect.min(), rect.max());
+            let arcs = [
+                l!(min.x, min.y, max.x, min.y),
+                l!(min.x, max.y, max.x, max.y),
+                l!(min.x, min.y, max.x, max.y),
+            ];
+
+            for line in &arcs {
+                for pt in sample_arc(line) {
+                    assert!(pt.y() >= bounds.min().y - 1e-12);
+                    assert!(pt.y() <= bounds.max().y + 1e-12);
+                }
+
+                // No arc is closer than its bounds
+                for pt in [p!(0.0, 1.4), p!(-2.0, 0.0), p!(1.0, -1.4), p!(0.0, 0.0)] {
+                    assert!(dist_pt_rect(&pt, &bounds) <= dist_pt_line(&pt, line) + 1e-12);
+                }
+            }
+        }
+    }
 }
@@ -39,6 +39,9 @@ where
     }
 }
 
+/// Two boxed [`DatumIter`] instances chained together.
+type BoxedChain<'a, N, D, T> = Chain<Box<DatumIter<'a, N, D, T>>, Box<DatumIter<'a, N, D, T>>>;
+
 /// Convenience Iterator wrapper to chain two [`DatumIter`] instances.
 ///
 /// Boxes the underlying iterators to priovide indirection.
@@ -47,7 +50,7 @@ where
     N: Node<D, T>,
     T: GeoNum,
 {
-    iter: Chain<Box<DatumIter<'a, N, D, T>>, Box<DatumIter<'a, N, D, T>>>,
+    iter: BoxedChain<'a, N, D, T>,
 }
 
 impl<'a, N, D, T> ChainSelfIter<'a, N, D, T>
@@ -76,14 +79,15 @@ where
 
 /// Iterator that emits all children that are descendants of the current Node.
 /// This includes any "stuck children" if the concept exists for the given
-/// implementation, then all children of all sub-nodes, recursively.
+/// implementation, then all children of all sub-nodes, in preorder.
 pub struct DescendantIter<'a, N, D, T>
 where
     N: Node<D, T>,
     T: GeoNum,
 {
-    children_iter: Box<DatumIter<'a, N, D, T>>,
-    nodes: Iter<'a, N>,
+    arena: &'a Arena<N>,
+    // Sub-nodes still to visit, with the next on top
+    stack: Vec<&'a N>,
     cur_node_iter: Box<DatumIter<'a, N, D, T>>,
     _num_type: PhantomData<T>,
 }
@@ -93,14 +97,11 @@ where
     N: Node<D, T>,
     T: GeoNum,
 {
-    pub fn new(children_iter: DatumIter<'a, N, D, T>, mut nodes: Iter<'a, N>) -> Self {
-        let children_iter = Box::new(children_iter);
-        // Unwrap will not panic because we know we always have four nodes
-        let cur_node_iter = Box::new(nodes.next().unwrap().descendants());
+    pub fn new(children_iter: DatumIter<'a, N, D, T>, arena: &'a Arena<N>, quad: QuadId) -> Self {
         Self {
-            children_iter,
-            nodes,
-            cur_node_iter,
+            arena,
+            stack: arena.quad(quad).iter().rev().collect(),
+            cur_node_iter: Box::new(children_iter),
             _num_type: PhantomData,
         }
     }
@@ -113,29 +114,21 @@ where
 {
     type Item = &'a D;
 
-    // This complex implementation is an adjustment for the lack of ability to
-    // return iterators simply. Would rather do:
-    // `children.chain(nodes.flat_map(|n| n.descendants()))` but can't return
-    // this due to inability to return impl Iterator from Traits.
+    // Walks the arena with an explicit stack rather than nesting an iterator
+    // per level, so each datum is a single step away
     fn next(&mut self) -> Option<Self::Item> {
-        // Return the item if we still have children
-        if let Some(child) = self.children_iter.next() {
-            return Some(child);
-        }
-
-        // Now move on to iterating through descendant children
-        if let Some(child) = self.cur_node_iter.next() {
-            return Some(child);
+        loop {
+            if let Some(child) = self.cur_node_iter.next() {
+                return Some(child);
+            }
+
+            // Move on to the next node, pushing its sub-nodes in reverse so
+            // the first is visited next
+            let node = self.stack.pop()?;
+            if let Some(nodes) = node.nodes(self.arena) {
+                self.stack.extend(nodes.iter().rev());
+            }
+            *self.cur_node_iter = node.children();
         }
-
-        // We have run out of current children and descendants, so move on to
-        // the next node, but must recurse so we return from next, otherwise
-        // we are all out
-        if let Some(node) = self.nodes.next() {
-            self.cur_node_iter = Box::new(node.descendants());
-            return self.next();
-        }
-
-        None
     }
 }
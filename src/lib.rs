@@ -5,33 +5,43 @@
  *
  * TODO: More permutations of the DistHaversine and DistEuclidean traits, consider a macro helper
  * TODO: Add clear and remove operations to the quadtree trait
- * TODO: Add more Haversine implementations for Spherical math
- * TODO: Build an integer-with-power-2-bounds version?
- * TODO: Should nodes and children be private on the node structs?
  * TODO: Force constraints on spherical coords?
  * TODO: Make a PR for the geo crate to add extra euclidean and haversine distance measures for Rect
  *       PR also should include fixing the TODO in https://docs.rs/geo/latest/src/geo/algorithm/contains/rect.rs.html#30-42
  *       This is wrong and should probably use > not >=
  */
 
+mod arena;
+mod cursor;
 mod error;
 mod geom;
 mod iter;
 mod node;
 mod quadtrees;
 
+use arena::*;
 use geom::*;
 use iter::*;
 use node::*;
 
 // Export the quadtree traits/constants, and implementations
+pub use cursor::*;
 pub use error::*;
+pub use node::SubNode;
 pub use quadtrees::bounds::*;
+pub use quadtrees::cube::*;
+pub use quadtrees::integer::*;
+pub use quadtrees::linear::*;
+pub use quadtrees::loose::*;
+pub use quadtrees::octree::*;
+pub use quadtrees::pmr::*;
 pub use quadtrees::point::*;
+pub use quadtrees::region::*;
 pub use quadtrees::*;
 
 // Export geometry items
 pub use geom::euclidean;
+pub use geom::geodesic;
 pub use geom::geometry::*;
 pub use geom::spherical;
 pub use geom::spherical::to_radians::*;
@@ -1,16 +1,17 @@
+use std::cmp::Ordering;
 use std::fmt::{Display, Formatter};
-use std::marker::PhantomData;
 
 use geo::{Coord, GeoNum, Rect, coord};
 
 use crate::Error;
+use crate::arena::{Arena, QuadId};
 use crate::iter::{DatumIter, DescendantIter};
 
 /// Sub-node indicies.
 ///
 /// Naming scheme based on clockwise rotation with a top-left origin. Or
 /// counterclockeise with a bottom-left origin.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SubNode {
     TopLeft = 0,
     TopRight = 1,
@@ -19,48 +20,166 @@ pub enum SubNode {
 }
 
 /// Internal enum to track whether an element in a Node iterator or stack is a
-/// child datum or a sub-node.
+/// child datum or a sub-node. `N` is whatever handle the stack uses to refer
+/// to a node, typically a reference or a [`Branch`].
 #[derive(Debug, Clone)]
-pub enum NodeType<'a, N, D, T>
-where
-    N: Node<D, T>,
-    T: GeoNum,
-{
-    Node(&'a N),
+pub enum NodeType<'a, N, D> {
+    Node(N),
     Child(&'a D),
-    _NumType(PhantomData<T>),
+}
+
+/// Entry in the priority queue driving the best-first search algorithms.
+///
+/// Ordered so that a max-heap pops the closest entry first, and data before
+/// nodes at the same distance, as no datum beneath a node can be closer than
+/// the node itself. Distances must not be NaN.
+pub(crate) struct Queued<'a, N, D, F> {
+    pub(crate) item: NodeType<'a, N, D>,
+    pub(crate) dist: F,
+}
+
+impl<'a, N, D, F> Queued<'a, N, D, F> {
+    pub(crate) fn new(item: NodeType<'a, N, D>, dist: F) -> Self {
+        Self { item, dist }
+    }
+}
+
+impl<N, D, F: PartialOrd> Ord for Queued<'_, N, D, F> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let is_child = |q: &Self| matches!(q.item, NodeType::Child(_));
+
+        other
+            .dist
+            .partial_cmp(&self.dist)
+            .expect("Unreachable, NaN distances already removed.")
+            .then_with(|| is_child(self).cmp(&is_child(other)))
+    }
+}
+
+impl<N, D, F: PartialOrd> PartialOrd for Queued<'_, N, D, F> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<N, D, F: PartialOrd> PartialEq for Queued<'_, N, D, F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<N, D, F: PartialOrd> Eq for Queued<'_, N, D, F> {}
+
+/// Crate-private view of a node as walked by the best-first search
+/// algorithms. Decouples those algorithms from the [`Node`] trait, so any
+/// node layout can be searched as long as it can hand out its data and its
+/// sub-nodes. Implementors are cheap copyable handles.
+pub(crate) trait Branch<'a, D: 'a>: Copy {
+    /// Iterate over the data held directly by this node, including any stuck
+    /// children.
+    fn data(self) -> impl Iterator<Item = &'a D>;
+
+    /// Iterate over handles to this node's sub-nodes, empty for leaves.
+    fn branches(self) -> impl Iterator<Item = Self>;
+}
+
+/// Depth and occupancy rules deciding when a node subdivides, shared by the
+/// QuadTree nodes and the [`crate::Octree`]'s whatever their number of
+/// sub-nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub(crate) depth: u8,
+    pub(crate) max_depth: u8,
+    pub(crate) max_children: usize,
+}
+
+impl Limits {
+    /// Limits of a root node.
+    pub(crate) fn root(max_depth: u8, max_children: usize) -> Self {
+        Self {
+            depth: 0,
+            max_depth,
+            max_children,
+        }
+    }
+
+    /// Whether a leaf already holding `held` data must subdivide before it
+    /// takes another. Max depth takes priority over the children length, so
+    /// children stack arbitrarily at the deepest level.
+    pub(crate) fn must_subdivide(&self, held: usize) -> bool {
+        held >= self.max_children && self.depth < self.max_depth
+    }
+
+    /// Build the `N` sub-nodes of a node with these limits at the next depth,
+    /// one for each of the passed bounds.
+    pub(crate) fn sub_nodes<B, S, const N: usize>(
+        &self,
+        bounds: [B; N],
+        mut new: impl FnMut(B, Limits) -> S,
+    ) -> [S; N] {
+        let limits = Limits {
+            depth: self.depth + 1,
+            ..*self
+        };
+
+        bounds.map(|b| new(b, limits))
+    }
 }
 
 /// Trait for a QuadTree node. Nodes should not be visible to the consumer.
+///
+/// Nodes live in an [`Arena`] owned by their tree, and link to their four
+/// sub-nodes by index, so any operation that walks the tree takes the arena
+/// alongside the node.
 pub trait Node<D, T>
 where
     Self: Sized,
     T: GeoNum,
 {
     /// Create a new Node with the passed structure.
-    fn new(bounds: Rect<T>, depth: u8, max_depth: u8, max_children: usize) -> Self;
+    fn new(bounds: Rect<T>, limits: Limits) -> Self;
 
     /// Get a single [`Coordinate`] position of the datum in a manner suitable
     /// for the constraints of the implementation.
     fn datum_position(datum: &D) -> Option<Coord<T>>;
 
+    /// Whether two data sit in the same place, so that no amount of
+    /// subdivision could separate them. Compares positions by default.
+    fn coincident(d1: &D, d2: &D) -> bool {
+        Self::datum_position(d1) == Self::datum_position(d2)
+    }
+
     /// Get the bounding rect for the Node.
     fn bounds(&self) -> &Rect<T>;
 
+    /// Get the depth and subdivision rules of the Node, see [`Limits`].
+    fn limits(&self) -> Limits;
+
     /// Get the depth of the current Node in the QuadTree.
-    fn depth(&self) -> u8;
+    fn depth(&self) -> u8 {
+        self.limits().depth
+    }
 
     /// Get the maximum depth permitted in the QuadTree.
-    fn max_depth(&self) -> u8;
+    fn max_depth(&self) -> u8 {
+        self.limits().max_depth
+    }
 
     /// Get the maxmimum number of children allowed per node before subdividing.
     /// Note that max depth has precedence, so children may stack arbitrarily at
     /// the deepest level.
-    fn max_children(&self) -> usize;
+    fn max_children(&self) -> usize {
+        self.limits().max_children
+    }
+
+    /// Returns the link to the Node's sub-nodes in the arena. Is an Option
+    /// because these won't exist for leaf Nodes.
+    fn quad(&self) -> Option<QuadId>;
 
-    /// Returns the Node's sub-nodes. Is an Option because these won;t exist
-    /// for leaf Nodes.
-    fn nodes(&self) -> &Option<Box<[Self; 4]>>;
+    /// Returns the Node's sub-nodes, looked up in the arena.
+    fn nodes<'a>(&self, arena: &'a Arena<Self>) -> Option<&'a [Self; 4]> {
+        self.quad().map(|quad| arena.quad(quad))
+    }
 
     /// Return an iterator with references to all children of the current node.
     /// This includes direct children and any stuck children if that concept
@@ -68,104 +187,81 @@ where
     /// nodes, may have children.
     fn children(&self) -> DatumIter<'_, Self, D, T>;
 
+    /// Return an iterator over only the stuck children of the current node,
+    /// i.e. children that straddle the sub-node boundaries and so could not
+    /// be pushed further down the tree. Empty for QuadTree types without the
+    /// concept.
+    fn stuck_children(&self) -> DatumIter<'_, Self, D, T> {
+        DatumIter::Empty
+    }
+
+    /// Return the indices of any data this node references in a tree-level
+    /// store rather than holding directly, as used by a multi-cell
+    /// [`crate::BoundsQuadTree`]. On a leaf these are the data overlapping
+    /// the leaf, and on any other node they are stuck. Empty for QuadTree
+    /// types without the concept.
+    fn shared(&self) -> &[usize] {
+        &[]
+    }
+
+    /// Heap memory owned by this node outside of its slot in the arena,
+    /// excluding spare capacity.
+    fn heap_bytes(&self) -> usize;
+
     /// Return all descendant data of this node in preorder. The iterator first
     /// emits the children of the current node, then recurses into the
     /// sub-nodes if they exist.
-    fn descendants(&self) -> DatumIter<'_, Self, D, T> {
-        if let Some(nodes) = self.nodes() {
-            DatumIter::Descendant(DescendantIter::new(self.children(), nodes.iter()))
-        } else {
-            self.children()
+    fn descendants<'a>(&'a self, arena: &'a Arena<Self>) -> DatumIter<'a, Self, D, T> {
+        match self.quad() {
+            Some(quad) => DatumIter::Descendant(DescendantIter::new(self.children(), arena, quad)),
+            None => self.children(),
         }
     }
 
-    /// Set the sub-nodes for this Node. Required as a separate method to
-    /// enable the sub-node logic to live in the trait.
-    fn set_nodes(&mut self, nodes: Option<Box<[Self; 4]>>);
+    /// Set the link to the sub-nodes for this Node. Required as a separate
+    /// method to enable the sub-node logic to live in the trait.
+    fn set_quad(&mut self, quad: Option<QuadId>);
+
+    /// Insert a child into the Node at index `id` of the arena, or delegate to
+    /// a sub-node where appropriate. `seq` is the datum's position in the
+    /// tree's insertion order, which is kept alongside it.
+    fn insert(arena: &mut Arena<Self>, id: usize, datum: D, seq: usize) -> Result<(), Error>;
 
-    /// Insert a child into this Node, or delegate to a sub-node where
-    /// appropriate.
-    fn insert(&mut self, datum: D) -> Result<(), Error>;
+    /// Return the insertion sequence number of `datum` if it is a reference
+    /// to a child held directly by this Node, see [`Node::insert`].
+    fn held_seq(&self, datum: &D) -> Option<usize>;
 
     /// Retrieve from this Node. Will return children and also delegate to the
     /// appropriate sub-nodes based on the implementation.
-    fn retrieve(&self, datum: &D) -> DatumIter<'_, Self, D, T>;
+    fn retrieve<'a>(&'a self, arena: &'a Arena<Self>, datum: &D) -> DatumIter<'a, Self, D, T>;
 
     /// Find the index of the appropriate sub-node to delegate an insert or
     /// retrieve operation if required.
     fn find_sub_node(&self, datum: &D) -> Option<SubNode> {
-        let (x, y) = Self::datum_position(datum)?.x_y();
-        let b = self.bounds();
-        let two = T::one() + T::one();
-        let left = x <= b.min().x + b.width() / two;
-        let top = y <= b.min().y + b.height() / two;
-
-        let sn = if left && top {
-            SubNode::TopLeft
-        } else if !left && top {
-            SubNode::TopRight
-        } else if left && !top {
-            SubNode::BottomLeft
-        } else {
-            SubNode::BottomRight
-        };
+        Some(sub_node_at(self.bounds(), Self::datum_position(datum)?))
+    }
+
+    /// Create the four sub-node siblings of this Node at the next-depth level.
+    fn sub_nodes(&self) -> [Self; 4] {
+        self.limits()
+            .sub_nodes(sub_node_bounds(self.bounds()), Self::new)
+    }
 
-        Some(sn)
-    }
-
-    /// Subdivide the current Node into four sub-nodes at the next-depth level
-    /// and set them in the current Node.
-    fn subdivide(&mut self) {
-        let bounds = self.bounds();
-        let depth = self.depth() + 1;
-        let md = self.max_depth();
-        let mc = self.max_children();
-
-        let two = T::one() + T::one();
-        let wh = bounds.width() / two;
-        let hh = bounds.height() / two;
-
-        let (x1, y1) = bounds.min().x_y();
-        let (x2, y2) = (x1 + wh, y1 + hh);
-        let (x3, y3) = bounds.max().x_y();
-
-        // Fixed order of iteration tl, tr, br, bl
-        self.set_nodes(Some(Box::new([
-            Self::new(
-                Rect::new(coord! {x: x1, y: y1}, coord! {x: x2, y: y2}),
-                depth,
-                md,
-                mc,
-            ),
-            Self::new(
-                Rect::new(coord! {x: x2, y: y1}, coord! {x: x3, y: y2}),
-                depth,
-                md,
-                mc,
-            ),
-            Self::new(
-                Rect::new(coord! {x: x2, y: y2}, coord! {x: x3, y: y3}),
-                depth,
-                md,
-                mc,
-            ),
-            Self::new(
-                Rect::new(coord! {x: x1, y: y2}, coord! {x: x2, y: y3}),
-                depth,
-                md,
-                mc,
-            ),
-        ])));
+    /// Subdivide the Node at index `id` of the arena into four sub-nodes,
+    /// pushing them onto the arena and linking them to the Node.
+    fn subdivide(arena: &mut Arena<Self>, id: usize) {
+        let quad = arena.push_quad(arena[id].sub_nodes());
+        arena[id].set_quad(Some(quad));
     }
 
     /// Heavy-lifting for custom Node display.
-    fn display(&self, f: &mut Formatter) -> std::fmt::Result
+    fn display(&self, arena: &Arena<Self>, f: &mut Formatter) -> std::fmt::Result
     where
         T: Display,
     {
         let indent = " ".repeat(self.depth() as usize * 4);
         let min = self.bounds().min();
-        let count = self.children().count();
+        let count = self.children().count() + self.shared().len();
         let children = if count == 0 {
             "".to_owned()
         } else if count == 1 {
@@ -176,11 +272,84 @@ where
 
         writeln!(f, "{indent}({:.2}, {:.2}):{children}", min.x, min.y)?;
 
-        if let Some(nodes) = &self.nodes() {
-            for node in &**nodes {
-                node.display(f)?
+        if let Some(nodes) = self.nodes(arena) {
+            for node in nodes {
+                node.display(arena, f)?
             }
         };
         write!(f, "")
     }
 }
+
+/// Find the sub-node of `bounds` that holds `position`. Positions on a
+/// midline belong to the top and/or left sub-node.
+pub(crate) fn sub_node_at<T>(bounds: &Rect<T>, position: Coord<T>) -> SubNode
+where
+    T: GeoNum,
+{
+    let (x, y) = position.x_y();
+    let two = T::one() + T::one();
+    let left = x <= bounds.min().x + bounds.width() / two;
+    let top = y <= bounds.min().y + bounds.height() / two;
+
+    if left && top {
+        SubNode::TopLeft
+    } else if !left && top {
+        SubNode::TopRight
+    } else if left && !top {
+        SubNode::BottomLeft
+    } else {
+        SubNode::BottomRight
+    }
+}
+
+/// Split `bounds` into the bounds of its four sub-nodes, returned in
+/// [`SubNode`] order.
+pub(crate) fn sub_node_bounds<T>(bounds: &Rect<T>) -> [Rect<T>; 4]
+where
+    T: GeoNum,
+{
+    let two = T::one() + T::one();
+    let wh = bounds.width() / two;
+    let hh = bounds.height() / two;
+
+    let (x1, y1) = bounds.min().x_y();
+    let (x2, y2) = (x1 + wh, y1 + hh);
+    let (x3, y3) = bounds.max().x_y();
+
+    // Fixed order of iteration tl, tr, br, bl
+    [
+        Rect::new(coord! {x: x1, y: y1}, coord! {x: x2, y: y2}),
+        Rect::new(coord! {x: x2, y: y1}, coord! {x: x3, y: y2}),
+        Rect::new(coord! {x: x2, y: y2}, coord! {x: x3, y: y3}),
+        Rect::new(coord! {x: x1, y: y2}, coord! {x: x2, y: y3}),
+    ]
+}
+
+/// Find the insertion sequence number of a reference to a datum held by the
+/// nodes in `arena`, following the path its position takes down from the
+/// root. Returns `None` for references the nodes do not hold.
+pub(crate) fn insertion_seq<N, D, T>(arena: &Arena<N>, datum: &D) -> Option<usize>
+where
+    N: Node<D, T>,
+    T: GeoNum,
+{
+    let mut node = arena.root();
+    loop {
+        if let Some(seq) = node.held_seq(datum) {
+            return Some(seq);
+        }
+        node = &node.nodes(arena)?[node.find_sub_node(datum)? as usize];
+    }
+}
+
+/// Index of `datum` within `slice`, or `None` if it is not a reference into
+/// the slice.
+pub(crate) fn index_in<D>(slice: &[D], datum: &D) -> Option<usize> {
+    let range = slice.as_ptr_range();
+    let ptr: *const D = datum;
+
+    range
+        .contains(&ptr)
+        .then(|| (ptr as usize - range.start as usize) / size_of::<D>())
+}
@@ -1,15 +1,29 @@
 mod node;
 
-use geo::{BoundingRect, GeoNum, Rect};
+use geo::coordinate_position::{CoordPos, CoordinatePosition};
+use geo::{BoundingRect, Coord, GeoNum, Line, Point, Rect};
+use std::collections::HashSet;
 use std::vec;
 
 use super::{
-    knn::knn,
-    sorted::{SortIter, sorted},
+    knn::{knn_approx_by, knn_by},
+    sorted::{sorted_by, sorted_desc_by, sorted_unchecked_by},
+    stats::stats,
 };
+use crate::geom::euclidean::math::{line_geom_entry, line_rect_entry, max_dist_pt_rect};
+use crate::geom::spherical::math::antimeridian_bounds;
+use crate::node::Branch;
 use crate::*;
 use node::*;
 
+/// Whether a point on the boundary of a geometry counts as contained by it,
+/// as used by [`BoundsQuadTree::containing`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Boundary {
+    Include,
+    Exclude,
+}
+
 /// A [`QuadTree`] implementation for bounded items (i.e. those with a finite
 /// width and/or height).
 ///
@@ -20,17 +34,43 @@ use node::*;
 ///
 /// Users can implement [`AsGeom`] on any custom type they wish to use as
 /// a datum.
+///
+/// By default, data that straddle a sub-node boundary are stuck in the parent
+/// node, so long thin geometries such as roads and rivers pile up near the
+/// root. A tree created with [`BoundsQuadTree::multi_cell`] instead keeps
+/// each datum once in a tree-level store and references it from every leaf
+/// its bounding box overlaps, up to a cap. All queries and iteration
+/// deduplicate these references, so each datum is still returned at most
+/// once.
+///
+/// With [`CalcMethod::Spherical`] or [`CalcMethod::Geodesic`], a datum
+/// crossing the antimeridian is split on insert into the parts of its bounds
+/// either side of it, see
+/// [`crate::geom::spherical::math::antimeridian_bounds`]. It is held once in
+/// the store and referenced for each part in the same way, then merged on
+/// return, so it is found from both sides of the dateline.
 #[derive(Debug)]
 pub struct BoundsQuadTree<D, T>
 where
     D: AsGeom<T>,
     T: GeoNum,
 {
-    root: BoundsNode<D, T>,
+    arena: Arena<BoundsNode<D, T>>,
     size: usize,
-    calc_method: CalcMethod,
+    calc_method: CalcMethod<T>,
+
+    // Multi-cell storage, only used when max_cells is set, or for data split
+    // at the antimeridian
+    max_cells: Option<usize>,
+    store: Vec<D>,
+    refs: Vec<usize>,
+    // Insertion sequence numbers, in step with the store
+    store_seqs: Vec<usize>,
 }
 
+/// Read-only cursor onto a node of a [`BoundsQuadTree`], see [`NodeRef`].
+pub type BoundsNodeRef<'a, D, T> = NodeRef<'a, BoundsNode<D, T>, D, T>;
+
 impl<D, T> BoundsQuadTree<D, T>
 where
     D: AsGeom<T>,
@@ -39,35 +79,238 @@ where
     /// Create a new Bounds QuadTree.
     pub fn new(
         bounds: Rect<T>,
-        calc_method: CalcMethod,
+        calc_method: CalcMethod<T>,
         max_depth: u8,
         max_children: usize,
     ) -> Self {
-        BoundsQuadTree::private_new(bounds, calc_method, Some(max_depth), Some(max_children))
+        BoundsQuadTree::private_new(
+            bounds,
+            calc_method,
+            Some(max_depth),
+            Some(max_children),
+            None,
+        )
     }
 
     /// Create a new Bounds QuadTree using default values for max_depth and
     /// max_children.
-    pub fn from_bounds(bounds: Rect<T>, calc_method: CalcMethod) -> Self {
-        BoundsQuadTree::private_new(bounds, calc_method, None, None)
+    pub fn from_bounds(bounds: Rect<T>, calc_method: CalcMethod<T>) -> Self {
+        BoundsQuadTree::private_new(bounds, calc_method, None, None, None)
+    }
+
+    /// Create a new multi-cell Bounds QuadTree, which references each datum
+    /// from every leaf its bounding box overlaps rather than sticking it in a
+    /// parent node. A datum that would be referenced from more than
+    /// `max_cells` nodes is instead stuck where it would have been split.
+    ///
+    /// Leaves count both references and data towards `max_children`. Node
+    /// cursors report references through [`NodeRef::shared`], which can be
+    /// resolved with [`BoundsQuadTree::datum`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_cells` is zero.
+    pub fn multi_cell(
+        bounds: Rect<T>,
+        calc_method: CalcMethod<T>,
+        max_depth: u8,
+        max_children: usize,
+        max_cells: usize,
+    ) -> Self {
+        assert!(max_cells > 0, "max_cells must be at least one");
+
+        BoundsQuadTree::private_new(
+            bounds,
+            calc_method,
+            Some(max_depth),
+            Some(max_children),
+            Some(max_cells),
+        )
+    }
+
+    /// Return the cap on the number of nodes referencing each datum, or
+    /// `None` if the tree does not use multi-cell storage.
+    pub fn max_cells(&self) -> Option<usize> {
+        self.max_cells
+    }
+
+    /// Return the datum at the passed index of the multi-cell store, or of
+    /// the data split at the antimeridian, as reported by [`NodeRef::shared`].
+    pub fn datum(&self, index: usize) -> Option<&D> {
+        self.store.get(index)
+    }
+
+    /// Return a read-only cursor onto the root node, from which the tree
+    /// structure can be walked. See [`NodeRef`].
+    pub fn root(&self) -> BoundsNodeRef<'_, D, T> {
+        NodeRef::new(&self.arena)
+    }
+
+    /// Summarise the structure of the QuadTree, reporting node counts,
+    /// depth, leaf occupancy and suggested configuration. See [`TreeStats`].
+    pub fn stats(&self) -> TreeStats {
+        stats(
+            &self.arena,
+            &self.store,
+            size_of_val(self.store.as_slice())
+                + size_of_val(self.refs.as_slice())
+                + size_of_val(self.store_seqs.as_slice()),
+        )
+    }
+
+    /// Iterate over every datum whose geometry contains `point`, walking only
+    /// the nodes whose bounds contain it.
+    ///
+    /// Containment is exact: a point in a polygon's interior ring is outside
+    /// it, while `boundary` decides whether a point on a boundary, including
+    /// a ring's, counts. Follows DE-9IM semantics, so lines contain the points
+    /// along them and points contain only themselves.
+    pub fn containing<'a>(
+        &'a self,
+        point: &Point<T>,
+        boundary: Boundary,
+    ) -> impl Iterator<Item = &'a D> + use<'a, D, T> {
+        let point = *point;
+        let root = self.root_ref();
+        let mut stack = match pt_in_rect(root.node.bounds(), &point) {
+            true => vec![root],
+            false => vec![],
+        };
+        let mut seen = HashSet::new();
+
+        let nodes = std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            // Push in reverse so nodes are visited in preorder
+            let sub_nodes = node
+                .branches()
+                .filter(|sub_node| pt_in_rect(sub_node.node.bounds(), &point))
+                .collect::<Vec<_>>();
+            stack.extend(sub_nodes.into_iter().rev());
+            Some(node)
+        });
+
+        // Multi-cell references are reported once
+        nodes
+            .flat_map(|node| node.data())
+            .filter(
+                move |datum| match datum.as_geom().coordinate_position(&point.0) {
+                    CoordPos::Inside => true,
+                    CoordPos::OnBoundary => boundary == Boundary::Include,
+                    CoordPos::Outside => false,
+                },
+            )
+            .filter(move |datum| seen.insert(*datum as *const D))
+    }
+
+    /// Return the first datum whose geometry contains `point`, as in
+    /// [`BoundsQuadTree::containing`]. Stops at the first match, so is a fast
+    /// path for data that partition the space without overlapping.
+    pub fn containing_first(&self, point: &Point<T>, boundary: Boundary) -> Option<&D> {
+        self.containing(point, boundary).next()
     }
 
     // Private constructor
     fn private_new(
         bounds: Rect<T>,
-        calc_method: CalcMethod,
+        calc_method: CalcMethod<T>,
         max_depth: Option<u8>,
         max_children: Option<usize>,
+        max_cells: Option<usize>,
     ) -> Self {
         let max_depth = max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
         let max_children = max_children.unwrap_or(DEFAULT_MAX_CHILDREN);
 
         Self {
-            root: BoundsNode::new(bounds, 0, max_depth, max_children),
+            arena: Arena::new(BoundsNode::new(
+                bounds,
+                Limits::root(max_depth, max_children),
+            )),
             size: 0,
             calc_method,
+            max_cells,
+            store: Vec::new(),
+            refs: Vec::new(),
+            store_seqs: Vec::new(),
+        }
+    }
+
+    // Parts of the datum's bounds either side of the antimeridian, if the
+    // tree is geographic and the datum crosses it
+    fn antimeridian_parts(&self, datum: &D) -> Option<[Rect<T>; 2]> {
+        match self.calc_method.is_geographic() {
+            true => antimeridian_bounds(&datum.as_geom()),
+            false => None,
         }
     }
+
+    // Handle onto the root for the search algorithms
+    fn root_ref(&self) -> BoundsRef<'_, D, T> {
+        BoundsRef {
+            arena: &self.arena,
+            node: self.arena.root(),
+            store: &self.store,
+        }
+    }
+}
+
+impl<D, T> BoundsQuadTree<D, T>
+where
+    D: AsGeom<T>,
+    T: QtFloat,
+{
+    /// Cast a ray from `origin` in `direction`, returning the first datum it
+    /// hits within `max_len` and the distance along the ray to the hit. A ray
+    /// starting inside a datum hits it at distance zero.
+    ///
+    /// Uses planar geometry whatever the tree's [`CalcMethod`], and returns
+    /// `None` if nothing is hit or `direction` has zero length. `max_len` may
+    /// be infinite. See [`BoundsQuadTree::intersecting_segment`].
+    pub fn raycast(&self, origin: Point<T>, direction: Coord<T>, max_len: T) -> Option<(&D, T)> {
+        let norm = direction.x.hypot(direction.y);
+        if norm == T::zero() || !norm.is_finite() {
+            return None;
+        }
+
+        // Nothing can be hit beyond the far corner of the tree
+        let reach = max_dist_pt_rect(&origin, self.arena.root().bounds());
+        let len = max_len.min(reach);
+        let end = origin.0 + direction * (len / norm);
+
+        self.intersecting_segment(&Line::new(origin.0, end)).next()
+    }
+
+    /// Iterate over every datum whose geometry crosses or touches `segment`,
+    /// in order along it from its start, alongside the distance along the
+    /// segment to where each is first touched.
+    ///
+    /// Nodes are visited in order of where the segment enters their bounds,
+    /// so only nodes along the segment are visited, and lazily. Uses planar
+    /// geometry whatever the tree's [`CalcMethod`].
+    pub fn intersecting_segment<'a>(
+        &'a self,
+        segment: &Line<T>,
+    ) -> impl Iterator<Item = (&'a D, T)> + use<'a, D, T> {
+        let segment = *segment;
+        let len = segment.delta().x.hypot(segment.delta().y);
+        let mut seen = HashSet::new();
+
+        // Fractions along the segment are scaled to distances, and multi-cell
+        // references are reported once
+        sorted_unchecked_by(
+            self.root_ref(),
+            move |node| {
+                line_rect_entry(&segment, node.node.bounds())
+                    .map(|t| t * len)
+                    .ok_or(Error::NoneInRadius)
+            },
+            move |child| {
+                line_geom_entry(&segment, &child.as_geom())
+                    .map(|t| t * len)
+                    .ok_or(Error::NoneInRadius)
+            },
+        )
+        .filter(move |(datum, _)| seen.insert(*datum as *const D))
+    }
 }
 
 impl<D, T> QuadTree<D, T> for BoundsQuadTree<D, T>
@@ -75,42 +318,99 @@ where
     D: AsGeom<T>,
     T: GeoNum,
 {
-    type Node = BoundsNode<D, T>;
-
     fn size(&self) -> usize {
         self.size
     }
 
     fn insert(&mut self, datum: D) -> Result<(), Error> {
         // Bounds check - discard nodes that are not completely contained
-        let qb = self.root.bounds();
-        let db = &datum
+        let qb = self.arena.root().bounds();
+        let db = datum
             .as_geom()
             .bounding_rect()
             .ok_or(Error::CannotMakeBbox)?;
 
+        // Spherical data crossing the antimeridian are split into the parts
+        // either side of it, which are each checked and placed separately
+        let parts = self.antimeridian_parts(&datum);
+        let bboxes = parts.map_or(vec![db], Vec::from);
+
         // Cannot use Rect::contains here, see notes on rect_in_rect for why
-        if rect_in_rect(qb, db) {
-            self.root.insert(datum)?;
-            self.size += 1;
-            Ok(())
-        } else {
-            Err(Error::OutOfBounds)
+        if !bboxes.iter().all(|bbox| rect_in_rect(qb, bbox)) {
+            return Err(Error::OutOfBounds);
+        }
+
+        match (self.max_cells, parts) {
+            (Some(max_cells), _) => {
+                let idx = self.store.len();
+                self.store.push(datum);
+                self.refs.push(0);
+                self.store_seqs.push(self.size);
+
+                let mut cells = Cells {
+                    store: &self.store,
+                    refs: &mut self.refs,
+                    max_cells,
+                    split: self.calc_method.is_geographic(),
+                };
+                for bbox in &bboxes {
+                    cells.refs[idx] += 1;
+                    BoundsNode::insert_shared(&mut self.arena, 0, idx, bbox, &mut cells)?;
+                }
+            }
+            // Split data are held in the store, referenced like stuck children
+            (None, Some(_)) => {
+                let idx = self.store.len();
+                self.store.push(datum);
+                self.refs.push(0);
+                self.store_seqs.push(self.size);
+
+                BoundsNode::stick_split(&mut self.arena, 0, idx, &self.store);
+            }
+            (None, None) => {
+                BoundsNode::insert_held(&mut self.arena, 0, datum, self.size, &self.store)?
+            }
         }
+
+        self.size += 1;
+        Ok(())
     }
 
-    fn retrieve(&self, datum: &D) -> DatumIter<'_, Self::Node, D, T> {
+    fn retrieve<'a>(&'a self, datum: &D) -> impl Iterator<Item = &'a D> + use<'a, D, T>
+    where
+        D: 'a,
+    {
         // Squash errors and return an empty iterator if we can't get the bbox
-        if let Some(bbox) = datum.as_geom().bounding_rect() {
+        let bbox = datum
+            .as_geom()
+            .bounding_rect()
             // Cannot use Rect::contains here, see notes on rect_in_rect for why
-            if rect_in_rect(self.root.bounds(), &bbox) {
-                self.root.retrieve(datum)
-            } else {
-                DatumIter::Empty
+            .filter(|bbox| rect_in_rect(self.arena.root().bounds(), bbox));
+
+        // Multi-cell references, and references to data split at the
+        // antimeridian, are gathered from every overlapping node, keeping only
+        // the first reference to each datum
+        let mut shared = vec![];
+        if let Some(bbox) = bbox.filter(|_| !self.store.is_empty()) {
+            let bboxes = self.antimeridian_parts(datum).map_or(vec![bbox], Vec::from);
+            let mut seen = HashSet::new();
+            for bbox in &bboxes {
+                self.arena
+                    .root()
+                    .retrieve_shared(&self.arena, bbox, &mut |idx| {
+                        if seen.insert(idx) {
+                            shared.push(&self.store[idx]);
+                        }
+                    });
             }
-        } else {
-            DatumIter::Empty
         }
+
+        let held = match bbox {
+            Some(_) => self.arena.root().retrieve(&self.arena, datum),
+            None => DatumIter::Empty,
+        };
+
+        held.chain(shared)
     }
 }
 
@@ -119,10 +419,16 @@ where
     D: AsGeom<T>,
     T: QtFloat,
 {
-    type Node = BoundsNode<D, T>;
+    fn calc_method(&self) -> CalcMethod<T> {
+        self.calc_method.clone()
+    }
 
-    fn calc_method(&self) -> CalcMethod {
-        self.calc_method
+    fn insertion_index(&self, datum: &D) -> Option<usize> {
+        // Multi-cell and split data are held in the store, the rest by nodes
+        match index_in(&self.store, datum) {
+            Some(idx) => Some(self.store_seqs[idx]),
+            None => insertion_seq(&self.arena, datum),
+        }
     }
 
     fn find_r<X>(&self, cmp: &X, r: T) -> Result<(&D, T), Error>
@@ -133,39 +439,35 @@ where
         let cmp = cmp.with_calc(self.calc_method());
 
         // Error early if invalid
-        if cmp.dist_bbox(self.root.bounds())? != T::zero() {
+        if cmp.dist_bbox(self.arena.root().bounds())? != T::zero() {
             return Err(Error::OutOfBounds);
         }
         if self.size == 0 {
             return Err(Error::Empty);
         }
 
-        let mut stack = vec![&self.root];
+        let mut stack = vec![self.root_ref()];
         let mut min_dist = r;
         let mut min_item = Err(Error::NoneInRadius);
 
         while let Some(node) = stack.pop() {
             // No need to check the children if the bounds are too far,
             // checking bounds is cheaper then checking each child
-            let bounds_dist = cmp.dist_bbox(node.bounds())?;
+            let bounds_dist = cmp.dist_bbox(node.node.bounds())?;
             if bounds_dist >= min_dist {
                 continue;
             }
 
             // Loop through all the children of the current node, retaining
             // only the currently closest child, stuck or otherwise
-            // Children will iterate through all children, stuck or otherwise
-            for child in node.children() {
+            // Data will iterate through all children, stuck or otherwise, and
+            // any multi-cell references, where a datum seen again simply ties
+            for child in node.data() {
                 // Shortcut the potentially complex distance calc by using the
                 // bounds. This optimization may not always be faster, but if
                 // the bbox is expensive to calculate then the distance likely
                 // is also.
-                let bbox = child
-                    .as_geom()
-                    .bounding_rect()
-                    .ok_or(Error::CannotMakeBbox)?;
-
-                if cmp.dist_bbox(&bbox)? > min_dist {
+                if cmp.dist_bounds(&child.as_geom())? > min_dist {
                     continue;
                 }
 
@@ -178,11 +480,8 @@ where
             }
 
             // Push nodes onto the stack in reverse order
-            if let Some(sub_nodes) = node.nodes() {
-                for i in 0..4 {
-                    stack.push(&sub_nodes[3 - i]);
-                }
-            }
+            let sub_nodes = node.branches().collect::<Vec<_>>();
+            stack.extend(sub_nodes.into_iter().rev());
         }
 
         min_item.map(|item| (item, min_dist))
@@ -192,14 +491,66 @@ where
     where
         X: AsGeom<T>,
     {
-        knn(&self.root, cmp.with_calc(self.calc_method()), k, r)
+        let cmp = cmp.with_calc(self.calc_method());
+
+        knn_by(
+            self.root_ref(),
+            |node| cmp.dist_bbox(node.node.bounds()),
+            |child| cmp.dist_geom(&child.as_geom()),
+            k,
+            r,
+        )
     }
 
-    fn sorted<'a, X>(&'a self, cmp: &'a X) -> SortIter<'a, Self::Node, D, T>
+    fn knn_approx<X>(&self, cmp: &X, k: usize, epsilon: T) -> Result<Vec<(&D, T)>, Error>
     where
+        X: AsGeom<T>,
+    {
+        let cmp = cmp.with_calc(self.calc_method());
+
+        knn_approx_by(
+            self.root_ref(),
+            |node| cmp.dist_bbox(node.node.bounds()),
+            |child| cmp.dist_geom(&child.as_geom()),
+            k,
+            epsilon,
+        )
+    }
+
+    fn sorted<'a, X>(&'a self, cmp: &'a X) -> impl Iterator<Item = (&'a D, T)> + 'a
+    where
+        D: 'a,
+        X: AsGeom<T> + 'a,
+    {
+        let cmp = cmp.with_calc(self.calc_method());
+
+        let node_cmp = cmp.clone();
+        sorted_by(
+            self.root_ref(),
+            move |node| node_cmp.dist_bbox(node.node.bounds()),
+            move |child| cmp.dist_geom(&child.as_geom()),
+        )
+    }
+
+    fn sorted_desc<'a, X>(&'a self, cmp: &'a X) -> impl Iterator<Item = (&'a D, T)> + 'a
+    where
+        D: 'a,
         X: AsGeom<T> + 'a,
     {
-        sorted(&self.root, cmp.with_calc(self.calc_method()))
+        let cmp = cmp.with_calc(self.calc_method());
+
+        // Multi-cell data overhang the leaves referencing them, so a node's
+        // bounds need not hold all of a datum. But each of its points lies in
+        // a leaf referencing it, or a node it is stuck at, whose upper bound
+        // is then at least the datum's distance, so that reference is reached
+        // before anything closer comes out. Later repeats are dropped.
+        let (min_cmp, max_cmp) = (cmp.clone(), cmp.clone());
+        sorted_desc_by(
+            self.root_ref(),
+            move |node| min_cmp.dist_bbox(node.node.bounds()),
+            move |node| max_cmp.max_dist_bbox(node.node.bounds()),
+            move |child| cmp.dist_geom(&child.as_geom()),
+        )
     }
 }
 
@@ -212,7 +563,16 @@ where
     type IntoIter = DatumIter<'a, BoundsNode<D, T>, D, T>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.root.descendants()
+        // Multi-cell trees hold every datum once in the store, otherwise it
+        // only holds data split at the antimeridian
+        match self.max_cells {
+            Some(_) => DatumIter::Slice(self.store.iter()),
+            None if self.store.is_empty() => self.arena.root().descendants(&self.arena),
+            None => DatumIter::ChainSelf(ChainSelfIter::new(
+                self.arena.root().descendants(&self.arena),
+                DatumIter::Slice(self.store.iter()),
+            )),
+        }
     }
 }
 
@@ -223,14 +583,14 @@ where
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "Bounds Quadtree Root:")?;
-        write!(f, "{}", self.root)
+        self.arena.root().display(&self.arena, f)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use geo::{Point, Rect, coord};
+    use geo::{Point, Polygon, Rect, coord, line_string};
 
     // helper function for bounds datum creation
     fn b(x: f64, y: f64, w: f64, h: f64) -> Rect {
@@ -257,13 +617,13 @@ mod tests {
         // In root[BR]
         let b6 = b(6.0, 5.0, 1.0, 1.0);
 
-        qt.insert(b1.clone()).unwrap();
-        qt.insert(b1.clone()).unwrap();
-        qt.insert(b2.clone()).unwrap();
-        qt.insert(b3.clone()).unwrap();
-        qt.insert(b4.clone()).unwrap();
-        qt.insert(b5.clone()).unwrap();
-        qt.insert(b6.clone()).unwrap();
+        qt.insert(b1).unwrap();
+        qt.insert(b1).unwrap();
+        qt.insert(b2).unwrap();
+        qt.insert(b3).unwrap();
+        qt.insert(b4).unwrap();
+        qt.insert(b5).unwrap();
+        qt.insert(b6).unwrap();
 
         // Dropping into an empty node returns only the one stuck on root
         let cmp = b(1.0, 5.0, 1.0, 1.0);
@@ -286,4 +646,209 @@ mod tests {
             vec![&b4, &b3, &b1, &b1, &b2]
         );
     }
+
+    #[test]
+    fn multi_cell_references_every_overlapped_leaf_once() {
+        let bounds = b(0.0, 0.0, 8.0, 8.0);
+        let mut qt = BoundsQuadTree::multi_cell(bounds, CalcMethod::Euclidean, 2, 1, 4);
+
+        // A small box, then a long thin box crossing the vertical midline
+        let small = b(1.0, 1.0, 0.0, 0.0);
+        let long = b(0.5, 2.5, 7.0, 0.0);
+        qt.insert(small).unwrap();
+        qt.insert(long).unwrap();
+
+        // Referenced from two leaves in TL and from TR, so nothing is stuck
+        let stats = qt.stats();
+        assert_eq!(stats.stuck_children, 0);
+        assert_eq!(stats.leaves, 7);
+        assert_eq!(stats.children_per_leaf, vec![3, 4]);
+
+        let tr = qt.root().sub_node(SubNode::TopRight).unwrap();
+        assert_eq!(tr.shared(), &[1]);
+        assert_eq!(qt.datum(tr.shared()[0]), Some(&long));
+
+        // Queries and iteration each see the long box once
+        let cmp = b(1.5, 1.5, 1.0, 1.5);
+        assert_eq!(qt.retrieve(&cmp).collect::<Vec<_>>(), vec![&small, &long]);
+        assert_eq!(qt.into_iter().collect::<Vec<_>>(), vec![&small, &long]);
+
+        let cmp = Point::new(3.0, 2.5);
+        let res = qt.knn(&cmp, 2).unwrap();
+        assert_eq!(
+            res.iter().map(|(d, _)| *d).collect::<Vec<_>>(),
+            vec![&long, &small]
+        );
+        assert_eq!(qt.sorted(&cmp).count(), 2);
+        assert_eq!(qt.find(&cmp).unwrap(), (&long, 0.0));
+    }
+
+    #[test]
+    fn multi_cell_sticks_data_over_the_cap() {
+        let bounds = b(0.0, 0.0, 8.0, 8.0);
+        let mut qt = BoundsQuadTree::multi_cell(bounds, CalcMethod::Euclidean, 2, 1, 2);
+
+        // Splitting TL would put the long box in a third leaf
+        let long = b(0.5, 2.5, 7.0, 0.0);
+        qt.insert(b(1.0, 1.0, 0.0, 0.0)).unwrap();
+        qt.insert(long).unwrap();
+
+        let stats = qt.stats();
+        assert_eq!(stats.stuck_by_depth, vec![0, 1]);
+        assert_eq!(qt.max_cells(), Some(2));
+        assert_eq!(
+            qt.retrieve(&b(5.0, 1.0, 1.0, 1.0)).collect::<Vec<_>>(),
+            vec![&long]
+        );
+    }
+
+    #[test]
+    fn stats_counts_stuck_children_by_depth() {
+        let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 8.0, y: 8.0});
+        let mut qt = BoundsQuadTree::new(bounds, CalcMethod::Euclidean, 2, 2);
+
+        // Same layout as the retrieve test above
+        qt.insert(b(1.0, 1.0, 0.0, 0.0)).unwrap();
+        qt.insert(b(1.0, 1.0, 0.0, 0.0)).unwrap();
+        qt.insert(b(3.0, 3.0, 1.0, 1.0)).unwrap();
+        qt.insert(b(1.0, 1.0, 2.0, 2.0)).unwrap();
+        qt.insert(b(6.0, 2.0, 1.0, 4.0)).unwrap();
+        qt.insert(b(6.0, 1.0, 1.0, 1.0)).unwrap();
+        qt.insert(b(6.0, 5.0, 1.0, 1.0)).unwrap();
+
+        let stats = qt.stats();
+        assert_eq!(stats.nodes, 9);
+        assert_eq!(stats.leaves, 7);
+        assert_eq!(stats.depth, 2);
+        assert_eq!(stats.stuck_children, 2);
+        assert_eq!(stats.stuck_by_depth, vec![1, 1]);
+        assert_eq!(stats.saturated_leaves, 0);
+        assert_eq!(stats.suggested_max_depth, 2);
+    }
+
+    #[test]
+    fn stats_only_suggests_depth_that_can_split_leaves() {
+        let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 8.0, y: 8.0});
+        let mut qt = BoundsQuadTree::new(bounds, CalcMethod::Euclidean, 1, 2);
+        let mut multi = BoundsQuadTree::multi_cell(bounds, CalcMethod::Euclidean, 1, 2, 4);
+
+        // Identical boxes, the multi-cell ones overlapping two leaves
+        for _ in 0..3 {
+            qt.insert(b(1.0, 1.0, 1.0, 1.0)).unwrap();
+            multi.insert(b(3.5, 1.0, 1.0, 1.0)).unwrap();
+        }
+        let stats = qt.stats();
+        assert_eq!(stats.coincident_leaves, 1);
+        assert_eq!(stats.suggested_max_depth, 1);
+        let stats = multi.stats();
+        assert_eq!(stats.saturated_leaves, 2);
+        assert_eq!(stats.coincident_leaves, 2);
+        assert_eq!(stats.suggested_max_depth, 1);
+
+        // A box sharing their centre but not their extent can be split off
+        qt.insert(b(0.5, 0.5, 2.0, 2.0)).unwrap();
+        let stats = qt.stats();
+        assert_eq!(stats.coincident_leaves, 0);
+        assert_eq!(stats.suggested_max_depth, 2);
+    }
+
+    #[test]
+    fn raycast_and_intersecting_segment_follow_the_ray() {
+        let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 8.0, y: 8.0});
+
+        for mut qt in [
+            BoundsQuadTree::new(bounds, CalcMethod::Euclidean, 3, 1),
+            BoundsQuadTree::multi_cell(bounds, CalcMethod::Euclidean, 3, 1, 8),
+        ] {
+            // Obstacles along y = 1, inserted out of order, one straddling
+            // the midline and one off the ray
+            let far = b(6.0, 0.5, 1.0, 1.0);
+            let wide = b(3.5, 0.0, 1.0, 2.0);
+            let near = b(2.0, 0.9, 0.5, 0.2);
+            let off = b(1.0, 5.0, 1.0, 1.0);
+            for datum in [far, wide, near, off] {
+                qt.insert(datum).unwrap();
+            }
+
+            let origin = Point::new(0.5, 1.0);
+            let east = coord! {x: 2.0, y: 0.0};
+            assert_eq!(qt.raycast(origin, east, f64::INFINITY), Some((&near, 1.5)));
+            assert_eq!(qt.raycast(origin, east, 1.0), None);
+            assert_eq!(qt.raycast(origin, coord! {x: 0.0, y: 0.0}, 5.0), None);
+
+            // Starting inside a datum hits it immediately
+            let inside = Point::new(4.0, 1.0);
+            assert_eq!(qt.raycast(inside, east, 8.0), Some((&wide, 0.0)));
+
+            let segment = Line::new(coord! {x: 0.5, y: 1.0}, coord! {x: 7.5, y: 1.0});
+            assert_eq!(
+                qt.intersecting_segment(&segment).collect::<Vec<_>>(),
+                vec![(&near, 1.5), (&wide, 3.0), (&far, 5.5)]
+            );
+        }
+    }
+
+    #[test]
+    fn containing_respects_holes_and_boundaries() {
+        let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 8.0, y: 8.0});
+
+        for mut qt in [
+            BoundsQuadTree::new(bounds, CalcMethod::Euclidean, 3, 1),
+            BoundsQuadTree::multi_cell(bounds, CalcMethod::Euclidean, 3, 1, 8),
+        ] {
+            // A square with a hole, straddling the midlines, plus a rect
+            // overlapping its corner and a line crossing it
+            let donut = Polygon::new(
+                line_string![(x: 1.0, y: 1.0), (x: 7.0, y: 1.0), (x: 7.0, y: 7.0), (x: 1.0, y: 7.0)],
+                vec![
+                    line_string![(x: 3.0, y: 3.0), (x: 5.0, y: 3.0), (x: 5.0, y: 5.0), (x: 3.0, y: 5.0)],
+                ],
+            );
+            let corner = b(0.5, 0.5, 2.0, 2.0);
+            let line = Line::new(coord! {x: 0.0, y: 2.0}, coord! {x: 8.0, y: 2.0});
+            qt.insert(Geometry::from(donut)).unwrap();
+            qt.insert(Geometry::from(corner)).unwrap();
+            qt.insert(Geometry::from(line)).unwrap();
+
+            // Name each datum by its kind, which is unique here
+            let name = |datum: &Geometry<f64>| match datum.as_geom() {
+                GeometryRef::Polygon(_) => "donut",
+                GeometryRef::Rect(_) => "corner",
+                _ => "line",
+            };
+            let containing = |x, y, boundary| {
+                let mut found = qt
+                    .containing(&Point::new(x, y), boundary)
+                    .map(name)
+                    .collect::<Vec<_>>();
+                found.sort();
+                found
+            };
+
+            assert_eq!(containing(1.5, 1.5, Boundary::Exclude), ["corner", "donut"]);
+            assert_eq!(
+                containing(1.5, 2.0, Boundary::Exclude),
+                ["corner", "donut", "line"]
+            );
+            assert_eq!(containing(6.0, 2.0, Boundary::Exclude), ["donut", "line"]);
+
+            // Inside the hole and outside everything
+            assert!(containing(4.0, 4.0, Boundary::Include).is_empty());
+            assert!(containing(7.5, 7.5, Boundary::Include).is_empty());
+
+            // On the hole's edge and the exterior's edge
+            assert_eq!(containing(3.0, 4.0, Boundary::Include), ["donut"]);
+            assert!(containing(3.0, 4.0, Boundary::Exclude).is_empty());
+            assert_eq!(containing(1.0, 6.0, Boundary::Include), ["donut"]);
+            assert!(containing(1.0, 6.0, Boundary::Exclude).is_empty());
+
+            // Line end points are its boundary
+            assert_eq!(containing(0.0, 2.0, Boundary::Include), ["line"]);
+            assert!(containing(0.0, 2.0, Boundary::Exclude).is_empty());
+
+            let first = |x, y| qt.containing_first(&Point::new(x, y), Boundary::Include);
+            assert_eq!(first(6.0, 6.0).map(name), Some("donut"));
+            assert_eq!(first(4.0, 4.0).map(name), None);
+        }
+    }
 }
@@ -1,6 +1,7 @@
-use std::fmt::{Display, Formatter};
 use std::marker::PhantomData;
 
+use crate::geom::spherical::math::antimeridian_bounds;
+use crate::node::Branch;
 use crate::*;
 use geo::{BoundingRect, Coord, GeoNum, Intersects, Rect};
 
@@ -12,12 +13,14 @@ where
     T: GeoNum,
 {
     bounds: Rect<T>,
-    depth: u8,
-    max_depth: u8,
-    max_children: usize,
-    pub children: Vec<D>,
-    pub stuck_children: Vec<D>,
-    pub nodes: Option<Box<[BoundsNode<D, T>; 4]>>,
+    limits: Limits,
+    pub(crate) children: Children<D>,
+    pub(crate) stuck_children: Vec<D>,
+    // Insertion sequence numbers, in step with the children and stuck children
+    seqs: Children<usize>,
+    stuck_seqs: Vec<usize>,
+    pub(crate) shared: Vec<usize>,
+    pub(crate) quad: Option<QuadId>,
     _num_type: PhantomData<T>,
 }
 
@@ -26,15 +29,16 @@ where
     D: AsGeom<T>,
     T: GeoNum,
 {
-    fn new(bounds: Rect<T>, depth: u8, max_depth: u8, max_children: usize) -> Self {
+    fn new(bounds: Rect<T>, limits: Limits) -> Self {
         Self {
             bounds,
-            depth,
-            max_depth,
-            max_children,
-            children: Vec::new(),
+            limits,
+            children: Children::new(),
             stuck_children: Vec::new(),
-            nodes: None,
+            seqs: Children::new(),
+            stuck_seqs: Vec::new(),
+            shared: Vec::new(),
+            quad: None,
             _num_type: PhantomData,
         }
     }
@@ -50,42 +54,120 @@ where
         })
     }
 
+    // Boxes are only inseparable when they cover exactly the same area
+    fn coincident(d1: &D, d2: &D) -> bool {
+        d1.as_geom().bounding_rect() == d2.as_geom().bounding_rect()
+    }
+
     // Getters
     fn bounds(&self) -> &Rect<T> {
         &self.bounds
     }
 
-    fn depth(&self) -> u8 {
-        self.depth
+    fn limits(&self) -> Limits {
+        self.limits
     }
 
-    fn max_depth(&self) -> u8 {
-        self.max_depth
+    fn quad(&self) -> Option<QuadId> {
+        self.quad
     }
 
-    fn max_children(&self) -> usize {
-        self.max_children
+    fn children(&self) -> DatumIter<'_, Self, D, T> {
+        DatumIter::ChainSlice(self.children.iter().chain(&self.stuck_children))
     }
 
-    fn nodes(&self) -> &Option<Box<[Self; 4]>> {
-        &self.nodes
+    fn stuck_children(&self) -> DatumIter<'_, Self, D, T> {
+        DatumIter::Slice(self.stuck_children.iter())
     }
 
-    fn children(&self) -> DatumIter<Self, D, T> {
-        DatumIter::ChainSlice(self.children.iter().chain(&self.stuck_children))
+    fn shared(&self) -> &[usize] {
+        &self.shared
+    }
+
+    fn heap_bytes(&self) -> usize {
+        children_heap_bytes(&self.children)
+            + children_heap_bytes(&self.seqs)
+            + size_of_val(self.stuck_children.as_slice())
+            + size_of_val(self.stuck_seqs.as_slice())
+            + size_of_val(self.shared.as_slice())
     }
 
     // Setters
-    fn set_nodes(&mut self, nodes: Option<Box<[Self; 4]>>) {
-        self.nodes = nodes;
+    fn set_quad(&mut self, quad: Option<QuadId>) {
+        self.quad = quad;
+    }
+
+    fn insert(arena: &mut Arena<Self>, id: usize, datum: D, seq: usize) -> Result<(), Error> {
+        Self::insert_held(arena, id, datum, seq, &[])
+    }
+
+    fn held_seq(&self, datum: &D) -> Option<usize> {
+        index_in(&self.children, datum)
+            .map(|i| self.seqs[i])
+            .or_else(|| index_in(&self.stuck_children, datum).map(|i| self.stuck_seqs[i]))
+    }
+
+    fn retrieve<'a>(&'a self, arena: &'a Arena<Self>, datum: &D) -> DatumIter<'a, Self, D, T> {
+        // Process all three functions that produce options in one hit
+        // Descendants overall will produce an iterator of children in all nodes
+        // that intersect with the passed node
+        let descendants = match self
+            .nodes(arena)
+            .zip(self.find_sub_node(datum))
+            .zip(datum.as_geom().bounding_rect())
+        {
+            Some(((nodes, sn_index), bbox)) => {
+                let sub_node = &nodes[sn_index as usize];
+                if rect_in_rect(sub_node.bounds(), &bbox) {
+                    sub_node.retrieve(arena, datum)
+                } else {
+                    let mut inner = DatumIter::Empty;
+                    // Return the entire contents of any overlapping node
+                    // Same semantics as https://github.com/mikechambers/ExamplesByMesh/blob/master/JavaScript/QuadTree/src/QuadTree.js
+                    for sub_node in nodes {
+                        if sub_node.bounds().intersects(&bbox) {
+                            inner = DatumIter::ChainSelf(ChainSelfIter::new(
+                                inner,
+                                sub_node.descendants(arena),
+                            ));
+                        }
+                    }
+                    inner
+                }
+            }
+            _ => DatumIter::Empty,
+        };
+
+        // Start with the immediate children, which may include stuck children
+        // Then chain in descendants
+        DatumIter::ChainSelf(ChainSelfIter::new(self.children(), descendants))
     }
+}
+
+// Storage outside of multi-cell trees, where data are held by nodes except
+// for those split at the antimeridian, which are held once in the store and
+// referenced from wherever each part would have been held
+impl<D, T> BoundsNode<D, T>
+where
+    D: AsGeom<T>,
+    T: GeoNum,
+{
+    /// Insert a datum to be held by the node at `id` or below it, as in
+    /// [`Node::insert`]. `store` holds the data split at the antimeridian,
+    /// whose references move down when a leaf holding them subdivides.
+    pub(crate) fn insert_held(
+        arena: &mut Arena<Self>,
+        id: usize,
+        datum: D,
+        seq: usize,
+        store: &[D],
+    ) -> Result<(), Error> {
+        let node = &arena[id];
 
-    fn insert(&mut self, datum: D) -> Result<(), Error> {
-        // See notes in the PointQuadTree implementation on take
-        match self.nodes.take() {
+        match node.quad {
             // If we have sub-nodes already, pass down the tree
             // Also works for stuck nodes, will be pushed down as far as they can go
-            Some(mut sub_nodes) => {
+            Some(quad) => {
                 // Generate the bounding box for the geometry, which may fail
                 let bbox = datum
                     .as_geom()
@@ -94,85 +176,295 @@ where
 
                 // Get the index of the datum - will be based on the datum's
                 // top-left point from its bounds
-                let sub_node_idx = self.find_sub_node(&datum).ok_or(Error::CannotFindSubNode)?;
-                let sub_node = &mut sub_nodes[sub_node_idx as usize];
+                let sub_node_idx = node.find_sub_node(&datum).ok_or(Error::CannotFindSubNode)?;
+                let sub_node_id = quad.sub_node(sub_node_idx as usize);
 
                 // Check if the datum is totally contained by the sub-node
                 // If not, it is a stuck child, noting that contains includes
                 // bordering, see notes in rect_in_rect for why
-                if rect_in_rect(sub_node.bounds(), &bbox) {
-                    sub_node.insert(datum)?
+                if rect_in_rect(arena[sub_node_id].bounds(), &bbox) {
+                    Self::insert_held(arena, sub_node_id, datum, seq, store)?
                 } else {
-                    self.stuck_children.push(datum);
+                    arena[id].stuck_children.push(datum);
+                    arena[id].stuck_seqs.push(seq);
                 }
-
-                // Make sure to replace the nodes
-                self.nodes = Some(sub_nodes);
             }
             // If no room left, subdivide
             // See notes in PointQuadTree implementation
-            None if self.children.len() >= self.max_children && !(self.depth >= self.max_depth) => {
-                self.subdivide();
-
-                let mut children = std::mem::replace(&mut self.children, Vec::new());
+            None if node.limits.must_subdivide(node.children.len()) => {
+                let mut children = std::mem::take(&mut arena[id].children);
                 children.push(datum);
+                let mut seqs = std::mem::take(&mut arena[id].seqs);
+                seqs.push(seq);
+                let shared = std::mem::take(&mut arena[id].shared);
+
+                Self::subdivide(arena, id);
 
-                // Re-insert all children
-                for pt in children {
-                    self.insert(pt)?;
+                // Re-insert all children, and references to split data
+                for (pt, seq) in children.into_iter().zip(seqs) {
+                    Self::insert_held(arena, id, pt, seq, store)?;
+                }
+                for idx in shared {
+                    Self::stick_split(arena, id, idx, store);
                 }
             }
             // Otherwise can simply push the point
-            None => self.children.push(datum),
+            None => {
+                arena[id].children.push(datum);
+                arena[id].seqs.push(seq);
+            }
         }
 
         Ok(())
     }
 
-    fn retrieve(&self, datum: &D) -> DatumIter<'_, BoundsNode<D, T>, D, T> {
-        // Process all three functions that produce options in one hit
-        // Descendants overall will produce an iterator of children in all nodes
-        // that intersect with the passed node
-        let descendants = match self
-            .nodes
-            .as_ref()
-            .zip(self.find_sub_node(datum))
-            .zip(datum.as_geom().bounding_rect())
-        {
-            Some(((nodes, sn_index), bbox)) => {
-                let sub_node = &nodes[sn_index as usize];
-                if rect_in_rect(sub_node.bounds(), &bbox) {
-                    sub_node.retrieve(datum)
-                } else {
-                    let mut inner = DatumIter::Empty;
-                    // Return the entire contents of any overlapping node
-                    // Same semantics as https://github.com/mikechambers/ExamplesByMesh/blob/master/JavaScript/QuadTree/src/QuadTree.js
-                    for sub_node in &**nodes {
-                        if sub_node.bounds().intersects(&bbox) {
-                            inner = DatumIter::ChainSelf(ChainSelfIter::new(
-                                inner,
-                                sub_node.descendants(),
-                            ));
-                        }
+    // Reference the datum at `idx` from the deepest node at or below `id`
+    // that contains the bbox, as if it were a stuck child
+    fn stick_shared(arena: &mut Arena<Self>, id: usize, idx: usize, bbox: &Rect<T>) {
+        let mut id = id;
+        while let Some(quad) = arena[id].quad {
+            match (0..4)
+                .map(|offset| quad.sub_node(offset))
+                .find(|sub_node_id| rect_in_rect(arena[*sub_node_id].bounds(), bbox))
+            {
+                Some(sub_node_id) => id = sub_node_id,
+                None => break,
+            }
+        }
+
+        if !arena[id].shared.contains(&idx) {
+            arena[id].shared.push(idx);
+        }
+    }
+
+    /// Reference the datum at `idx` of the store, which is split at the
+    /// antimeridian, from the deepest nodes at or below `id` that contain
+    /// each of its parts held there.
+    pub(crate) fn stick_split(arena: &mut Arena<Self>, id: usize, idx: usize, store: &[D]) {
+        let bounds = arena[id].bounds;
+        let parts = antimeridian_bounds(&store[idx].as_geom())
+            .into_iter()
+            .flatten();
+
+        for part in parts.filter(|part| rect_in_rect(&bounds, part)) {
+            Self::stick_shared(arena, id, idx, &part);
+        }
+    }
+}
+
+/// Tree-level state needed to place multi-cell references. `refs` counts the
+/// nodes referencing each datum in the store, which is capped at `max_cells`.
+/// `split` is set for Spherical trees, where data crossing the antimeridian
+/// are placed by the parts of their bounds either side of it.
+pub(crate) struct Cells<'a, D> {
+    pub(crate) store: &'a [D],
+    pub(crate) refs: &'a mut [usize],
+    pub(crate) max_cells: usize,
+    pub(crate) split: bool,
+}
+
+impl<D> Cells<'_, D> {
+    // Bounds of the datum at `idx` where it is referenced from a node with
+    // the passed bounds, which are the parts of a split datum overlapping it
+    fn bboxes<T>(&self, idx: usize, node_bounds: &Rect<T>) -> Result<Vec<Rect<T>>, Error>
+    where
+        D: AsGeom<T>,
+        T: GeoNum,
+    {
+        let geom = self.store[idx].as_geom();
+        let parts = match self.split {
+            true => antimeridian_bounds(&geom),
+            false => None,
+        };
+
+        match parts {
+            Some(parts) => Ok(parts
+                .into_iter()
+                .filter(|part| part.intersects(node_bounds))
+                .collect()),
+            None => Ok(vec![geom.bounding_rect().ok_or(Error::CannotMakeBbox)?]),
+        }
+    }
+}
+
+// Multi-cell storage, where nodes hold indices into a tree-level store rather
+// than data. A datum is referenced from every leaf it overlaps, unless that
+// would take it over the cap, in which case it is stuck at the node where it
+// would have been split further
+impl<D, T> BoundsNode<D, T>
+where
+    D: AsGeom<T>,
+    T: GeoNum,
+{
+    /// Insert a reference to the datum at `idx` into the node at `id`, which
+    /// already counts as one of the datum's references.
+    pub(crate) fn insert_shared(
+        arena: &mut Arena<Self>,
+        id: usize,
+        idx: usize,
+        bbox: &Rect<T>,
+        cells: &mut Cells<D>,
+    ) -> Result<(), Error> {
+        match arena[id].quad {
+            Some(_) => Self::place_shared(arena, id, idx, bbox, cells),
+            None => Self::push_shared(arena, id, idx, cells),
+        }
+    }
+
+    // Push onto a leaf, subdividing following the same rules as insert
+    fn push_shared(
+        arena: &mut Arena<Self>,
+        id: usize,
+        idx: usize,
+        cells: &mut Cells<D>,
+    ) -> Result<(), Error> {
+        let node = &mut arena[id];
+        Self::add_shared(node, idx, cells);
+
+        if node.limits.must_subdivide(node.shared.len() - 1) {
+            let shared = std::mem::take(&mut node.shared);
+            Self::subdivide(arena, id);
+
+            // Each part of a split datum held here is placed separately
+            for idx in shared {
+                let bboxes = cells.bboxes(idx, &arena[id].bounds)?;
+                cells.refs[idx] += bboxes.len().saturating_sub(1);
+                for bbox in &bboxes {
+                    Self::place_shared(arena, id, idx, bbox, cells)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Spread a reference held by this node across the leaves below it that
+    // the datum overlaps, or keep it here as stuck if that would exceed the cap
+    fn place_shared(
+        arena: &mut Arena<Self>,
+        id: usize,
+        idx: usize,
+        bbox: &Rect<T>,
+        cells: &mut Cells<D>,
+    ) -> Result<(), Error> {
+        let leaves = arena[id].overlapping_leaves(arena, bbox);
+
+        if cells.refs[idx] - 1 + leaves > cells.max_cells {
+            Self::add_shared(&mut arena[id], idx, cells);
+            return Ok(());
+        }
+
+        cells.refs[idx] += leaves - 1;
+        Self::push_to_leaves(arena, id, idx, bbox, cells)
+    }
+
+    fn push_to_leaves(
+        arena: &mut Arena<Self>,
+        id: usize,
+        idx: usize,
+        bbox: &Rect<T>,
+        cells: &mut Cells<D>,
+    ) -> Result<(), Error> {
+        match arena[id].quad {
+            Some(quad) => {
+                for offset in 0..4 {
+                    let sub_node_id = quad.sub_node(offset);
+                    if arena[sub_node_id].bounds.intersects(bbox) {
+                        Self::push_to_leaves(arena, sub_node_id, idx, bbox, cells)?;
                     }
-                    inner
                 }
+                Ok(())
             }
-            _ => DatumIter::Empty,
-        };
+            None => Self::push_shared(arena, id, idx, cells),
+        }
+    }
 
-        // Start with the immediate children, which may include stuck children
-        // Then chain in descendants
-        DatumIter::ChainSelf(ChainSelfIter::new(self.children(), descendants))
+    // Hold a reference on the node, unless both parts of a split datum reach
+    // it, when the second no longer counts
+    fn add_shared(node: &mut Self, idx: usize, cells: &mut Cells<D>) {
+        match node.shared.contains(&idx) {
+            true => cells.refs[idx] -= 1,
+            false => node.shared.push(idx),
+        }
+    }
+
+    // Count the leaves below this node that overlap the bbox
+    fn overlapping_leaves(&self, arena: &Arena<Self>, bbox: &Rect<T>) -> usize {
+        match self.nodes(arena) {
+            Some(nodes) => nodes
+                .iter()
+                .filter(|n| n.bounds.intersects(bbox))
+                .map(|n| n.overlapping_leaves(arena, bbox))
+                .sum(),
+            None => 1,
+        }
+    }
+
+    /// Visit, in preorder, the shared references of every node whose bounds
+    /// overlap the bbox.
+    pub(crate) fn retrieve_shared(
+        &self,
+        arena: &Arena<Self>,
+        bbox: &Rect<T>,
+        f: &mut impl FnMut(usize),
+    ) {
+        self.shared.iter().for_each(|idx| f(*idx));
+
+        if let Some(nodes) = self.nodes(arena) {
+            for node in nodes.iter().filter(|n| n.bounds.intersects(bbox)) {
+                node.retrieve_shared(arena, bbox, f);
+            }
+        }
     }
 }
 
-impl<D, T> Display for BoundsNode<D, T>
+/// Handle onto a node together with the tree-level store, so searches see
+/// both the data a node holds and the data it references.
+pub(crate) struct BoundsRef<'a, D, T>
 where
     D: AsGeom<T>,
-    T: GeoNum + Display,
+    T: GeoNum,
 {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        self.display(f)
+    pub(crate) arena: &'a Arena<BoundsNode<D, T>>,
+    pub(crate) node: &'a BoundsNode<D, T>,
+    pub(crate) store: &'a [D],
+}
+
+impl<D, T> Clone for BoundsRef<'_, D, T>
+where
+    D: AsGeom<T>,
+    T: GeoNum,
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<D, T> Copy for BoundsRef<'_, D, T>
+where
+    D: AsGeom<T>,
+    T: GeoNum,
+{
+}
+
+impl<'a, D, T> Branch<'a, D> for BoundsRef<'a, D, T>
+where
+    D: AsGeom<T>,
+    T: GeoNum,
+{
+    fn data(self) -> impl Iterator<Item = &'a D> {
+        let store = self.store;
+        self.node
+            .children()
+            .chain(self.node.shared.iter().map(move |idx| &store[*idx]))
+    }
+
+    fn branches(self) -> impl Iterator<Item = Self> {
+        let (arena, store) = (self.arena, self.store);
+        self.node
+            .nodes(arena)
+            .into_iter()
+            .flatten()
+            .map(move |node| BoundsRef { arena, node, store })
     }
 }
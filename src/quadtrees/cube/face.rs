@@ -0,0 +1,235 @@
+use geo::{Coord, Line, Point, Rect, coord};
+
+use crate::geom::spherical::math::{dist_pt_line, dist_pt_pt, from_vec, to_vec};
+use crate::*;
+
+/// The number of faces on the cube.
+pub(crate) const FACES: usize = 6;
+
+/// The face of the cube that a point on the sphere lies behind, and its
+/// gnomonic projection onto that face, with both coordinates in `[-1, 1]`.
+///
+/// Faces 0 to 2 look along the positive x, y and z axes, and faces 3 to 5
+/// along the negative ones, where x points to longitude zero on the equator
+/// and z to the north pole. Points on an edge of the cube go to the face
+/// whose axis comes first.
+pub(crate) fn face_uv<T>(pt: &Point<T>) -> (usize, Point<T>)
+where
+    T: QtFloat,
+{
+    let v = to_vec(pt);
+    let axis = (0..3).fold(0, |a, b| if v[b].abs() > v[a].abs() { b } else { a });
+    let face = if v[axis] >= T::zero() { axis } else { axis + 3 };
+    let uv = project(face, v).expect("Unreachable, the point faces its own face.");
+
+    // Rounding can take points on the edge of the face just outside it
+    let clamp = |x: T| x.max(-T::one()).min(T::one());
+    (face, Point::new(clamp(uv.x()), clamp(uv.y())))
+}
+
+/// Great circle distance from a geometry to the region of the sphere that
+/// projects onto the `uv` rect of a face. Exact for points, and otherwise a
+/// lower bound, measured to the smallest cap around the cell's center that
+/// holds its corners.
+pub(crate) fn dist_cell<T>(geom: &GeometryRef<T>, face: usize, uv: &Rect<T>) -> Result<T, Error>
+where
+    T: QtFloat,
+{
+    match geom {
+        GeometryRef::Point(pt) => Ok(dist_pt_cell(pt, face, uv)),
+        _ => {
+            let (center, radius) = cap(face, uv);
+            let d = geom
+                .into_calc(CalcMethod::Spherical)
+                .dist_geom(&GeometryRef::Point(&center))?;
+
+            Ok((d - radius).max(T::zero()))
+        }
+    }
+}
+
+/// Upper bound on the great circle distance from a geometry to any point in
+/// a face cell, following [`dist_cell`] with the same cap. Exact for points, as the farthest
+/// point of the cell is the one closest to the point's antipode.
+pub(crate) fn max_dist_cell<T>(geom: &GeometryRef<T>, face: usize, uv: &Rect<T>) -> Result<T, Error>
+where
+    T: QtFloat,
+{
+    let pi = T::PI();
+
+    match geom {
+        GeometryRef::Point(pt) => {
+            let antipode = from_vec(to_vec(pt).map(|x| -x));
+            Ok(pi - dist_pt_cell(&antipode, face, uv))
+        }
+        _ => {
+            let (center, radius) = cap(face, uv);
+            let d = geom
+                .into_calc(CalcMethod::Spherical)
+                .dist_geom(&GeometryRef::Point(&center))?;
+
+            Ok((d + radius).min(pi))
+        }
+    }
+}
+
+// The point's distance is zero when it projects into the cell from the same
+// side of the sphere, and otherwise is to the nearest of the cell's edges,
+// which are great circle arcs between its corners
+fn dist_pt_cell<T>(pt: &Point<T>, face: usize, uv: &Rect<T>) -> T
+where
+    T: QtFloat,
+{
+    if project(face, to_vec(pt)).is_some_and(|p| pt_in_rect(uv, &p)) {
+        return T::zero();
+    }
+
+    let corners = corners(face, uv);
+    (0..4)
+        .map(|i| dist_pt_line(pt, &Line::new(corners[i].0, corners[(i + 1) % 4].0)))
+        .fold(T::infinity(), T::min)
+}
+
+// Center of the cell on the sphere and the distance to its farthest corner.
+// Cells are convex and well under a hemisphere, so the cap holds all of it
+fn cap<T>(face: usize, uv: &Rect<T>) -> (Point<T>, T)
+where
+    T: QtFloat,
+{
+    let center = unproject(face, uv.center());
+    let radius = corners(face, uv)
+        .iter()
+        .map(|c| dist_pt_pt(&center, c))
+        .fold(T::zero(), T::max);
+
+    (center, radius)
+}
+
+// Corners of the cell on the sphere, in order around it
+fn corners<T>(face: usize, uv: &Rect<T>) -> [Point<T>; 4]
+where
+    T: QtFloat,
+{
+    let (min, max) = (uv.min(), uv.max());
+
+    [
+        min,
+        coord! {x: max.x, y: min.y},
+        max,
+        coord! {x: min.x, y: max.y},
+    ]
+    .map(|c| unproject(face, c))
+}
+
+// The face's axis, the axes its u and v coordinates run along, and the sign
+// of the direction it looks in
+fn axes<T>(face: usize) -> (usize, usize, usize, T)
+where
+    T: QtFloat,
+{
+    let a = face % 3;
+    let sign = if face < 3 { T::one() } else { -T::one() };
+
+    (a, (a + 1) % 3, (a + 2) % 3, sign)
+}
+
+// Gnomonic projection of a unit vector onto the plane of a face, or `None`
+// if the vector is in the opposite hemisphere and so never reaches it
+fn project<T>(face: usize, v: [T; 3]) -> Option<Point<T>>
+where
+    T: QtFloat,
+{
+    let (a, b, c, sign) = axes::<T>(face);
+    let d = v[a] * sign;
+
+    (d > T::zero()).then(|| Point::new(v[b] / d, v[c] / d))
+}
+
+// Point on the sphere that projects onto the passed position on a face
+fn unproject<T>(face: usize, uv: Coord<T>) -> Point<T>
+where
+    T: QtFloat,
+{
+    let (a, b, c, sign) = axes(face);
+    let mut v = [T::zero(); 3];
+    v[a] = sign;
+    v[b] = uv.x;
+    v[c] = uv.y;
+
+    from_vec(v)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::{FRAC_PI_2, PI};
+
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn points_project_onto_their_face_and_back() {
+        assert_eq!(face_uv(&Point::new(0.0, 0.0)), (0, Point::new(0.0, 0.0)));
+        assert_eq!(face_uv(&Point::new(0.0, FRAC_PI_2)).0, 2);
+        assert_eq!(face_uv(&Point::new(PI, 0.0)).0, 3);
+        assert_eq!(face_uv(&Point::new(-FRAC_PI_2, 0.1)).0, 4);
+        assert_eq!(face_uv(&Point::new(1.0, -1.4)).0, 5);
+
+        for i in 0..50 {
+            let pt = Point::new(-3.1 + i as f64 * 0.126, -1.55 + i as f64 * 0.063);
+            let (face, uv) = face_uv(&pt);
+            let back = unproject(face, uv.0);
+
+            assert!(uv.x().abs() <= 1.0 && uv.y().abs() <= 1.0);
+            assert_abs_diff_eq!(dist_pt_pt(&pt, &back), 0.0, epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn cell_distances_bound_the_distance_to_points_in_the_cell() {
+        let uv = Rect::new(coord! {x: -0.5, y: 0.25}, coord! {x: 0.5, y: 1.0});
+        let samples = (0..=20)
+            .flat_map(|i| (0..=20).map(move |j| (i as f64 / 20.0, j as f64 / 20.0)))
+            .map(|(s, t)| coord! {x: -0.5 + s, y: 0.25 + 0.75 * t})
+            .collect::<Vec<_>>();
+        let line = Line::new(coord! {x: 0.3, y: -0.2}, coord! {x: 0.6, y: 0.1});
+
+        for face in 0..FACES {
+            let cell = samples
+                .iter()
+                .map(|c| unproject(face, *c))
+                .collect::<Vec<_>>();
+            for pt in [
+                Point::new(0.0, 0.0),
+                Point::new(2.0, 1.5),
+                Point::new(-1.0, -0.3),
+                cell[200],
+            ] {
+                let geom = GeometryRef::Point(&pt);
+                let near = cell.iter().map(|c| dist_pt_pt(&pt, c)).fold(PI, f64::min);
+                let far = cell.iter().map(|c| dist_pt_pt(&pt, c)).fold(0.0, f64::max);
+                let (d, max_d) = (
+                    dist_cell(&geom, face, &uv).unwrap(),
+                    max_dist_cell(&geom, face, &uv).unwrap(),
+                );
+
+                // Exact for points, up to the sampling resolution
+                assert!(d <= near + 1e-12 && d > near - 0.05);
+                assert!(max_d >= far - 1e-12 && max_d < far + 0.05);
+            }
+
+            let geom = GeometryRef::Line(&line);
+            let calc = geom.into_calc(CalcMethod::Spherical);
+            let near = cell
+                .iter()
+                .map(|c| calc.dist_geom(&GeometryRef::Point(c)).unwrap())
+                .fold(PI, f64::min);
+            let far = cell
+                .iter()
+                .map(|c| calc.dist_geom(&GeometryRef::Point(c)).unwrap())
+                .fold(0.0, f64::max);
+            assert!(dist_cell(&geom, face, &uv).unwrap() <= near + 1e-12);
+            assert!(max_dist_cell(&geom, face, &uv).unwrap() >= far - 1e-12);
+        }
+    }
+}
@@ -0,0 +1,346 @@
+mod face;
+
+use geo::{GeoNum, Point, Rect, coord};
+
+use super::knn::{knn_approx_by, knn_by};
+use super::sorted::{sorted_by, sorted_desc_by};
+use crate::node::Branch;
+use crate::*;
+use face::*;
+
+/// A spherical [`QuadTree`] for point-like geometries that partitions the
+/// sphere into the six faces of a cube, indexing each face with its own
+/// [`PointQuadTree`], in the manner of S2.
+///
+/// Data are positioned by longitude and latitude in radians, as for
+/// [`CalcMethod::Spherical`]. Each point is projected from the center of the
+/// sphere onto the face of the cube it lies behind, so cells stay a similar
+/// size and shape everywhere on the sphere, rather than narrowing to slivers
+/// near the poles as the cells of a longitude/latitude rect do. There is no
+/// seam at the antimeridian or singularity at the poles.
+///
+/// Searches always use [`CalcMethod::Spherical`] distances. Nodes are measured
+/// to the great circle edges of their cell, so queries run across face
+/// boundaries and over the poles with the same exact results as the other
+/// [`QuadTreeSearch`] implementations.
+#[derive(Debug)]
+pub struct CubeQuadTree<D, T = f64>
+where
+    D: AsPoint<T>,
+    T: GeoNum,
+{
+    faces: [PointQuadTree<FaceEntry<T>, T>; FACES],
+    data: Vec<D>,
+}
+
+// What each face's tree holds: the datum's position on the face, and where
+// to find the datum itself
+#[derive(Debug, Clone, Copy)]
+struct FaceEntry<T>
+where
+    T: GeoNum,
+{
+    uv: Point<T>,
+    idx: usize,
+}
+
+impl<T> AsPoint<T> for FaceEntry<T>
+where
+    T: GeoNum,
+{
+    fn as_point(&self) -> Point<T> {
+        self.uv
+    }
+}
+
+impl<D, T> CubeQuadTree<D, T>
+where
+    D: AsPoint<T>,
+    T: QtFloat,
+{
+    /// Create a new Cube QuadTree, with each face's tree using the passed
+    /// max_depth and max_children.
+    pub fn new(max_depth: u8, max_children: usize) -> Self {
+        CubeQuadTree::private_new(Some(max_depth), Some(max_children))
+    }
+
+    /// Create a new Cube QuadTree using default values for max_depth and
+    /// max_children.
+    pub fn with_defaults() -> Self {
+        CubeQuadTree::private_new(None, None)
+    }
+
+    /// Return the number of data held on each face of the cube. See
+    /// [`CubeQuadTree`] for how the faces are laid out.
+    pub fn face_sizes(&self) -> [usize; FACES] {
+        std::array::from_fn(|face| self.faces[face].size())
+    }
+
+    // Private constructor
+    fn private_new(max_depth: Option<u8>, max_children: Option<usize>) -> Self {
+        let max_depth = max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+        let max_children = max_children.unwrap_or(DEFAULT_MAX_CHILDREN);
+        let bounds = Rect::new(
+            coord! {x: -T::one(), y: -T::one()},
+            coord! {x: T::one(), y: T::one()},
+        );
+
+        Self {
+            faces: std::array::from_fn(|_| {
+                PointQuadTree::new(bounds, CalcMethod::None, max_depth, max_children)
+            }),
+            data: vec![],
+        }
+    }
+
+    // The face entry for a position, or `None` if it is not on the sphere
+    fn entry(pt: &Point<T>, idx: usize) -> Option<(usize, FaceEntry<T>)> {
+        let (lng, lat) = pt.x_y();
+
+        (lng.is_finite() && lat.abs() <= T::FRAC_PI_2()).then(|| {
+            let (face, uv) = face_uv(pt);
+            (face, FaceEntry { uv, idx })
+        })
+    }
+
+    // Handle on the top of the tree, above the faces
+    fn top(&self) -> CubeRef<'_, D, T> {
+        CubeRef {
+            data: &self.data,
+            faces: &self.faces,
+            cell: None,
+        }
+    }
+}
+
+impl<D, T> Default for CubeQuadTree<D, T>
+where
+    D: AsPoint<T>,
+    T: QtFloat,
+{
+    fn default() -> Self {
+        Self::with_defaults()
+    }
+}
+
+impl<D, T> QuadTree<D, T> for CubeQuadTree<D, T>
+where
+    D: AsPoint<T>,
+    T: QtFloat,
+{
+    fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    fn insert(&mut self, datum: D) -> Result<(), Error> {
+        let (face, entry) =
+            Self::entry(&datum.as_point(), self.data.len()).ok_or(Error::OutOfBounds)?;
+
+        self.faces[face].insert(entry)?;
+        self.data.push(datum);
+        Ok(())
+    }
+
+    fn retrieve<'a>(&'a self, datum: &D) -> impl Iterator<Item = &'a D> + use<'a, D, T>
+    where
+        D: 'a,
+    {
+        Self::entry(&datum.as_point(), usize::MAX)
+            .into_iter()
+            .flat_map(|(face, entry)| self.faces[face].retrieve(&entry))
+            .map(|entry| &self.data[entry.idx])
+    }
+}
+
+impl<D, T> QuadTreeSearch<D, T> for CubeQuadTree<D, T>
+where
+    D: AsGeom<T> + AsPoint<T>,
+    T: QtFloat,
+{
+    fn calc_method(&self) -> CalcMethod<T> {
+        CalcMethod::Spherical
+    }
+
+    fn insertion_index(&self, datum: &D) -> Option<usize> {
+        index_in(&self.data, datum)
+    }
+
+    fn find_r<X>(&self, cmp: &X, r: T) -> Result<(&D, T), Error>
+    where
+        X: AsGeom<T>,
+    {
+        let mut found = self.knn_r(cmp, 1, r)?;
+
+        match found.pop() {
+            Some(item) => Ok(item),
+            None if self.data.is_empty() => Err(Error::Empty),
+            None => Err(Error::NoneInRadius),
+        }
+    }
+
+    fn knn_r<X>(&self, cmp: &X, k: usize, r: T) -> Result<Vec<(&D, T)>, Error>
+    where
+        X: AsGeom<T>,
+    {
+        let geom = cmp.as_geom();
+        let cmp = cmp.with_calc(self.calc_method());
+
+        knn_by(
+            self.top(),
+            |node| node.dist(&geom),
+            |child| cmp.dist_geom(&child.as_geom()),
+            k,
+            r,
+        )
+    }
+
+    fn knn_approx<X>(&self, cmp: &X, k: usize, epsilon: T) -> Result<Vec<(&D, T)>, Error>
+    where
+        X: AsGeom<T>,
+    {
+        let geom = cmp.as_geom();
+        let cmp = cmp.with_calc(self.calc_method());
+
+        knn_approx_by(
+            self.top(),
+            |node| node.dist(&geom),
+            |child| cmp.dist_geom(&child.as_geom()),
+            k,
+            epsilon,
+        )
+    }
+
+    fn sorted<'a, X>(&'a self, cmp: &'a X) -> impl Iterator<Item = (&'a D, T)> + 'a
+    where
+        D: 'a,
+        X: AsGeom<T> + 'a,
+    {
+        let geom = cmp.as_geom();
+        let cmp = cmp.with_calc(self.calc_method());
+
+        sorted_by(
+            self.top(),
+            move |node| node.dist(&geom),
+            move |child| cmp.dist_geom(&child.as_geom()),
+        )
+    }
+
+    fn sorted_desc<'a, X>(&'a self, cmp: &'a X) -> impl Iterator<Item = (&'a D, T)> + 'a
+    where
+        D: 'a,
+        X: AsGeom<T> + 'a,
+    {
+        let geom = cmp.as_geom();
+        let cmp = cmp.with_calc(self.calc_method());
+
+        sorted_desc_by(
+            self.top(),
+            move |node| node.dist(&geom),
+            move |node| node.max_dist(&geom),
+            move |child| cmp.dist_geom(&child.as_geom()),
+        )
+    }
+}
+
+// Cursor onto a node of one face's tree
+type FaceRef<'a, T> = PointNodeRef<'a, FaceEntry<T>, T>;
+
+// Handle on a node of a Cube QuadTree for the search algorithms, either the
+// top of the tree, which branches into the root of each face, or a node of
+// one face's tree
+struct CubeRef<'a, D, T>
+where
+    T: GeoNum,
+{
+    data: &'a [D],
+    faces: &'a [PointQuadTree<FaceEntry<T>, T>; FACES],
+    cell: Option<(usize, FaceRef<'a, T>)>,
+}
+
+impl<D, T> Clone for CubeRef<'_, D, T>
+where
+    T: GeoNum,
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<D, T> Copy for CubeRef<'_, D, T> where T: GeoNum {}
+
+impl<D, T> CubeRef<'_, D, T>
+where
+    T: QtFloat,
+{
+    // The top of the tree holds the whole sphere
+    fn dist(&self, geom: &GeometryRef<T>) -> Result<T, Error> {
+        match self.cell {
+            Some((face, node)) => dist_cell(geom, face, node.bounds()),
+            None => Ok(T::zero()),
+        }
+    }
+
+    fn max_dist(&self, geom: &GeometryRef<T>) -> Result<T, Error> {
+        match self.cell {
+            Some((face, node)) => max_dist_cell(geom, face, node.bounds()),
+            None => Ok(T::PI()),
+        }
+    }
+}
+
+impl<'a, D, T> Branch<'a, D> for CubeRef<'a, D, T>
+where
+    T: GeoNum,
+{
+    fn data(self) -> impl Iterator<Item = &'a D> {
+        self.cell
+            .into_iter()
+            .flat_map(|(_, node)| node.children())
+            .map(move |entry| &self.data[entry.idx])
+    }
+
+    fn branches(self) -> impl Iterator<Item = Self> {
+        let cells = match self.cell {
+            Some((face, node)) => node
+                .sub_nodes()
+                .map(|nodes| nodes.map(|node| (face, node)).to_vec())
+                .unwrap_or_default(),
+            None => (0..FACES)
+                .map(|face| (face, self.faces[face].root()))
+                .collect(),
+        };
+
+        cells.into_iter().map(move |cell| Self {
+            data: self.data,
+            faces: self.faces,
+            cell: Some(cell),
+        })
+    }
+}
+
+impl<'a, D, T> IntoIterator for &'a CubeQuadTree<D, T>
+where
+    D: AsPoint<T>,
+    T: GeoNum,
+{
+    type Item = &'a D;
+    type IntoIter = std::slice::Iter<'a, D>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.iter()
+    }
+}
+
+impl<D, T> std::fmt::Display for CubeQuadTree<D, T>
+where
+    D: AsPoint<T>,
+    T: GeoNum + std::fmt::Display,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (face, tree) in self.faces.iter().enumerate() {
+            writeln!(f, "Cube Quadtree Face {face}:")?;
+            write!(f, "{tree}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,345 @@
+mod node;
+
+use geo::{Coord, Rect};
+use std::slice::Iter;
+
+use super::knn::knn_by;
+use crate::*;
+use node::*;
+
+/// A [`QuadTree`]-style implementation for point-like data on an integer grid.
+///
+/// The root is a square of side `2^order` anchored at `origin`, so every node
+/// is an exact power-of-two square. Subdivision splits on coordinate bits
+/// rather than computing a midpoint, so there is no rounding error at any
+/// depth, and each node is addressable by its [`MortonKey`].
+///
+/// Coordinates may be any [`QtInt`], and data need only implement
+/// [`AsPoint`]. As integer coordinates cannot measure distances, the search
+/// methods are generic over a [`QtFloat`] distance type chosen by the
+/// comparator, with data positions converted on the fly. Integer nodes cover
+/// whole cells, so a node's bounds run from its first cell to its last cell
+/// inclusive.
+#[derive(Debug)]
+pub struct IntQuadTree<D, T>
+where
+    D: AsPoint<T>,
+    T: QtInt,
+{
+    root: IntNode<D, T>,
+    size: usize,
+    calc_method: CalcMethod,
+}
+
+impl<D, T> IntQuadTree<D, T>
+where
+    D: AsPoint<T>,
+    T: QtInt,
+{
+    /// Create a new Integer QuadTree covering the square of side `2^order`
+    /// with its minimum corner at `origin`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `order` is greater than 32, or the square does not fit in
+    /// the coordinate type.
+    pub fn new(
+        origin: Coord<T>,
+        order: u8,
+        calc_method: CalcMethod,
+        max_depth: u8,
+        max_children: usize,
+    ) -> Self {
+        IntQuadTree::private_new(
+            origin,
+            order,
+            calc_method,
+            Some(max_depth),
+            Some(max_children),
+        )
+    }
+
+    /// Create a new Integer QuadTree using default values for max_depth and
+    /// max_children.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `order` is greater than 32, or the square does not fit in
+    /// the coordinate type.
+    pub fn from_order(origin: Coord<T>, order: u8, calc_method: CalcMethod) -> Self {
+        IntQuadTree::private_new(origin, order, calc_method, None, None)
+    }
+
+    // Private constructor
+    fn private_new(
+        origin: Coord<T>,
+        order: u8,
+        calc_method: CalcMethod,
+        max_depth: Option<u8>,
+        max_children: Option<usize>,
+    ) -> Self {
+        assert!(order <= 32, "order must be at most 32");
+        assert!(
+            key_bounds(origin, order, MortonKey::ROOT).is_some(),
+            "square must fit in the coordinate type"
+        );
+
+        let max_depth = max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+        let max_children = max_children.unwrap_or(DEFAULT_MAX_CHILDREN);
+
+        Self {
+            root: IntNode::new(MortonKey::ROOT, origin, order, max_depth, max_children),
+            size: 0,
+            calc_method,
+        }
+    }
+
+    /// Return the inclusive bounds of the QuadTree.
+    pub fn bounds(&self) -> Rect<T> {
+        // Checked on construction, so cannot fail
+        key_bounds(self.root.origin(), self.root.order(), MortonKey::ROOT)
+            .expect("Unreachable, root bounds checked on construction.")
+    }
+
+    /// Return the number of datums currently stored in the quadtree.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Insert a datum into the QuadTree. Returns [`Error::OutOfBounds`] if the
+    /// datum falls outside the root square.
+    pub fn insert(&mut self, datum: D) -> Result<(), Error> {
+        if self.root.datum_offset(&datum).is_none() {
+            return Err(Error::OutOfBounds);
+        }
+
+        self.root.insert(datum)?;
+        self.size += 1;
+        Ok(())
+    }
+
+    /// Retrieve the data in the leaf that would hold the passed datum. Empty
+    /// if the datum is out of bounds.
+    pub fn retrieve(&self, datum: &D) -> Iter<'_, D> {
+        match self.root.datum_offset(datum) {
+            Some(offset) => self.root.leaf(offset).children.iter(),
+            None => [].iter(),
+        }
+    }
+
+    /// Return the key of the leaf that holds, or would hold, the passed
+    /// datum, or `None` if it is out of bounds.
+    pub fn key(&self, datum: &D) -> Option<MortonKey> {
+        let offset = self.root.datum_offset(datum)?;
+        Some(self.root.leaf(offset).key())
+    }
+
+    /// Return the data held directly by the node with the passed key, or
+    /// `None` if there is no such node in the tree.
+    pub fn get(&self, key: MortonKey) -> Option<&[D]> {
+        self.root.get(key).map(|node| node.children.as_slice())
+    }
+
+    /// Return the inclusive bounds of the cell with the passed key, whether or
+    /// not the node exists. `None` if the key is deeper than the order.
+    pub fn cell_bounds(&self, key: MortonKey) -> Option<Rect<T>> {
+        key_bounds(self.root.origin(), self.root.order(), key)
+    }
+
+    /// Return the calculation methodology used to determine distances.
+    /// Searches are made in any float type, and [`CalcMethod::Custom`]
+    /// metrics, which measure in `f64`, are given geometries converted to it.
+    pub fn calc_method(&self) -> CalcMethod {
+        self.calc_method.clone()
+    }
+
+    /// Find the closest datum in the quadtree to the passed comparator. Works
+    /// as [`QuadTreeSearch::find`], with distances in the comparator's float
+    /// type `F`.
+    pub fn find<F, X>(&self, cmp: &X) -> Result<(&D, F), Error>
+    where
+        F: QtFloat,
+        X: AsGeom<F>,
+    {
+        let infinity = F::from(f64::INFINITY).ok_or(Error::CannotCastInfinity)?;
+        self.find_r(cmp, infinity)
+    }
+
+    /// Similar to [`IntQuadTree::find`], but takes a maximum distance
+    /// parameter to constrain the maximum search radius. Will return an
+    /// [`Error::NoneInRadius`] if no match is found inside `r`.
+    pub fn find_r<F, X>(&self, cmp: &X, r: F) -> Result<(&D, F), Error>
+    where
+        F: QtFloat,
+        X: AsGeom<F>,
+    {
+        let cmp = cmp.with_calc(self.calc_method().cast()).over_points();
+
+        // Error early if invalid
+        if cmp.dist_bbox(&self.root.float_bounds()?)? != F::zero() {
+            return Err(Error::OutOfBounds);
+        }
+        if self.size == 0 {
+            return Err(Error::Empty);
+        }
+
+        let mut stack = vec![&self.root];
+        let mut min_dist = r;
+        let mut min_item = Err(Error::NoneInRadius);
+
+        while let Some(node) = stack.pop() {
+            let bounds_dist = cmp.dist_bbox(&node.float_bounds()?)?;
+            if bounds_dist >= min_dist {
+                continue;
+            }
+
+            for child in &node.children {
+                let child_dist = dist_datum(&cmp, child)?;
+                // See notes in point about <= usage
+                if child_dist <= min_dist {
+                    min_dist = child_dist;
+                    min_item = Ok(child);
+                }
+            }
+
+            // Push nodes onto the stack in reverse order
+            if let Some(sub_nodes) = &node.nodes {
+                for i in 0..4 {
+                    stack.push(&sub_nodes[3 - i]);
+                }
+            }
+        }
+
+        min_item.map(|item| (item, min_dist))
+    }
+
+    /// Find `k` nearest neighbors of the comparator `cmp`. Works as
+    /// [`QuadTreeSearch::knn`], with distances in the comparator's float type
+    /// `F`.
+    pub fn knn<F, X>(&self, cmp: &X, k: usize) -> Result<Vec<(&D, F)>, Error>
+    where
+        F: QtFloat,
+        X: AsGeom<F>,
+    {
+        let infinity = F::from(f64::INFINITY).ok_or(Error::CannotCastInfinity)?;
+        self.knn_r(cmp, k, infinity)
+    }
+
+    /// Similar to [`IntQuadTree::knn`], but takes a maximum distance
+    /// parameter to constrain the maximum search radius. Returns an empty
+    /// vector if no data is found within the search radius.
+    pub fn knn_r<F, X>(&self, cmp: &X, k: usize, r: F) -> Result<Vec<(&D, F)>, Error>
+    where
+        F: QtFloat,
+        X: AsGeom<F>,
+    {
+        let cmp = cmp.with_calc(self.calc_method().cast()).over_points();
+
+        knn_by(
+            &self.root,
+            |node| cmp.dist_bbox(&node.float_bounds()?),
+            |child| dist_datum(&cmp, child),
+            k,
+            r,
+        )
+    }
+}
+
+// Distance from the comparator to an integer datum, via a float copy of its
+// position
+fn dist_datum<D, T, F>(cmp: &GeomCalc<'_, F>, datum: &D) -> Result<F, Error>
+where
+    D: AsPoint<T>,
+    T: QtInt,
+    F: QtFloat,
+{
+    let pt = to_float_point(datum.as_point()).ok_or(Error::InvalidDistance)?;
+    cmp.dist_geom(&pt.as_geom())
+}
+
+impl<'a, D, T> IntoIterator for &'a IntQuadTree<D, T>
+where
+    D: AsPoint<T>,
+    T: QtInt,
+{
+    type Item = &'a D;
+    type IntoIter = IntDatumIter<'a, D, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.root.descendants()
+    }
+}
+
+impl<D, T> std::fmt::Display for IntQuadTree<D, T>
+where
+    D: AsPoint<T>,
+    T: QtInt + std::fmt::Display,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Integer Quadtree Root:")?;
+        write!(f, "{}", self.root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::{Point, coord};
+
+    use super::*;
+
+    #[test]
+    fn subdivision_splits_exactly_on_bits() {
+        let mut qt = IntQuadTree::new(coord! {x: 0u32, y: 0}, 3, CalcMethod::Euclidean, 3, 1);
+        assert_eq!(qt.bounds(), Rect::new((0, 0), (7, 7)));
+
+        // Either side of the midline at 4 must land in opposite quadrants
+        let p1 = Point::new(3u32, 3);
+        let p2 = Point::new(4u32, 4);
+        qt.insert(p1).unwrap();
+        qt.insert(p2).unwrap();
+
+        let tl = MortonKey::ROOT.child(SubNode::TopLeft);
+        let br = MortonKey::ROOT.child(SubNode::BottomRight);
+        assert_eq!(qt.key(&p1), Some(tl));
+        assert_eq!(qt.key(&p2), Some(br));
+        assert_eq!(qt.get(tl), Some(&[p1][..]));
+        assert_eq!(qt.get(br), Some(&[p2][..]));
+        assert_eq!(qt.get(MortonKey::ROOT), Some(&[][..]));
+        assert_eq!(qt.get(tl.child(SubNode::TopLeft)), None);
+        assert_eq!(qt.cell_bounds(br), Some(Rect::new((4, 4), (7, 7))));
+        assert_eq!(
+            qt.retrieve(&Point::new(6, 5)).collect::<Vec<_>>(),
+            vec![&p2]
+        );
+    }
+
+    #[test]
+    fn unit_cells_stack_instead_of_splitting() {
+        let origin = coord! {x: -2i32, y: -2};
+        let mut qt = IntQuadTree::new(origin, 1, CalcMethod::Euclidean, 8, 1);
+
+        let pt = Point::new(-1, -2);
+        qt.insert(pt).unwrap();
+        qt.insert(pt).unwrap();
+        qt.insert(pt).unwrap();
+
+        let key = MortonKey::ROOT.child(SubNode::TopRight);
+        assert_eq!(qt.size(), 3);
+        assert_eq!(qt.key(&pt), Some(key));
+        assert_eq!(qt.get(key).unwrap().len(), 3);
+        assert_eq!(qt.insert(Point::new(0, 0)), Err(Error::OutOfBounds));
+    }
+
+    #[test]
+    fn iterator_runs_preorder() {
+        let mut qt = IntQuadTree::new(coord! {x: 0u16, y: 0}, 4, CalcMethod::Euclidean, 4, 1);
+
+        let data = [(9, 9), (1, 1), (3, 3), (1, 9)].map(|(x, y)| Point::new(x, y));
+        for d in data {
+            qt.insert(d).unwrap();
+        }
+
+        let res = qt.into_iter().map(|p| p.x_y()).collect::<Vec<_>>();
+        assert_eq!(res, vec![(1, 1), (3, 3), (9, 9), (1, 9)]);
+    }
+}
@@ -0,0 +1,327 @@
+use std::fmt::{Display, Formatter};
+use std::slice::Iter;
+
+use geo::{Coord, Point, Rect, coord};
+use num_traits::NumCast;
+
+use crate::node::Branch;
+use crate::quadtrees::morton::sub_node;
+use crate::*;
+
+/// Node for an [`IntQuadTree`].
+///
+/// Nodes do not store their bounds. These are implied exactly by the node's
+/// [`MortonKey`] and the root's origin and order, so subdivision is pure bit
+/// manipulation with no midpoint rounding.
+#[derive(Debug)]
+pub struct IntNode<D, T>
+where
+    D: AsPoint<T>,
+    T: QtInt,
+{
+    key: MortonKey,
+    origin: Coord<T>,
+    order: u8,
+    max_depth: u8,
+    max_children: usize,
+    pub(crate) children: Vec<D>,
+    pub(crate) nodes: Option<Box<[IntNode<D, T>; 4]>>,
+}
+
+impl<D, T> IntNode<D, T>
+where
+    D: AsPoint<T>,
+    T: QtInt,
+{
+    pub(crate) fn new(
+        key: MortonKey,
+        origin: Coord<T>,
+        order: u8,
+        max_depth: u8,
+        max_children: usize,
+    ) -> Self {
+        Self {
+            key,
+            origin,
+            order,
+            max_depth,
+            max_children,
+            children: Vec::new(),
+            nodes: None,
+        }
+    }
+
+    // Getters
+    pub(crate) fn key(&self) -> MortonKey {
+        self.key
+    }
+
+    pub(crate) fn order(&self) -> u8 {
+        self.order
+    }
+
+    pub(crate) fn origin(&self) -> Coord<T> {
+        self.origin
+    }
+
+    /// Offset of the datum from the root origin, or `None` if it falls
+    /// outside the root square.
+    pub(crate) fn datum_offset(&self, datum: &D) -> Option<(u32, u32)> {
+        offset(self.origin, self.order, datum.as_point().0)
+    }
+
+    /// Inclusive bounds of this node in tree coordinates. Only fails if the
+    /// key is deeper than the tree's order.
+    pub(crate) fn bounds(&self) -> Option<Rect<T>> {
+        key_bounds(self.origin, self.order, self.key)
+    }
+
+    /// Inclusive bounds of this node converted to a float type for distance
+    /// calculations.
+    pub(crate) fn float_bounds<F>(&self) -> Result<Rect<F>, Error>
+    where
+        F: QtFloat,
+    {
+        let bounds = self.bounds().ok_or(Error::OutOfBounds)?;
+        let min = to_float(bounds.min()).ok_or(Error::InvalidDistance)?;
+        let max = to_float(bounds.max()).ok_or(Error::InvalidDistance)?;
+        Ok(Rect::new(min, max))
+    }
+
+    /// Find the sub-node that holds the passed offset by reading the next bit
+    /// of each coordinate below this node's level.
+    pub(crate) fn find_sub_node(&self, (x, y): (u32, u32)) -> SubNode {
+        let shift = self.order - self.key.level - 1;
+        let quadrant = (((y >> shift) & 1) << 1) | ((x >> shift) & 1);
+        sub_node(quadrant as u64)
+    }
+
+    /// Split the node into four sub-nodes one level down.
+    fn subdivide(&mut self) {
+        let sub_node = |sn| {
+            IntNode::new(
+                self.key.child(sn),
+                self.origin,
+                self.order,
+                self.max_depth,
+                self.max_children,
+            )
+        };
+
+        // Fixed order of iteration tl, tr, br, bl
+        self.nodes = Some(Box::new([
+            sub_node(SubNode::TopLeft),
+            sub_node(SubNode::TopRight),
+            sub_node(SubNode::BottomRight),
+            sub_node(SubNode::BottomLeft),
+        ]));
+    }
+
+    pub(crate) fn insert(&mut self, datum: D) -> Result<(), Error> {
+        // See notes in the PointQuadTree implementation on take
+        match self.nodes.take() {
+            Some(mut sub_nodes) => {
+                let offset = self.datum_offset(&datum).ok_or(Error::OutOfBounds)?;
+                sub_nodes[self.find_sub_node(offset) as usize].insert(datum)?;
+                self.nodes = Some(sub_nodes);
+            }
+            // Single unit cells cannot be split any further, so they also
+            // stack children in the same way as max depth
+            None if self.children.len() >= self.max_children
+                && self.key.level < self.max_depth
+                && self.key.level < self.order =>
+            {
+                self.subdivide();
+
+                let mut children = std::mem::take(&mut self.children);
+                children.push(datum);
+
+                for child in children {
+                    self.insert(child)?;
+                }
+            }
+            None => self.children.push(datum),
+        }
+
+        Ok(())
+    }
+
+    /// Return the leaf that holds, or would hold, the passed offset.
+    pub(crate) fn leaf(&self, offset: (u32, u32)) -> &Self {
+        match &self.nodes {
+            Some(nodes) => nodes[self.find_sub_node(offset) as usize].leaf(offset),
+            None => self,
+        }
+    }
+
+    /// Return the node with the passed key, if it exists.
+    pub(crate) fn get(&self, key: MortonKey) -> Option<&Self> {
+        if key.level == self.key.level {
+            return (key == self.key).then_some(self);
+        }
+        if key.level < self.key.level {
+            return None;
+        }
+
+        // Pull the pair of bits that picks the next sub-node towards the key
+        let shift = 2 * (key.level - self.key.level - 1);
+        let sn = sub_node(key.code >> shift);
+        self.nodes.as_ref()?[sn as usize].get(key)
+    }
+
+    /// Iterate over all data in this node and its descendants in preorder.
+    pub(crate) fn descendants(&self) -> IntDatumIter<'_, D, T> {
+        IntDatumIter {
+            stack: vec![],
+            children: self.children.iter(),
+            nodes: self.nodes.as_ref().map(|n| n.iter()),
+        }
+    }
+
+    // Custom display mirroring the Node trait's display
+    fn display(&self, f: &mut Formatter) -> std::fmt::Result
+    where
+        T: Display,
+    {
+        let indent = " ".repeat(self.key.level as usize * 4);
+        let count = self.children.len();
+        let children = if count == 0 {
+            "".to_owned()
+        } else if count == 1 {
+            " 1 child".to_owned()
+        } else {
+            format!(" {count} children")
+        };
+
+        match self.bounds() {
+            Some(b) => writeln!(f, "{indent}({}, {}):{children}", b.min().x, b.min().y)?,
+            None => writeln!(f, "{indent}?:{children}")?,
+        }
+
+        if let Some(nodes) = &self.nodes {
+            for node in &**nodes {
+                node.display(f)?
+            }
+        };
+        write!(f, "")
+    }
+}
+
+impl<'a, D, T> Branch<'a, D> for &'a IntNode<D, T>
+where
+    D: AsPoint<T>,
+    T: QtInt,
+{
+    fn data(self) -> impl Iterator<Item = &'a D> {
+        self.children.iter()
+    }
+
+    fn branches(self) -> impl Iterator<Item = Self> {
+        self.nodes.iter().flat_map(|nodes| nodes.iter())
+    }
+}
+
+impl<D, T> Display for IntNode<D, T>
+where
+    D: AsPoint<T>,
+    T: QtInt + Display,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.display(f)
+    }
+}
+
+/// Preorder iterator over the data in an [`IntQuadTree`].
+pub struct IntDatumIter<'a, D, T>
+where
+    D: AsPoint<T>,
+    T: QtInt,
+{
+    stack: Vec<Iter<'a, IntNode<D, T>>>,
+    children: Iter<'a, D>,
+    nodes: Option<Iter<'a, IntNode<D, T>>>,
+}
+
+impl<'a, D, T> Iterator for IntDatumIter<'a, D, T>
+where
+    D: AsPoint<T>,
+    T: QtInt,
+{
+    type Item = &'a D;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(child) = self.children.next() {
+                return Some(child);
+            }
+
+            // Move on to the next node at this level, or pop back up a level
+            // once all the sub-nodes have been visited
+            match self.nodes.as_mut().and_then(|n| n.next()) {
+                Some(node) => {
+                    self.children = node.children.iter();
+                    if let Some(sub_nodes) = &node.nodes {
+                        let siblings = self.nodes.replace(sub_nodes.iter());
+                        self.stack.extend(siblings);
+                    }
+                }
+                None => self.nodes = Some(self.stack.pop()?),
+            }
+        }
+    }
+}
+
+/// Offset of a coordinate from the origin of a square of side `2^order`, or
+/// `None` if it falls outside the square.
+pub(crate) fn offset<T>(origin: Coord<T>, order: u8, c: Coord<T>) -> Option<(u32, u32)>
+where
+    T: QtInt,
+{
+    let side = 1i64 << order;
+    let dx = c.x.to_i64()? - origin.x.to_i64()?;
+    let dy = c.y.to_i64()? - origin.y.to_i64()?;
+
+    if (0..side).contains(&dx) && (0..side).contains(&dy) {
+        Some((dx as u32, dy as u32))
+    } else {
+        None
+    }
+}
+
+/// Inclusive bounds in tree coordinates of the node with the passed key.
+pub(crate) fn key_bounds<T>(origin: Coord<T>, order: u8, key: MortonKey) -> Option<Rect<T>>
+where
+    T: QtInt,
+{
+    if key.level > order {
+        return None;
+    }
+
+    let shift = order - key.level;
+    let (cx, cy) = key.cell();
+    let x1 = origin.x.to_i64()? + ((cx as i64) << shift);
+    let y1 = origin.y.to_i64()? + ((cy as i64) << shift);
+    let side = 1i64 << shift;
+
+    let min = coord! {x: T::from(x1)?, y: T::from(y1)?};
+    let max = coord! {x: T::from(x1 + side - 1)?, y: T::from(y1 + side - 1)?};
+    Some(Rect::new(min, max))
+}
+
+/// Convert an integer coordinate to a float coordinate.
+pub(crate) fn to_float<T, F>(c: Coord<T>) -> Option<Coord<F>>
+where
+    T: QtInt,
+    F: QtFloat,
+{
+    Some(coord! {x: <F as NumCast>::from(c.x)?, y: <F as NumCast>::from(c.y)?})
+}
+
+/// Convert an integer point to a float point.
+pub(crate) fn to_float_point<T, F>(p: Point<T>) -> Option<Point<F>>
+where
+    T: QtInt,
+    F: QtFloat,
+{
+    to_float(p.0).map(Point::from)
+}
@@ -1,8 +1,15 @@
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::hash::Hash;
+
+use crate::node::{Branch, Queued};
 use crate::*;
 
-/// Private, general, knn function implementation that takes an explcit node
+/// Private, general, knn function implementation that takes the tree's node
+/// arena, searching from its root.
 /// This gets around forcing Node to be object safe and doing priv-in-pub to
-/// get access to the root node, as root is just passed here.
+/// get access to the nodes, as the arena is just passed here.
 /// QT implementations can simply delegate to this function.
 /// Note that unlike find, knn will not return Err for empty trees as it is not
 /// necessary (find would return an Err anyway).
@@ -10,7 +17,7 @@ use crate::*;
 /// This could be implemented in terms of the `sorted` function with
 /// `take_while`, but here we have more aggressive error semantics.
 pub(crate) fn knn<'a, D, N, T>(
-    root: &'a N,
+    arena: &'a Arena<N>,
     cmp: GeomCalc<'_, T>,
     k: usize,
     r: T,
@@ -19,76 +26,283 @@ where
     N: Node<D, T>,
     D: AsGeom<T>,
     T: QtFloat,
+{
+    knn_by(
+        NodeRef::new(arena),
+        |node| cmp.dist_bbox(node.bounds()),
+        |child| cmp.dist_geom(&child.as_geom()),
+        k,
+        r,
+    )
+}
+
+/// The knn algorithm proper, generic over the node layout and over how
+/// distances are measured. `dist_node` must return a lower bound on the
+/// distance to any datum beneath the node for the results to be correct.
+///
+/// Node coordinates and distances are independent here, so trees with
+/// non-float coordinates can still be searched with a float distance.
+pub(crate) fn knn_by<'a, B, D, F>(
+    root: B,
+    dist_node: impl Fn(B) -> Result<F, Error>,
+    dist_datum: impl Fn(&D) -> Result<F, Error>,
+    k: usize,
+    r: F,
+) -> Result<Vec<(&'a D, F)>, Error>
+where
+    B: Branch<'a, D>,
+    D: 'a,
+    F: QtFloat,
+{
+    // A datum referenced from several nodes is only returned once
+    knn_unique_by(root, dist_node, dist_datum, |d| d as *const D, k, r)
+}
+
+/// As [`knn_by`], but only the closest datum for each distinct `key` is
+/// returned, so several data can stand in for one result. For example,
+/// segments keyed by their parent linestring.
+pub(crate) fn knn_unique_by<'a, B, D, F, K>(
+    root: B,
+    dist_node: impl Fn(B) -> Result<F, Error>,
+    dist_datum: impl Fn(&D) -> Result<F, Error>,
+    key: impl Fn(&'a D) -> K,
+    k: usize,
+    r: F,
+) -> Result<Vec<(&'a D, F)>, Error>
+where
+    B: Branch<'a, D>,
+    D: 'a,
+    F: QtFloat,
+    K: Eq + Hash,
 {
     // Error early on invalid inputs
-    let root_d = cmp.dist_bbox(root.bounds())?;
-    if root_d != T::zero() {
+    let root_d = dist_node(root)?;
+    if root_d != F::zero() {
         return Err(Error::OutOfBounds);
     }
 
-    // We work on a tuple that contains the distance plus an enum
-    // containing either a child or a node, and start by seeding the root
-    let mut work_stack = vec![(NodeType::Node(root), root_d)];
+    // We work on a priority queue of nodes and children keyed on distance,
+    // and start by seeding the root
+    let mut queue = BinaryHeap::from([Queued::new(NodeType::Node(root), root_d)]);
     let mut results = vec![];
+    let mut seen = HashSet::new();
+
+    // Traverse the queue in distance sorted order, ending if it empties
+    while let Some(Queued { item, dist: d }) = queue.pop() {
+        // If the distance is > r, we are done completely
+        if d > r {
+            return Ok(results);
+        }
 
-    // Traverse the work stack in distance sorted order
-    loop {
-        // 1. Sort the stack in distance-descending order
-        work_stack.sort_unstable_by(|(_, d1), (_, d2)| {
-            d2.partial_cmp(d1)
-                .expect("Unreachable, NaN distances already removed.")
-        });
-
-        // 2. Process any Children at the top of the stack
-        //    Done in an inner loop to prevent re-sorting if multiple
-        //    children are on top
-        while let Some(&(NodeType::Child(child), d)) = work_stack.last() {
-            // If the distance is > r, we are done completely
-            if d > r {
-                return Ok(results);
+        match item {
+            NodeType::Child(child) => {
+                // Data come off the queue in distance order, so the first
+                // with each key is the closest
+                if !seen.insert(key(child)) {
+                    continue;
+                }
+
+                // Push the result, returning if we've reached k results
+                results.push((child, d));
+                if results.len() >= k {
+                    return Ok(results);
+                }
             }
+            // Queue the node's children and sub-nodes
+            NodeType::Node(node) => {
+                for child in node.data() {
+                    let d = dist_datum(child)?;
+
+                    if !d.is_finite() {
+                        return Err(Error::InvalidDistance);
+                    }
+
+                    queue.push(Queued::new(NodeType::Child(child), d));
+                }
+
+                for sub_node in node.branches() {
+                    let d = dist_node(sub_node)?;
 
-            // Pop the stack once we know its a child
-            work_stack.pop();
+                    if !d.is_finite() {
+                        return Err(Error::InvalidDistance);
+                    }
 
-            // Push the result, returning if we've reached k results
-            results.push((child, d));
-            if results.len() >= k {
-                return Ok(results);
+                    queue.push(Queued::new(NodeType::Node(sub_node), d));
+                }
             }
         }
+    }
 
-        // 3. Push sub Nodes and Children onto the stack if inside the radius
-        //    We know that the top of the stack is either None or Some(Node(...))
-        if let Some((NodeType::Node(node), d)) = work_stack.pop() {
-            if d > r {
-                return Ok(results);
+    Ok(results)
+}
+
+/// Private, general, approximate knn function implementation that takes the
+/// tree's node arena. See [`knn_approx_unique_by`].
+pub(crate) fn knn_approx<'a, D, N, T>(
+    arena: &'a Arena<N>,
+    cmp: GeomCalc<'_, T>,
+    k: usize,
+    epsilon: T,
+) -> Result<Vec<(&'a D, T)>, Error>
+where
+    N: Node<D, T>,
+    D: AsGeom<T>,
+    T: QtFloat,
+{
+    knn_approx_by(
+        NodeRef::new(arena),
+        |node| cmp.dist_bbox(node.bounds()),
+        |child| cmp.dist_geom(&child.as_geom()),
+        k,
+        epsilon,
+    )
+}
+
+/// As [`knn_by`], but approximate. See [`knn_approx_unique_by`].
+pub(crate) fn knn_approx_by<'a, B, D, F>(
+    root: B,
+    dist_node: impl Fn(B) -> Result<F, Error>,
+    dist_datum: impl Fn(&D) -> Result<F, Error>,
+    k: usize,
+    epsilon: F,
+) -> Result<Vec<(&'a D, F)>, Error>
+where
+    B: Branch<'a, D>,
+    D: 'a,
+    F: QtFloat,
+{
+    knn_approx_unique_by(root, dist_node, dist_datum, |d| d as *const D, k, epsilon)
+}
+
+/// The approximate knn algorithm proper, with the same key semantics as
+/// [`knn_unique_by`].
+///
+/// Nodes are visited best-first, but data are measured as soon as their node
+/// is, and held as the k best candidates so far. Once k candidates are held,
+/// any node further than `kth / (1 + epsilon)` is pruned, so every returned
+/// distance is within a factor of `1 + epsilon` of the true one at its rank.
+/// An `epsilon` of zero gives an exact result, and a negative or NaN one is
+/// an [`Error::InvalidDistance`].
+pub(crate) fn knn_approx_unique_by<'a, B, D, F, K>(
+    root: B,
+    dist_node: impl Fn(B) -> Result<F, Error>,
+    dist_datum: impl Fn(&D) -> Result<F, Error>,
+    key: impl Fn(&'a D) -> K,
+    k: usize,
+    epsilon: F,
+) -> Result<Vec<(&'a D, F)>, Error>
+where
+    B: Branch<'a, D>,
+    D: 'a,
+    F: QtFloat,
+    K: Eq + Hash,
+{
+    // Error early on invalid inputs
+    if epsilon.is_nan() || epsilon < F::zero() {
+        return Err(Error::InvalidDistance);
+    }
+    let root_d = dist_node(root)?;
+    if root_d != F::zero() {
+        return Err(Error::OutOfBounds);
+    }
+    if k == 0 {
+        return Ok(vec![]);
+    }
+
+    let scale = F::one() + epsilon;
+    let mut queue = BinaryHeap::from([Queued::new(NodeType::<B, D>::Node(root), root_d)]);
+
+    // The best candidates so far, farthest on top, and the one held for each
+    // key. Candidates replaced by a closer one with the same key stay in the
+    // heap until they surface or outnumber those held
+    let mut best: BinaryHeap<Reverse<Queued<B, D, F>>> = BinaryHeap::new();
+    let mut held: HashMap<K, (&'a D, F)> = HashMap::new();
+
+    let child_of = |q: &Queued<'a, B, D, F>| match q.item {
+        NodeType::Child(child) => child,
+        NodeType::Node(_) => unreachable!("Only data are held."),
+    };
+    let is_held = |held: &HashMap<K, (&'a D, F)>, q: &Queued<'a, B, D, F>| {
+        let child = child_of(q);
+        held.get(&key(child))
+            .is_some_and(|(h, _)| std::ptr::eq(*h, child))
+    };
+    // With k candidates held, the top of the heap is the k-th
+    let kth = |best: &BinaryHeap<Reverse<Queued<'a, B, D, F>>>, held: &HashMap<K, (&'a D, F)>| {
+        best.peek()
+            .filter(|_| held.len() == k)
+            .map(|Reverse(q)| q.dist)
+    };
+
+    while let Some(Queued { item, dist }) = queue.pop() {
+        let NodeType::Node(node) = item else {
+            unreachable!("Only nodes are queued.");
+        };
+
+        // Nodes come off the queue in distance order, so we are done
+        if kth(&best, &held).is_some_and(|kth| dist > kth / scale) {
+            break;
+        }
+
+        for child in node.data() {
+            let d = dist_datum(child)?;
+
+            if !d.is_finite() {
+                return Err(Error::InvalidDistance);
             }
 
-            for child in node.children() {
-                let d = cmp.dist_geom(&child.as_geom())?;
+            // Once k are held, only data closer than the k-th can improve them
+            if kth(&best, &held).is_some_and(|kth| d >= kth) {
+                continue;
+            }
 
-                if !d.is_finite() {
-                    return Err(Error::InvalidDistance);
+            // Keep only the closest candidate for each key
+            match held.entry(key(child)) {
+                Entry::Occupied(e) if e.get().1 <= d => continue,
+                Entry::Occupied(mut e) => {
+                    e.insert((child, d));
                 }
+                Entry::Vacant(e) => {
+                    e.insert((child, d));
+                }
+            }
+            best.push(Reverse(Queued::new(NodeType::Child(child), d)));
 
-                work_stack.push((NodeType::Child(child), d))
+            // Evict the farthest candidate if over k, then drop replaced ones
+            // off the top so that it is held again
+            if held.len() > k {
+                while let Some(Reverse(q)) = best.pop() {
+                    if is_held(&held, &q) {
+                        held.remove(&key(child_of(&q)));
+                        break;
+                    }
+                }
+            }
+            while best.peek().is_some_and(|Reverse(q)| !is_held(&held, q)) {
+                best.pop();
             }
+            if best.len() > 2 * held.len() {
+                best.retain(|Reverse(q)| is_held(&held, q));
+            }
+        }
 
-            if let Some(nodes) = node.nodes() {
-                for sub_node in nodes.iter() {
-                    let d = cmp.dist_bbox(sub_node.bounds())?;
+        for sub_node in node.branches() {
+            let d = dist_node(sub_node)?;
 
-                    if !d.is_finite() {
-                        return Err(Error::InvalidDistance);
-                    }
+            if !d.is_finite() {
+                return Err(Error::InvalidDistance);
+            }
 
-                    work_stack.push((NodeType::Node(sub_node), d));
-                }
+            if !kth(&best, &held).is_some_and(|kth| d > kth / scale) {
+                queue.push(Queued::new(NodeType::Node(sub_node), d));
             }
-        } else {
-            // If we don't match here, then we are done with the loop
-            return Ok(results);
         }
     }
+
+    Ok(best
+        .into_sorted_vec()
+        .into_iter()
+        .filter(|Reverse(q)| is_held(&held, q))
+        .map(|Reverse(q)| (child_of(&q), q.dist))
+        .collect())
 }
@@ -0,0 +1,417 @@
+mod node;
+
+use std::slice::Iter;
+
+use super::knn::{knn_approx_by, knn_by};
+use super::morton::sub_node;
+use super::sorted::{sorted_by, sorted_desc_by};
+use crate::*;
+use geo::{GeoNum, Rect};
+use node::*;
+
+/// Deepest level a [`LinearQuadTree`] can reach, as full depth keys are
+/// packed into a `u64` at two bits per level.
+pub const LINEAR_MAX_DEPTH: u8 = 31;
+
+/// A pointer-free [`QuadTree`] implementation for point-like geometries.
+///
+/// Rather than allocating nodes, the tree stores its data in a single vector
+/// kept sorted by the full depth Z-order (Morton) code of each datum's
+/// position. Every node of the equivalent [`PointQuadTree`] then corresponds
+/// to a contiguous run of that vector, which is found by binary search on
+/// the codes. This trades a slower `insert`, which shifts the vector, for a
+/// compact, cache friendly layout that is quick to bulk load through
+/// [`LinearQuadTree::extend`].
+///
+/// Positions are keyed using the same midpoint splits as [`PointQuadTree`],
+/// and a node is treated as subdivided exactly when it holds more than
+/// `max_children` data above `max_depth`, so [`QuadTree::retrieve`] and the
+/// [`QuadTreeSearch`] methods return the same results as a [`PointQuadTree`]
+/// with the same configuration. `max_depth` is capped at
+/// [`LINEAR_MAX_DEPTH`].
+///
+/// Iteration runs in Z-order, which visits the bottom left sub-node before
+/// the bottom right, rather than in the [`SubNode`] order used by the
+/// pointer-based trees.
+#[derive(Debug)]
+pub struct LinearQuadTree<D, T>
+where
+    D: AsPoint<T>,
+    T: GeoNum,
+{
+    bounds: Rect<T>,
+    max_depth: u8,
+    max_children: usize,
+
+    // Full depth keys, sorted, in step with the data and their insertion
+    // sequence numbers
+    keys: Vec<u64>,
+    data: Vec<D>,
+    seqs: Vec<usize>,
+
+    calc_method: CalcMethod<T>,
+}
+
+impl<D, T> LinearQuadTree<D, T>
+where
+    D: AsPoint<T>,
+    T: GeoNum,
+{
+    /// Create a new Linear QuadTree.
+    pub fn new(
+        bounds: Rect<T>,
+        calc_method: CalcMethod<T>,
+        max_depth: u8,
+        max_children: usize,
+    ) -> Self {
+        LinearQuadTree::private_new(bounds, calc_method, Some(max_depth), Some(max_children))
+    }
+
+    /// Create a new Linear QuadTree using default values for max_depth and
+    /// max_children.
+    pub fn from_bounds(bounds: Rect<T>, calc_method: CalcMethod<T>) -> Self {
+        LinearQuadTree::private_new(bounds, calc_method, None, None)
+    }
+
+    // Private constructor
+    fn private_new(
+        bounds: Rect<T>,
+        calc_method: CalcMethod<T>,
+        max_depth: Option<u8>,
+        max_children: Option<usize>,
+    ) -> Self {
+        let max_depth = max_depth.unwrap_or(DEFAULT_MAX_DEPTH).min(LINEAR_MAX_DEPTH);
+        let max_children = max_children.unwrap_or(DEFAULT_MAX_CHILDREN);
+
+        Self {
+            bounds,
+            max_depth,
+            max_children,
+            keys: Vec::new(),
+            data: Vec::new(),
+            seqs: Vec::new(),
+            calc_method,
+        }
+    }
+
+    /// Insert many data at once, sorting the combined data a single time
+    /// rather than shifting it for each datum. Either all data are inserted,
+    /// or none are and [`Error::OutOfBounds`] is returned.
+    ///
+    /// Data with equal keys keep their insertion order, as with
+    /// [`QuadTree::insert`].
+    pub fn extend<I>(&mut self, data: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = D>,
+    {
+        let size = self.data.len();
+        let mut keyed = data
+            .into_iter()
+            .enumerate()
+            .map(|(i, datum)| {
+                let key = self.datum_key(&datum).ok_or(Error::OutOfBounds)?;
+                Ok((key, (datum, size + i)))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        keyed.splice(
+            0..0,
+            std::mem::take(&mut self.keys).into_iter().zip(
+                std::mem::take(&mut self.data)
+                    .into_iter()
+                    .zip(std::mem::take(&mut self.seqs)),
+            ),
+        );
+        // Stable, so earlier data stay ahead of later data with equal keys
+        keyed.sort_by_key(|(key, _)| *key);
+        (self.keys, (self.data, self.seqs)) = keyed.into_iter().unzip();
+
+        Ok(())
+    }
+
+    /// Return the key of the leaf that holds, or would hold, the passed
+    /// datum, or `None` if it is out of bounds.
+    pub fn key(&self, datum: &D) -> Option<MortonKey> {
+        pt_in_rect(&self.bounds, &datum.as_point())
+            .then(|| LinearNode::root(self).leaf(datum).key())
+    }
+
+    /// Return the data in the node with the passed key, including data in
+    /// all of its descendants, or `None` if there is no such node.
+    pub fn get(&self, key: MortonKey) -> Option<&[D]> {
+        let mut node = LinearNode::root(self);
+        for level in (0..key.level).rev() {
+            node = node.sub_node(sub_node(key.code >> (2 * level)))?;
+        }
+        Some(node.contents())
+    }
+
+    /// Return the bounds of the QuadTree.
+    pub fn bounds(&self) -> &Rect<T> {
+        &self.bounds
+    }
+
+    // Full depth key for a datum, or None if out of bounds
+    fn datum_key(&self, datum: &D) -> Option<u64> {
+        let position = datum.as_point();
+        pt_in_rect(&self.bounds, &position)
+            .then(|| position_key(&self.bounds, position.0, self.max_depth))
+    }
+}
+
+impl<D, T> QuadTree<D, T> for LinearQuadTree<D, T>
+where
+    D: AsPoint<T>,
+    T: GeoNum,
+{
+    fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    fn insert(&mut self, datum: D) -> Result<(), Error> {
+        let key = self.datum_key(&datum).ok_or(Error::OutOfBounds)?;
+
+        // Insert after any equal keys to keep insertion order within a leaf
+        let i = self.keys.partition_point(|k| *k <= key);
+        self.seqs.insert(i, self.data.len());
+        self.keys.insert(i, key);
+        self.data.insert(i, datum);
+        Ok(())
+    }
+
+    fn retrieve<'a>(&'a self, datum: &D) -> impl Iterator<Item = &'a D> + use<'a, D, T>
+    where
+        D: 'a,
+    {
+        // Cannot use Rect::contains here, see notes on pt_in_rect for why
+        if pt_in_rect(&self.bounds, &datum.as_point()) {
+            LinearNode::root(self).leaf(datum).children().iter()
+        } else {
+            [].iter()
+        }
+    }
+}
+
+impl<D, T> QuadTreeSearch<D, T> for LinearQuadTree<D, T>
+where
+    D: AsGeom<T> + AsPoint<T>,
+    T: QtFloat,
+{
+    fn calc_method(&self) -> CalcMethod<T> {
+        self.calc_method.clone()
+    }
+
+    fn insertion_index(&self, datum: &D) -> Option<usize> {
+        index_in(&self.data, datum).map(|i| self.seqs[i])
+    }
+
+    fn find_r<X>(&self, cmp: &X, r: T) -> Result<(&D, T), Error>
+    where
+        X: AsGeom<T>,
+    {
+        let cmp = cmp.with_calc(self.calc_method()).over_points();
+
+        // Error early if invalid
+        if cmp.dist_bbox(&self.bounds)? != T::zero() {
+            return Err(Error::OutOfBounds);
+        }
+        if self.data.is_empty() {
+            return Err(Error::Empty);
+        }
+
+        let mut stack = vec![LinearNode::root(self)];
+        let mut min_dist = r;
+        let mut min_item = Err(Error::NoneInRadius);
+
+        while let Some(node) = stack.pop() {
+            let bounds_dist = cmp.dist_bbox(node.bounds())?;
+            if bounds_dist >= min_dist {
+                continue;
+            }
+
+            for child in node.children() {
+                let child_dist = cmp.dist_geom(&child.as_geom())?;
+                // See notes in point about <= usage
+                if child_dist <= min_dist {
+                    min_dist = child_dist;
+                    min_item = Ok(child);
+                }
+            }
+
+            // Push nodes onto the stack in reverse order
+            if let Some(sub_nodes) = node.sub_nodes() {
+                stack.extend(sub_nodes.into_iter().rev());
+            }
+        }
+
+        min_item.map(|item| (item, min_dist))
+    }
+
+    fn knn_r<X>(&self, cmp: &X, k: usize, r: T) -> Result<Vec<(&D, T)>, Error>
+    where
+        X: AsGeom<T>,
+    {
+        let cmp = cmp.with_calc(self.calc_method()).over_points();
+
+        knn_by(
+            LinearNode::root(self),
+            |node| cmp.dist_bbox(node.bounds()),
+            |child| cmp.dist_geom(&child.as_geom()),
+            k,
+            r,
+        )
+    }
+
+    fn knn_approx<X>(&self, cmp: &X, k: usize, epsilon: T) -> Result<Vec<(&D, T)>, Error>
+    where
+        X: AsGeom<T>,
+    {
+        let cmp = cmp.with_calc(self.calc_method()).over_points();
+
+        knn_approx_by(
+            LinearNode::root(self),
+            |node| cmp.dist_bbox(node.bounds()),
+            |child| cmp.dist_geom(&child.as_geom()),
+            k,
+            epsilon,
+        )
+    }
+
+    fn sorted<'a, X>(&'a self, cmp: &'a X) -> impl Iterator<Item = (&'a D, T)> + 'a
+    where
+        D: 'a,
+        X: AsGeom<T> + 'a,
+    {
+        let cmp = cmp.with_calc(self.calc_method()).over_points();
+
+        let node_cmp = cmp.clone();
+        sorted_by(
+            LinearNode::root(self),
+            move |node| node_cmp.dist_bbox(node.bounds()),
+            move |child| cmp.dist_geom(&child.as_geom()),
+        )
+    }
+
+    fn sorted_desc<'a, X>(&'a self, cmp: &'a X) -> impl Iterator<Item = (&'a D, T)> + 'a
+    where
+        D: 'a,
+        X: AsGeom<T> + 'a,
+    {
+        let cmp = cmp.with_calc(self.calc_method()).over_points();
+
+        let (min_cmp, max_cmp) = (cmp.clone(), cmp.clone());
+        sorted_desc_by(
+            LinearNode::root(self),
+            move |node| min_cmp.dist_bbox(node.bounds()),
+            move |node| max_cmp.max_dist_bbox(node.bounds()),
+            move |child| cmp.dist_geom(&child.as_geom()),
+        )
+    }
+}
+
+impl<'a, D, T> IntoIterator for &'a LinearQuadTree<D, T>
+where
+    D: AsPoint<T>,
+    T: GeoNum,
+{
+    type Item = &'a D;
+    type IntoIter = Iter<'a, D>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.iter()
+    }
+}
+
+impl<D, T> std::fmt::Display for LinearQuadTree<D, T>
+where
+    D: AsPoint<T>,
+    T: GeoNum + std::fmt::Display,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Linear Quadtree Root:")?;
+        write!(f, "{}", LinearNode::root(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::{Point, coord};
+
+    use super::*;
+
+    fn unit_square() -> Rect {
+        Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 1.0, y: 1.0})
+    }
+
+    #[test]
+    fn nodes_split_only_past_max_children() {
+        let mut qt = LinearQuadTree::new(unit_square(), CalcMethod::Euclidean, 4, 2);
+
+        let p1 = Point::new(0.1, 0.1);
+        let p2 = Point::new(0.2, 0.2);
+        let p3 = Point::new(0.8, 0.8);
+        qt.insert(p1).unwrap();
+        qt.insert(p3).unwrap();
+
+        // Two data fit in the root, so both are retrieved anywhere
+        assert_eq!(qt.key(&p1), Some(MortonKey::ROOT));
+        assert_eq!(qt.retrieve(&p3).count(), 2);
+
+        qt.insert(p2).unwrap();
+
+        // Third datum splits the root, sending p1 and p2 top left
+        let tl = MortonKey::ROOT.child(SubNode::TopLeft);
+        let br = MortonKey::ROOT.child(SubNode::BottomRight);
+        assert_eq!(qt.key(&p1), Some(tl));
+        assert_eq!(qt.key(&p3), Some(br));
+        assert_eq!(qt.get(tl), Some(&[p1, p2][..]));
+        assert_eq!(qt.get(br), Some(&[p3][..]));
+        assert_eq!(qt.get(MortonKey::ROOT).unwrap().len(), 3);
+        assert_eq!(qt.get(tl.child(SubNode::TopLeft)), None);
+        assert_eq!(qt.retrieve(&p2).collect::<Vec<_>>(), vec![&p1, &p2]);
+        assert_eq!(qt.insert(Point::new(2.0, 0.0)), Err(Error::OutOfBounds));
+    }
+
+    #[test]
+    fn stacks_in_insertion_order_at_max_depth() {
+        let mut qt = LinearQuadTree::new(unit_square(), CalcMethod::Euclidean, 2, 1);
+
+        let pts = [(0.1, 0.1), (0.11, 0.11), (0.12, 0.12)].map(|(x, y)| Point::new(x, y));
+        for pt in pts {
+            qt.insert(pt).unwrap();
+        }
+
+        let leaf = qt.key(&pts[0]).unwrap();
+        assert_eq!(leaf.level, 2);
+        assert_eq!(qt.get(leaf), Some(&pts[..]));
+    }
+
+    #[test]
+    fn extend_matches_repeated_insert() {
+        let pts = [
+            (0.9, 0.1),
+            (0.1, 0.1),
+            (0.5, 0.5),
+            (0.1, 0.9),
+            (0.1, 0.1),
+            (0.3, 0.7),
+        ]
+        .map(|(x, y)| Point::new(x, y));
+
+        let mut q1 = LinearQuadTree::new(unit_square(), CalcMethod::Euclidean, 3, 1);
+        let mut q2 = LinearQuadTree::new(unit_square(), CalcMethod::Euclidean, 3, 1);
+        for pt in pts {
+            q1.insert(pt).unwrap();
+        }
+        q2.extend(pts[..2].iter().copied()).unwrap();
+        q2.extend(pts[2..].iter().copied()).unwrap();
+
+        assert_eq!(q1.keys, q2.keys);
+        assert_eq!(q1.data, q2.data);
+        assert_eq!(
+            q2.extend([Point::new(0.5, 0.5), Point::new(-1.0, 0.5)]),
+            Err(Error::OutOfBounds)
+        );
+        assert_eq!(q2.size(), pts.len());
+    }
+}
@@ -0,0 +1,198 @@
+use std::fmt::{Display, Formatter};
+
+use geo::{GeoNum, Rect};
+
+use super::LinearQuadTree;
+use crate::node::{Branch, sub_node_at, sub_node_bounds};
+use crate::quadtrees::morton::quadrant;
+use crate::*;
+
+/// Implicit node of a [`LinearQuadTree`].
+///
+/// Nothing is allocated per node. A node is a cheap handle holding its key,
+/// its bounds and the range of the tree's key-sorted data that falls inside
+/// it. A node is a leaf when that range holds no more than `max_children`
+/// data, or it sits at `max_depth`, which is exactly when a
+/// [`PointQuadTree`] would have stopped subdividing.
+pub(crate) struct LinearNode<'a, D, T>
+where
+    D: AsPoint<T>,
+    T: GeoNum,
+{
+    tree: &'a LinearQuadTree<D, T>,
+    key: MortonKey,
+    bounds: Rect<T>,
+    start: usize,
+    end: usize,
+}
+
+impl<D, T> Clone for LinearNode<'_, D, T>
+where
+    D: AsPoint<T>,
+    T: GeoNum,
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<D, T> Copy for LinearNode<'_, D, T>
+where
+    D: AsPoint<T>,
+    T: GeoNum,
+{
+}
+
+impl<'a, D, T> LinearNode<'a, D, T>
+where
+    D: AsPoint<T>,
+    T: GeoNum,
+{
+    /// Handle onto the root node, covering all the data.
+    pub(crate) fn root(tree: &'a LinearQuadTree<D, T>) -> Self {
+        Self {
+            tree,
+            key: MortonKey::ROOT,
+            bounds: tree.bounds,
+            start: 0,
+            end: tree.data.len(),
+        }
+    }
+
+    // Getters
+    pub(crate) fn key(&self) -> MortonKey {
+        self.key
+    }
+
+    pub(crate) fn bounds(&self) -> &Rect<T> {
+        &self.bounds
+    }
+
+    /// The data falling inside this node, whether or not it is a leaf.
+    pub(crate) fn contents(&self) -> &'a [D] {
+        &self.tree.data[self.start..self.end]
+    }
+
+    pub(crate) fn is_leaf(&self) -> bool {
+        self.key.level >= self.tree.max_depth || self.end - self.start <= self.tree.max_children
+    }
+
+    /// The data held directly by this node, which is empty unless it is a
+    /// leaf.
+    pub(crate) fn children(&self) -> &'a [D] {
+        if self.is_leaf() { self.contents() } else { &[] }
+    }
+
+    /// Return the requested sub-node, or `None` for leaves. The key range of
+    /// the sub-node is found by binary search within this node's range.
+    pub(crate) fn sub_node(&self, sub_node: SubNode) -> Option<Self> {
+        if self.is_leaf() {
+            return None;
+        }
+
+        let key = self.key.child(sub_node);
+        let shift = 2 * (self.tree.max_depth - key.level) as u32;
+        let lo = key.code << shift;
+        let hi = (key.code + 1) << shift;
+
+        let keys = &self.tree.keys[self.start..self.end];
+        let start = self.start + keys.partition_point(|k| *k < lo);
+        let end = self.start + keys.partition_point(|k| *k < hi);
+
+        Some(Self {
+            tree: self.tree,
+            key,
+            bounds: sub_node_bounds(&self.bounds)[sub_node as usize],
+            start,
+            end,
+        })
+    }
+
+    /// Return all four sub-nodes in [`SubNode`] order, or `None` for leaves.
+    pub(crate) fn sub_nodes(&self) -> Option<[Self; 4]> {
+        Some([
+            self.sub_node(SubNode::TopLeft)?,
+            self.sub_node(SubNode::TopRight)?,
+            self.sub_node(SubNode::BottomRight)?,
+            self.sub_node(SubNode::BottomLeft)?,
+        ])
+    }
+
+    /// Return the leaf that holds, or would hold, the passed datum.
+    pub(crate) fn leaf(self, datum: &D) -> Self {
+        let mut node = self;
+        while let Some(sub_node) = node.sub_node(sub_node_at(&node.bounds, datum.as_point().0)) {
+            node = sub_node;
+        }
+        node
+    }
+
+    // Custom display mirroring the Node trait's display
+    fn display(&self, f: &mut Formatter) -> std::fmt::Result
+    where
+        T: Display,
+    {
+        let indent = " ".repeat(self.key.level as usize * 4);
+        let min = self.bounds.min();
+        let count = self.children().len();
+        let children = if count == 0 {
+            "".to_owned()
+        } else if count == 1 {
+            " 1 child".to_owned()
+        } else {
+            format!(" {count} children")
+        };
+
+        writeln!(f, "{indent}({:.2}, {:.2}):{children}", min.x, min.y)?;
+
+        if let Some(nodes) = self.sub_nodes() {
+            for node in nodes {
+                node.display(f)?
+            }
+        };
+        write!(f, "")
+    }
+}
+
+impl<'a, D, T> Branch<'a, D> for LinearNode<'a, D, T>
+where
+    D: AsPoint<T>,
+    T: GeoNum,
+{
+    fn data(self) -> impl Iterator<Item = &'a D> {
+        self.children().iter()
+    }
+
+    fn branches(self) -> impl Iterator<Item = Self> {
+        self.sub_nodes().into_iter().flatten()
+    }
+}
+
+impl<D, T> Display for LinearNode<'_, D, T>
+where
+    D: AsPoint<T>,
+    T: GeoNum + Display,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.display(f)
+    }
+}
+
+/// Compute the Z-order key of a position at full depth, descending through
+/// the same midpoint splits that [`PointQuadTree`] uses so both trees place
+/// data identically, including data sitting on a midline.
+pub(crate) fn position_key<T>(bounds: &Rect<T>, position: geo::Coord<T>, depth: u8) -> u64
+where
+    T: GeoNum,
+{
+    let mut bounds = *bounds;
+    let mut code = 0;
+
+    for _ in 0..depth {
+        let sn = sub_node_at(&bounds, position);
+        code = (code << 2) | quadrant(sn);
+        bounds = sub_node_bounds(&bounds)[sn as usize];
+    }
+
+    code
+}
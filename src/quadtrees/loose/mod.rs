@@ -0,0 +1,389 @@
+mod node;
+
+use geo::{BoundingRect, GeoNum, Rect};
+
+use super::{
+    knn::{knn_approx_by, knn_by},
+    sorted::{sorted_by, sorted_desc_by},
+    stats::stats,
+};
+use crate::geom::spherical::math::antimeridian_bounds;
+use crate::*;
+use node::*;
+
+/// A loose [`QuadTree`] implementation for bounded items.
+///
+/// In a [`BoundsQuadTree`], anything that straddles a midline gets stuck in
+/// the parent node, so small data near the centre lines of the root end up in
+/// the root and are returned by every `retrieve`. Here each node instead
+/// accepts any datum whose centre falls inside it and whose extent fits
+/// within the node's loose bounds, which are the node's bounds enlarged about
+/// their centre by the `looseness` factor. With the default looseness of two,
+/// a datum only gets stuck if it is larger than the sub-node its centre falls
+/// in, so the number of stuck data at any node is bounded by their size rather
+/// than their position.
+///
+/// Searches prune against the loose bounds, which are guaranteed to contain
+/// every datum beneath a node, so [`QuadTreeSearch`] results match those of a
+/// [`BoundsQuadTree`] holding the same data.
+///
+/// With a geographic [`CalcMethod`], data crossing the antimeridian are not
+/// split as in a [`BoundsQuadTree`], but held at the root, so every search
+/// measures them. Trees with many such data are better served by a
+/// [`BoundsQuadTree`].
+#[derive(Debug)]
+pub struct LooseQuadTree<D, T>
+where
+    D: AsGeom<T>,
+    T: GeoNum,
+{
+    arena: Arena<LooseNode<D, T>>,
+    looseness: T,
+    size: usize,
+    calc_method: CalcMethod<T>,
+}
+
+/// Read-only cursor onto a node of a [`LooseQuadTree`], see [`NodeRef`].
+pub type LooseNodeRef<'a, D, T> = NodeRef<'a, LooseNode<D, T>, D, T>;
+
+impl<D, T> LooseQuadTree<D, T>
+where
+    D: AsGeom<T>,
+    T: GeoNum,
+{
+    /// Create a new Loose QuadTree with the passed looseness factor.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `looseness` is less than one.
+    pub fn new(
+        bounds: Rect<T>,
+        calc_method: CalcMethod<T>,
+        max_depth: u8,
+        max_children: usize,
+        looseness: T,
+    ) -> Self {
+        LooseQuadTree::private_new(
+            bounds,
+            calc_method,
+            Some(max_depth),
+            Some(max_children),
+            Some(looseness),
+        )
+    }
+
+    /// Create a new Loose QuadTree using default values for max_depth,
+    /// max_children, and a looseness of two.
+    pub fn from_bounds(bounds: Rect<T>, calc_method: CalcMethod<T>) -> Self {
+        LooseQuadTree::private_new(bounds, calc_method, None, None, None)
+    }
+
+    /// Return a read-only cursor onto the root node, from which the tree
+    /// structure can be walked. See [`NodeRef`]. Note that the cursor reports
+    /// the regular, not the loose, bounds of each node.
+    pub fn root(&self) -> LooseNodeRef<'_, D, T> {
+        NodeRef::new(&self.arena)
+    }
+
+    /// Summarise the structure of the QuadTree, reporting node counts,
+    /// depth, leaf occupancy and suggested configuration. See [`TreeStats`].
+    pub fn stats(&self) -> TreeStats {
+        stats(&self.arena, &[], 0)
+    }
+
+    /// Return the looseness factor applied to node bounds.
+    pub fn looseness(&self) -> T {
+        self.looseness
+    }
+
+    // Private constructor
+    fn private_new(
+        bounds: Rect<T>,
+        calc_method: CalcMethod<T>,
+        max_depth: Option<u8>,
+        max_children: Option<usize>,
+        looseness: Option<T>,
+    ) -> Self {
+        let max_depth = max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+        let max_children = max_children.unwrap_or(DEFAULT_MAX_CHILDREN);
+        let looseness = looseness.unwrap_or(T::one() + T::one());
+        assert!(looseness >= T::one(), "looseness must be at least one");
+
+        Self {
+            arena: Arena::new(LooseNode::with_looseness(
+                bounds,
+                Limits::root(max_depth, max_children),
+                looseness,
+            )),
+            looseness,
+            size: 0,
+            calc_method,
+        }
+    }
+
+    // Loose bounds for a node seen through a cursor
+    fn node_bounds(&self, node: LooseNodeRef<'_, D, T>) -> Rect<T> {
+        *node.node().loose_bounds()
+    }
+}
+
+impl<D, T> QuadTree<D, T> for LooseQuadTree<D, T>
+where
+    D: AsGeom<T>,
+    T: GeoNum,
+{
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    fn insert(&mut self, datum: D) -> Result<(), Error> {
+        let db = &datum
+            .as_geom()
+            .bounding_rect()
+            .ok_or(Error::CannotMakeBbox)?;
+
+        // Cannot use Rect::contains here, see notes on rect_in_rect for why
+        if !rect_in_rect(self.arena.root().bounds(), db) {
+            return Err(Error::OutOfBounds);
+        }
+
+        // The planar bounds of spherical data crossing the antimeridian span
+        // the wrong side of the world, so they are held at the root, which
+        // every search visits
+        let crosses =
+            self.calc_method.is_geographic() && antimeridian_bounds(&datum.as_geom()).is_some();
+        match crosses {
+            true => self.arena[0].stick(datum, self.size),
+            false => LooseNode::insert(&mut self.arena, 0, datum, self.size)?,
+        }
+
+        self.size += 1;
+        Ok(())
+    }
+
+    fn retrieve<'a>(&'a self, datum: &D) -> impl Iterator<Item = &'a D> + use<'a, D, T>
+    where
+        D: 'a,
+    {
+        // Squash errors and return an empty iterator if we can't get the bbox
+        match datum.as_geom().bounding_rect() {
+            // Cannot use Rect::contains here, see notes on rect_in_rect for why
+            Some(bbox) if rect_in_rect(self.arena.root().bounds(), &bbox) => {
+                self.arena.root().retrieve(&self.arena, datum)
+            }
+            _ => DatumIter::Empty,
+        }
+    }
+}
+
+impl<D, T> QuadTreeSearch<D, T> for LooseQuadTree<D, T>
+where
+    D: AsGeom<T>,
+    T: QtFloat,
+{
+    fn calc_method(&self) -> CalcMethod<T> {
+        self.calc_method.clone()
+    }
+
+    fn insertion_index(&self, datum: &D) -> Option<usize> {
+        insertion_seq(&self.arena, datum)
+    }
+
+    fn find_r<X>(&self, cmp: &X, r: T) -> Result<(&D, T), Error>
+    where
+        X: AsGeom<T>,
+    {
+        let cmp = cmp.with_calc(self.calc_method());
+
+        // Error early if invalid
+        if cmp.dist_bbox(self.arena.root().bounds())? != T::zero() {
+            return Err(Error::OutOfBounds);
+        }
+        if self.size == 0 {
+            return Err(Error::Empty);
+        }
+
+        let mut stack = vec![self.arena.root()];
+        let mut min_dist = r;
+        let mut min_item = Err(Error::NoneInRadius);
+
+        while let Some(node) = stack.pop() {
+            // Prune on the loose bounds, as data may overhang the node
+            let bounds_dist = cmp.dist_bbox(node.loose_bounds())?;
+            if bounds_dist >= min_dist {
+                continue;
+            }
+
+            // See notes in the BoundsQuadTree implementation on the bbox check
+            for child in node.children() {
+                if cmp.dist_bounds(&child.as_geom())? > min_dist {
+                    continue;
+                }
+
+                let child_dist = cmp.dist_geom(&child.as_geom())?;
+                // See notes in point about <= usage
+                if child_dist <= min_dist {
+                    min_dist = child_dist;
+                    min_item = Ok(child);
+                }
+            }
+
+            // Push nodes onto the stack in reverse order
+            if let Some(sub_nodes) = node.nodes(&self.arena) {
+                stack.extend(sub_nodes.iter().rev());
+            }
+        }
+
+        min_item.map(|item| (item, min_dist))
+    }
+
+    fn knn_r<X>(&self, cmp: &X, k: usize, r: T) -> Result<Vec<(&D, T)>, Error>
+    where
+        X: AsGeom<T>,
+    {
+        let cmp = cmp.with_calc(self.calc_method());
+
+        knn_by(
+            self.root(),
+            |node| cmp.dist_bbox(&self.node_bounds(node)),
+            |child| cmp.dist_geom(&child.as_geom()),
+            k,
+            r,
+        )
+    }
+
+    fn knn_approx<X>(&self, cmp: &X, k: usize, epsilon: T) -> Result<Vec<(&D, T)>, Error>
+    where
+        X: AsGeom<T>,
+    {
+        let cmp = cmp.with_calc(self.calc_method());
+
+        knn_approx_by(
+            self.root(),
+            |node| cmp.dist_bbox(&self.node_bounds(node)),
+            |child| cmp.dist_geom(&child.as_geom()),
+            k,
+            epsilon,
+        )
+    }
+
+    fn sorted<'a, X>(&'a self, cmp: &'a X) -> impl Iterator<Item = (&'a D, T)> + 'a
+    where
+        D: 'a,
+        X: AsGeom<T> + 'a,
+    {
+        let cmp = cmp.with_calc(self.calc_method());
+
+        let node_cmp = cmp.clone();
+        sorted_by(
+            self.root(),
+            move |node| node_cmp.dist_bbox(&self.node_bounds(node)),
+            move |child| cmp.dist_geom(&child.as_geom()),
+        )
+    }
+
+    fn sorted_desc<'a, X>(&'a self, cmp: &'a X) -> impl Iterator<Item = (&'a D, T)> + 'a
+    where
+        D: 'a,
+        X: AsGeom<T> + 'a,
+    {
+        let cmp = cmp.with_calc(self.calc_method());
+
+        let (min_cmp, max_cmp) = (cmp.clone(), cmp.clone());
+        sorted_desc_by(
+            self.root(),
+            move |node| min_cmp.dist_bbox(&self.node_bounds(node)),
+            move |node| max_cmp.max_dist_bbox(&self.node_bounds(node)),
+            move |child| cmp.dist_geom(&child.as_geom()),
+        )
+    }
+}
+
+impl<'a, D, T> IntoIterator for &'a LooseQuadTree<D, T>
+where
+    D: AsGeom<T>,
+    T: GeoNum,
+{
+    type Item = &'a D;
+    type IntoIter = DatumIter<'a, LooseNode<D, T>, D, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.arena.root().descendants(&self.arena)
+    }
+}
+
+impl<D, T> std::fmt::Display for LooseQuadTree<D, T>
+where
+    D: AsGeom<T>,
+    T: GeoNum + std::fmt::Display,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Loose Quadtree Root:")?;
+        self.arena.root().display(&self.arena, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::coord;
+
+    // helper function for bounds datum creation
+    fn b(x: f64, y: f64, w: f64, h: f64) -> Rect {
+        Rect::new(coord! {x: x, y: y}, coord! {x: x + w, y: y + h})
+    }
+
+    #[test]
+    fn small_data_on_midlines_move_down_the_tree() {
+        let bounds = b(0.0, 0.0, 8.0, 8.0);
+        let mut bqt = BoundsQuadTree::new(bounds, CalcMethod::Euclidean, 2, 1);
+        let mut lqt = LooseQuadTree::new(bounds, CalcMethod::Euclidean, 2, 1, 2.0);
+
+        // Small boxes straddling the root centre lines
+        let data = [
+            b(3.5, 1.0, 1.0, 1.0),
+            b(1.0, 3.5, 1.0, 1.0),
+            b(5.0, 3.9, 1.0, 0.2),
+        ];
+        for d in data {
+            bqt.insert(d).unwrap();
+            lqt.insert(d).unwrap();
+        }
+
+        assert_eq!(bqt.stats().stuck_by_depth, vec![3]);
+        assert_eq!(lqt.stats().stuck_children, 0);
+
+        // Away from the midlines, only nearby data are retrieved
+        let cmp = b(6.5, 5.0, 0.5, 0.5);
+        assert_eq!(bqt.retrieve(&cmp).count(), 3);
+        assert_eq!(lqt.retrieve(&cmp).collect::<Vec<_>>(), vec![&data[2]]);
+    }
+
+    #[test]
+    fn data_larger_than_the_loose_bounds_still_get_stuck() {
+        let bounds = b(0.0, 0.0, 8.0, 8.0);
+        let mut qt = LooseQuadTree::new(bounds, CalcMethod::Euclidean, 2, 1, 1.5);
+
+        let small = b(1.0, 1.0, 1.0, 1.0);
+        let large = b(0.5, 0.5, 5.5, 1.0);
+        qt.insert(small).unwrap();
+        qt.insert(large).unwrap();
+
+        let root = qt.root();
+        assert_eq!(root.stuck_children().collect::<Vec<_>>(), vec![&large]);
+        assert_eq!(
+            root.sub_node(SubNode::TopLeft)
+                .unwrap()
+                .children()
+                .collect::<Vec<_>>(),
+            vec![&small]
+        );
+        assert_eq!(qt.looseness(), 1.5);
+    }
+
+    #[test]
+    #[should_panic]
+    fn looseness_below_one_panics() {
+        LooseQuadTree::<Rect, f64>::new(b(0.0, 0.0, 1.0, 1.0), CalcMethod::Euclidean, 2, 1, 0.5);
+    }
+}
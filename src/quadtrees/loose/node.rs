@@ -0,0 +1,259 @@
+use std::marker::PhantomData;
+
+use crate::node::sub_node_bounds;
+use crate::*;
+use geo::{BoundingRect, Coord, GeoNum, Intersects, Rect};
+
+/// [`Node`] implementation for [`LooseQuadTree`].
+///
+/// Alongside its regular bounds, each node keeps loose bounds enlarged about
+/// the same centre by the tree's looseness factor. Data are routed down the
+/// tree by their centre, and only get stuck when they do not fit within the
+/// loose bounds of the sub-node holding that centre.
+#[derive(Debug)]
+pub struct LooseNode<D, T>
+where
+    D: AsGeom<T>,
+    T: GeoNum,
+{
+    bounds: Rect<T>,
+    loose_bounds: Rect<T>,
+    looseness: T,
+    limits: Limits,
+    pub(crate) children: Children<D>,
+    pub(crate) stuck_children: Vec<D>,
+    // Insertion sequence numbers, in step with the children and stuck children
+    seqs: Children<usize>,
+    stuck_seqs: Vec<usize>,
+    pub(crate) quad: Option<QuadId>,
+    _num_type: PhantomData<T>,
+}
+
+impl<D, T> LooseNode<D, T>
+where
+    D: AsGeom<T>,
+    T: GeoNum,
+{
+    /// Create a new node with an explicit looseness factor.
+    pub(crate) fn with_looseness(bounds: Rect<T>, limits: Limits, looseness: T) -> Self {
+        Self {
+            bounds,
+            loose_bounds: loose_bounds(&bounds, limits.depth, looseness),
+            looseness,
+            limits,
+            children: Children::new(),
+            stuck_children: Vec::new(),
+            seqs: Children::new(),
+            stuck_seqs: Vec::new(),
+            quad: None,
+            _num_type: PhantomData,
+        }
+    }
+
+    /// Get the loose bounds of the node, which bound every datum beneath it.
+    pub(crate) fn loose_bounds(&self) -> &Rect<T> {
+        &self.loose_bounds
+    }
+
+    /// Hold a datum stuck at this node whatever its bounds, which stays here
+    /// even if the node subdivides.
+    pub(crate) fn stick(&mut self, datum: D, seq: usize) {
+        self.stuck_children.push(datum);
+        self.stuck_seqs.push(seq);
+    }
+}
+
+impl<D, T> Node<D, T> for LooseNode<D, T>
+where
+    D: AsGeom<T>,
+    T: GeoNum,
+{
+    fn new(bounds: Rect<T>, limits: Limits) -> Self {
+        let looseness = T::one() + T::one();
+        Self::with_looseness(bounds, limits, looseness)
+    }
+
+    fn datum_position(datum: &D) -> Option<Coord<T>> {
+        let bbox = datum.as_geom().bounding_rect()?;
+        let (x, y) = bbox.min().x_y();
+        let two = T::one() + T::one();
+
+        Some(Coord {
+            x: x + bbox.width() / two,
+            y: y + bbox.height() / two,
+        })
+    }
+
+    // Boxes are only inseparable when they cover exactly the same area
+    fn coincident(d1: &D, d2: &D) -> bool {
+        d1.as_geom().bounding_rect() == d2.as_geom().bounding_rect()
+    }
+
+    // Getters
+    fn bounds(&self) -> &Rect<T> {
+        &self.bounds
+    }
+
+    fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    fn quad(&self) -> Option<QuadId> {
+        self.quad
+    }
+
+    fn children(&self) -> DatumIter<'_, Self, D, T> {
+        DatumIter::ChainSlice(self.children.iter().chain(&self.stuck_children))
+    }
+
+    fn stuck_children(&self) -> DatumIter<'_, Self, D, T> {
+        DatumIter::Slice(self.stuck_children.iter())
+    }
+
+    fn heap_bytes(&self) -> usize {
+        children_heap_bytes(&self.children)
+            + children_heap_bytes(&self.seqs)
+            + size_of_val(self.stuck_children.as_slice())
+            + size_of_val(self.stuck_seqs.as_slice())
+    }
+
+    // Setters
+    fn set_quad(&mut self, quad: Option<QuadId>) {
+        self.quad = quad;
+    }
+
+    // Override to carry the looseness factor down to the new sub-nodes, and
+    // keep their loose bounds inside this node's, so inside the root
+    fn sub_nodes(&self) -> [Self; 4] {
+        let k = self.looseness;
+
+        self.limits
+            .sub_nodes(sub_node_bounds(&self.bounds), |b, limits| {
+                let mut node = Self::with_looseness(b, limits, k);
+                node.loose_bounds = clamp_rect(&node.loose_bounds, &self.loose_bounds);
+                node
+            })
+    }
+
+    fn insert(arena: &mut Arena<Self>, id: usize, datum: D, seq: usize) -> Result<(), Error> {
+        let node = &arena[id];
+
+        match node.quad {
+            Some(quad) => {
+                let bbox = datum
+                    .as_geom()
+                    .bounding_rect()
+                    .ok_or(Error::CannotMakeBbox)?;
+
+                // The centre picks the sub-node, and the datum only needs to
+                // fit inside that sub-node's loose bounds to move down
+                let sub_node_idx = node.find_sub_node(&datum).ok_or(Error::CannotFindSubNode)?;
+                let sub_node_id = quad.sub_node(sub_node_idx as usize);
+
+                if rect_in_rect(arena[sub_node_id].loose_bounds(), &bbox) {
+                    Self::insert(arena, sub_node_id, datum, seq)?
+                } else {
+                    arena[id].stuck_children.push(datum);
+                    arena[id].stuck_seqs.push(seq);
+                }
+            }
+            // See notes in PointQuadTree implementation
+            None if node.limits.must_subdivide(node.children.len()) => {
+                let mut children = std::mem::take(&mut arena[id].children);
+                children.push(datum);
+                let mut seqs = std::mem::take(&mut arena[id].seqs);
+                seqs.push(seq);
+
+                Self::subdivide(arena, id);
+
+                for (child, seq) in children.into_iter().zip(seqs) {
+                    Self::insert(arena, id, child, seq)?;
+                }
+            }
+            None => {
+                arena[id].children.push(datum);
+                arena[id].seqs.push(seq);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn held_seq(&self, datum: &D) -> Option<usize> {
+        index_in(&self.children, datum)
+            .map(|i| self.seqs[i])
+            .or_else(|| index_in(&self.stuck_children, datum).map(|i| self.stuck_seqs[i]))
+    }
+
+    fn retrieve<'a>(&'a self, arena: &'a Arena<Self>, datum: &D) -> DatumIter<'a, Self, D, T> {
+        // Data beneath a sub-node may reach anywhere in its loose bounds, so
+        // descend into every sub-node whose loose bounds overlap the datum
+        let descendants = match self.nodes(arena).zip(datum.as_geom().bounding_rect()) {
+            Some((nodes, bbox)) => {
+                let mut inner = DatumIter::Empty;
+                for sub_node in nodes {
+                    if sub_node.loose_bounds().intersects(&bbox) {
+                        inner = DatumIter::ChainSelf(ChainSelfIter::new(
+                            inner,
+                            sub_node.retrieve(arena, datum),
+                        ));
+                    }
+                }
+                inner
+            }
+            None => DatumIter::Empty,
+        };
+
+        DatumIter::ChainSelf(ChainSelfIter::new(self.children(), descendants))
+    }
+}
+
+/// Loose bounds for a node with the passed regular bounds, enlarged about
+/// their centre to `looseness` times the width and height. The root is never
+/// loosened, as inserts already require data to fit within it, and sub-nodes
+/// clamp the result to their parent's loose bounds, so the bounds never reach
+/// outside the root, such as past the poles or antimeridian.
+pub(crate) fn loose_bounds<T>(bounds: &Rect<T>, depth: u8, looseness: T) -> Rect<T>
+where
+    T: GeoNum,
+{
+    if depth == 0 {
+        return *bounds;
+    }
+
+    let two = T::one() + T::one();
+    let pad_x = (looseness - T::one()) * bounds.width() / two;
+    let pad_y = (looseness - T::one()) * bounds.height() / two;
+    let (min, max) = (bounds.min(), bounds.max());
+
+    Rect::new(
+        Coord {
+            x: min.x - pad_x,
+            y: min.y - pad_y,
+        },
+        Coord {
+            x: max.x + pad_x,
+            y: max.y + pad_y,
+        },
+    )
+}
+
+// The part of `rect` inside `within`, which it must overlap
+fn clamp_rect<T>(rect: &Rect<T>, within: &Rect<T>) -> Rect<T>
+where
+    T: GeoNum,
+{
+    let max = |a: T, b: T| if a > b { a } else { b };
+    let min = |a: T, b: T| if a < b { a } else { b };
+
+    Rect::new(
+        Coord {
+            x: max(rect.min().x, within.min().x),
+            y: max(rect.min().y, within.min().y),
+        },
+        Coord {
+            x: min(rect.max().x, within.max().x),
+            y: min(rect.max().y, within.max().y),
+        },
+    )
+}
@@ -1,17 +1,26 @@
 pub mod bounds;
+pub mod cube;
+pub mod integer;
 mod knn;
+pub mod linear;
+pub mod loose;
+mod morton;
+pub mod octree;
+pub mod pmr;
 pub mod point;
+pub mod region;
 mod sorted;
+mod stats;
 
 use crate::{
     AsGeom, Error,
-    geom::{CalcMethod, QtFloat},
-    iter::DatumIter,
-    node::Node,
+    geom::{CalcMethod, ClosestPoints, QtFloat, Snap},
 };
-use geo::GeoNum;
+use geo::{GeoNum, Point};
+use sorted::KeyedTies;
 
-use self::sorted::SortIter;
+pub use self::morton::MortonKey;
+pub use self::stats::TreeStats;
 
 pub const DEFAULT_MAX_CHILDREN: usize = 4;
 pub const DEFAULT_MAX_DEPTH: u8 = 4;
@@ -27,9 +36,6 @@ pub trait QuadTree<D, T>
 where
     T: GeoNum,
 {
-    /// The specific node built for this QuadTree implementation
-    type Node: Node<D, T>;
-
     /// Return the number of datums currently stored in the quadtree.
     fn size(&self) -> usize;
 
@@ -41,7 +47,9 @@ where
     ///
     /// This retrieval is useful for collision detection and other spatial
     /// approximations, and works best when the quadtree is evenly populated.
-    fn retrieve(&self, datum: &D) -> DatumIter<'_, Self::Node, D, T>;
+    fn retrieve<'a>(&'a self, datum: &D) -> impl Iterator<Item = &'a D> + use<'a, Self, D, T>
+    where
+        D: 'a;
 }
 
 /// Add-on QuadTree trait that adds distance-based search methods to a
@@ -54,13 +62,21 @@ where
     D: AsGeom<T>,
     T: QtFloat,
 {
-    /// The specific node built for this QuadTree implementation
-    type Node: Node<D, T>;
-
     /// Return the calculation methodology that the QuadTree will use to determine distances. The
     /// [`CalcMethod`] governs the geometry system used to determine distances within each of the
     /// find methods.
-    fn calc_method(&self) -> CalcMethod;
+    fn calc_method(&self) -> CalcMethod<T>;
+
+    /// Return the position of `datum` in the order data were inserted into
+    /// the QuadTree, counting from zero, where `datum` is a reference handed
+    /// out by the tree, such as a search result.
+    ///
+    /// Returns `None` for references to data held elsewhere, or for
+    /// implementations that do not record insertion order.
+    fn insertion_index(&self, datum: &D) -> Option<usize> {
+        let _ = datum;
+        None
+    }
 
     /// Find the closest datum in the quadtree to the passed comparator.
     ///
@@ -97,14 +113,48 @@ where
     /// Similar to [`QuadTreeSearch::find`], but takes a maximum distance
     /// parameter to constrain the maximum search radius. Will return an
     /// [`Error::NoneInRadius`] if no match is found inside `r`.
+    ///
+    /// If several data are equally close, an arbitrary one is returned. Use
+    /// [`QuadTreeSearch::find_by_insertion`] where this matters.
     fn find_r<X>(&self, cmp: &X, r: T) -> Result<(&D, T), Error>
     where
         X: AsGeom<T>;
 
+    /// Similar to [`QuadTreeSearch::find`], but when several data are equally
+    /// close, returns the one with the smallest `key`.
+    ///
+    /// Keying on an id held by the datum makes the result independent of the
+    /// tree layout, see also [`QuadTreeSearch::find_by_insertion`].
+    fn find_by_key<X, K>(&self, cmp: &X, key: impl Fn(&D) -> K) -> Result<(&D, T), Error>
+    where
+        X: AsGeom<T>,
+        K: Ord,
+    {
+        let (_, d) = self.find(cmp)?;
+
+        // Everything within the closest distance is tied with it
+        self.knn_r(cmp, usize::MAX, d)?
+            .into_iter()
+            .min_by_key(|(datum, _)| key(datum))
+            .ok_or(Error::NoneInRadius)
+    }
+
+    /// Similar to [`QuadTreeSearch::find`], but when several data are equally
+    /// close, returns the one inserted first. See
+    /// [`QuadTreeSearch::insertion_index`].
+    fn find_by_insertion<X>(&self, cmp: &X) -> Result<(&D, T), Error>
+    where
+        X: AsGeom<T>,
+    {
+        self.find_by_key(cmp, |datum| self.insertion_index(datum))
+    }
+
     /// Find `k` nearest neighbors of the comparator `cmp`.
     ///
-    /// The algorithm uses a partial unstable sort on nodes in any order, so
-    /// the method makes no ordering promise when data are at the same distance.
+    /// Data at the same distance are returned in no particular order, and
+    /// when several tie at the k-th distance an arbitrary subset is kept. Use
+    /// [`QuadTreeSearch::knn_by_insertion`], [`QuadTreeSearch::knn_by_key`]
+    /// or [`QuadTreeSearch::knn_with_ties`] where this matters.
     ///
     /// Returns a vector with a maximum length of k, but the result maybe
     /// shorter if insufficient points can be found. The vector's members
@@ -129,17 +179,249 @@ where
     where
         X: AsGeom<T>;
 
+    /// Similar to [`QuadTreeSearch::knn`], but rather than truncating
+    /// arbitrarily, returns every datum tied at the k-th distance, so the
+    /// result may be longer than `k`.
+    fn knn_with_ties<X>(&self, cmp: &X, k: usize) -> Result<Vec<(&D, T)>, Error>
+    where
+        X: AsGeom<T>,
+    {
+        let infinity = T::from(f64::INFINITY).ok_or(Error::CannotCastInfinity)?;
+        self.knn_with_ties_r(cmp, k, infinity)
+    }
+
+    /// Approximate version of [`QuadTreeSearch::knn`], trading accuracy for
+    /// speed by pruning nodes that could only improve the result by a small
+    /// factor.
+    ///
+    /// Each returned distance is guaranteed to be within a factor of
+    /// `1 + epsilon` of the true distance of the neighbour at the same rank,
+    /// so an `epsilon` of zero gives an exact result. The result is still in
+    /// distance order.
+    ///
+    /// The default implementation ignores `epsilon` and returns the exact
+    /// result of [`QuadTreeSearch::knn`], which always meets the guarantee.
+    ///
+    /// Returns an [`Error::InvalidDistance`] if `epsilon` is negative or NaN.
+    fn knn_approx<X>(&self, cmp: &X, k: usize, epsilon: T) -> Result<Vec<(&D, T)>, Error>
+    where
+        X: AsGeom<T>,
+    {
+        if epsilon.is_nan() || epsilon < T::zero() {
+            return Err(Error::InvalidDistance);
+        }
+
+        let infinity = T::from(f64::INFINITY).ok_or(Error::CannotCastInfinity)?;
+        self.knn_r(cmp, k, infinity)
+    }
+
+    /// Approximate version of [`QuadTreeSearch::find`]. The distance returned
+    /// is guaranteed to be within a factor of `1 + epsilon` of the closest.
+    /// See [`QuadTreeSearch::knn_approx`].
+    fn find_approx<X>(&self, cmp: &X, epsilon: T) -> Result<(&D, T), Error>
+    where
+        X: AsGeom<T>,
+    {
+        match self.knn_approx(cmp, 1, epsilon)?.into_iter().next() {
+            Some(found) => Ok(found),
+            // Let find report why nothing was found, as with an empty tree
+            None => self.find(cmp),
+        }
+    }
+
+    /// Similar to [`QuadTreeSearch::find`], but also returns the closest
+    /// pair of points between the comparator and the datum, as in
+    /// [`crate::GeomCalc::closest_points`]. The first point lies on the
+    /// comparator and the second on the datum.
+    fn find_with_points<X>(&self, cmp: &X) -> Result<(&D, T, ClosestPoints<T>), Error>
+    where
+        X: AsGeom<T>,
+    {
+        let calc = cmp.with_calc(self.calc_method());
+        let (datum, d) = self.find(cmp)?;
+
+        Ok((datum, d, calc.closest_points(&datum.as_geom())?))
+    }
+
+    /// Similar to [`QuadTreeSearch::knn`], but also returns the closest pair
+    /// of points between the comparator and each datum, as in
+    /// [`QuadTreeSearch::find_with_points`].
+    #[allow(clippy::type_complexity)]
+    fn knn_with_points<X>(&self, cmp: &X, k: usize) -> Result<Vec<(&D, T, ClosestPoints<T>)>, Error>
+    where
+        X: AsGeom<T>,
+    {
+        let calc = cmp.with_calc(self.calc_method());
+
+        self.knn(cmp, k)?
+            .into_iter()
+            .map(|(datum, d)| Ok((datum, d, calc.closest_points(&datum.as_geom())?)))
+            .collect()
+    }
+
+    /// Snap a point onto the closest datum within `r`, for trees holding
+    /// [`geo::Line`] or [`geo::LineString`] data, such as when map matching.
+    ///
+    /// Returns the datum and the [`Snap`], giving the closest point on the
+    /// datum, the segment it lies on, how far along that segment it is, and
+    /// its distance from `point`. Errors as [`QuadTreeSearch::find_r`], or
+    /// with [`Error::UnsupportedGeometry`] if the closest datum is not a line.
+    fn snap(&self, point: &Point<T>, r: T) -> Result<(&D, Snap<T>), Error> {
+        let (datum, _) = self.find_r(point, r)?;
+        let snap = point.with_calc(self.calc_method()).snap(&datum.as_geom())?;
+
+        Ok((datum, snap))
+    }
+
+    /// Similar to [`QuadTreeSearch::knn_with_ties`], but takes a maximum
+    /// distance parameter to constrain the maximum search radius.
+    fn knn_with_ties_r<X>(&self, cmp: &X, k: usize, r: T) -> Result<Vec<(&D, T)>, Error>
+    where
+        X: AsGeom<T>,
+    {
+        let res = self.knn_r(cmp, k, r)?;
+
+        // Only a full result can have dropped data tied with its last member,
+        // in which case search again for everything out to that distance
+        match res.get(k.wrapping_sub(1)) {
+            Some(&(_, kth)) if res.len() == k => self.knn_r(cmp, usize::MAX, kth),
+            _ => Ok(res),
+        }
+    }
+
+    /// Similar to [`QuadTreeSearch::knn`], but data at the same distance are
+    /// ordered by `key`, including when choosing between data tied at the
+    /// k-th distance. The result is deterministic as long as `key` gives
+    /// distinct data distinct keys.
+    fn knn_by_key<X, K>(
+        &self,
+        cmp: &X,
+        k: usize,
+        key: impl Fn(&D) -> K,
+    ) -> Result<Vec<(&D, T)>, Error>
+    where
+        X: AsGeom<T>,
+        K: Ord,
+    {
+        let mut res = self.knn_with_ties(cmp, k)?;
+
+        // Distances are already sorted, so a stable sort only reorders ties
+        res.sort_by(|(d1, dist1), (d2, dist2)| {
+            dist1
+                .partial_cmp(dist2)
+                .expect("Unreachable, NaN distances already removed.")
+                .then_with(|| key(d1).cmp(&key(d2)))
+        });
+        res.truncate(k);
+
+        Ok(res)
+    }
+
+    /// Similar to [`QuadTreeSearch::knn`], but data at the same distance are
+    /// ordered by when they were inserted, earliest first, including when
+    /// choosing between data tied at the k-th distance.
+    fn knn_by_insertion<X>(&self, cmp: &X, k: usize) -> Result<Vec<(&D, T)>, Error>
+    where
+        X: AsGeom<T>,
+    {
+        self.knn_by_key(cmp, k, |datum| self.insertion_index(datum))
+    }
+
     /// Iterate through all data in the QuadTree in distance-sorted order.
     ///
-    /// The algorithm uses a partial unstable sort, so it makes no ordering
-    /// promise when data are at the same distance.
+    /// Data at the same distance are returned in no particular order. Use
+    /// [`QuadTreeSearch::sorted_by_insertion`] or
+    /// [`QuadTreeSearch::sorted_by_key`] where this matters.
     ///
     /// The iterator is designed to be more forgiving than [`QuadTreeSearch::find`]
     /// and [`QuadTreeSearch::knn`], attempting to skip items on error rather
     /// than returning an [`Err`].
     ///
     /// As with the other search methods, the comparator only needs to implement [`AsGeom`].
-    fn sorted<'a, X>(&'a self, cmp: &'a X) -> SortIter<'a, Self::Node, D, T>
+    fn sorted<'a, X>(&'a self, cmp: &'a X) -> impl Iterator<Item = (&'a D, T)> + 'a
     where
+        D: 'a,
         X: AsGeom<T> + 'a;
+
+    /// Iterate through all data in the QuadTree in descending distance order,
+    /// farthest first.
+    ///
+    /// Nodes are visited in order of an upper bound on the distance to their
+    /// contents, see [`crate::GeomCalc::max_dist_bbox`]. Otherwise this
+    /// behaves like [`QuadTreeSearch::sorted`], skipping items it cannot
+    /// process, and yielding nothing if the comparator is out of bounds.
+    ///
+    /// The default implementation collects [`QuadTreeSearch::sorted`] and
+    /// reverses it, so visits every datum before yielding the first.
+    fn sorted_desc<'a, X>(&'a self, cmp: &'a X) -> impl Iterator<Item = (&'a D, T)> + 'a
+    where
+        D: 'a,
+        X: AsGeom<T> + 'a,
+        T: 'a,
+    {
+        let mut data = self.sorted(cmp).collect::<Vec<_>>();
+        data.reverse();
+        data.into_iter()
+    }
+
+    /// Find the `k` data farthest from the comparator `cmp`, farthest first.
+    ///
+    /// Takes the first `k` items of [`QuadTreeSearch::sorted_desc`], so shares
+    /// its forgiving error semantics. The result may be shorter than `k` if
+    /// there are insufficient data.
+    fn farthest<'a, X>(&'a self, cmp: &'a X, k: usize) -> Vec<(&'a D, T)>
+    where
+        D: 'a,
+        X: AsGeom<T> + 'a,
+        T: 'a,
+    {
+        self.sorted_desc(cmp).take(k).collect()
+    }
+
+    /// Similar to [`QuadTreeSearch::sorted`], but also returns the closest
+    /// pair of points between the comparator and each datum, as in
+    /// [`QuadTreeSearch::find_with_points`]. Data whose closest points cannot
+    /// be found are skipped.
+    fn sorted_with_points<'a, X>(
+        &'a self,
+        cmp: &'a X,
+    ) -> impl Iterator<Item = (&'a D, T, ClosestPoints<T>)> + 'a
+    where
+        D: 'a,
+        T: 'a,
+        X: AsGeom<T> + 'a,
+    {
+        let calc = cmp.with_calc(self.calc_method());
+
+        self.sorted(cmp).filter_map(move |(datum, d)| {
+            Some((datum, d, calc.closest_points(&datum.as_geom()).ok()?))
+        })
+    }
+
+    /// Similar to [`QuadTreeSearch::sorted`], but data at the same distance
+    /// are emitted in `key` order.
+    fn sorted_by_key<'a, X, K>(
+        &'a self,
+        cmp: &'a X,
+        key: impl Fn(&D) -> K + 'a,
+    ) -> impl Iterator<Item = (&'a D, T)> + 'a
+    where
+        D: 'a,
+        T: 'a,
+        X: AsGeom<T> + 'a,
+        K: Ord + 'a,
+    {
+        KeyedTies::new(self.sorted(cmp), key)
+    }
+
+    /// Similar to [`QuadTreeSearch::sorted`], but data at the same distance
+    /// are emitted in the order they were inserted.
+    fn sorted_by_insertion<'a, X>(&'a self, cmp: &'a X) -> impl Iterator<Item = (&'a D, T)> + 'a
+    where
+        D: 'a,
+        T: 'a,
+        X: AsGeom<T> + 'a,
+    {
+        self.sorted_by_key(cmp, move |datum| self.insertion_index(datum))
+    }
 }
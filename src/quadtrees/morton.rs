@@ -0,0 +1,151 @@
+use crate::SubNode;
+
+/// Address of a node in an [`crate::IntQuadTree`] or [`crate::LinearQuadTree`] as a Morton
+/// (Z-order) code.
+///
+/// The code holds two bits per level below the root, most significant first,
+/// with the x bit in the low position and the y bit in the high position of
+/// each pair. The root is the empty code at level zero. Because sibling codes
+/// are contiguous, sorting keys at the same level walks the tree in Z-order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MortonKey {
+    /// Depth of the node, with the root at zero.
+    pub level: u8,
+
+    /// Interleaved position bits, `2 * level` bits wide.
+    pub code: u64,
+}
+
+impl MortonKey {
+    /// Key of the root node.
+    pub const ROOT: MortonKey = MortonKey { level: 0, code: 0 };
+
+    /// Key of the node at `level` with the passed cell position, counted in
+    /// units of the node's side length from the tree origin. Only the low
+    /// `level` bits of each coordinate are used.
+    pub fn from_cell(level: u8, x: u32, y: u32) -> MortonKey {
+        let mask = u32::MAX.checked_shr(32 - level.min(32) as u32).unwrap_or(0);
+        MortonKey {
+            level,
+            code: interleave(x & mask, y & mask),
+        }
+    }
+
+    /// Key of the given sub-node of this node.
+    pub fn child(&self, sub_node: SubNode) -> MortonKey {
+        MortonKey {
+            level: self.level + 1,
+            code: (self.code << 2) | quadrant(sub_node),
+        }
+    }
+
+    /// Key of the parent of this node, or `None` for the root.
+    pub fn parent(&self) -> Option<MortonKey> {
+        (self.level > 0).then(|| MortonKey {
+            level: self.level - 1,
+            code: self.code >> 2,
+        })
+    }
+
+    /// Which sub-node of its parent this node is, or `None` for the root.
+    pub fn sub_node(&self) -> Option<SubNode> {
+        (self.level > 0).then(|| sub_node(self.code & 0b11))
+    }
+
+    /// Cell position of this node in units of its own side length, counted
+    /// from the tree origin.
+    pub fn cell(&self) -> (u32, u32) {
+        deinterleave(self.code)
+    }
+}
+
+/// Interleave the bits of `x` and `y` into a single Morton code, with `x` in
+/// the even bits.
+pub(crate) fn interleave(x: u32, y: u32) -> u64 {
+    spread(x) | (spread(y) << 1)
+}
+
+/// Inverse of [`interleave`].
+pub(crate) fn deinterleave(code: u64) -> (u32, u32) {
+    (compact(code), compact(code >> 1))
+}
+
+/// Two bit Morton quadrant for a [`SubNode`]. The orders differ because
+/// sub-nodes rotate clockwise while Z-order zig-zags.
+pub(crate) fn quadrant(sub_node: SubNode) -> u64 {
+    match sub_node {
+        SubNode::TopLeft => 0,
+        SubNode::TopRight => 1,
+        SubNode::BottomLeft => 2,
+        SubNode::BottomRight => 3,
+    }
+}
+
+/// Inverse of [`quadrant`], ignoring all but the lowest two bits.
+pub(crate) fn sub_node(quadrant: u64) -> SubNode {
+    match quadrant & 0b11 {
+        0 => SubNode::TopLeft,
+        1 => SubNode::TopRight,
+        2 => SubNode::BottomLeft,
+        _ => SubNode::BottomRight,
+    }
+}
+
+// Spread the 32 bits of n into the even bits of a u64
+fn spread(n: u32) -> u64 {
+    let mut n = n as u64;
+    n = (n | (n << 16)) & 0x0000_FFFF_0000_FFFF;
+    n = (n | (n << 8)) & 0x00FF_00FF_00FF_00FF;
+    n = (n | (n << 4)) & 0x0F0F_0F0F_0F0F_0F0F;
+    n = (n | (n << 2)) & 0x3333_3333_3333_3333;
+    n = (n | (n << 1)) & 0x5555_5555_5555_5555;
+    n
+}
+
+// Gather the even bits of a u64 back into a u32
+fn compact(n: u64) -> u32 {
+    let mut n = n & 0x5555_5555_5555_5555;
+    n = (n | (n >> 1)) & 0x3333_3333_3333_3333;
+    n = (n | (n >> 2)) & 0x0F0F_0F0F_0F0F_0F0F;
+    n = (n | (n >> 4)) & 0x00FF_00FF_00FF_00FF;
+    n = (n | (n >> 8)) & 0x0000_FFFF_0000_FFFF;
+    n = (n | (n >> 16)) & 0x0000_0000_FFFF_FFFF;
+    n as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interleave_round_trips_and_orders_in_z() {
+        assert_eq!(interleave(0b11, 0b00), 0b0101);
+        assert_eq!(interleave(0b00, 0b11), 0b1010);
+        assert_eq!(deinterleave(interleave(u32::MAX, 12345)), (u32::MAX, 12345));
+
+        // Walking the codes zig-zags x first, then y
+        let cells = (0..4).map(deinterleave).collect::<Vec<_>>();
+        assert_eq!(cells, vec![(0, 0), (1, 0), (0, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn keys_navigate_between_parent_and_child() {
+        let key = MortonKey::ROOT
+            .child(SubNode::BottomLeft)
+            .child(SubNode::TopRight);
+
+        assert_eq!(
+            key,
+            MortonKey {
+                level: 2,
+                code: 0b1001
+            }
+        );
+        assert_eq!(key.cell(), (1, 2));
+        assert_eq!(key.sub_node(), Some(SubNode::TopRight));
+        assert_eq!(key.parent().unwrap().sub_node(), Some(SubNode::BottomLeft));
+        assert_eq!(MortonKey::ROOT.parent(), None);
+        assert_eq!(MortonKey::from_cell(2, 1, 2), key);
+        assert_eq!(MortonKey::from_cell(0, 1, 2), MortonKey::ROOT);
+    }
+}
@@ -0,0 +1,244 @@
+use geo::{CoordNum, GeoNum};
+
+use crate::QtFloat;
+
+/// A point in three dimensions, the 3D counterpart to [`geo::Point`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Point3<T = f64>
+where
+    T: CoordNum,
+{
+    pub x: T,
+    pub y: T,
+    pub z: T,
+}
+
+impl<T> Point3<T>
+where
+    T: CoordNum,
+{
+    /// Create a new point from its coordinates.
+    pub fn new(x: T, y: T, z: T) -> Self {
+        Self { x, y, z }
+    }
+}
+
+/// An axis-aligned box in three dimensions, the 3D counterpart to
+/// [`geo::Rect`]. As with [`geo::Rect`], the corners are normalized on
+/// creation so that `min` is less than or equal to `max` on every axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cuboid<T = f64>
+where
+    T: CoordNum,
+{
+    min: Point3<T>,
+    max: Point3<T>,
+}
+
+impl<T> Cuboid<T>
+where
+    T: CoordNum,
+{
+    /// Create a new cuboid from any two opposite corners.
+    pub fn new(a: Point3<T>, b: Point3<T>) -> Self {
+        let (min_x, max_x) = if a.x <= b.x { (a.x, b.x) } else { (b.x, a.x) };
+        let (min_y, max_y) = if a.y <= b.y { (a.y, b.y) } else { (b.y, a.y) };
+        let (min_z, max_z) = if a.z <= b.z { (a.z, b.z) } else { (b.z, a.z) };
+
+        Self {
+            min: Point3::new(min_x, min_y, min_z),
+            max: Point3::new(max_x, max_y, max_z),
+        }
+    }
+
+    /// Get the corner with the smallest coordinates.
+    pub fn min(&self) -> Point3<T> {
+        self.min
+    }
+
+    /// Get the corner with the largest coordinates.
+    pub fn max(&self) -> Point3<T> {
+        self.max
+    }
+
+    /// Get the centre of the cuboid.
+    pub fn center(&self) -> Point3<T> {
+        let two = T::one() + T::one();
+        Point3::new(
+            self.min.x + (self.max.x - self.min.x) / two,
+            self.min.y + (self.max.y - self.min.y) / two,
+            self.min.z + (self.max.z - self.min.z) / two,
+        )
+    }
+
+    /// Check whether the other cuboid lies inside this one, where lying on
+    /// the boundary counts as inside, matching how the 2D trees test bounds.
+    pub fn contains(&self, other: &Cuboid<T>) -> bool {
+        self.min.x <= other.min.x
+            && self.min.y <= other.min.y
+            && self.min.z <= other.min.z
+            && self.max.x >= other.max.x
+            && self.max.y >= other.max.y
+            && self.max.z >= other.max.z
+    }
+
+    /// Check whether the two cuboids overlap, including touching.
+    pub fn intersects(&self, other: &Cuboid<T>) -> bool {
+        self.min.x <= other.max.x
+            && self.max.x >= other.min.x
+            && self.min.y <= other.max.y
+            && self.max.y >= other.min.y
+            && self.min.z <= other.max.z
+            && self.max.z >= other.min.z
+    }
+}
+
+/// 3D geometries that can be held in an [`crate::Octree`]. Both are small and
+/// `Copy`, so unlike [`crate::GeometryRef`] the geometries are owned.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Geometry3<T = f64>
+where
+    T: CoordNum,
+{
+    Point(Point3<T>),
+    Cuboid(Cuboid<T>),
+}
+
+impl<T> Geometry3<T>
+where
+    T: CoordNum,
+{
+    /// Get the bounding cuboid of the geometry, which is degenerate for
+    /// points.
+    pub fn bounding_cuboid(&self) -> Cuboid<T> {
+        match *self {
+            Geometry3::Point(p) => Cuboid::new(p, p),
+            Geometry3::Cuboid(c) => c,
+        }
+    }
+}
+
+impl<T> Geometry3<T>
+where
+    T: QtFloat,
+{
+    /// Euclidean distance between two geometries, which is zero when they
+    /// touch or overlap.
+    pub fn dist_euclidean(&self, other: &Geometry3<T>) -> T {
+        dist_cuboid_cuboid(&self.bounding_cuboid(), &other.bounding_cuboid())
+    }
+}
+
+/// Trait required for an item to be used as a point in an [`crate::Octree`],
+/// the 3D counterpart to [`crate::AsPoint`].
+///
+/// As in 2D, custom point types used as data also need [`AsGeom3`], which
+/// can simply wrap the point in [`Geometry3::Point`].
+pub trait AsPoint3<T = f64>
+where
+    T: CoordNum,
+{
+    fn as_point3(&self) -> Point3<T>;
+}
+
+impl<T> AsPoint3<T> for Point3<T>
+where
+    T: CoordNum,
+{
+    fn as_point3(&self) -> Point3<T> {
+        *self
+    }
+}
+
+/// The constraint for [`crate::Octree`] data and comparators, the 3D
+/// counterpart to [`crate::AsGeom`].
+pub trait AsGeom3<T = f64>
+where
+    T: CoordNum,
+{
+    fn as_geom3(&self) -> Geometry3<T>;
+}
+
+impl<T> AsGeom3<T> for Point3<T>
+where
+    T: CoordNum,
+{
+    fn as_geom3(&self) -> Geometry3<T> {
+        Geometry3::Point(*self)
+    }
+}
+
+impl<T> AsGeom3<T> for Cuboid<T>
+where
+    T: CoordNum,
+{
+    fn as_geom3(&self) -> Geometry3<T> {
+        Geometry3::Cuboid(*self)
+    }
+}
+
+impl<T> AsGeom3<T> for Geometry3<T>
+where
+    T: CoordNum,
+{
+    fn as_geom3(&self) -> Geometry3<T> {
+        *self
+    }
+}
+
+/// Euclidean distance between two cuboids, zero if they touch or overlap.
+/// Points are treated as degenerate cuboids.
+pub(crate) fn dist_cuboid_cuboid<T>(a: &Cuboid<T>, b: &Cuboid<T>) -> T
+where
+    T: QtFloat,
+{
+    let gap =
+        |a_min: T, a_max: T, b_min: T, b_max: T| (b_min - a_max).max(a_min - b_max).max(T::zero());
+
+    let dx = gap(a.min.x, a.max.x, b.min.x, b.max.x);
+    let dy = gap(a.min.y, a.max.y, b.min.y, b.max.y);
+    let dz = gap(a.min.z, a.max.z, b.min.z, b.max.z);
+
+    (dx * dx + dy * dy + dz * dz).sqrt()
+}
+
+/// Index of the octant of `bounds` holding `position`, with bit 0 set for
+/// the high x half, bit 1 for high y and bit 2 for high z. As in
+/// [`crate::SubNode`], positions on a midplane belong to the low side.
+pub(crate) fn octant_at<T>(bounds: &Cuboid<T>, position: Point3<T>) -> usize
+where
+    T: GeoNum,
+{
+    let mid = bounds.center();
+    usize::from(position.x > mid.x)
+        | usize::from(position.y > mid.y) << 1
+        | usize::from(position.z > mid.z) << 2
+}
+
+/// Split `bounds` into its eight octants, in the order used by
+/// [`octant_at`].
+pub(crate) fn octant_bounds<T>(bounds: &Cuboid<T>) -> [Cuboid<T>; 8]
+where
+    T: GeoNum,
+{
+    let (min, mid, max) = (bounds.min, bounds.center(), bounds.max);
+
+    std::array::from_fn(|i| {
+        let (x1, x2) = if i & 1 == 0 {
+            (min.x, mid.x)
+        } else {
+            (mid.x, max.x)
+        };
+        let (y1, y2) = if i & 2 == 0 {
+            (min.y, mid.y)
+        } else {
+            (mid.y, max.y)
+        };
+        let (z1, z2) = if i & 4 == 0 {
+            (min.z, mid.z)
+        } else {
+            (mid.z, max.z)
+        };
+        Cuboid::new(Point3::new(x1, y1, z1), Point3::new(x2, y2, z2))
+    })
+}
@@ -0,0 +1,267 @@
+mod geom;
+mod node;
+
+use geo::GeoNum;
+
+use super::knn::knn_by;
+use super::sorted::sorted_by;
+use crate::*;
+pub use geom::*;
+use node::OctNode;
+pub use node::OctreeIter;
+
+/// An Octree implementation for 3D points and boxes.
+///
+/// The 3D counterpart to [`BoundsQuadTree`], with each node splitting into
+/// eight octants rather than four sub-nodes. Data must implement [`AsGeom3`],
+/// which comes implemented for [`Point3`], [`Cuboid`] and [`Geometry3`]. Boxes that straddle the midplanes
+/// of a node get stuck in that node, exactly as in a [`BoundsQuadTree`], while
+/// points always move to the leaves.
+///
+/// As there is no 3D counterpart to [`CalcMethod`], searches always use 3D
+/// Euclidean distance. They share the best-first algorithms used by the
+/// [`QuadTreeSearch`] implementations, with the same error semantics.
+#[derive(Debug)]
+pub struct Octree<D, T = f64>
+where
+    T: GeoNum,
+{
+    root: OctNode<D, T>,
+    size: usize,
+}
+
+impl<D, T> Octree<D, T>
+where
+    D: AsGeom3<T>,
+    T: GeoNum,
+{
+    /// Create a new Octree.
+    pub fn new(bounds: Cuboid<T>, max_depth: u8, max_children: usize) -> Self {
+        Octree::private_new(bounds, Some(max_depth), Some(max_children))
+    }
+
+    /// Create a new Octree using default values for max_depth and
+    /// max_children.
+    pub fn from_bounds(bounds: Cuboid<T>) -> Self {
+        Octree::private_new(bounds, None, None)
+    }
+
+    // Private constructor
+    fn private_new(bounds: Cuboid<T>, max_depth: Option<u8>, max_children: Option<usize>) -> Self {
+        let max_depth = max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+        let max_children = max_children.unwrap_or(DEFAULT_MAX_CHILDREN);
+
+        Self {
+            root: OctNode::new(bounds, Limits::root(max_depth, max_children)),
+            size: 0,
+        }
+    }
+
+    /// Return the bounds of the Octree.
+    pub fn bounds(&self) -> &Cuboid<T> {
+        self.root.bounds()
+    }
+
+    /// Return the number of data in the Octree.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Insert a datum, which must lie within the bounds of the Octree.
+    pub fn insert(&mut self, datum: D) -> Result<(), Error> {
+        let bbox = datum.as_geom3().bounding_cuboid();
+
+        if self.root.bounds().contains(&bbox) {
+            self.root.insert(datum)?;
+            self.size += 1;
+            Ok(())
+        } else {
+            Err(Error::OutOfBounds)
+        }
+    }
+
+    /// Retrieve data that may be close to the passed datum, following the
+    /// same rules as [`QuadTree::retrieve`] on a [`BoundsQuadTree`].
+    pub fn retrieve(&self, datum: &D) -> impl Iterator<Item = &D> {
+        let bbox = datum.as_geom3().bounding_cuboid();
+        let mut found = vec![];
+
+        if self.root.bounds().contains(&bbox) {
+            self.root.retrieve(&bbox, &mut |d| found.push(d));
+        }
+
+        found.into_iter()
+    }
+}
+
+impl<D, T> Octree<D, T>
+where
+    D: AsGeom3<T>,
+    T: QtFloat,
+{
+    /// Find the closest datum to the passed geometry. See
+    /// [`QuadTreeSearch::find`].
+    pub fn find<X>(&self, cmp: &X) -> Result<(&D, T), Error>
+    where
+        X: AsGeom3<T>,
+    {
+        self.find_r(cmp, T::infinity())
+    }
+
+    /// Find the closest datum to the passed geometry within radius `r`. See
+    /// [`QuadTreeSearch::find_r`].
+    pub fn find_r<X>(&self, cmp: &X, r: T) -> Result<(&D, T), Error>
+    where
+        X: AsGeom3<T>,
+    {
+        let mut found = self.knn_r(cmp, 1, r)?;
+
+        match found.pop() {
+            Some(item) => Ok(item),
+            None if self.size == 0 => Err(Error::Empty),
+            None => Err(Error::NoneInRadius),
+        }
+    }
+
+    /// Find the `k` closest data to the passed geometry. See
+    /// [`QuadTreeSearch::knn`].
+    pub fn knn<X>(&self, cmp: &X, k: usize) -> Result<Vec<(&D, T)>, Error>
+    where
+        X: AsGeom3<T>,
+    {
+        self.knn_r(cmp, k, T::infinity())
+    }
+
+    /// Find up to `k` closest data to the passed geometry within radius `r`.
+    /// See [`QuadTreeSearch::knn_r`].
+    pub fn knn_r<X>(&self, cmp: &X, k: usize, r: T) -> Result<Vec<(&D, T)>, Error>
+    where
+        X: AsGeom3<T>,
+    {
+        let cmp = cmp.as_geom3();
+
+        knn_by(
+            &self.root,
+            |node| Ok(cmp.dist_euclidean(&Geometry3::Cuboid(*node.bounds()))),
+            |child| Ok(cmp.dist_euclidean(&child.as_geom3())),
+            k,
+            r,
+        )
+    }
+
+    /// Iterate over all data in order of distance from the passed geometry.
+    /// See [`QuadTreeSearch::sorted`].
+    pub fn sorted<'a, X>(&'a self, cmp: &X) -> impl Iterator<Item = (&'a D, T)> + 'a
+    where
+        D: 'a,
+        X: AsGeom3<T>,
+    {
+        let cmp = cmp.as_geom3();
+
+        sorted_by(
+            &self.root,
+            move |node| Ok(cmp.dist_euclidean(&Geometry3::Cuboid(*node.bounds()))),
+            move |child| Ok(cmp.dist_euclidean(&child.as_geom3())),
+        )
+    }
+}
+
+impl<'a, D, T> IntoIterator for &'a Octree<D, T>
+where
+    D: AsGeom3<T>,
+    T: GeoNum,
+{
+    type Item = &'a D;
+    type IntoIter = OctreeIter<'a, D, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        OctreeIter::new(&self.root)
+    }
+}
+
+impl<D, T> std::fmt::Display for Octree<D, T>
+where
+    D: AsGeom3<T>,
+    T: GeoNum + std::fmt::Display,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Octree Root:")?;
+        write!(f, "{}", self.root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn p(x: f64, y: f64, z: f64) -> Point3 {
+        Point3::new(x, y, z)
+    }
+
+    fn bounds() -> Cuboid {
+        Cuboid::new(p(0.0, 0.0, 0.0), p(8.0, 8.0, 8.0))
+    }
+
+    #[test]
+    fn points_subdivide_into_octants() {
+        let mut ot = Octree::new(bounds(), 2, 1);
+        let pts = [p(1.0, 1.0, 1.0), p(7.0, 1.0, 1.0), p(1.0, 1.0, 7.0)];
+        for pt in pts {
+            ot.insert(pt).unwrap();
+        }
+
+        let nodes = ot.root.nodes.as_ref().unwrap();
+        let counts = nodes.iter().map(|n| n.children.len()).collect::<Vec<_>>();
+        assert_eq!(counts, vec![1, 1, 0, 0, 1, 0, 0, 0]);
+        assert_eq!(
+            ot.retrieve(&p(2.0, 2.0, 6.0)).collect::<Vec<_>>(),
+            vec![&pts[2]]
+        );
+        assert_eq!(
+            ot.into_iter().collect::<Vec<_>>(),
+            pts.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(ot.insert(p(9.0, 1.0, 1.0)), Err(Error::OutOfBounds));
+    }
+
+    #[test]
+    fn boxes_straddling_midplanes_get_stuck() {
+        let mut ot = Octree::new(bounds(), 2, 1);
+        let small = Cuboid::new(p(1.0, 1.0, 1.0), p(2.0, 2.0, 2.0));
+        let large = Cuboid::new(p(1.0, 1.0, 3.0), p(2.0, 2.0, 5.0));
+        ot.insert(small).unwrap();
+        ot.insert(large).unwrap();
+
+        assert_eq!(ot.root.stuck_children, vec![large]);
+        assert_eq!(ot.size(), 2);
+    }
+
+    #[test]
+    fn searches_use_3d_euclidean_distance() {
+        let mut ot = Octree::new(bounds(), 3, 1);
+        let pts = [
+            p(1.0, 1.0, 1.0),
+            p(1.0, 1.0, 4.0),
+            p(4.0, 4.0, 4.0),
+            p(7.0, 7.0, 7.0),
+        ];
+        for pt in pts {
+            ot.insert(pt).unwrap();
+        }
+
+        let cmp = p(1.0, 1.0, 2.0);
+        assert_eq!(ot.find(&cmp).unwrap(), (&pts[0], 1.0));
+        assert_eq!(
+            ot.knn(&cmp, 2).unwrap(),
+            vec![(&pts[0], 1.0), (&pts[1], 2.0)]
+        );
+        assert_eq!(ot.find_r(&p(7.0, 1.0, 1.0), 1.0), Err(Error::NoneInRadius));
+
+        let sorted = ot.sorted(&cmp).map(|(d, _)| d).collect::<Vec<_>>();
+        assert_eq!(sorted, vec![&pts[0], &pts[1], &pts[2], &pts[3]]);
+
+        // Distances to a box are measured to its nearest face
+        let cmp = Cuboid::new(p(5.0, 5.0, 5.0), p(6.0, 6.0, 6.0));
+        assert_eq!(ot.find(&cmp).unwrap(), (&pts[3], 3f64.sqrt()));
+    }
+}
@@ -0,0 +1,216 @@
+use std::fmt::{Display, Formatter};
+use std::iter::Chain;
+use std::slice::Iter;
+
+use geo::GeoNum;
+
+use super::geom::*;
+use crate::Error;
+use crate::node::{Branch, Limits};
+
+/// Node for an [`crate::Octree`].
+///
+/// Subdivides by the same [`Limits`] as the [`crate::Node`] implementations,
+/// but into eight sub-nodes. A datum is pushed into the octant holding its
+/// centre only if it fits entirely within it, otherwise it is stuck here.
+#[derive(Debug)]
+pub(crate) struct OctNode<D, T>
+where
+    T: GeoNum,
+{
+    bounds: Cuboid<T>,
+    limits: Limits,
+    pub(crate) children: Vec<D>,
+    pub(crate) stuck_children: Vec<D>,
+    pub(crate) nodes: Option<Box<[OctNode<D, T>; 8]>>,
+}
+
+impl<D, T> OctNode<D, T>
+where
+    D: AsGeom3<T>,
+    T: GeoNum,
+{
+    pub(crate) fn new(bounds: Cuboid<T>, limits: Limits) -> Self {
+        Self {
+            bounds,
+            limits,
+            children: Vec::new(),
+            stuck_children: Vec::new(),
+            nodes: None,
+        }
+    }
+
+    // Getters
+    pub(crate) fn bounds(&self) -> &Cuboid<T> {
+        &self.bounds
+    }
+
+    /// All data held by this node, including stuck children.
+    pub(crate) fn children(&self) -> Chain<Iter<'_, D>, Iter<'_, D>> {
+        self.children.iter().chain(&self.stuck_children)
+    }
+
+    fn subdivide(&mut self) {
+        let nodes = self
+            .limits
+            .sub_nodes(octant_bounds(&self.bounds), Self::new);
+        self.nodes = Some(Box::new(nodes));
+    }
+
+    pub(crate) fn insert(&mut self, datum: D) -> Result<(), Error> {
+        // See notes in the PointQuadTree implementation on take
+        match self.nodes.take() {
+            Some(mut sub_nodes) => {
+                let bbox = datum.as_geom3().bounding_cuboid();
+                let sub_node = &mut sub_nodes[octant_at(&self.bounds, bbox.center())];
+
+                if sub_node.bounds().contains(&bbox) {
+                    sub_node.insert(datum)?
+                } else {
+                    self.stuck_children.push(datum);
+                }
+
+                self.nodes = Some(sub_nodes);
+            }
+            // See notes in PointQuadTree implementation
+            None if self.limits.must_subdivide(self.children.len()) => {
+                self.subdivide();
+
+                let mut children = std::mem::take(&mut self.children);
+                children.push(datum);
+
+                for child in children {
+                    self.insert(child)?;
+                }
+            }
+            None => self.children.push(datum),
+        }
+
+        Ok(())
+    }
+
+    /// Visit the data of this node, then descend into the octant that wholly
+    /// holds the bbox, or else every octant it overlaps, with the same
+    /// semantics as the [`crate::BoundsQuadTree`] retrieve.
+    pub(crate) fn retrieve<'a>(&'a self, bbox: &Cuboid<T>, f: &mut impl FnMut(&'a D)) {
+        self.children().for_each(&mut *f);
+
+        if let Some(nodes) = &self.nodes {
+            let sub_node = &nodes[octant_at(&self.bounds, bbox.center())];
+            if sub_node.bounds().contains(bbox) {
+                sub_node.retrieve(bbox, f);
+            } else {
+                for sub_node in nodes.iter().filter(|n| n.bounds.intersects(bbox)) {
+                    sub_node.descendants(f);
+                }
+            }
+        }
+    }
+
+    /// Visit all data at or below this node in preorder.
+    pub(crate) fn descendants<'a>(&'a self, f: &mut impl FnMut(&'a D)) {
+        self.children().for_each(&mut *f);
+
+        if let Some(nodes) = &self.nodes {
+            nodes.iter().for_each(|node| node.descendants(f));
+        }
+    }
+
+    // Custom display mirroring the Node trait's display
+    fn display(&self, f: &mut Formatter) -> std::fmt::Result
+    where
+        T: Display,
+    {
+        let indent = " ".repeat(self.limits.depth as usize * 4);
+        let min = self.bounds.min();
+        let count = self.children().count();
+        let children = if count == 0 {
+            "".to_owned()
+        } else if count == 1 {
+            " 1 child".to_owned()
+        } else {
+            format!(" {count} children")
+        };
+
+        writeln!(
+            f,
+            "{indent}({:.2}, {:.2}, {:.2}):{children}",
+            min.x, min.y, min.z
+        )?;
+
+        if let Some(nodes) = &self.nodes {
+            for node in &**nodes {
+                node.display(f)?
+            }
+        };
+        write!(f, "")
+    }
+}
+
+impl<D, T> Display for OctNode<D, T>
+where
+    D: AsGeom3<T>,
+    T: GeoNum + Display,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.display(f)
+    }
+}
+
+impl<'a, D, T> Branch<'a, D> for &'a OctNode<D, T>
+where
+    D: AsGeom3<T>,
+    T: GeoNum,
+{
+    fn data(self) -> impl Iterator<Item = &'a D> {
+        self.children()
+    }
+
+    fn branches(self) -> impl Iterator<Item = Self> {
+        self.nodes.iter().flat_map(|nodes| nodes.iter())
+    }
+}
+
+/// Iterator over every datum in an [`crate::Octree`] in preorder.
+pub struct OctreeIter<'a, D, T>
+where
+    T: GeoNum,
+{
+    stack: Vec<&'a OctNode<D, T>>,
+    current: Chain<Iter<'a, D>, Iter<'a, D>>,
+}
+
+impl<'a, D, T> OctreeIter<'a, D, T>
+where
+    D: AsGeom3<T>,
+    T: GeoNum,
+{
+    pub(crate) fn new(root: &'a OctNode<D, T>) -> Self {
+        Self {
+            stack: vec![root],
+            current: [].iter().chain(&[]),
+        }
+    }
+}
+
+impl<'a, D, T> Iterator for OctreeIter<'a, D, T>
+where
+    D: AsGeom3<T>,
+    T: GeoNum,
+{
+    type Item = &'a D;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(datum) = self.current.next() {
+                return Some(datum);
+            }
+
+            let node = self.stack.pop()?;
+            self.current = node.children();
+            if let Some(nodes) = &node.nodes {
+                self.stack.extend(nodes.iter().rev());
+            }
+        }
+    }
+}
@@ -0,0 +1,441 @@
+mod node;
+
+use std::collections::HashSet;
+use std::slice::Iter;
+
+use super::knn::{knn_approx_unique_by, knn_by, knn_unique_by};
+use super::sorted::{sorted_by, sorted_desc_by};
+use crate::*;
+use geo::{BoundingRect, GeoNum, Line, Rect};
+use node::*;
+
+/// A PMR [`QuadTree`] implementation for line networks.
+///
+/// Each `Line` or `LineString` inserted is broken into its segments, and
+/// every segment is held by each leaf it crosses, so no datum ever gets stuck
+/// high in the tree the way long linestrings do in a [`BoundsQuadTree`]. A
+/// leaf whose segment count exceeds the `threshold` is split as the insert
+/// that overflows it lands, but only once: its segments are handed to the new
+/// sub-nodes without splitting them further. The shape of the tree therefore
+/// depends on insertion order, but in exchange stays shallow around dense
+/// clusters of segments that cannot be separated.
+///
+/// Alongside the datum-level [`QuadTreeSearch`] methods, segment-level
+/// searches report which segment of a linestring is closest, by its index in
+/// [`geo::LineString::lines`].
+///
+/// Only `Line` and `LineString` geometries are supported, anything else fails
+/// to insert with [`Error::UnsupportedGeometry`].
+#[derive(Debug)]
+pub struct PmrQuadTree<D, T>
+where
+    D: AsGeom<T>,
+    T: GeoNum,
+{
+    root: PmrNode<T>,
+    max_depth: u8,
+    threshold: usize,
+    data: Vec<D>,
+    segments: Vec<Segment<T>>,
+    calc_method: CalcMethod<T>,
+}
+
+impl<D, T> PmrQuadTree<D, T>
+where
+    D: AsGeom<T>,
+    T: GeoNum,
+{
+    /// Create a new PMR QuadTree, where a leaf holding more than `threshold`
+    /// segments is split on insert, unless it is at `max_depth`.
+    pub fn new(
+        bounds: Rect<T>,
+        calc_method: CalcMethod<T>,
+        max_depth: u8,
+        threshold: usize,
+    ) -> Self {
+        PmrQuadTree::private_new(bounds, calc_method, Some(max_depth), Some(threshold))
+    }
+
+    /// Create a new PMR QuadTree using default values for max_depth and
+    /// threshold, the latter being the default max_children.
+    pub fn from_bounds(bounds: Rect<T>, calc_method: CalcMethod<T>) -> Self {
+        PmrQuadTree::private_new(bounds, calc_method, None, None)
+    }
+
+    /// Return the bounds of the QuadTree.
+    pub fn bounds(&self) -> &Rect<T> {
+        self.root.bounds()
+    }
+
+    /// Return the number of segments held across all data.
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    // Private constructor
+    fn private_new(
+        bounds: Rect<T>,
+        calc_method: CalcMethod<T>,
+        max_depth: Option<u8>,
+        threshold: Option<usize>,
+    ) -> Self {
+        Self {
+            root: PmrNode::new(bounds, 0),
+            max_depth: max_depth.unwrap_or(DEFAULT_MAX_DEPTH),
+            threshold: threshold.unwrap_or(DEFAULT_MAX_CHILDREN),
+            data: Vec::new(),
+            segments: Vec::new(),
+            calc_method,
+        }
+    }
+
+    fn root_ref(&self) -> PmrRef<'_, T> {
+        PmrRef {
+            node: &self.root,
+            segments: &self.segments,
+        }
+    }
+}
+
+// Break a datum into its segments, or fail for non-linear geometries
+fn lines<T>(geom: &GeometryRef<T>) -> Result<Vec<Line<T>>, Error>
+where
+    T: GeoNum,
+{
+    match geom {
+        GeometryRef::Line(d) => Ok(vec![**d]),
+        GeometryRef::LineString(d) => Ok(d.lines().collect()),
+        _ => Err(Error::UnsupportedGeometry),
+    }
+}
+
+impl<D, T> QuadTree<D, T> for PmrQuadTree<D, T>
+where
+    D: AsGeom<T>,
+    T: GeoNum,
+{
+    fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    fn insert(&mut self, datum: D) -> Result<(), Error> {
+        let lines = lines(&datum.as_geom())?;
+        let db = datum
+            .as_geom()
+            .bounding_rect()
+            .ok_or(Error::CannotMakeBbox)?;
+
+        // Cannot use Rect::contains here, see notes on rect_in_rect for why
+        if !rect_in_rect(self.root.bounds(), &db) {
+            return Err(Error::OutOfBounds);
+        }
+
+        let datum_idx = self.data.len();
+        self.data.push(datum);
+
+        for (index, line) in lines.into_iter().enumerate() {
+            let idx = self.segments.len();
+            self.segments.push(Segment {
+                datum: datum_idx,
+                index,
+                line,
+            });
+            self.root
+                .insert(idx, &self.segments, self.threshold, self.max_depth);
+        }
+
+        Ok(())
+    }
+
+    fn retrieve<'a>(&'a self, datum: &D) -> impl Iterator<Item = &'a D> + use<'a, D, T>
+    where
+        D: 'a,
+    {
+        // Each datum is returned once, in the order its segments are first met
+        let mut found = vec![];
+        if let Some(bbox) = datum.as_geom().bounding_rect() {
+            let mut seen = HashSet::new();
+            self.root.retrieve(&bbox, &mut |idx| {
+                let datum = self.segments[idx].datum;
+                if seen.insert(datum) {
+                    found.push(&self.data[datum]);
+                }
+            });
+        }
+
+        found.into_iter()
+    }
+}
+
+impl<D, T> PmrQuadTree<D, T>
+where
+    D: AsGeom<T>,
+    T: QtFloat,
+{
+    /// Find the closest segment to the passed geometry, returning its datum,
+    /// the index of the segment within the datum, and the distance.
+    pub fn find_segment<X>(&self, cmp: &X) -> Result<(&D, usize, T), Error>
+    where
+        X: AsGeom<T>,
+    {
+        self.find_segment_r(cmp, T::infinity())
+    }
+
+    /// As [`PmrQuadTree::find_segment`], but only search within radius `r`.
+    pub fn find_segment_r<X>(&self, cmp: &X, r: T) -> Result<(&D, usize, T), Error>
+    where
+        X: AsGeom<T>,
+    {
+        let mut found = self.knn_segments_r(cmp, 1, r)?;
+
+        match found.pop() {
+            Some(item) => Ok(item),
+            None if self.data.is_empty() => Err(Error::Empty),
+            None => Err(Error::NoneInRadius),
+        }
+    }
+
+    /// Find the `k` closest segments to the passed geometry, returning the
+    /// datum, segment index, and distance for each. Several segments of the
+    /// same datum may appear.
+    pub fn knn_segments<X>(&self, cmp: &X, k: usize) -> Result<Vec<(&D, usize, T)>, Error>
+    where
+        X: AsGeom<T>,
+    {
+        self.knn_segments_r(cmp, k, T::infinity())
+    }
+
+    /// As [`PmrQuadTree::knn_segments`], but only search within radius `r`.
+    pub fn knn_segments_r<X>(&self, cmp: &X, k: usize, r: T) -> Result<Vec<(&D, usize, T)>, Error>
+    where
+        X: AsGeom<T>,
+    {
+        let cmp = cmp.with_calc(self.calc_method.clone());
+
+        // Segments are shared between leaves, so knn_by dedups them
+        let found = knn_by(
+            self.root_ref(),
+            |node| cmp.dist_bbox(node.node.bounds()),
+            |seg| cmp.dist_geom(&GeometryRef::Line(&seg.line)),
+            k,
+            r,
+        )?;
+
+        Ok(found
+            .into_iter()
+            .map(|(seg, d)| (&self.data[seg.datum], seg.index, d))
+            .collect())
+    }
+}
+
+impl<D, T> QuadTreeSearch<D, T> for PmrQuadTree<D, T>
+where
+    D: AsGeom<T>,
+    T: QtFloat,
+{
+    fn calc_method(&self) -> CalcMethod<T> {
+        self.calc_method.clone()
+    }
+
+    fn insertion_index(&self, datum: &D) -> Option<usize> {
+        index_in(&self.data, datum)
+    }
+
+    fn find_r<X>(&self, cmp: &X, r: T) -> Result<(&D, T), Error>
+    where
+        X: AsGeom<T>,
+    {
+        self.find_segment_r(cmp, r).map(|(datum, _, d)| (datum, d))
+    }
+
+    fn knn_r<X>(&self, cmp: &X, k: usize, r: T) -> Result<Vec<(&D, T)>, Error>
+    where
+        X: AsGeom<T>,
+    {
+        let cmp = cmp.with_calc(self.calc_method());
+
+        // The closest segment of each datum stands in for the datum
+        let found = knn_unique_by(
+            self.root_ref(),
+            |node| cmp.dist_bbox(node.node.bounds()),
+            |seg| cmp.dist_geom(&GeometryRef::Line(&seg.line)),
+            |seg| seg.datum,
+            k,
+            r,
+        )?;
+
+        Ok(found
+            .into_iter()
+            .map(|(seg, d)| (&self.data[seg.datum], d))
+            .collect())
+    }
+
+    fn knn_approx<X>(&self, cmp: &X, k: usize, epsilon: T) -> Result<Vec<(&D, T)>, Error>
+    where
+        X: AsGeom<T>,
+    {
+        let cmp = cmp.with_calc(self.calc_method());
+
+        // As knn_r, the closest segment of each datum stands in for it
+        let found = knn_approx_unique_by(
+            self.root_ref(),
+            |node| cmp.dist_bbox(node.node.bounds()),
+            |seg| cmp.dist_geom(&GeometryRef::Line(&seg.line)),
+            |seg| seg.datum,
+            k,
+            epsilon,
+        )?;
+
+        Ok(found
+            .into_iter()
+            .map(|(seg, d)| (&self.data[seg.datum], d))
+            .collect())
+    }
+
+    fn sorted<'a, X>(&'a self, cmp: &'a X) -> impl Iterator<Item = (&'a D, T)> + 'a
+    where
+        D: 'a,
+        X: AsGeom<T> + 'a,
+    {
+        let cmp = cmp.with_calc(self.calc_method());
+        let mut seen = HashSet::new();
+
+        // Segments come out in distance order, so the first segment of each
+        // datum gives its distance
+        let node_cmp = cmp.clone();
+        sorted_by(
+            self.root_ref(),
+            move |node| node_cmp.dist_bbox(node.node.bounds()),
+            move |seg| cmp.dist_geom(&GeometryRef::Line(&seg.line)),
+        )
+        .filter(move |(seg, _)| seen.insert(seg.datum))
+        .map(|(seg, d)| (&self.data[seg.datum], d))
+    }
+
+    fn sorted_desc<'a, X>(&'a self, cmp: &'a X) -> impl Iterator<Item = (&'a D, T)> + 'a
+    where
+        D: 'a,
+        X: AsGeom<T> + 'a,
+    {
+        let cmp = cmp.with_calc(self.calc_method());
+        let mut seen = HashSet::new();
+
+        // The farthest segment says nothing about the datum's distance, so
+        // measure the whole datum, which is never further than any segment
+        let (min_cmp, max_cmp) = (cmp.clone(), cmp.clone());
+        sorted_desc_by(
+            self.root_ref(),
+            move |node| min_cmp.dist_bbox(node.node.bounds()),
+            move |node| max_cmp.max_dist_bbox(node.node.bounds()),
+            move |seg| cmp.dist_geom(&self.data[seg.datum].as_geom()),
+        )
+        .filter(move |(seg, _)| seen.insert(seg.datum))
+        .map(|(seg, d)| (&self.data[seg.datum], d))
+    }
+}
+
+impl<'a, D, T> IntoIterator for &'a PmrQuadTree<D, T>
+where
+    D: AsGeom<T>,
+    T: GeoNum,
+{
+    type Item = &'a D;
+    type IntoIter = Iter<'a, D>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.iter()
+    }
+}
+
+impl<D, T> std::fmt::Display for PmrQuadTree<D, T>
+where
+    D: AsGeom<T>,
+    T: GeoNum + std::fmt::Display,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "PMR Quadtree Root:")?;
+        write!(f, "{}", self.root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::{LineString, coord, line_string};
+
+    fn bounds() -> Rect {
+        Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 8.0, y: 8.0})
+    }
+
+    #[test]
+    fn segments_are_held_by_every_leaf_they_cross() {
+        let mut qt = PmrQuadTree::new(bounds(), CalcMethod::Euclidean, 2, 2);
+
+        // The third insert splits the root, and the long diagonal crosses
+        // three of the four sub-nodes
+        let a: LineString = line_string![(x: 1.0, y: 1.0), (x: 2.0, y: 1.0)];
+        let b: LineString = line_string![(x: 6.0, y: 6.0), (x: 7.0, y: 6.0)];
+        let c: LineString = line_string![(x: 1.0, y: 7.0), (x: 7.0, y: 2.0)];
+        qt.insert(a.clone()).unwrap();
+        qt.insert(b.clone()).unwrap();
+        qt.insert(c.clone()).unwrap();
+
+        let nodes = qt.root.nodes.as_ref().unwrap();
+        let counts = nodes.iter().map(|n| n.segments.len()).collect::<Vec<_>>();
+        assert_eq!(counts, vec![1, 1, 2, 1]);
+
+        let cmp: LineString = line_string![(x: 5.5, y: 5.5), (x: 6.5, y: 6.5)];
+        assert_eq!(qt.retrieve(&cmp).collect::<Vec<_>>(), vec![&b, &c]);
+        assert_eq!(qt.size(), 3);
+        assert_eq!(qt.segment_count(), 3);
+    }
+
+    #[test]
+    fn leaves_split_only_once_per_insert() {
+        let mut qt = PmrQuadTree::new(bounds(), CalcMethod::Euclidean, 4, 1);
+
+        // Both segments land in the same sub-node, which is left over the
+        // threshold rather than split again
+        qt.insert(Line::new(coord! {x: 0.5, y: 0.5}, coord! {x: 1.0, y: 0.5}))
+            .unwrap();
+        qt.insert(Line::new(coord! {x: 0.5, y: 1.0}, coord! {x: 1.0, y: 1.0}))
+            .unwrap();
+
+        let nodes = qt.root.nodes.as_ref().unwrap();
+        assert!(nodes[0].nodes.is_none());
+        assert_eq!(nodes[0].segments.len(), 2);
+    }
+
+    #[test]
+    fn segment_search_reports_the_segment_index() {
+        let mut qt = PmrQuadTree::new(bounds(), CalcMethod::Euclidean, 3, 2);
+        let path: LineString =
+            line_string![(x: 1.0, y: 1.0), (x: 7.0, y: 1.0), (x: 7.0, y: 7.0), (x: 1.0, y: 7.0)];
+        let other: LineString = line_string![(x: 3.0, y: 5.0), (x: 4.0, y: 5.0)];
+        qt.insert(path.clone()).unwrap();
+        qt.insert(other.clone()).unwrap();
+
+        let cmp = geo::Point::new(6.5, 5.0);
+        assert_eq!(qt.find_segment(&cmp).unwrap(), (&path, 1, 0.5));
+
+        let knn = qt.knn_segments(&cmp, 3).unwrap();
+        let found = knn.iter().map(|(_, i, _)| *i).collect::<Vec<_>>();
+        assert_eq!(found, vec![1, 2, 0]);
+
+        // Datum-level searches return each linestring once
+        let knn = qt.knn(&cmp, 3).unwrap();
+        assert_eq!(knn, vec![(&path, 0.5), (&other, 2.5)]);
+        let sorted = qt.sorted(&cmp).map(|(d, _)| d).collect::<Vec<_>>();
+        assert_eq!(sorted, vec![&path, &other]);
+    }
+
+    #[test]
+    fn unsupported_geometries_fail_to_insert() {
+        let mut qt = PmrQuadTree::<geo::Point, f64>::from_bounds(bounds(), CalcMethod::Euclidean);
+        assert_eq!(
+            qt.insert(geo::Point::new(1.0, 1.0)),
+            Err(Error::UnsupportedGeometry)
+        );
+        assert_eq!(qt.find(&geo::Point::new(1.0, 1.0)), Err(Error::Empty));
+    }
+}
@@ -0,0 +1,183 @@
+use std::fmt::{Display, Formatter};
+
+use geo::{GeoNum, Intersects, Line, Rect};
+
+use crate::node::{Branch, sub_node_bounds};
+
+/// A single segment of a datum held in a [`crate::PmrQuadTree`], with the index of
+/// its datum in the tree and its own index within that datum.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Segment<T>
+where
+    T: GeoNum,
+{
+    pub(crate) datum: usize,
+    pub(crate) index: usize,
+    pub(crate) line: Line<T>,
+}
+
+/// Node for a [`crate::PmrQuadTree`].
+///
+/// Leaves hold the indices of every segment that crosses them, so a segment
+/// may be held by many leaves. Nodes never hold segments once split.
+#[derive(Debug)]
+pub(crate) struct PmrNode<T>
+where
+    T: GeoNum,
+{
+    bounds: Rect<T>,
+    depth: u8,
+    pub(crate) segments: Vec<usize>,
+    pub(crate) nodes: Option<Box<[PmrNode<T>; 4]>>,
+}
+
+impl<T> PmrNode<T>
+where
+    T: GeoNum,
+{
+    pub(crate) fn new(bounds: Rect<T>, depth: u8) -> Self {
+        Self {
+            bounds,
+            depth,
+            segments: Vec::new(),
+            nodes: None,
+        }
+    }
+
+    // Getters
+    pub(crate) fn bounds(&self) -> &Rect<T> {
+        &self.bounds
+    }
+
+    /// Insert the segment at `idx` into every leaf it crosses. Any leaf that
+    /// ends up over the threshold is split, but only once, so its new
+    /// sub-nodes may themselves be over the threshold until a later insert
+    /// reaches them.
+    pub(crate) fn insert(
+        &mut self,
+        idx: usize,
+        segments: &[Segment<T>],
+        threshold: usize,
+        max_depth: u8,
+    ) {
+        match &mut self.nodes {
+            Some(nodes) => {
+                for node in nodes.iter_mut() {
+                    if segments[idx].line.intersects(node.bounds()) {
+                        node.insert(idx, segments, threshold, max_depth);
+                    }
+                }
+            }
+            None => {
+                self.segments.push(idx);
+                if self.segments.len() > threshold && self.depth < max_depth {
+                    self.split(segments);
+                }
+            }
+        }
+    }
+
+    // Split a leaf once, pushing its segments into the sub-nodes they cross
+    fn split(&mut self, segments: &[Segment<T>]) {
+        let depth = self.depth + 1;
+        let mut nodes = sub_node_bounds(&self.bounds).map(|b| PmrNode::new(b, depth));
+
+        for idx in std::mem::take(&mut self.segments) {
+            for node in nodes.iter_mut() {
+                if segments[idx].line.intersects(node.bounds()) {
+                    node.segments.push(idx);
+                }
+            }
+        }
+
+        self.nodes = Some(Box::new(nodes));
+    }
+
+    /// Visit, in preorder, the segments of every leaf that intersects the
+    /// bbox.
+    pub(crate) fn retrieve(&self, bbox: &Rect<T>, f: &mut impl FnMut(usize)) {
+        match &self.nodes {
+            Some(nodes) => {
+                for node in nodes.iter().filter(|n| n.bounds.intersects(bbox)) {
+                    node.retrieve(bbox, f);
+                }
+            }
+            None => self.segments.iter().for_each(|idx| f(*idx)),
+        }
+    }
+
+    // Custom display mirroring the Node trait's display
+    fn display(&self, f: &mut Formatter) -> std::fmt::Result
+    where
+        T: Display,
+    {
+        let indent = " ".repeat(self.depth as usize * 4);
+        let min = self.bounds.min();
+        let count = self.segments.len();
+        let segments = if count == 0 {
+            "".to_owned()
+        } else if count == 1 {
+            " 1 segment".to_owned()
+        } else {
+            format!(" {count} segments")
+        };
+
+        writeln!(f, "{indent}({:.2}, {:.2}):{segments}", min.x, min.y)?;
+
+        if let Some(nodes) = &self.nodes {
+            for node in &**nodes {
+                node.display(f)?
+            }
+        };
+        write!(f, "")
+    }
+}
+
+impl<T> Display for PmrNode<T>
+where
+    T: GeoNum + Display,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.display(f)
+    }
+}
+
+/// Handle onto a node together with the tree's segments, so the search
+/// algorithms can walk segments rather than indices.
+pub(crate) struct PmrRef<'a, T>
+where
+    T: GeoNum,
+{
+    pub(crate) node: &'a PmrNode<T>,
+    pub(crate) segments: &'a [Segment<T>],
+}
+
+impl<T> Clone for PmrRef<'_, T>
+where
+    T: GeoNum,
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for PmrRef<'_, T> where T: GeoNum {}
+
+impl<'a, T> Branch<'a, Segment<T>> for PmrRef<'a, T>
+where
+    T: GeoNum,
+{
+    fn data(self) -> impl Iterator<Item = &'a Segment<T>> {
+        let segments = self.segments;
+        self.node.segments.iter().map(move |idx| &segments[*idx])
+    }
+
+    fn branches(self) -> impl Iterator<Item = Self> {
+        let segments = self.segments;
+        self.node
+            .nodes
+            .iter()
+            .flat_map(|nodes| nodes.iter())
+            .map(move |node| PmrRef { node, segments })
+    }
+}
@@ -1,9 +1,10 @@
 mod node;
 
-use super::knn::knn;
-use super::sorted::{SortIter, sorted};
+use super::knn::{knn, knn_approx};
+use super::sorted::{sorted, sorted_desc};
+use super::stats::stats;
 use crate::*;
-use geo::{Coord, GeoNum, Point, Rect};
+use geo::{Coord, CoordNum, GeoNum, Point, Rect};
 use node::PointNode;
 
 /// Trait required for an item to be useable in a [`crate::PointQuadTree`].
@@ -12,15 +13,16 @@ use node::PointNode;
 /// This trait comes implemented for [`Coordinate`] and [`Point`], so
 /// coords and points can be used in quadtrees directly.
 ///
-/// We only constrain by [`GeoNum`] here as floats are not required if we are not
-/// using distance-based search methods.
+/// We only constrain by [`CoordNum`] here as floats are not required if we are
+/// not using distance-based search methods, and integer coordinates outside
+/// [`GeoNum`] can be used in an [`crate::IntQuadTree`].
 ///
 /// We do not provide implementations for non-point shapes. It is up for the
 /// user to decide how to generate a point from a polygon, etc. if they wish to
 /// use them as points.
 pub trait AsPoint<T = f64>
 where
-    T: GeoNum,
+    T: CoordNum,
 {
     fn as_point(&self) -> Point<T>;
 }
@@ -28,7 +30,7 @@ where
 // We turn a Point into a datum so it can be used in the qt directly
 impl<T> AsPoint<T> for Coord<T>
 where
-    T: GeoNum,
+    T: CoordNum,
 {
     fn as_point(&self) -> Point<T> {
         Point::from(*self)
@@ -37,7 +39,7 @@ where
 
 impl<T> AsPoint<T> for Point<T>
 where
-    T: GeoNum,
+    T: CoordNum,
 {
     fn as_point(&self) -> Point<T> {
         *self
@@ -63,15 +65,18 @@ where
     D: AsPoint<T>,
     T: GeoNum,
 {
-    root: PointNode<D, T>,
+    arena: Arena<PointNode<D, T>>,
 
     // Maintain a count for size
     // Could calculate this each time, but it only saves usize memory
     size: usize,
 
-    calc_method: CalcMethod,
+    calc_method: CalcMethod<T>,
 }
 
+/// Read-only cursor onto a node of a [`PointQuadTree`], see [`NodeRef`].
+pub type PointNodeRef<'a, D, T> = NodeRef<'a, PointNode<D, T>, D, T>;
+
 impl<D, T> PointQuadTree<D, T>
 where
     D: AsPoint<T>,
@@ -80,7 +85,7 @@ where
     /// Create a new Point QuadTree.
     pub fn new(
         bounds: Rect<T>,
-        calc_method: CalcMethod,
+        calc_method: CalcMethod<T>,
         max_depth: u8,
         max_children: usize,
     ) -> Self {
@@ -89,14 +94,26 @@ where
 
     /// Create a new Point QuadTree using default values for max_depth and
     /// max_children.
-    pub fn from_bounds(bounds: Rect<T>, calc_method: CalcMethod) -> Self {
+    pub fn from_bounds(bounds: Rect<T>, calc_method: CalcMethod<T>) -> Self {
         PointQuadTree::private_new(bounds, calc_method, None, None)
     }
 
+    /// Return a read-only cursor onto the root node, from which the tree
+    /// structure can be walked. See [`NodeRef`].
+    pub fn root(&self) -> PointNodeRef<'_, D, T> {
+        NodeRef::new(&self.arena)
+    }
+
+    /// Summarise the structure of the QuadTree, reporting node counts,
+    /// depth, leaf occupancy and suggested configuration. See [`TreeStats`].
+    pub fn stats(&self) -> TreeStats {
+        stats(&self.arena, &[], 0)
+    }
+
     // Private constructor
     fn private_new(
         bounds: Rect<T>,
-        calc_method: CalcMethod,
+        calc_method: CalcMethod<T>,
         max_depth: Option<u8>,
         max_children: Option<usize>,
     ) -> Self {
@@ -104,7 +121,10 @@ where
         let max_children = max_children.unwrap_or(DEFAULT_MAX_CHILDREN);
 
         Self {
-            root: PointNode::new(bounds, 0, max_depth, max_children),
+            arena: Arena::new(PointNode::new(
+                bounds,
+                Limits::root(max_depth, max_children),
+            )),
             size: 0,
             calc_method,
         }
@@ -116,16 +136,14 @@ where
     D: AsPoint<T>,
     T: GeoNum,
 {
-    type Node = PointNode<D, T>;
-
     fn size(&self) -> usize {
         self.size
     }
 
     fn insert(&mut self, pt: D) -> Result<(), Error> {
         // Cannot use Rect::contains here, see notes on pt_in_rect for why
-        if pt_in_rect(&self.root.bounds(), &pt.as_point()) {
-            self.root.insert(pt)?;
+        if pt_in_rect(self.arena.root().bounds(), &pt.as_point()) {
+            PointNode::insert(&mut self.arena, 0, pt, self.size)?;
             self.size += 1;
             Ok(())
         } else {
@@ -133,12 +151,15 @@ where
         }
     }
 
-    fn retrieve(&self, pt: &D) -> DatumIter<'_, Self::Node, D, T> {
+    fn retrieve<'a>(&'a self, pt: &D) -> impl Iterator<Item = &'a D> + use<'a, D, T>
+    where
+        D: 'a,
+    {
         // Bounds check first - capturing out of bounds here
         // This trusts the Node implementation to act correctly
         // Cannot use Rect::contains here, see notes on pt_in_rect for why
-        if pt_in_rect(&self.root.bounds(), &pt.as_point()) {
-            self.root.retrieve(pt)
+        if pt_in_rect(self.arena.root().bounds(), &pt.as_point()) {
+            self.arena.root().retrieve(&self.arena, pt)
         } else {
             DatumIter::Empty
         }
@@ -150,10 +171,12 @@ where
     D: AsGeom<T> + AsPoint<T>,
     T: QtFloat,
 {
-    type Node = PointNode<D, T>;
+    fn calc_method(&self) -> CalcMethod<T> {
+        self.calc_method.clone()
+    }
 
-    fn calc_method(&self) -> CalcMethod {
-        self.calc_method
+    fn insertion_index(&self, datum: &D) -> Option<usize> {
+        insertion_seq(&self.arena, datum)
     }
 
     fn find_r<X>(&self, cmp: &X, r: T) -> Result<(&D, T), Error>
@@ -161,17 +184,17 @@ where
         X: AsGeom<T>,
     {
         // Convert the comparison geometry to something we can work with internally
-        let cmp = cmp.with_calc(self.calc_method());
+        let cmp = cmp.with_calc(self.calc_method()).over_points();
 
         // Error early if invalid
-        if cmp.dist_bbox(self.root.bounds())? != T::zero() {
+        if cmp.dist_bbox(self.arena.root().bounds())? != T::zero() {
             return Err(Error::OutOfBounds);
         }
         if self.size == 0 {
             return Err(Error::Empty);
         }
 
-        let mut stack = vec![&self.root];
+        let mut stack = vec![self.arena.root()];
         let mut min_dist = r;
         let mut min_item = Err(Error::NoneInRadius);
 
@@ -199,10 +222,8 @@ where
             }
 
             // Push nodes onto the stack in reverse order
-            if let Some(sub_nodes) = node.nodes() {
-                for i in 0..4 {
-                    stack.push(&sub_nodes[3 - i]);
-                }
+            if let Some(sub_nodes) = node.nodes(&self.arena) {
+                stack.extend(sub_nodes.iter().rev());
             }
         }
 
@@ -213,14 +234,40 @@ where
     where
         X: AsGeom<T>,
     {
-        knn(&self.root, cmp.with_calc(self.calc_method()), k, r)
+        knn(
+            &self.arena,
+            cmp.with_calc(self.calc_method()).over_points(),
+            k,
+            r,
+        )
     }
 
-    fn sorted<'a, X>(&'a self, cmp: &'a X) -> SortIter<'a, Self::Node, D, T>
+    fn knn_approx<X>(&self, cmp: &X, k: usize, epsilon: T) -> Result<Vec<(&D, T)>, Error>
     where
+        X: AsGeom<T>,
+    {
+        knn_approx(
+            &self.arena,
+            cmp.with_calc(self.calc_method()).over_points(),
+            k,
+            epsilon,
+        )
+    }
+
+    fn sorted<'a, X>(&'a self, cmp: &'a X) -> impl Iterator<Item = (&'a D, T)> + 'a
+    where
+        D: 'a,
+        X: AsGeom<T> + 'a,
+    {
+        sorted(&self.arena, cmp.with_calc(self.calc_method()).over_points())
+    }
+
+    fn sorted_desc<'a, X>(&'a self, cmp: &'a X) -> impl Iterator<Item = (&'a D, T)> + 'a
+    where
+        D: 'a,
         X: AsGeom<T> + 'a,
     {
-        sorted(&self.root, cmp.with_calc(self.calc_method()))
+        sorted_desc(&self.arena, cmp.with_calc(self.calc_method()).over_points())
     }
 }
 
@@ -233,7 +280,7 @@ where
     type IntoIter = DatumIter<'a, PointNode<D, T>, D, T>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.root.descendants()
+        self.arena.root().descendants(&self.arena)
     }
 }
 
@@ -244,11 +291,12 @@ where
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "Point Quadtree Root:")?;
-        write!(f, "{}", self.root)
+        self.arena.root().display(&self.arena, f)
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use geo::{Point, coord};
 
@@ -276,9 +324,9 @@ mod tests {
         let pt3 = MyData(0.1, 0.8);
 
         // Initially will be no sub-nodes, no children
-        let root = &qt.root;
+        let root = qt.arena.root();
         assert_eq!(root.depth(), 0);
-        assert_eq!(root.nodes.is_none(), true);
+        assert_eq!(root.quad.is_none(), true);
         assert_eq!(root.children.len(), 0);
 
         qt.insert(pt1).unwrap();
@@ -287,22 +335,22 @@ mod tests {
         qt.insert(pt3).unwrap();
 
         // Insert four points, still no sub-nodes, but now four children
-        let root = &qt.root;
-        assert_eq!(root.nodes.is_none(), true);
+        let root = qt.arena.root();
+        assert_eq!(root.quad.is_none(), true);
         assert_eq!(root.children.len(), 4);
 
         qt.insert(pt2).unwrap();
 
         // Fifth point, now subdivided, with four in the first sub-node
-        let root = &qt.root;
-        let nodes = root.nodes.as_ref().unwrap();
+        let root = qt.arena.root();
+        let nodes = root.nodes(&qt.arena).unwrap();
         let n0 = &nodes[0];
         assert_eq!(root.children.len(), 0);
         assert_eq!(nodes.len(), 4);
 
         // n0 takes 4 children
         assert_eq!(n0.depth(), 1);
-        assert_eq!(n0.nodes.is_none(), true);
+        assert_eq!(n0.quad.is_none(), true);
         assert_eq!(n0.children.len(), 4);
 
         // n3 takes 1 child and the others are empty
@@ -329,9 +377,114 @@ mod tests {
 
         // All points the same value should immediately max out the depth
         // With the value putting them all in the top left
-        let node = &qt.root.nodes.as_ref().unwrap()[0].nodes.as_ref().unwrap()[0];
+        let node = &qt.arena.root().nodes(&qt.arena).unwrap()[0]
+            .nodes(&qt.arena)
+            .unwrap()[0];
         assert_eq!(qt.size(), 3);
         assert_eq!(node.depth(), 2);
         assert_eq!(node.children.len(), 3);
     }
+
+    #[test]
+    fn stats_reports_structure_and_saturated_leaves() {
+        let origin: Point = Point::new(0.0, 0.0);
+        let mut qt = PointQuadTree::new(
+            Rect::new(origin.0, coord! { x: 1.0, y: 1.0 }),
+            CalcMethod::Euclidean,
+            2,
+            2,
+        );
+
+        // Three identical points subdivide to max depth and then stack
+        let pt1 = MyData(0.1, 0.1);
+        qt.insert(pt1).unwrap();
+        qt.insert(pt1).unwrap();
+        qt.insert(pt1).unwrap();
+
+        let stats = qt.stats();
+        assert_eq!(stats.nodes, 9);
+        assert_eq!(stats.leaves, 7);
+        assert_eq!(stats.depth, 2);
+        assert_eq!(stats.children_per_leaf, vec![6, 0, 0, 1]);
+        assert_eq!(stats.saturated_leaves, 1);
+        assert_eq!(stats.stuck_children, 0);
+
+        // All nine nodes share the arena, and the three data in the saturated
+        // leaf, and their sequence numbers, fit inline with the smallvec feature
+        let children = if cfg!(feature = "smallvec") {
+            0
+        } else {
+            3 * (std::mem::size_of::<MyData>() + std::mem::size_of::<usize>())
+        };
+        assert_eq!(
+            stats.heap_bytes,
+            9 * std::mem::size_of::<PointNode<MyData, f64>>() + children
+        );
+
+        // No depth splits identical points, but most leaves are empty
+        assert_eq!(stats.coincident_leaves, 1);
+        assert_eq!(stats.suggested_max_depth, 2);
+        assert_eq!(stats.suggested_max_children, 4);
+
+        // A distinct point in the same leaf can be split off one level down
+        qt.insert(MyData(0.2, 0.2)).unwrap();
+        let stats = qt.stats();
+        assert_eq!(stats.saturated_leaves, 1);
+        assert_eq!(stats.coincident_leaves, 0);
+        assert_eq!(stats.suggested_max_depth, 3);
+    }
+
+    #[test]
+    fn geodesic_knn_over_dense_local_points_prunes_and_matches_brute_force() {
+        use crate::geodesic::math::dist_pt_pt;
+        use crate::quadtrees::knn::knn_by;
+        use approx::assert_abs_diff_eq;
+        use std::cell::Cell;
+
+        // A 40 by 40 grid of points about 50 m apart, well inside the 42 km
+        // that lower bounds on segments have to allow for
+        let deg = |x: f64, y: f64| Point::new(x.to_radians(), y.to_radians());
+        let bounds = Rect::new(deg(-0.01, 51.5).0, deg(0.01, 51.52).0);
+        let mut qt = PointQuadTree::new(bounds, CalcMethod::Geodesic, 8, 4);
+        let pts = (0..40)
+            .flat_map(|i| (0..40).map(move |j| (i, j)))
+            .map(|(i, j)| deg(-0.00975 + 0.0005 * i as f64, 51.50025 + 0.0005 * j as f64))
+            .collect::<Vec<_>>();
+        for pt in &pts {
+            qt.insert(*pt).unwrap();
+        }
+
+        let cmp = deg(0.00312, 51.50707);
+        let calc = cmp.with_calc(CalcMethod::Geodesic).over_points();
+        let visited = Cell::new(0);
+        let found = knn_by(
+            NodeRef::new(&qt.arena),
+            |node| {
+                visited.set(visited.get() + 1);
+                calc.dist_bbox(node.bounds())
+            },
+            |child: &Point| calc.dist_geom(&child.as_geom()),
+            5,
+            f64::INFINITY,
+        )
+        .unwrap();
+
+        let mut brute = pts
+            .iter()
+            .map(|pt| dist_pt_pt(&cmp, pt))
+            .collect::<Vec<_>>();
+        brute.sort_by(f64::total_cmp);
+        assert_eq!(found.len(), 5);
+        for ((_, d), e) in found.iter().zip(&brute) {
+            assert_abs_diff_eq!(*d, *e, epsilon = 1e-6);
+        }
+        let dists = |res: Vec<(&Point, f64)>| res.into_iter().map(|(_, d)| d).collect::<Vec<_>>();
+        assert_eq!(dists(qt.knn(&cmp, 5).unwrap()), dists(found));
+
+        // Only the nodes around the comparator are opened
+        assert!(visited.get() * 4 < qt.stats().nodes);
+
+        // A comparator 70 m outside the root is rejected
+        assert_eq!(qt.find(&deg(0.011, 51.51)), Err(Error::OutOfBounds));
+    }
 }
@@ -1,4 +1,3 @@
-use std::fmt::{Display, Formatter};
 use std::marker::PhantomData;
 
 use crate::*;
@@ -12,11 +11,11 @@ where
     T: GeoNum,
 {
     bounds: Rect<T>,
-    depth: u8,
-    max_depth: u8,
-    max_children: usize,
-    pub children: Vec<D>,
-    pub nodes: Option<Box<[PointNode<D, T>; 4]>>,
+    limits: Limits,
+    pub(crate) children: Children<D>,
+    // Insertion sequence numbers, in step with the children
+    seqs: Children<usize>,
+    pub(crate) quad: Option<QuadId>,
     _num_type: PhantomData<T>,
 }
 
@@ -25,14 +24,13 @@ where
     D: AsPoint<T>,
     T: GeoNum,
 {
-    fn new(bounds: Rect<T>, depth: u8, max_depth: u8, max_children: usize) -> Self {
+    fn new(bounds: Rect<T>, limits: Limits) -> Self {
         Self {
             bounds,
-            depth,
-            max_depth,
-            max_children,
-            children: Vec::new(),
-            nodes: None,
+            limits,
+            children: Children::new(),
+            seqs: Children::new(),
+            quad: None,
             _num_type: PhantomData,
         }
     }
@@ -46,77 +44,74 @@ where
         &self.bounds
     }
 
-    fn depth(&self) -> u8 {
-        self.depth
+    fn limits(&self) -> Limits {
+        self.limits
     }
 
-    fn max_depth(&self) -> u8 {
-        self.max_depth
+    fn quad(&self) -> Option<QuadId> {
+        self.quad
     }
 
-    fn max_children(&self) -> usize {
-        self.max_children
-    }
-
-    fn nodes(&self) -> &Option<Box<[PointNode<D, T>; 4]>> {
-        &self.nodes
+    fn children(&self) -> DatumIter<'_, Self, D, T> {
+        DatumIter::Slice(self.children.iter())
     }
 
-    fn children(&self) -> DatumIter<Self, D, T> {
-        DatumIter::Slice(self.children.iter())
+    fn heap_bytes(&self) -> usize {
+        children_heap_bytes(&self.children) + children_heap_bytes(&self.seqs)
     }
 
     // Setters
-    fn set_nodes(&mut self, nodes: Option<Box<[Self; 4]>>) {
-        self.nodes = nodes;
+    fn set_quad(&mut self, quad: Option<QuadId>) {
+        self.quad = quad;
     }
 
-    fn insert(&mut self, datum: D) -> Result<(), Error> {
-        // Take ownership of the sub-nodes before matching to enable the insertion
-        // This, apparently, is a very common pattern
-        // Works here because we replace the nodes at the end, and the None branch
-        // is unaffected. Overall a more ergonomic solution than the alterantive
-        // `let sub_nodes = self.nodes.as_mut().unwrap()`
-        match self.nodes.take() {
-            // If we have sub-nodes already, pass down the tree
-            Some(mut sub_nodes) => {
-                let sub_node_idx = self.find_sub_node(&datum).ok_or(Error::CannotFindSubNode)?;
-                sub_nodes[sub_node_idx as usize].insert(datum)?;
+    fn insert(arena: &mut Arena<Self>, id: usize, datum: D, seq: usize) -> Result<(), Error> {
+        let node = &mut arena[id];
 
-                // Make sure to replace the nodes
-                self.nodes = Some(sub_nodes);
+        match node.quad {
+            // If we have sub-nodes already, pass down the tree
+            Some(quad) => {
+                let sub_node_idx = node.find_sub_node(&datum).ok_or(Error::CannotFindSubNode)?;
+                Self::insert(arena, quad.sub_node(sub_node_idx as usize), datum, seq)?;
             }
             // If there is no room left, subdivide and push all children down
             // Subdivision does not happen if we've exceeded the max depth,
             // which takes priority over the children length
-            None if self.children.len() >= self.max_children && !(self.depth >= self.max_depth) => {
-                self.subdivide();
-
+            None if node.limits.must_subdivide(node.children.len()) => {
                 // Replace the old children with a new empty vector
                 // and push the new point on last to preserve ordering
-                let mut children = std::mem::replace(&mut self.children, Vec::new());
+                let mut children = std::mem::take(&mut node.children);
                 children.push(datum);
+                let mut seqs = std::mem::take(&mut node.seqs);
+                seqs.push(seq);
+
+                Self::subdivide(arena, id);
 
                 // Now consume the original children vector
-                for pt in children {
-                    self.insert(pt)?;
+                for (pt, seq) in children.into_iter().zip(seqs) {
+                    Self::insert(arena, id, pt, seq)?;
                 }
             }
             // Otherwise can simply push the point
             None => {
-                self.children.push(datum);
+                node.children.push(datum);
+                node.seqs.push(seq);
             }
         }
 
         Ok(())
     }
 
+    fn held_seq(&self, datum: &D) -> Option<usize> {
+        index_in(&self.children, datum).map(|i| self.seqs[i])
+    }
+
     // Pulls all children within the node that would contain the passed point
-    fn retrieve(&self, datum: &D) -> DatumIter<'_, PointNode<D, T>, D, T> {
-        match &self.nodes {
+    fn retrieve<'a>(&'a self, arena: &'a Arena<Self>, datum: &D) -> DatumIter<'a, Self, D, T> {
+        match self.nodes(arena) {
             Some(nodes) => {
                 if let Some(sn) = self.find_sub_node(datum) {
-                    nodes[sn as usize].retrieve(datum)
+                    nodes[sn as usize].retrieve(arena, datum)
                 } else {
                     DatumIter::Empty
                 }
@@ -125,13 +120,3 @@ where
         }
     }
 }
-
-impl<D, T> Display for PointNode<D, T>
-where
-    D: AsPoint<T>,
-    T: GeoNum + Display,
-{
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        self.display(f)
-    }
-}
@@ -0,0 +1,297 @@
+mod node;
+
+use geo::{Coord, Point, Polygon, Rect};
+use num_traits::NumCast;
+
+use super::morton::sub_node;
+use crate::*;
+pub use node::RegionNode;
+
+/// A region quadtree over a 2D grid of values, such as land-use classes or
+/// elevation bands.
+///
+/// The grid covers `bounds` with `width` by `height` equal cells, with row
+/// zero along the top (minimum `y`) edge to match the [`SubNode`] layout.
+/// The tree is built once from a row-major buffer, splitting the grid into
+/// quadrants using the same `Rect` bounds as the other quadtrees, and any
+/// quadrant whose cells all share a value collapses into a single leaf. Grids
+/// that are not a power-of-two square are padded past their right and bottom
+/// edges with cells that hold no value, so the root may extend beyond
+/// `bounds`.
+///
+/// Values only need [`PartialEq`] to be merged.
+#[derive(Debug)]
+pub struct RegionQuadTree<V, T>
+where
+    T: QtFloat,
+{
+    root: RegionNode<V, T>,
+    bounds: Rect<T>,
+    width: usize,
+    height: usize,
+    order: u8,
+}
+
+impl<V, T> RegionQuadTree<V, T>
+where
+    V: PartialEq,
+    T: QtFloat,
+{
+    /// Create a new Region QuadTree from a row-major buffer of `width` by
+    /// `height` values covering `bounds`.
+    ///
+    /// Returns [`Error::InvalidBuffer`] if either dimension is zero or the
+    /// buffer does not hold exactly `width * height` values.
+    pub fn from_buffer(
+        bounds: Rect<T>,
+        width: usize,
+        height: usize,
+        buffer: Vec<V>,
+    ) -> Result<Self, Error> {
+        if width == 0 || height == 0 || width.checked_mul(height) != Some(buffer.len()) {
+            return Err(Error::InvalidBuffer);
+        }
+
+        let side = width.max(height).next_power_of_two();
+        let order = side.trailing_zeros() as u8;
+
+        // Extend the bounds to cover the padded square of cells
+        let scale = |n: usize, len: T| -> Result<T, Error> {
+            let n = <T as NumCast>::from(n).ok_or(Error::InvalidBuffer)?;
+            let side = <T as NumCast>::from(side).ok_or(Error::InvalidBuffer)?;
+            Ok(len / n * side)
+        };
+        let min = bounds.min();
+        let max = Coord {
+            x: min.x + scale(width, bounds.width())?,
+            y: min.y + scale(height, bounds.height())?,
+        };
+
+        let mut cells = buffer.into_iter().map(Some).collect::<Vec<_>>();
+        let root = RegionNode::build(
+            &mut cells,
+            width,
+            height,
+            Rect::new(min, max),
+            0,
+            (0, 0),
+            side,
+        );
+
+        Ok(Self {
+            root,
+            bounds,
+            width,
+            height,
+            order,
+        })
+    }
+}
+
+impl<V, T> RegionQuadTree<V, T>
+where
+    T: QtFloat,
+{
+    /// Return the bounds of the grid.
+    pub fn bounds(&self) -> &Rect<T> {
+        &self.bounds
+    }
+
+    /// Return the number of columns in the grid.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Return the number of rows in the grid.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Return the root node of the tree.
+    pub fn root(&self) -> &RegionNode<V, T> {
+        &self.root
+    }
+
+    /// Return the value of the cell containing the passed point, or `None`
+    /// if it falls outside the grid. Points on a shared cell edge take the
+    /// cell to their right or below, except on the far edges of the grid.
+    pub fn value_at(&self, pt: &Point<T>) -> Option<&V> {
+        if !pt_in_rect(&self.bounds, pt) {
+            return None;
+        }
+
+        let cell = |v: T, min: T, len: T, n: usize| -> Option<usize> {
+            let n_t = <T as NumCast>::from(n)?;
+            Some(((v - min) / len * n_t).floor().to_usize()?.min(n - 1))
+        };
+        let min = self.bounds.min();
+        let col = cell(pt.x(), min.x, self.bounds.width(), self.width)?;
+        let row = cell(pt.y(), min.y, self.bounds.height(), self.height)?;
+
+        // Read a bit of each of the column and row at every level down
+        let mut node = &self.root;
+        while let Some(nodes) = &node.nodes {
+            let shift = self.order - node.depth - 1;
+            let quadrant = (((row >> shift) & 1) << 1) | ((col >> shift) & 1);
+            node = &nodes[sub_node(quadrant as u64) as usize];
+        }
+        node.value.as_ref()
+    }
+
+    /// Iterate over the bounds and value of every leaf that holds a value,
+    /// in preorder.
+    pub fn leaves(&self) -> impl Iterator<Item = (&Rect<T>, &V)> {
+        let mut leaves = vec![];
+        self.root
+            .visit_leaves(&|_| true, &mut |b, v| leaves.push((b, v)));
+        leaves.into_iter()
+    }
+
+    /// Total the area covered by each distinct value within the passed
+    /// rectangle. Cells without a value are not counted.
+    ///
+    /// Values are returned with their area in the order they are first
+    /// reached in a preorder walk of the tree.
+    pub fn aggregate_rect(&self, region: &Rect<T>) -> Vec<(&V, T)>
+    where
+        V: PartialEq,
+    {
+        self.aggregate_by(|b| rect_overlap_area(b, region))
+    }
+
+    /// Total the area covered by each distinct value within the passed
+    /// polygon, respecting any holes. Cells without a value are not counted.
+    ///
+    /// Values are returned with their area in the order they are first
+    /// reached in a preorder walk of the tree.
+    pub fn aggregate(&self, region: &Polygon<T>) -> Vec<(&V, T)>
+    where
+        V: PartialEq,
+    {
+        self.aggregate_by(|b| polygon_area_in_rect(region, b))
+    }
+
+    // Shared aggregation, given the area of a node's bounds inside the region.
+    // Nodes with no area in the region are pruned along with their subtree
+    fn aggregate_by(&self, area: impl Fn(&Rect<T>) -> T) -> Vec<(&V, T)>
+    where
+        V: PartialEq,
+    {
+        let mut totals: Vec<(&V, T)> = vec![];
+
+        self.root
+            .visit_leaves(&|b| area(b) > T::zero(), &mut |b, v| {
+                let a = area(b);
+                match totals.iter_mut().find(|(tv, _)| *tv == v) {
+                    Some((_, total)) => *total = *total + a,
+                    None => totals.push((v, a)),
+                }
+            });
+
+        totals
+    }
+}
+
+impl<V, T> std::fmt::Display for RegionQuadTree<V, T>
+where
+    V: std::fmt::Display,
+    T: QtFloat + std::fmt::Display,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Region Quadtree Root:")?;
+        write!(f, "{}", self.root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use geo::{coord, polygon};
+
+    use super::*;
+
+    // A 4x4 grid with a uniform top left quadrant and a mixed bottom right
+    fn grid() -> RegionQuadTree<u8, f64> {
+        let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 4.0, y: 4.0});
+        #[rustfmt::skip]
+        let buffer = vec![
+            1, 1, 2, 2,
+            1, 1, 2, 2,
+            3, 3, 1, 2,
+            3, 3, 3, 1,
+        ];
+        RegionQuadTree::from_buffer(bounds, 4, 4, buffer).unwrap()
+    }
+
+    #[test]
+    fn homogeneous_quadrants_merge_into_single_leaves() {
+        let qt = grid();
+        let nodes = qt.root().nodes.as_ref().unwrap();
+
+        // Fixed order of iteration tl, tr, br, bl
+        assert_eq!(nodes[0].value, Some(1));
+        assert!(nodes[0].nodes.is_none());
+        assert_eq!(nodes[1].value, Some(2));
+        assert!(nodes[2].nodes.is_some());
+        assert_eq!(nodes[3].value, Some(3));
+        assert_eq!(qt.leaves().count(), 7);
+
+        // A uniform grid is a single leaf
+        let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 1.0, y: 1.0});
+        let qt = RegionQuadTree::from_buffer(bounds, 2, 2, vec![5; 4]).unwrap();
+        assert!(qt.root().nodes.is_none());
+        assert_eq!(qt.root().value, Some(5));
+    }
+
+    #[test]
+    fn value_at_reads_the_containing_cell() {
+        let qt = grid();
+
+        assert_eq!(qt.value_at(&Point::new(0.5, 0.5)), Some(&1));
+        assert_eq!(qt.value_at(&Point::new(3.5, 2.5)), Some(&2));
+        assert_eq!(qt.value_at(&Point::new(2.5, 3.5)), Some(&3));
+        assert_eq!(qt.value_at(&Point::new(4.0, 4.0)), Some(&1));
+        assert_eq!(qt.value_at(&Point::new(4.1, 0.0)), None);
+    }
+
+    #[test]
+    fn non_square_grids_are_padded_without_values() {
+        let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 3.0, y: 1.0});
+        let qt = RegionQuadTree::from_buffer(bounds, 3, 1, vec![7, 7, 8]).unwrap();
+
+        assert_eq!(qt.root().bounds().max(), coord! {x: 4.0, y: 4.0});
+        assert_eq!(qt.value_at(&Point::new(2.5, 0.5)), Some(&8));
+        assert_eq!(qt.value_at(&Point::new(2.5, 1.5)), None);
+        assert_eq!(
+            qt.aggregate_rect(qt.root().bounds()),
+            vec![(&7, 2.0), (&8, 1.0)]
+        );
+
+        assert_eq!(
+            RegionQuadTree::from_buffer(bounds, 3, 1, vec![7, 7]).unwrap_err(),
+            Error::InvalidBuffer
+        );
+    }
+
+    #[test]
+    fn aggregates_area_by_value_over_rect_and_polygon() {
+        let qt = grid();
+
+        let res = qt.aggregate_rect(&Rect::new(coord! {x: 1.0, y: 1.0}, coord! {x: 3.0, y: 3.0}));
+        assert_eq!(res, vec![(&1, 2.0), (&2, 1.0), (&3, 1.0)]);
+
+        // Triangle over the top left quadrant, with a hole in value 1
+        let tri = polygon!(
+            exterior: [(x: 0.0, y: 0.0), (x: 4.0, y: 0.0), (x: 0.0, y: 4.0)],
+            interiors: [[(x: 0.5, y: 0.5), (x: 1.0, y: 0.5), (x: 1.0, y: 1.0), (x: 0.5, y: 1.0)]],
+        );
+        let res = qt.aggregate(&tri);
+        assert_eq!(res.len(), 3);
+        assert_eq!(res[0].0, &1);
+        assert_abs_diff_eq!(res[0].1, 3.75);
+        assert_eq!(res[1].0, &2);
+        assert_abs_diff_eq!(res[1].1, 2.0);
+        assert_eq!(res[2].0, &3);
+        assert_abs_diff_eq!(res[2].1, 2.0);
+    }
+}
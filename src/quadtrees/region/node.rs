@@ -0,0 +1,171 @@
+use std::fmt::{Display, Formatter};
+
+use geo::Rect;
+
+use crate::node::sub_node_bounds;
+use crate::*;
+
+/// Node for a [`RegionQuadTree`].
+///
+/// Leaves cover a square block of cells that all share one value, or `None`
+/// for padding beyond the edge of the grid. Branches hold no value, just
+/// their four sub-nodes in [`SubNode`] order.
+#[derive(Debug)]
+pub struct RegionNode<V, T>
+where
+    T: QtFloat,
+{
+    pub(crate) bounds: Rect<T>,
+    pub(crate) depth: u8,
+    pub(crate) value: Option<V>,
+    pub(crate) nodes: Option<Box<[RegionNode<V, T>; 4]>>,
+}
+
+impl<V, T> RegionNode<V, T>
+where
+    V: PartialEq,
+    T: QtFloat,
+{
+    /// Build the node covering the block of `side` cells with its top left
+    /// cell at `(col, row)`, merging sub-nodes that turn out homogeneous.
+    /// Values are taken out of the row-major `cells` as they are used.
+    pub(crate) fn build(
+        cells: &mut [Option<V>],
+        width: usize,
+        height: usize,
+        bounds: Rect<T>,
+        depth: u8,
+        (col, row): (usize, usize),
+        side: usize,
+    ) -> Self {
+        // Blocks entirely off the grid are padding and need no splitting
+        if col >= width || row >= height {
+            return Self::leaf(bounds, depth, None);
+        }
+        if side == 1 {
+            return Self::leaf(bounds, depth, cells[row * width + col].take());
+        }
+
+        let half = side / 2;
+        // Fixed order of iteration tl, tr, br, bl
+        let offsets = [(0, 0), (half, 0), (half, half), (0, half)];
+        let mut sub_bounds = sub_node_bounds(&bounds).into_iter();
+        let nodes = offsets.map(|(dc, dr)| {
+            let b = sub_bounds
+                .next()
+                .expect("Unreachable, always four sub-node bounds.");
+            Self::build(
+                cells,
+                width,
+                height,
+                b,
+                depth + 1,
+                (col + dc, row + dr),
+                half,
+            )
+        });
+
+        let homogeneous = nodes
+            .iter()
+            .all(|n| n.nodes.is_none() && n.value == nodes[0].value);
+
+        if homogeneous {
+            let [first, ..] = nodes;
+            Self::leaf(bounds, depth, first.value)
+        } else {
+            Self {
+                bounds,
+                depth,
+                value: None,
+                nodes: Some(Box::new(nodes)),
+            }
+        }
+    }
+
+    fn leaf(bounds: Rect<T>, depth: u8, value: Option<V>) -> Self {
+        Self {
+            bounds,
+            depth,
+            value,
+            nodes: None,
+        }
+    }
+}
+
+impl<V, T> RegionNode<V, T>
+where
+    T: QtFloat,
+{
+    /// Return the bounds of the node.
+    pub fn bounds(&self) -> &Rect<T> {
+        &self.bounds
+    }
+
+    /// Return the depth of the node, zero at the root.
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    /// Return the value shared by every cell of a leaf. `None` for branches
+    /// and for padding beyond the edge of the grid.
+    pub fn value(&self) -> Option<&V> {
+        self.value.as_ref()
+    }
+
+    /// Return the sub-nodes in [`SubNode`] order, or `None` for leaves.
+    pub fn sub_nodes(&self) -> Option<&[RegionNode<V, T>; 4]> {
+        self.nodes.as_deref()
+    }
+
+    /// Walk the tree, calling `f` with the bounds and value of every leaf
+    /// that holds a value and which `keep` does not rule out. `keep` is
+    /// checked on branches too, so whole subtrees can be skipped.
+    pub(crate) fn visit_leaves<'a>(
+        &'a self,
+        keep: &impl Fn(&Rect<T>) -> bool,
+        f: &mut impl FnMut(&'a Rect<T>, &'a V),
+    ) {
+        if !keep(&self.bounds) {
+            return;
+        }
+
+        match (&self.nodes, &self.value) {
+            (Some(nodes), _) => nodes.iter().for_each(|n| n.visit_leaves(keep, f)),
+            (None, Some(value)) => f(&self.bounds, value),
+            (None, None) => {}
+        }
+    }
+
+    // Custom display mirroring the Node trait's display
+    fn display(&self, f: &mut Formatter) -> std::fmt::Result
+    where
+        V: Display,
+        T: Display,
+    {
+        let indent = " ".repeat(self.depth as usize * 4);
+        let min = self.bounds.min();
+        let value = match (&self.nodes, &self.value) {
+            (None, Some(value)) => format!(" {value}"),
+            _ => "".to_owned(),
+        };
+
+        writeln!(f, "{indent}({:.2}, {:.2}):{value}", min.x, min.y)?;
+
+        if let Some(nodes) = &self.nodes {
+            for node in &**nodes {
+                node.display(f)?
+            }
+        };
+        write!(f, "")
+    }
+}
+
+impl<V, T> Display for RegionNode<V, T>
+where
+    V: Display,
+    T: QtFloat + Display,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.display(f)
+    }
+}
@@ -1,116 +1,278 @@
+use std::collections::{BinaryHeap, HashSet};
+use std::iter::Peekable;
+
+use crate::node::{Branch, Queued};
 use crate::*;
 
 /// Iterator to output QuadTree data in distance-sorted order.
 ///
 /// Due to the additional requirement for supporting arbitrary test types, this
-/// is not unifed with [`DatumIter`].
-pub struct SortIter<'a, N, D, T>
+/// is not unifed with [`DatumIter`]. Generic over the node layout `B` and the
+/// node and datum distance functions `N` and `C`, in the same way as the knn
+/// implementation.
+pub struct SortIter<'a, B, D, T, N, C>
 where
-    N: Node<D, T>,
-    D: AsGeom<T>,
+    B: Branch<'a, D>,
+    D: 'a,
     T: QtFloat,
+    N: Fn(B) -> Result<T, Error>,
+    C: Fn(&D) -> Result<T, Error>,
 {
-    // We work on a tuple of a node/child enum and its distance to the comparator
-    stack: Vec<(NodeType<'a, N, D, T>, T)>,
-    cmp: GeomCalc<'a, T>,
-    is_sorted: bool,
+    // Priority queue of nodes and children keyed on distance to the comparator
+    queue: BinaryHeap<Queued<'a, B, D, T>>,
+    dist_node: N,
+    dist_datum: C,
+    // Data already returned, used to skip repeat references to the same
+    // datum, which may reach the top of the queue long after the first
+    seen: HashSet<*const D>,
 }
 
-impl<'a, N, D, T> Iterator for SortIter<'a, N, D, T>
+impl<'a, B, D, T, N, C> Iterator for SortIter<'a, B, D, T, N, C>
 where
-    N: Node<D, T>,
-    D: AsGeom<T>,
+    B: Branch<'a, D>,
+    D: 'a,
     T: QtFloat,
+    N: Fn(B) -> Result<T, Error>,
+    C: Fn(&D) -> Result<T, Error>,
 {
     type Item = (&'a D, T);
 
     fn next(&mut self) -> Option<Self::Item> {
-        // 1. Sort the stack in distance-descending order
-        //    Only do this if the is_sorted flag is false
-        if !self.is_sorted {
-            self.stack.sort_unstable_by(|(_, d1), (_, d2)| {
-                d2.partial_cmp(d1)
-                    .expect("Unreachable, NaN distances already removed.")
-            });
-        }
+        // Loop until a datum can be returned, ending when the queue empties
+        loop {
+            let Queued { item, dist: d } = self.queue.pop()?;
 
-        // 2. Return early if the stack is empty
-        let (item, d) = self.stack.pop()?;
-
-        // Now process the rest in a match
-        match item {
-            // 2. Process any children at the top of the stack
-            //    No need to re-sort as no pushing
-            NodeType::Child(child) => Some((child, d)),
-
-            // 3. Push sub-nodes and children onto the stack
-            //    Set the flag to re-sort because new items hve been added
-            //    Then recurse because we have not returned anything
-            NodeType::Node(node) => {
-                for child in node.children() {
-                    if let Some(d) = self
-                        .cmp
-                        .dist_geom(&child.as_geom())
-                        .ok()
-                        .and_then(|d| d.is_finite().then_some(d))
-                    {
-                        self.stack.push((NodeType::Child(child), d));
+            match item {
+                // 1. Return children as they reach the top of the queue
+                NodeType::Child(child) => {
+                    if !self.seen.insert(child as *const D) {
+                        continue;
                     }
+
+                    return Some((child, d));
                 }
 
-                if let Some(nodes) = node.nodes() {
-                    for sub_node in nodes.iter() {
-                        if let Some(d) = self
-                            .cmp
-                            .dist_bbox(sub_node.bounds())
+                // 2. Push sub-nodes and children onto the queue, then go
+                //    round again because we have not returned anything
+                NodeType::Node(node) => {
+                    for child in node.data() {
+                        if let Some(d) = (self.dist_datum)(child)
                             .ok()
                             .and_then(|d| d.is_finite().then_some(d))
                         {
-                            self.stack.push((NodeType::Node(sub_node), d));
+                            self.queue.push(Queued::new(NodeType::Child(child), d));
                         }
                     }
-                }
 
-                self.is_sorted = false;
-                self.next()
+                    for sub_node in node.branches() {
+                        if let Some(d) = (self.dist_node)(sub_node)
+                            .ok()
+                            .and_then(|d| d.is_finite().then_some(d))
+                        {
+                            self.queue.push(Queued::new(NodeType::Node(sub_node), d));
+                        }
+                    }
+                }
             }
+        }
+    }
+}
 
-            // This is PhantomData
-            NodeType::_NumType(_) => unreachable!(),
+impl<'a, B, D, T, N, C> SortIter<'a, B, D, T, N, C>
+where
+    B: Branch<'a, D>,
+    D: 'a,
+    T: QtFloat,
+    N: Fn(B) -> Result<T, Error>,
+    C: Fn(&D) -> Result<T, Error>,
+{
+    // Start from the root at the passed distance, or empty if there is none
+    fn seeded(root: Option<(B, T)>, dist_node: N, dist_datum: C) -> Self {
+        SortIter {
+            queue: root
+                .map(|(root, d)| Queued::new(NodeType::Node(root), d))
+                .into_iter()
+                .collect(),
+            dist_node,
+            dist_datum,
+            seen: HashSet::new(),
         }
     }
 }
 
 /// Private, general, implementation that returns an iterator that produces
-/// QuadTree data in distance sorted order starting at the passed root node.
+/// QuadTree data in distance sorted order starting at the root of the passed
+/// node arena.
 /// This gets around forcing Node to be object safe and doing priv-in-pub to
-/// get access to the root node, as root is just passed here.
+/// get access to the nodes, as the arena is just passed here.
 /// QT implementations can simply delegate to this function.
 /// Note that unlike find and knn, this method tries to not error, skipping over
 /// items it cannot process.
-pub(crate) fn sorted<'a, D, N, T>(root: &'a N, cmp: GeomCalc<'a, T>) -> SortIter<'a, N, D, T>
+pub(crate) fn sorted<'a, D, N, T>(
+    arena: &'a Arena<N>,
+    cmp: GeomCalc<'a, T>,
+) -> impl Iterator<Item = (&'a D, T)> + 'a
 where
     N: Node<D, T>,
-    D: AsGeom<T>,
+    D: AsGeom<T> + 'a,
     T: QtFloat,
+{
+    let node_cmp = cmp.clone();
+    sorted_by(
+        NodeRef::new(arena),
+        move |node| node_cmp.dist_bbox(node.bounds()),
+        move |child| cmp.dist_geom(&child.as_geom()),
+    )
+}
+
+/// As [`sorted`], but farthest first. See [`sorted_desc_by`].
+pub(crate) fn sorted_desc<'a, D, N, T>(
+    arena: &'a Arena<N>,
+    cmp: GeomCalc<'a, T>,
+) -> impl Iterator<Item = (&'a D, T)> + 'a
+where
+    N: Node<D, T>,
+    D: AsGeom<T> + 'a,
+    T: QtFloat,
+{
+    let (min_cmp, max_cmp) = (cmp.clone(), cmp.clone());
+    sorted_desc_by(
+        NodeRef::new(arena),
+        move |node| min_cmp.dist_bbox(node.bounds()),
+        move |node| max_cmp.max_dist_bbox(node.bounds()),
+        move |child| cmp.dist_geom(&child.as_geom()),
+    )
+}
+
+/// The sorted iterator proper, generic over the node layout and over how
+/// distances are measured. See [`super::knn::knn_by`] for the requirements on
+/// the distance functions.
+pub(crate) fn sorted_by<'a, B, D, T, N, C>(
+    root: B,
+    dist_node: N,
+    dist_datum: C,
+) -> SortIter<'a, B, D, T, N, C>
+where
+    B: Branch<'a, D>,
+    D: 'a,
+    T: QtFloat,
+    N: Fn(B) -> Result<T, Error>,
+    C: Fn(&D) -> Result<T, Error>,
 {
     // Simply return an empty iterator if the bbox is out of bounds or the
     // distance calc fails
-    let root_d = cmp
-        .dist_bbox(root.bounds())
+    let root_d = dist_node(root)
         .ok()
         .and_then(|d| (d == T::zero()).then_some(d));
 
-    match root_d {
-        Some(d) => SortIter {
-            stack: vec![(NodeType::Node(root), d)],
-            cmp,
-            is_sorted: false,
-        },
-        None => SortIter {
-            stack: vec![],
-            cmp,
-            is_sorted: true,
-        },
+    SortIter::seeded(root_d.map(|d| (root, d)), dist_node, dist_datum)
+}
+
+/// As [`sorted_by`], but without requiring the root to be at distance zero,
+/// for orderings other than distance from a comparator inside the tree. The
+/// ordering still has to satisfy the same bound as the distance functions.
+pub(crate) fn sorted_unchecked_by<'a, B, D, T, N, C>(
+    root: B,
+    dist_node: N,
+    dist_datum: C,
+) -> SortIter<'a, B, D, T, N, C>
+where
+    B: Branch<'a, D>,
+    D: 'a,
+    T: QtFloat,
+    N: Fn(B) -> Result<T, Error>,
+    C: Fn(&D) -> Result<T, Error>,
+{
+    let root_d = dist_node(root).ok().filter(|d| d.is_finite());
+
+    SortIter::seeded(root_d.map(|d| (root, d)), dist_node, dist_datum)
+}
+
+/// As [`sorted_by`], but in descending distance order. `max_dist_node` must
+/// return an upper bound on the distance to any datum beneath the node, while
+/// `dist_node` is only used to check the comparator is in bounds.
+pub(crate) fn sorted_desc_by<'a, B, D, T>(
+    root: B,
+    dist_node: impl Fn(B) -> Result<T, Error>,
+    max_dist_node: impl Fn(B) -> Result<T, Error> + 'a,
+    dist_datum: impl Fn(&D) -> Result<T, Error> + 'a,
+) -> impl Iterator<Item = (&'a D, T)> + 'a
+where
+    B: Branch<'a, D> + 'a,
+    D: 'a,
+    T: QtFloat + 'a,
+{
+    let in_bounds = dist_node(root).is_ok_and(|d| d == T::zero());
+    let root_d = in_bounds
+        .then(|| max_dist_node(root).ok())
+        .flatten()
+        .filter(|d| d.is_finite());
+
+    // Searching on negated distances pops the farthest first
+    SortIter::seeded(
+        root_d.map(|d| (root, -d)),
+        move |node| max_dist_node(node).map(|d| -d),
+        move |datum: &D| dist_datum(datum).map(|d| -d),
+    )
+    .map(|(datum, d)| (datum, -d))
+}
+
+/// Adapter over a distance-sorted iterator that emits each run of data at the
+/// same distance in `key` order, so ties come out deterministically.
+pub(crate) struct KeyedTies<'a, I, D, T, K, F>
+where
+    I: Iterator<Item = (&'a D, T)>,
+    D: 'a,
+    T: QtFloat,
+    K: Ord,
+    F: Fn(&D) -> K,
+{
+    inner: Peekable<I>,
+    group: std::vec::IntoIter<(&'a D, T)>,
+    key: F,
+}
+
+impl<'a, I, D, T, K, F> KeyedTies<'a, I, D, T, K, F>
+where
+    I: Iterator<Item = (&'a D, T)>,
+    D: 'a,
+    T: QtFloat,
+    K: Ord,
+    F: Fn(&D) -> K,
+{
+    pub(crate) fn new(inner: I, key: F) -> Self {
+        Self {
+            inner: inner.peekable(),
+            group: vec![].into_iter(),
+            key,
+        }
+    }
+}
+
+impl<'a, I, D, T, K, F> Iterator for KeyedTies<'a, I, D, T, K, F>
+where
+    I: Iterator<Item = (&'a D, T)>,
+    D: 'a,
+    T: QtFloat,
+    K: Ord,
+    F: Fn(&D) -> K,
+{
+    type Item = (&'a D, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(item) = self.group.next() {
+            return Some(item);
+        }
+
+        // Pull the next run of equal distances and order it by key
+        let first = self.inner.next()?;
+        let mut group = vec![first];
+        while let Some(item) = self.inner.next_if(|(_, d)| *d == first.1) {
+            group.push(item);
+        }
+        group.sort_by_key(|(datum, _)| (self.key)(datum));
+
+        self.group = group.into_iter();
+        self.group.next()
     }
 }
@@ -0,0 +1,183 @@
+use std::fmt::{Display, Formatter};
+use std::mem::size_of;
+
+use geo::GeoNum;
+
+use crate::arena::Arena;
+use crate::node::Node;
+
+/// Structural summary of a QuadTree, useful for checking whether the chosen
+/// `max_depth` and `max_children` suit the data.
+///
+/// Produced by the `stats` method on each QuadTree implementation. Display
+/// renders a short human-readable report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeStats {
+    /// Total number of nodes in the QuadTree, including the root.
+    pub nodes: usize,
+
+    /// Number of leaf nodes, i.e. nodes without sub-nodes.
+    pub leaves: usize,
+
+    /// The deepest level actually reached, with the root at depth zero.
+    pub depth: u8,
+
+    /// Histogram of children per leaf, where the value at index `n` is the
+    /// number of leaves holding exactly `n` children. In a multi-cell
+    /// [`crate::BoundsQuadTree`] a datum counts once in every leaf it overlaps.
+    pub children_per_leaf: Vec<usize>,
+
+    /// Number of leaves at `max_depth` holding more than `max_children`
+    /// children. These leaves would have subdivided if depth allowed, so
+    /// children stack in them without bound.
+    pub saturated_leaves: usize,
+
+    /// Number of saturated leaves whose children all sit in one place, such
+    /// as duplicate points, which no amount of depth can split.
+    pub coincident_leaves: usize,
+
+    /// Total number of stuck children. Always zero for QuadTree types without
+    /// the concept.
+    pub stuck_children: usize,
+
+    /// Histogram of stuck children by depth, where the value at index `d` is
+    /// the number of stuck children held by nodes at depth `d`.
+    pub stuck_by_depth: Vec<usize>,
+
+    /// Estimated heap footprint in bytes. Counts the node arena, data and
+    /// multi-cell references stored outside of it, but not spare capacity or
+    /// any heap memory owned by the data themselves, so treat it as a lower
+    /// bound.
+    pub heap_bytes: usize,
+
+    /// Suggested `max_depth`. Deeper than the current setting only when
+    /// saturated leaves exist that are not coincident, in which case it is
+    /// deep enough to split the fullest of them down to `max_children`.
+    pub suggested_max_depth: u8,
+
+    /// Suggested `max_children`. Doubles the current setting when most leaves
+    /// are empty, a sign that the tree is subdividing too eagerly.
+    pub suggested_max_children: usize,
+}
+
+impl Display for TreeStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} nodes, {} leaves, depth {}",
+            self.nodes, self.leaves, self.depth
+        )?;
+        writeln!(f, "children per leaf: {:?}", self.children_per_leaf)?;
+        writeln!(
+            f,
+            "saturated leaves: {}, {} coincident",
+            self.saturated_leaves, self.coincident_leaves
+        )?;
+        writeln!(
+            f,
+            "stuck children: {} by depth {:?}",
+            self.stuck_children, self.stuck_by_depth
+        )?;
+        writeln!(f, "estimated heap bytes: {}", self.heap_bytes)?;
+        write!(
+            f,
+            "suggested max_depth: {}, max_children: {}",
+            self.suggested_max_depth, self.suggested_max_children
+        )
+    }
+}
+
+/// Private, general, stats function implementation that takes the node arena,
+/// any tree-level store of shared data, and the bytes of any tree-level
+/// storage outside of the arena. QT implementations can simply delegate to
+/// this function.
+pub(crate) fn stats<N, D, T>(arena: &Arena<N>, store: &[D], store_bytes: usize) -> TreeStats
+where
+    N: Node<D, T>,
+    T: GeoNum,
+{
+    let max_depth = arena.root().max_depth();
+    let max_children = arena.root().max_children();
+
+    let mut stats = TreeStats {
+        nodes: 0,
+        leaves: 0,
+        depth: 0,
+        children_per_leaf: vec![],
+        saturated_leaves: 0,
+        coincident_leaves: 0,
+        stuck_children: 0,
+        stuck_by_depth: vec![],
+        heap_bytes: arena.len() * size_of::<N>() + store_bytes,
+        suggested_max_depth: max_depth,
+        suggested_max_children: max_children,
+    };
+    let mut fullest_saturated = 0;
+
+    // Every node is in the arena, so there is no need to walk the links
+    for node in arena.iter() {
+        let depth = node.depth();
+        stats.nodes += 1;
+        stats.depth = stats.depth.max(depth);
+        stats.heap_bytes += node.heap_bytes();
+
+        // Shared references on a node with sub-nodes are stuck there
+        let shared = node.shared().len();
+        let stuck = match node.quad() {
+            Some(_) => node.stuck_children().count() + shared,
+            None => node.stuck_children().count(),
+        };
+        if stuck > 0 {
+            let d = depth as usize;
+            if stats.stuck_by_depth.len() <= d {
+                stats.stuck_by_depth.resize(d + 1, 0);
+            }
+            stats.stuck_by_depth[d] += stuck;
+            stats.stuck_children += stuck;
+        }
+
+        // Leaves never hold stuck children, so count everything
+        if node.quad().is_none() {
+            let count = node.children().count() + shared;
+            stats.leaves += 1;
+            if stats.children_per_leaf.len() <= count {
+                stats.children_per_leaf.resize(count + 1, 0);
+            }
+            stats.children_per_leaf[count] += 1;
+
+            if depth >= max_depth && count > max_children {
+                stats.saturated_leaves += 1;
+
+                // Extra depth cannot split data that all sit in one place
+                let mut data = node
+                    .children()
+                    .chain(node.shared().iter().map(|&i| &store[i]));
+                let first = data.next();
+                if first.is_some_and(|first| data.all(|d| N::coincident(first, d))) {
+                    stats.coincident_leaves += 1;
+                } else {
+                    fullest_saturated = fullest_saturated.max(count);
+                }
+            }
+        }
+    }
+
+    // Each extra level splits a saturated leaf four ways, so add enough levels
+    // to bring the fullest one back under max_children
+    if fullest_saturated > 0 {
+        let mut extra = 0u8;
+        let mut capacity = max_children.max(1);
+        while capacity < fullest_saturated {
+            capacity = capacity.saturating_mul(4);
+            extra = extra.saturating_add(1);
+        }
+        stats.suggested_max_depth = max_depth.saturating_add(extra);
+    }
+
+    let empty_leaves = stats.children_per_leaf.first().copied().unwrap_or(0);
+    if stats.leaves > 1 && empty_leaves * 2 > stats.leaves {
+        stats.suggested_max_children = max_children.saturating_mul(2).max(1);
+    }
+
+    stats
+}
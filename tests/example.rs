@@ -109,7 +109,7 @@ fn euclidean_point_example() {
     // To return a GeometryRef, we have to wrap the reified type in the right
     // Geometry enum to get proper polymorphism.
     impl AsGeom<f64> for MyDatum {
-        fn as_geom(&self) -> GeometryRef<f64> {
+        fn as_geom(&self) -> GeometryRef<'_, f64> {
             GeometryRef::Point(&self.location)
         }
     }
@@ -192,7 +192,7 @@ fn spherical_point_example() {
     ];
 
     for d in &data {
-        qt.insert(d.clone()).unwrap();
+        qt.insert(*d).unwrap();
     }
 
     // Here we use the spherical calculations dropped into the quadtree's new method
@@ -1,5 +1,10 @@
-use approx::assert_abs_diff_eq;
-use geo::{Distance, Euclidean, Line, Point, Rect, coord};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use approx::{assert_abs_diff_eq, assert_relative_eq};
+use geo::{
+    Distance, Euclidean, Line, LineString, MapCoords, Point, Polygon, Rect, coord, line_string,
+};
 use quadtree::spherical::math::dist_pt_pt;
 use quadtree::*;
 
@@ -21,7 +26,7 @@ fn create_and_retrieve_single_point_returns_vec_of_point() {
     let mut qt = PointQuadTree::from_bounds(bounds, CalcMethod::None);
     let pt1 = Point::new(0.1, 0.1);
 
-    qt.insert(pt1.clone()).unwrap();
+    qt.insert(pt1).unwrap();
 
     assert_eq!(qt.size(), 1);
     assert_eq!(qt.retrieve(&pt1).collect::<Vec<_>>(), vec![&pt1]);
@@ -35,8 +40,8 @@ fn insert_out_of_bounds_doesnt_add_and_retrieve_out_of_bounds_yields_error() {
     let pt1 = Point::new(0.1, 0.1);
     let pt2 = Point::new(2.0, 2.0);
 
-    qt.insert(pt1.clone()).unwrap();
-    let res = qt.insert(pt2.clone());
+    qt.insert(pt1).unwrap();
+    let res = qt.insert(pt2);
 
     assert_eq!(res, Err(Error::OutOfBounds));
     assert_eq!(qt.size(), 1);
@@ -53,12 +58,12 @@ fn iterator_runs_preorder() {
     let pt3 = Point::new(0.1, 0.8);
 
     // Inserting in a random order
-    qt.insert(pt3.clone()).unwrap();
-    qt.insert(pt1.clone()).unwrap();
-    qt.insert(pt2.clone()).unwrap();
-    qt.insert(pt1.clone()).unwrap();
-    qt.insert(pt2.clone()).unwrap();
-    qt.insert(pt1.clone()).unwrap();
+    qt.insert(pt3).unwrap();
+    qt.insert(pt1).unwrap();
+    qt.insert(pt2).unwrap();
+    qt.insert(pt1).unwrap();
+    qt.insert(pt2).unwrap();
+    qt.insert(pt1).unwrap();
 
     assert_eq!(qt.size(), 6);
 
@@ -91,11 +96,11 @@ fn find_returns_closest_in_eucildean_for_point_qt() {
     let p3 = Point::new(0.1, 0.1);
     let p4 = Point::new(0.8, 0.8);
 
-    qt.insert(p1.clone()).unwrap();
-    qt.insert(p2.clone()).unwrap();
-    qt.insert(p3.clone()).unwrap();
-    qt.insert(p4.clone()).unwrap();
-    qt.insert(p4.clone()).unwrap();
+    qt.insert(p1).unwrap();
+    qt.insert(p2).unwrap();
+    qt.insert(p3).unwrap();
+    qt.insert(p4).unwrap();
+    qt.insert(p4).unwrap();
 
     let cmp = Point::new(0.4, 0.39);
     assert_eq!(qt.find(&cmp).unwrap(), (&p1, 0.19));
@@ -112,11 +117,11 @@ fn find_returns_closest_in_spherical_for_point_qt() {
     let p3 = Point::new(0.1, 0.1);
     let p4 = Point::new(0.8, 0.8);
 
-    qt.insert(p1.clone()).unwrap();
-    qt.insert(p2.clone()).unwrap();
-    qt.insert(p3.clone()).unwrap();
-    qt.insert(p4.clone()).unwrap();
-    qt.insert(p4.clone()).unwrap();
+    qt.insert(p1).unwrap();
+    qt.insert(p2).unwrap();
+    qt.insert(p3).unwrap();
+    qt.insert(p4).unwrap();
+    qt.insert(p4).unwrap();
 
     // Make this slightly closer to the x axis
     // Then in spherical the distance is closer to the other point
@@ -146,11 +151,11 @@ fn find_returns_closest_in_euclidean_for_segments_in_bounds_qt() {
     // In the TR
     let d5 = line(0.9, 0.8, 0.9, 0.9);
 
-    qt.insert(d1.clone()).unwrap();
-    qt.insert(d2.clone()).unwrap();
-    qt.insert(d3.clone()).unwrap();
-    qt.insert(d4.clone()).unwrap();
-    qt.insert(d5.clone()).unwrap();
+    qt.insert(d1).unwrap();
+    qt.insert(d2).unwrap();
+    qt.insert(d3).unwrap();
+    qt.insert(d4).unwrap();
+    qt.insert(d5).unwrap();
 
     // Closer to the y-axis
     let cmp = Point::new(0.05, 0.1);
@@ -181,18 +186,231 @@ fn find_returns_closest_in_speherical_in_bounds_qt() {
     let d1 = Line::new(coord!(x: -0.4, y: 0.0), coord!(x: -0.4, y: -0.4));
     let d2 = Line::new(coord!(x: 0.0, y: -0.4), coord!(x: -0.4, y: -0.4));
 
-    qt.insert(d1.clone()).unwrap();
-    qt.insert(d2.clone()).unwrap();
+    qt.insert(d1).unwrap();
+    qt.insert(d2).unwrap();
 
     // Should be closer to the vertical line due to curvature
+    // The distance is cross-track to the meridian through the vertical line
     let cmp = Point::new(-0.2, -0.2);
-    let dist_cmp = dist_pt_pt(&Point::new(-0.2, -0.2), &Point::new(-0.4, -0.2));
+    let dist_cmp = (0.2f64.sin() * 0.2f64.cos()).asin();
+    assert!(dist_cmp < dist_pt_pt(&cmp, &Point::new(-0.4, -0.2)));
     let (datum, dist) = qt.find(&cmp).unwrap();
 
     assert_abs_diff_eq!(dist, dist_cmp);
     assert_eq!(datum, &d1);
 }
 
+#[test]
+fn find_with_line_and_polygon_cmp_in_spherical_bounds_qt() {
+    let bounds = Rect::new(coord! {x: -1.0, y: -1.0}, coord! {x: 1.0, y: 1.0});
+    let mut qt = BoundsQuadTree::new(bounds, CalcMethod::Spherical, 2, 2);
+
+    // Roads either side of the comparators
+    let roads: [LineString; 2] = [
+        line_string![(x: -0.8, y: 0.5), (x: -0.2, y: 0.6), (x: 0.4, y: 0.5)],
+        line_string![(x: -0.5, y: -0.6), (x: 0.5, y: -0.3)],
+    ];
+    for road in &roads {
+        qt.insert(road.clone()).unwrap();
+    }
+
+    // Closest approach is the road's end point to the line
+    let cmp = line(0.3, 0.2, 0.6, 0.4);
+    let (datum, dist) = qt.find(&cmp).unwrap();
+    assert_eq!(datum, &roads[0]);
+    assert_abs_diff_eq!(
+        dist,
+        spherical::math::dist_pt_line(&Point::new(0.4, 0.5), &cmp)
+    );
+
+    // A polygon crossed by a road is at zero distance
+    let cmp = Rect::new(coord! {x: -0.1, y: -0.5}, coord! {x: 0.1, y: -0.3}).to_polygon();
+    assert_eq!(qt.find(&cmp).unwrap(), (&roads[1], 0.0));
+}
+
+#[test]
+fn knn_on_spherical_bounds_qt_matches_brute_force_for_high_latitude_lines() {
+    let bounds = Rect::new(coord! {x: -1.5, y: 0.6}, coord! {x: 1.5, y: 1.4});
+    let mut qt = BoundsQuadTree::new(bounds, CalcMethod::Spherical, 4, 1);
+
+    // Short lines packed near the pole, and long ones whose arcs bulge
+    // north of their bounds
+    let mut data = vec![];
+    for i in 0..12 {
+        let x = -1.4 + i as f64 * 0.25;
+        data.push(line(x, 1.2, x + 0.1, 1.25));
+        data.push(line(x, 0.7 + i as f64 * 0.05, -x, 0.7 + i as f64 * 0.05));
+    }
+    for d in &data {
+        qt.insert(*d).unwrap();
+    }
+
+    for cmp in [(0.0, 1.3), (0.9, 1.35), (-0.3, 0.9), (1.2, 0.65)] {
+        let cmp = Point::new(cmp.0, cmp.1);
+        let mut brute = data
+            .iter()
+            .map(|d| spherical::math::dist_pt_line(&cmp, d))
+            .collect::<Vec<_>>();
+        brute.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let knn = qt.knn(&cmp, 5).unwrap();
+        for ((_, d), b) in knn.iter().zip(&brute) {
+            assert_abs_diff_eq!(*d, *b);
+        }
+    }
+
+    // A long line held in a southern node whose arc reaches into the node
+    // north of it, beating the line held there
+    let mut qt = BoundsQuadTree::new(bounds, CalcMethod::Spherical, 4, 1);
+    let long = line(0.05, 0.95, 1.45, 0.95);
+    qt.insert(long).unwrap();
+    qt.insert(line(0.7, 1.17, 0.8, 1.17)).unwrap();
+    let knn = qt.knn(&Point::new(0.75, 1.1), 2).unwrap();
+    assert_eq!(knn[0].0, &long);
+    assert!(knn[0].1 < knn[1].1);
+}
+
+#[test]
+fn knn_on_spherical_point_qt_finds_neighbours_across_the_antimeridian() {
+    use std::f64::consts::{FRAC_PI_2, PI};
+
+    let bounds = Rect::new(coord! {x: -PI, y: -FRAC_PI_2}, coord! {x: PI, y: FRAC_PI_2});
+    let mut qt = PointQuadTree::new(bounds, CalcMethod::Spherical, 6, 2);
+
+    // Columns of points either side of the dateline, and a few far away
+    let mut data = vec![];
+    for i in 0..8 {
+        let y = -0.4 + i as f64 * 0.1;
+        data.push(Point::new(PI - 0.02 - i as f64 * 0.01, y));
+        data.push(Point::new(-PI + 0.03 + i as f64 * 0.01, y + 0.05));
+        data.push(Point::new(i as f64 * 0.3 - 1.0, y));
+    }
+    for d in &data {
+        qt.insert(*d).unwrap();
+    }
+
+    for cmp in [
+        (PI - 0.001, 0.0),
+        (-PI + 0.001, 0.1),
+        (PI, -0.2),
+        (-3.0, 0.3),
+    ] {
+        let cmp = Point::new(cmp.0, cmp.1);
+        let mut brute = data.iter().map(|d| dist_pt_pt(&cmp, d)).collect::<Vec<_>>();
+        brute.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let knn = qt.knn(&cmp, 6).unwrap();
+        assert!(knn.iter().any(|(d, _)| d.x() > 0.0));
+        assert!(knn.iter().any(|(d, _)| d.x() < 0.0));
+        for ((_, d), b) in knn.iter().zip(&brute) {
+            assert_abs_diff_eq!(*d, *b);
+        }
+        assert_abs_diff_eq!(qt.find(&cmp).unwrap().1, brute[0]);
+    }
+}
+
+#[test]
+fn data_crossing_the_antimeridian_are_found_from_both_sides() {
+    use std::f64::consts::{FRAC_PI_2, PI};
+
+    let bounds = Rect::new(coord! {x: -PI, y: -FRAC_PI_2}, coord! {x: PI, y: FRAC_PI_2});
+    let ls = line_string![(x: 3.0, y: 0.1), (x: -3.0, y: 0.2), (x: -2.9, y: 0.4)];
+    let crossing: [Geometry<f64>; 2] = [
+        ls.clone().into(),
+        Rect::new(coord! {x: -0.1, y: -0.6}, coord! {x: 0.1, y: -0.4})
+            .to_polygon()
+            .map_coords(|c| coord! {x: c.x + if c.x > 0.0 { -3.0 } else { 3.0 }, y: c.y})
+            .into(),
+    ];
+
+    for mut qt in [
+        BoundsQuadTree::new(bounds, CalcMethod::Spherical, 4, 1),
+        BoundsQuadTree::multi_cell(bounds, CalcMethod::Spherical, 4, 1, 8),
+    ] {
+        for datum in &crossing {
+            qt.insert(datum.clone()).unwrap();
+        }
+        for i in 0..10 {
+            let x = i as f64 * 0.6 - 2.7;
+            let road = line_string![(x: x, y: 0.8), (x: x + 0.2, y: 0.9)];
+            qt.insert(road.into()).unwrap();
+        }
+
+        // Split rather than stuck at the root by their planar bounds, and
+        // each is returned once, whichever side it is approached from
+        assert_eq!(qt.root().children().count(), 0);
+        assert!(qt.root().shared().is_empty());
+        assert_eq!(qt.into_iter().count(), 12);
+        for x in [3.1, -3.1] {
+            let cmp = Point::new(x, 0.15);
+            let (datum, d) = qt.find(&cmp).unwrap();
+            assert!(matches!(datum, Geometry::LineString(_)));
+            assert_abs_diff_eq!(d, spherical::math::dist_pt_linestring(&cmp, &ls));
+            assert!(d < 0.05);
+
+            let (datum, d) = qt.find(&Point::new(x, -0.5)).unwrap();
+            assert!(matches!(datum, Geometry::Polygon(_)));
+            assert_eq!(d, 0.0);
+
+            let knn = qt.knn(&Point::new(x, -0.1), 3).unwrap();
+            assert!(matches!(knn[0].0, Geometry::LineString(_)));
+            assert!(matches!(knn[1].0, Geometry::Polygon(_)));
+            assert!(knn[2].1 > 0.5);
+        }
+
+        // And retrieved from either side
+        let near = Rect::new(coord! {x: 3.05, y: 0.0}, coord! {x: 3.1, y: 0.3});
+        assert!(
+            qt.retrieve(&Geometry::from(near))
+                .any(|d| matches!(d, Geometry::LineString(_)))
+        );
+        let cmp = Point::new(-3.1, 0.15);
+        assert_eq!(qt.sorted(&cmp).count(), 12);
+    }
+}
+
+#[test]
+fn sorted_over_data_split_at_the_antimeridian_returns_each_once_in_order() {
+    use std::f64::consts::{FRAC_PI_2, PI};
+
+    let mut seed = 0x853C49E6748FEA9Bu64;
+    let mut rnd = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        (seed >> 11) as f64 / (1u64 << 53) as f64
+    };
+    let bounds = Rect::new(coord! {x: -PI, y: -FRAC_PI_2}, coord! {x: PI, y: FRAC_PI_2});
+
+    for _ in 0..20 {
+        // Lines within a few tenths of the antimeridian, about half crossing
+        let mut lines = vec![];
+        for _ in 0..60 {
+            let (x1, x2) = (PI - rnd() * 0.3, PI - rnd() * 0.3);
+            let x2 = if rnd() < 0.5 { -x2 } else { x2 };
+            let y = rnd() * 2.0 - 1.0;
+            lines.push(Line::new(coord! {x: x1, y: y}, coord! {x: x2, y: y + 0.1}));
+        }
+
+        let mut qt = BoundsQuadTree::new(bounds, CalcMethod::Spherical, 6, 2);
+        for l in &lines {
+            qt.insert(*l).unwrap();
+        }
+
+        let metric = |cmp: &Point, l: &Line| {
+            let calc = cmp.with_calc(CalcMethod::Spherical);
+            calc.dist_geom(&l.as_geom()).unwrap()
+        };
+        for cmp in [(PI - 0.1, 0.2), (-PI + 0.05, -0.4), (0.0, 0.0)] {
+            let cmp = Point::new(cmp.0, cmp.1);
+            let sorted = qt.sorted(&cmp).map(|(_, d)| d).collect::<Vec<_>>();
+            assert_eq!(sorted.len(), qt.size());
+            assert!(sorted.windows(2).all(|w| w[0] <= w[1]));
+            assert_searches_match_brute_force(&qt, &lines, &cmp, metric);
+        }
+    }
+}
+
 #[test]
 fn knn_on_point_qt_returns_k_nodes_in_dist_order() {
     let origin = Point::new(0.0, 0.0);
@@ -203,10 +421,10 @@ fn knn_on_point_qt_returns_k_nodes_in_dist_order() {
     let p2 = Point::new(3.0, 3.0);
     let p3 = Point::new(6.0, 6.0);
 
-    qt.insert(p1.clone()).unwrap();
-    qt.insert(p1.clone()).unwrap();
-    qt.insert(p2.clone()).unwrap();
-    qt.insert(p3.clone()).unwrap();
+    qt.insert(p1).unwrap();
+    qt.insert(p1).unwrap();
+    qt.insert(p2).unwrap();
+    qt.insert(p3).unwrap();
 
     let cmp = Point::new(6.0, 5.0);
     let res = qt.knn_r(&cmp, 3, f64::INFINITY).unwrap();
@@ -230,10 +448,10 @@ fn knn_on_point_qt_stops_at_r() {
     let p2 = Point::new(3.0, 3.0);
     let p3 = Point::new(6.0, 6.0);
 
-    qt.insert(p1.clone()).unwrap();
-    qt.insert(p1.clone()).unwrap();
-    qt.insert(p2.clone()).unwrap();
-    qt.insert(p3.clone()).unwrap();
+    qt.insert(p1).unwrap();
+    qt.insert(p1).unwrap();
+    qt.insert(p2).unwrap();
+    qt.insert(p3).unwrap();
 
     let cmp = Point::new(6.0, 5.0);
     let res = qt.knn_r(&cmp, 3, 4.0).unwrap();
@@ -244,3 +462,1353 @@ fn knn_on_point_qt_stops_at_r() {
     assert_eq!(res[1].0.x_y(), p2.x_y());
     assert_abs_diff_eq!(res[1].1, 13.0f64.sqrt());
 }
+
+#[test]
+fn sorted_drain_on_deep_point_qt_is_ordered_and_matches_knn() {
+    let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 1.0, y: 1.0});
+    let mut qt = PointQuadTree::new(bounds, CalcMethod::Euclidean, 24, 1);
+
+    // A tight diagonal cluster forces a deep, narrow tree
+    let pts = (0..500)
+        .map(|i| Point::new(0.75 + i as f64 * 1e-6, 0.75 + i as f64 * 2e-6))
+        .collect::<Vec<_>>();
+    for pt in &pts {
+        qt.insert(*pt).unwrap();
+    }
+
+    let cmp = Point::new(0.1, 0.2);
+    let sorted = qt.sorted(&cmp).collect::<Vec<_>>();
+    assert_eq!(sorted.len(), pts.len());
+    assert!(sorted.windows(2).all(|w| w[0].1 <= w[1].1));
+
+    let knn = qt.knn(&cmp, 50).unwrap();
+    let dists = |res: &[(&Point, f64)]| res.iter().map(|(_, d)| *d).collect::<Vec<_>>();
+    assert_eq!(dists(&knn), dists(&sorted[..50]));
+}
+
+#[test]
+fn ties_on_point_qt_are_kept_or_broken_by_key() {
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Id(u32, Point);
+
+    impl AsPoint for Id {
+        fn as_point(&self) -> Point {
+            self.1
+        }
+    }
+
+    impl AsGeom<f64> for Id {
+        fn as_geom(&self) -> GeometryRef<'_, f64> {
+            GeometryRef::Point(&self.1)
+        }
+    }
+
+    let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 8.0, y: 8.0});
+    let mut qt = PointQuadTree::new(bounds, CalcMethod::Euclidean, 3, 1);
+
+    // Four data at distance 1 from the comparator, inserted out of id order,
+    // plus one closer and one further away
+    let cmp = Point::new(4.0, 4.0);
+    for (id, x, y) in [
+        (3, 5.0, 4.0),
+        (5, 4.0, 4.5),
+        (1, 4.0, 3.0),
+        (4, 3.0, 4.0),
+        (2, 4.0, 5.0),
+        (6, 6.0, 6.0),
+    ] {
+        qt.insert(Id(id, Point::new(x, y))).unwrap();
+    }
+    let ids = |res: Vec<(&Id, f64)>| res.into_iter().map(|(d, _)| d.0).collect::<Vec<_>>();
+
+    let res = qt.knn_with_ties(&cmp, 2).unwrap();
+    assert_eq!(res.len(), 5);
+    assert!(res[1..].iter().all(|(_, d)| *d == 1.0));
+    assert_eq!(qt.knn_with_ties(&cmp, 6).unwrap().len(), 6);
+
+    assert_eq!(ids(qt.knn_by_key(&cmp, 3, |d| d.0).unwrap()), vec![5, 1, 2]);
+    assert_eq!(
+        ids(qt.knn_by_key(&cmp, 3, |d| std::cmp::Reverse(d.0)).unwrap()),
+        vec![5, 4, 3]
+    );
+    assert_eq!(
+        ids(qt.sorted_by_key(&cmp, |d| d.0).collect()),
+        vec![5, 1, 2, 3, 4, 6]
+    );
+
+    // Equidistant from 1 and 5
+    let cmp = Point::new(4.0, 3.75);
+    assert_eq!(qt.find_by_key(&cmp, |d| d.0).unwrap().0.0, 1);
+    let (datum, _) = qt.find_by_key(&cmp, |d| std::cmp::Reverse(d.0)).unwrap();
+    assert_eq!(datum.0, 5);
+}
+
+#[test]
+fn ties_are_broken_by_insertion_order_in_every_tree() {
+    // Searches from (4, 4) and (4.5, 3.5), with ties at both, including
+    // between coincident data
+    fn assert_by_insertion<Q>(qt: &Q, pts: &[Point])
+    where
+        Q: QuadTreeSearch<Point, f64>,
+    {
+        let ranked = |cmp: &Point| {
+            let mut order = (0..pts.len()).collect::<Vec<_>>();
+            // Stable, so ties stay in insertion order
+            order.sort_by(|&i, &j| {
+                Euclidean::distance(cmp, &pts[i]).total_cmp(&Euclidean::distance(cmp, &pts[j]))
+            });
+            order
+        };
+        let seqs = |res: Vec<(&Point, f64)>| {
+            res.into_iter()
+                .map(|(datum, _)| qt.insertion_index(datum).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        let cmp = Point::new(4.0, 4.0);
+        assert_eq!(seqs(qt.sorted_by_insertion(&cmp).collect()), ranked(&cmp));
+        assert_eq!(
+            seqs(qt.knn_by_insertion(&cmp, 3).unwrap()),
+            ranked(&cmp)[..3]
+        );
+
+        let cmp = Point::new(4.5, 3.5);
+        let (datum, _) = qt.find_by_insertion(&cmp).unwrap();
+        assert_eq!(qt.insertion_index(datum), Some(ranked(&cmp)[0]));
+    }
+
+    let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 8.0, y: 8.0});
+    let mut pts = [
+        (5.0, 4.0),
+        (4.0, 5.0),
+        (4.0, 3.0),
+        (3.0, 4.0),
+        (4.2, 4.0),
+        (6.0, 6.0),
+        (5.0, 4.0),
+        (4.0, 3.0),
+    ]
+    .map(|(x, y)| Point::new(x, y));
+
+    for _ in 0..2 {
+        let mut point = PointQuadTree::new(bounds, CalcMethod::Euclidean, 4, 1);
+        let mut plain = BoundsQuadTree::new(bounds, CalcMethod::Euclidean, 4, 1);
+        let mut multi = BoundsQuadTree::multi_cell(bounds, CalcMethod::Euclidean, 4, 1, 4);
+        let mut loose = LooseQuadTree::new(bounds, CalcMethod::Euclidean, 4, 1, 2.0);
+        let mut linear = LinearQuadTree::new(bounds, CalcMethod::Euclidean, 4, 1);
+        for pt in pts {
+            point.insert(pt).unwrap();
+            plain.insert(pt).unwrap();
+            multi.insert(pt).unwrap();
+            loose.insert(pt).unwrap();
+        }
+        // Sequence numbers carry across a mix of single and bulk inserts
+        linear.insert(pts[0]).unwrap();
+        linear.extend(pts[1..5].iter().copied()).unwrap();
+        for pt in &pts[5..] {
+            linear.insert(*pt).unwrap();
+        }
+
+        assert_by_insertion(&point, &pts);
+        assert_by_insertion(&plain, &pts);
+        assert_by_insertion(&multi, &pts);
+        assert_by_insertion(&loose, &pts);
+        assert_by_insertion(&linear, &pts);
+        pts.reverse();
+    }
+
+    // Coincident data in the stores of the cube and PMR trees
+    let mut cube = CubeQuadTree::new(4, 1);
+    for pt in [(0.5, 0.5), (0.1, 0.2), (0.5, 0.5), (0.5, 0.5)] {
+        cube.insert(Point::new(pt.0, pt.1)).unwrap();
+    }
+    let res = cube.knn_by_insertion(&Point::new(0.5, 0.5), 2).unwrap();
+    let seqs = res.iter().map(|(d, _)| cube.insertion_index(d));
+    assert_eq!(seqs.collect::<Vec<_>>(), vec![Some(0), Some(2)]);
+
+    let mut pmr = PmrQuadTree::new(bounds, CalcMethod::Euclidean, 3, 1);
+    for _ in 0..3 {
+        pmr.insert(line(1.0, 1.0, 2.0, 2.0)).unwrap();
+    }
+    let (datum, _) = pmr.find_by_insertion(&Point::new(1.5, 1.5)).unwrap();
+    assert_eq!(pmr.insertion_index(datum), Some(0));
+    assert_eq!(pmr.insertion_index(&line(1.0, 1.0, 2.0, 2.0)), None);
+}
+
+#[test]
+fn sorted_keeps_equidistant_data_and_drops_repeat_references() {
+    let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 1.0, y: 1.0});
+    let cmp = Point::new(0.1, 0.1);
+
+    // Thousands of distinct data at one distance all come out
+    let mut qt = PointQuadTree::new(bounds, CalcMethod::Euclidean, 4, 8);
+    for _ in 0..5000 {
+        qt.insert(Point::new(0.5, 0.5)).unwrap();
+    }
+    assert_eq!(qt.sorted(&cmp).count(), 5000);
+
+    // A datum referenced from every leaf comes out once, as do data tying
+    // with it
+    let mut qt = BoundsQuadTree::multi_cell(bounds, CalcMethod::Euclidean, 3, 1, 64);
+    let across = line(0.0, 0.0, 1.0, 1.0);
+    qt.insert(across).unwrap();
+    for _ in 0..3 {
+        qt.insert(line(0.1, 0.1, 0.2, 0.2)).unwrap();
+    }
+    let res = qt.sorted(&cmp).collect::<Vec<_>>();
+    assert_eq!(res.len(), 4);
+    assert!(res.iter().all(|(_, d)| *d == 0.0));
+
+    // From outside, a reference held in a farther leaf is only reached after
+    // farther data have come out, and is still dropped
+    let bounds = Rect::new(coord! {x: -10.0, y: -10.0}, coord! {x: 10.0, y: 10.0});
+    let mut qt = BoundsQuadTree::multi_cell(bounds, CalcMethod::Euclidean, 5, 3, 8);
+    let triangle = Polygon::new(
+        line_string![(x: 0.0, y: 0.0), (x: 3.0, y: 0.0), (x: 3.0, y: 3.0)],
+        vec![],
+    );
+    qt.insert(Geometry::Polygon(triangle)).unwrap();
+    for l in [
+        line(2.0, 6.0, 6.0, 6.0),
+        line(6.0, 1.0, 6.0, 2.0),
+        line(-1.0, 2.0, 0.5, 7.0),
+    ] {
+        qt.insert(Geometry::Line(l)).unwrap();
+    }
+    let res = qt
+        .sorted(&Point::new(4.0, 4.0))
+        .map(|(_, d)| d)
+        .collect::<Vec<_>>();
+    assert_eq!(res.len(), qt.size());
+    assert!(res.windows(2).all(|w| w[0] <= w[1]), "{res:?}");
+}
+
+#[test]
+fn knn_approx_on_point_qt_is_within_epsilon_for_both_calc_methods() {
+    // Deterministic pseudo-random points in a small lat/lng box
+    let mut seed = 0x9E3779B97F4A7C15u64;
+    let mut rnd = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        (seed >> 11) as f64 / (1u64 << 53) as f64
+    };
+    let bounds = Rect::new(coord! {x: -0.02, y: 0.87}, coord! {x: 0.02, y: 0.91});
+    let mut rnd_pt = move || Point::new(rnd() * 0.04 - 0.02, 0.87 + rnd() * 0.04);
+    let pts = (0..2000).map(|_| rnd_pt()).collect::<Vec<_>>();
+
+    for calc in [CalcMethod::Euclidean, CalcMethod::Spherical] {
+        let mut qt = PointQuadTree::new(bounds, calc, 8, 4);
+        for pt in &pts {
+            qt.insert(*pt).unwrap();
+        }
+
+        for _ in 0..20 {
+            let cmp = rnd_pt();
+            let exact = qt.knn(&cmp, 10).unwrap();
+
+            assert_eq!(qt.knn_approx(&cmp, 10, 0.0).unwrap(), exact);
+
+            let approx = qt.knn_approx(&cmp, 10, 0.5).unwrap();
+            assert_eq!(approx.len(), 10);
+            for (a, e) in approx.iter().zip(&exact) {
+                assert!(a.1 <= e.1 * 1.5);
+            }
+
+            let (_, d) = qt.find_approx(&cmp, 0.5).unwrap();
+            assert!(d <= exact[0].1 * 1.5);
+        }
+    }
+}
+
+#[test]
+fn knn_approx_rejects_bad_epsilons_and_takes_any_k() {
+    let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 1.0, y: 1.0});
+    let mut qt = PointQuadTree::new(bounds, CalcMethod::Euclidean, 6, 2);
+    let cmp = Point::new(0.3, 0.6);
+    assert_eq!(qt.find_approx(&cmp, 0.5), Err(Error::Empty));
+
+    for i in 0..200 {
+        let f = |n: u32| f64::from(n % 97) / 97.0;
+        qt.insert(Point::new(f(i * 13), f(i * 29))).unwrap();
+    }
+
+    for epsilon in [-0.5, f64::NAN] {
+        assert_eq!(
+            qt.knn_approx(&cmp, 10, epsilon),
+            Err(Error::InvalidDistance)
+        );
+        assert_eq!(qt.find_approx(&cmp, epsilon), Err(Error::InvalidDistance));
+    }
+
+    let dists = |res: Vec<(&Point, f64)>| res.into_iter().map(|(_, d)| d).collect::<Vec<_>>();
+    let all = qt.knn_approx(&cmp, usize::MAX, 0.0).unwrap();
+    assert_eq!(dists(all), dists(qt.knn(&cmp, usize::MAX).unwrap()));
+
+    // Segments of the same linestring stand in for one another, and are often
+    // replaced by a closer one while held
+    let mut seed = 0x2545F4914F6CDD1Du64;
+    let mut rnd = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        (seed >> 11) as f64 / (1u64 << 53) as f64
+    };
+    let mut pmr = PmrQuadTree::new(bounds, CalcMethod::Euclidean, 6, 4);
+    for _ in 0..50 {
+        let ls = (0..8)
+            .map(|_| coord! {x: rnd(), y: rnd()})
+            .collect::<LineString>();
+        pmr.insert(ls).unwrap();
+    }
+    for _ in 0..20 {
+        let cmp = Point::new(rnd(), rnd());
+        let dists =
+            |res: Vec<(&LineString, f64)>| res.into_iter().map(|(_, d)| d).collect::<Vec<_>>();
+        for k in [1, 5, 20, usize::MAX] {
+            let exact = pmr.knn(&cmp, k).unwrap();
+            let approx = pmr.knn_approx(&cmp, k, 0.0).unwrap();
+            assert_eq!(dists(approx), dists(exact));
+        }
+    }
+}
+
+/// Search wrapper implementing only the required [`QuadTreeSearch`] methods,
+/// as a downstream tree would, to exercise the provided ones.
+struct Minimal(PointQuadTree<Point, f64>);
+
+impl QuadTreeSearch<Point, f64> for Minimal {
+    fn calc_method(&self) -> CalcMethod {
+        self.0.calc_method()
+    }
+
+    fn find_r<X>(&self, cmp: &X, r: f64) -> Result<(&Point, f64), Error>
+    where
+        X: AsGeom<f64>,
+    {
+        self.0.find_r(cmp, r)
+    }
+
+    fn knn_r<X>(&self, cmp: &X, k: usize, r: f64) -> Result<Vec<(&Point, f64)>, Error>
+    where
+        X: AsGeom<f64>,
+    {
+        self.0.knn_r(cmp, k, r)
+    }
+
+    fn sorted<'a, X>(&'a self, cmp: &'a X) -> impl Iterator<Item = (&'a Point, f64)> + 'a
+    where
+        X: AsGeom<f64> + 'a,
+    {
+        self.0.sorted(cmp)
+    }
+}
+
+#[test]
+fn provided_search_methods_fall_back_to_exact_searches() {
+    let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 1.0, y: 1.0});
+    let mut qt = PointQuadTree::new(bounds, CalcMethod::Euclidean, 6, 2);
+    for i in 0..200 {
+        let f = |n: u32| f64::from(n % 97) / 97.0;
+        qt.insert(Point::new(f(i * 13), f(i * 29))).unwrap();
+    }
+    let minimal = Minimal(qt);
+
+    let cmp = Point::new(0.3, 0.6);
+    let exact = minimal.knn(&cmp, 10).unwrap();
+    assert_eq!(minimal.knn_approx(&cmp, 10, 0.5).unwrap(), exact);
+    assert_eq!(minimal.find_approx(&cmp, 0.5).unwrap(), exact[0]);
+    assert_eq!(
+        minimal.knn_approx(&cmp, 10, -0.5),
+        Err(Error::InvalidDistance)
+    );
+
+    let dists = |res: Vec<(&Point, f64)>| res.into_iter().map(|(_, d)| d).collect::<Vec<_>>();
+    let farthest = minimal.farthest(&cmp, 10);
+    assert_eq!(dists(farthest), dists(minimal.0.farthest(&cmp, 10)));
+    assert_eq!(minimal.sorted_desc(&cmp).count(), 200);
+    assert_eq!(minimal.sorted_desc(&Point::new(2.0, 2.0)).count(), 0);
+}
+
+#[test]
+fn farthest_on_point_and_bounds_qt_matches_brute_force() {
+    let mut seed = 0x2545F4914F6CDD1Du64;
+    let mut rnd = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        (seed >> 11) as f64 / (1u64 << 53) as f64
+    };
+    let bounds = Rect::new(coord! {x: -1.0, y: -1.0}, coord! {x: 1.0, y: 1.0});
+    let pts = (0..500)
+        .map(|_| Point::new(rnd() * 2.0 - 1.0, rnd() * 2.0 - 1.0))
+        .collect::<Vec<_>>();
+
+    for calc in [CalcMethod::Euclidean, CalcMethod::Spherical] {
+        let mut qt = PointQuadTree::new(bounds, calc.clone(), 6, 4);
+        let mut bqt = BoundsQuadTree::new(bounds, calc.clone(), 6, 4);
+        for pt in &pts {
+            qt.insert(*pt).unwrap();
+            bqt.insert(Rect::new(pt.0, pt.0)).unwrap();
+        }
+
+        let cmp = Point::new(0.3, -0.6);
+        let dist = |pt: &Point| match calc {
+            CalcMethod::Spherical => dist_pt_pt(&cmp, pt),
+            _ => Euclidean::distance(&cmp, pt),
+        };
+        let mut brute = pts.iter().map(dist).collect::<Vec<_>>();
+        brute.sort_by(|a, b| b.partial_cmp(a).unwrap());
+
+        let desc = qt.sorted_desc(&cmp).map(|(_, d)| d).collect::<Vec<_>>();
+        assert_eq!(desc.len(), pts.len());
+        for (d, b) in desc.iter().zip(&brute) {
+            assert_abs_diff_eq!(d, b, epsilon = 1e-12);
+        }
+
+        let far = bqt.farthest(&cmp, 5);
+        assert_eq!(far.len(), 5);
+        for ((_, d), b) in far.iter().zip(&brute) {
+            assert_abs_diff_eq!(d, b, epsilon = 1e-12);
+        }
+    }
+}
+
+#[test]
+fn multi_cell_farthest_matches_brute_force_for_overhanging_data() {
+    let mut seed = 0xD1B54A32D192ED03u64;
+    let mut rnd = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        (seed >> 11) as f64 / (1u64 << 53) as f64
+    };
+    let bounds = Rect::new(coord! {x: -10.0, y: -10.0}, coord! {x: 10.0, y: 10.0});
+
+    for _ in 0..20 {
+        // Diagonal lines and triangles of all sizes, whose bounds reach well
+        // past the parts of the leaves they pass through, and boxes
+        let mut data = vec![];
+        for i in 0..40 {
+            let (x, y) = (rnd() * 20.0 - 10.0, rnd() * 20.0 - 10.0);
+            let (x2, y2) = ((x + rnd() * 12.0).min(10.0), (y - rnd() * 12.0).max(-10.0));
+            data.push(match i % 3 {
+                0 => Geometry::Line(line(x, y, x2, y2)),
+                1 => Geometry::Polygon(Polygon::new(
+                    line_string![(x: x, y: y), (x: x2, y: y), (x: x2, y: y2)],
+                    vec![],
+                )),
+                _ => Geometry::Rect(Rect::new(coord! {x: x, y: y}, coord! {x: x2, y: y2})),
+            });
+        }
+
+        // Low caps stick data part way down as well
+        let max_cells = 1 + (rnd() * 8.0) as usize;
+        let mut qt = BoundsQuadTree::multi_cell(bounds, CalcMethod::Euclidean, 5, 2, max_cells);
+        for d in &data {
+            qt.insert(d.clone()).unwrap();
+        }
+
+        let metric = |cmp: &Point, d: &Geometry<f64>| {
+            let calc = cmp.with_calc(CalcMethod::Euclidean);
+            calc.dist_geom(&d.as_geom()).unwrap()
+        };
+        for _ in 0..5 {
+            let cmp = Point::new(rnd() * 20.0 - 10.0, rnd() * 20.0 - 10.0);
+            assert_searches_match_brute_force(&qt, &data, &cmp, metric);
+
+            let mut desc = qt.sorted(&cmp).map(|(_, d)| d).collect::<Vec<_>>();
+            desc.reverse();
+            let found = qt.sorted_desc(&cmp).map(|(_, d)| d).collect::<Vec<_>>();
+            assert_eq!(found, desc);
+        }
+    }
+}
+
+#[test]
+fn node_cursor_walks_structure_in_preorder_bfs_and_leaves() {
+    let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 8.0, y: 8.0});
+    let mut qt = BoundsQuadTree::new(bounds, CalcMethod::Euclidean, 2, 2);
+
+    let b1 = Rect::new(coord! {x: 1.0, y: 1.0}, coord! {x: 1.0, y: 1.0});
+    let b2 = Rect::new(coord! {x: 3.0, y: 3.0}, coord! {x: 4.0, y: 4.0});
+    let b3 = Rect::new(coord! {x: 1.0, y: 1.0}, coord! {x: 3.0, y: 3.0});
+    let b4 = Rect::new(coord! {x: 6.0, y: 2.0}, coord! {x: 7.0, y: 6.0});
+
+    qt.insert(b1).unwrap();
+    qt.insert(b1).unwrap();
+    qt.insert(b2).unwrap();
+    qt.insert(b3).unwrap();
+    qt.insert(b4).unwrap();
+
+    // Root is split, with b4 stuck across the right hand quadrants
+    let root = qt.root();
+    assert_eq!(root.depth(), 0);
+    assert!(!root.is_leaf());
+    assert_eq!(root.stuck_children().collect::<Vec<_>>(), vec![&b4]);
+
+    // Top left is split again, with b3 stuck in it
+    let tl = root.sub_node(SubNode::TopLeft).unwrap();
+    assert_eq!(tl.depth(), 1);
+    assert_eq!(tl.bounds().max(), coord! {x: 4.0, y: 4.0});
+    assert_eq!(tl.stuck_children().collect::<Vec<_>>(), vec![&b3]);
+    assert_eq!(tl.descendants().count(), 4);
+
+    let tl_tl = tl.sub_node(SubNode::TopLeft).unwrap();
+    assert!(tl_tl.is_leaf());
+    assert!(tl_tl.sub_node(SubNode::TopLeft).is_none());
+    assert_eq!(tl_tl.children().collect::<Vec<_>>(), vec![&b1, &b1]);
+
+    // Preorder descends into the top left before the rest of the root
+    let depths = root.preorder().map(|n| n.depth()).collect::<Vec<_>>();
+    assert_eq!(depths, vec![0, 1, 2, 2, 2, 2, 1, 1, 1]);
+
+    // Breadth first visits all shallower nodes first
+    let depths = root.bfs().map(|n| n.depth()).collect::<Vec<_>>();
+    assert_eq!(depths, vec![0, 1, 1, 1, 1, 2, 2, 2, 2]);
+
+    // Leaves cover all data except the stuck children
+    let leaves = root.leaves().collect::<Vec<_>>();
+    assert_eq!(leaves.len(), 7);
+    assert_eq!(leaves.iter().flat_map(|n| n.children()).count(), 3);
+}
+
+#[test]
+fn find_and_knn_on_integer_qt_use_float_distances() {
+    let mut qt = IntQuadTree::new(coord! {x: 0u32, y: 0}, 4, CalcMethod::Euclidean, 4, 2);
+
+    let p1 = Point::new(2u32, 2);
+    let p2 = Point::new(3u32, 3);
+    let p3 = Point::new(12u32, 12);
+
+    qt.insert(p1).unwrap();
+    qt.insert(p1).unwrap();
+    qt.insert(p2).unwrap();
+    qt.insert(p3).unwrap();
+
+    // Comparators need not sit on the grid
+    let cmp = Point::new(11.5, 12.0);
+    let (res, d) = qt.find(&cmp).unwrap();
+    assert_eq!(res, &p3);
+    assert_abs_diff_eq!(d, 0.5);
+
+    let res = qt.knn(&Point::new(4.0, 4.0), 3).unwrap();
+    assert_eq!(res.len(), 3);
+    assert_eq!(res[0].0, &p2);
+    assert_abs_diff_eq!(res[0].1, 2.0f64.sqrt());
+    assert_eq!(res[1].0, &p1);
+    assert_eq!(res[2].0, &p1);
+
+    assert_eq!(qt.find_r(&cmp, 0.1), Err(Error::NoneInRadius));
+    assert_eq!(qt.find(&Point::new(16.5, 0.0)), Err(Error::OutOfBounds));
+}
+
+#[test]
+fn linear_qt_matches_point_qt_for_retrieve_and_search() {
+    let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 1.0, y: 1.0});
+    let mut pqt = PointQuadTree::new(bounds, CalcMethod::Euclidean, 3, 2);
+    let mut lqt = LinearQuadTree::new(bounds, CalcMethod::Euclidean, 3, 2);
+
+    // Includes data on the midlines and repeated points
+    let data = [
+        (0.1, 0.1),
+        (0.5, 0.5),
+        (0.9, 0.2),
+        (0.25, 0.75),
+        (0.1, 0.1),
+        (0.6, 0.9),
+        (0.5, 0.1),
+        (0.12, 0.14),
+        (0.8, 0.8),
+    ]
+    .map(|(x, y)| Point::new(x, y));
+    for d in data {
+        pqt.insert(d).unwrap();
+        lqt.insert(d).unwrap();
+    }
+    assert_eq!(lqt.size(), pqt.size());
+
+    for d in &data {
+        let p = pqt.retrieve(d).collect::<Vec<_>>();
+        let l = lqt.retrieve(d).collect::<Vec<_>>();
+        assert_eq!(p, l);
+    }
+
+    let cmp = Point::new(0.55, 0.45);
+    assert_eq!(lqt.find(&cmp), pqt.find(&cmp));
+    assert_eq!(lqt.find_r(&cmp, 0.01), Err(Error::NoneInRadius));
+
+    let dists = |res: Vec<(&Point, f64)>| res.into_iter().map(|(_, d)| d).collect::<Vec<_>>();
+    assert_eq!(
+        dists(lqt.knn(&cmp, 4).unwrap()),
+        dists(pqt.knn(&cmp, 4).unwrap())
+    );
+    assert_eq!(
+        dists(lqt.sorted(&cmp).collect()),
+        dists(pqt.sorted(&cmp).collect())
+    );
+    assert_eq!(lqt.into_iter().count(), data.len());
+}
+
+#[test]
+fn region_qt_overlays_points_from_a_point_qt() {
+    let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 1.0, y: 1.0});
+    let mut qt = PointQuadTree::from_bounds(bounds, CalcMethod::Euclidean);
+    let raster = RegionQuadTree::from_buffer(bounds, 2, 2, vec!['a', 'b', 'b', 'b']).unwrap();
+
+    let pts = [(0.2, 0.2), (0.8, 0.3), (0.3, 0.9)].map(|(x, y)| Point::new(x, y));
+    for pt in pts {
+        qt.insert(pt).unwrap();
+    }
+
+    let classes = qt
+        .into_iter()
+        .filter_map(|pt| raster.value_at(pt))
+        .collect::<String>();
+    assert_eq!(classes, "abb");
+    assert_eq!(raster.leaves().count(), 4);
+    assert_eq!(
+        raster.aggregate_rect(&bounds),
+        vec![(&'a', 0.25), (&'b', 0.75)]
+    );
+}
+
+#[test]
+fn loose_qt_search_matches_bounds_qt() {
+    let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 1.0, y: 1.0});
+    let mut bqt = BoundsQuadTree::new(bounds, CalcMethod::Euclidean, 3, 1);
+    let mut lqt = LooseQuadTree::new(bounds, CalcMethod::Euclidean, 3, 1, 2.0);
+
+    // Short segments scattered across the midlines at several depths
+    let data = [
+        line(0.45, 0.1, 0.55, 0.1),
+        line(0.2, 0.48, 0.2, 0.52),
+        line(0.7, 0.74, 0.8, 0.76),
+        line(0.1, 0.1, 0.15, 0.12),
+        line(0.9, 0.2, 0.95, 0.3),
+        line(0.3, 0.8, 0.3, 0.9),
+        line(0.6, 0.45, 0.4, 0.55),
+    ];
+    for d in data {
+        bqt.insert(d).unwrap();
+        lqt.insert(d).unwrap();
+    }
+
+    for cmp in [(0.5, 0.5), (0.05, 0.95), (0.74, 0.7), (1.0, 0.0)] {
+        let cmp = Point::new(cmp.0, cmp.1);
+        assert_eq!(lqt.find(&cmp).unwrap().1, bqt.find(&cmp).unwrap().1);
+
+        let dists = |res: Vec<(&Line, f64)>| res.into_iter().map(|(_, d)| d).collect::<Vec<_>>();
+        assert_eq!(
+            dists(lqt.knn(&cmp, 3).unwrap()),
+            dists(bqt.knn(&cmp, 3).unwrap())
+        );
+        assert_eq!(
+            dists(lqt.sorted(&cmp).collect()),
+            dists(bqt.sorted(&cmp).collect())
+        );
+    }
+    assert!(lqt.stats().stuck_children < bqt.stats().stuck_children);
+}
+
+#[test]
+fn spherical_loose_qt_matches_brute_force_at_the_edges_of_the_world() {
+    use std::f64::consts::{FRAC_PI_2, PI};
+
+    let mut seed = 0x2545F4914F6CDD1Du64;
+    let mut rnd = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        (seed >> 11) as f64 / (1u64 << 53) as f64
+    };
+    let bounds = Rect::new(coord! {x: -PI, y: -FRAC_PI_2}, coord! {x: PI, y: FRAC_PI_2});
+
+    for _ in 0..20 {
+        // Short lines crowding the antimeridian and the poles, whose loose
+        // bounds would otherwise reach past them, and a few crossing it, some
+        // the long way round in planar terms
+        let mut rnd_pt = || {
+            let x = (rnd() * 2.0 - 1.0) * PI;
+            let y = (rnd() * 2.0 - 1.0) * FRAC_PI_2;
+            let x = if rnd() < 0.5 {
+                x.signum() * (PI - rnd() * 0.2)
+            } else {
+                x
+            };
+            let y = if rnd() < 0.3 {
+                y.signum() * (FRAC_PI_2 - rnd() * 0.2)
+            } else {
+                y
+            };
+            Point::new(x, y)
+        };
+        let mut lines = vec![];
+        for i in 0..60 {
+            let p = rnd_pt();
+            let side = p.x().signum();
+            lines.push(match i % 10 {
+                0 => Line::new(p.0, coord! {x: -side * (PI - 0.05), y: p.y()}),
+                // Along the equator, where arcs do not bow past their bounds
+                5 => Line::new(
+                    coord! {x: side * 3.1, y: 0.0},
+                    coord! {x: -side * 1.5, y: 0.0},
+                ),
+                _ => Line::new(
+                    p.0,
+                    coord! {x: (p.x() + 0.1).min(PI), y: (p.y() + 0.1).min(FRAC_PI_2)},
+                ),
+            });
+        }
+
+        let mut lqt = LooseQuadTree::new(bounds, CalcMethod::Spherical, 6, 2, 2.0);
+        for l in &lines {
+            lqt.insert(*l).unwrap();
+        }
+
+        let metric = |cmp: &Point, l: &Line| {
+            let calc = cmp.with_calc(CalcMethod::Spherical);
+            calc.dist_geom(&l.as_geom()).unwrap()
+        };
+        for _ in 0..5 {
+            let cmp = rnd_pt();
+            assert_searches_match_brute_force(&lqt, &lines, &cmp, metric);
+
+            let exact = lqt.knn(&cmp, 5).unwrap();
+            assert_eq!(lqt.find(&cmp).unwrap().1, exact[0].1);
+            let approx = lqt.knn_approx(&cmp, 5, 0.5).unwrap();
+            for (a, e) in approx.iter().zip(&exact) {
+                assert!(a.1 <= e.1 * 1.5);
+            }
+        }
+    }
+}
+
+#[test]
+fn pmr_qt_search_matches_bounds_qt_for_linestrings() {
+    let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 1.0, y: 1.0});
+    let mut bqt = BoundsQuadTree::new(bounds, CalcMethod::Euclidean, 3, 1);
+    let mut pqt = PmrQuadTree::new(bounds, CalcMethod::Euclidean, 3, 2);
+
+    // A few paths criss-crossing the root, as in a road network
+    let data: [LineString; 4] = [
+        line_string![(x: 0.1, y: 0.1), (x: 0.9, y: 0.1), (x: 0.9, y: 0.9)],
+        line_string![(x: 0.05, y: 0.5), (x: 0.5, y: 0.55), (x: 0.95, y: 0.45)],
+        line_string![(x: 0.3, y: 0.95), (x: 0.35, y: 0.6), (x: 0.2, y: 0.2)],
+        line_string![(x: 0.6, y: 0.7), (x: 0.7, y: 0.8)],
+    ];
+    for d in &data {
+        bqt.insert(d.clone()).unwrap();
+        pqt.insert(d.clone()).unwrap();
+    }
+    assert_eq!(pqt.segment_count(), 7);
+
+    for cmp in [(0.5, 0.5), (0.05, 0.95), (0.74, 0.7), (1.0, 0.0)] {
+        let cmp = Point::new(cmp.0, cmp.1);
+        assert_eq!(pqt.find(&cmp), bqt.find(&cmp));
+        assert_eq!(pqt.knn(&cmp, 4).unwrap(), bqt.knn(&cmp, 4).unwrap());
+        assert_eq!(
+            pqt.sorted(&cmp).collect::<Vec<_>>(),
+            bqt.sorted(&cmp).collect::<Vec<_>>()
+        );
+    }
+
+    // The segment index picks out the leg of the path that is closest
+    let (datum, index, _) = pqt.find_segment(&Point::new(0.95, 0.7)).unwrap();
+    assert_eq!((datum, index), (&data[0], 1));
+}
+
+#[test]
+fn snap_projects_onto_nearest_linestring_for_both_calc_methods() {
+    let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 1.0, y: 1.0});
+    let data: [LineString; 2] = [
+        line_string![(x: 0.1, y: 0.1), (x: 0.9, y: 0.1), (x: 0.9, y: 0.9)],
+        line_string![(x: 0.2, y: 0.5), (x: 0.6, y: 0.5)],
+    ];
+    let mut bqt = BoundsQuadTree::new(bounds, CalcMethod::Euclidean, 3, 1);
+    let mut pqt = PmrQuadTree::new(bounds, CalcMethod::Euclidean, 3, 2);
+    for d in &data {
+        bqt.insert(d.clone()).unwrap();
+        pqt.insert(d.clone()).unwrap();
+    }
+
+    // Three quarters of the way up the second leg of the first path
+    let pt = Point::new(0.95, 0.7);
+    let (datum, snap) = bqt.snap(&pt, 1.0).unwrap();
+    assert_eq!(datum, &data[0]);
+    assert_eq!(snap.segment, 1);
+    assert_abs_diff_eq!(snap.fraction, 0.75, epsilon = 1e-12);
+    assert_abs_diff_eq!(snap.point.x(), 0.9, epsilon = 1e-12);
+    assert_abs_diff_eq!(snap.point.y(), 0.7, epsilon = 1e-12);
+    assert_abs_diff_eq!(snap.distance, 0.05, epsilon = 1e-12);
+    assert_eq!(pqt.snap(&pt, 1.0), Ok((datum, snap)));
+
+    // Beyond the end of a path snaps to its end point
+    let (datum, snap) = bqt.snap(&Point::new(0.05, 0.55), 1.0).unwrap();
+    assert_eq!((datum, snap.segment, snap.fraction), (&data[1], 0, 0.0));
+    assert_eq!(snap.point, Point::new(0.2, 0.5));
+    assert_eq!(bqt.snap(&pt, 0.01), Err(Error::NoneInRadius));
+
+    // Spherical distances are great circle distances to the snapped point
+    let mut sqt = BoundsQuadTree::new(bounds, CalcMethod::Spherical, 3, 1);
+    for d in &data {
+        sqt.insert(d.clone()).unwrap();
+    }
+    let pt = Point::new(0.4, 0.6);
+    let (datum, snap) = sqt.snap(&pt, 1.0).unwrap();
+    assert_eq!(datum, &data[1]);
+    assert_abs_diff_eq!(snap.fraction, 0.5, epsilon = 1e-12);
+    assert_abs_diff_eq!(snap.distance, dist_pt_pt(&pt, &snap.point));
+    assert_abs_diff_eq!(snap.distance, sqt.find(&pt).unwrap().1);
+}
+
+#[test]
+fn searches_with_points_return_connectors_the_distance_long() {
+    let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 8.0, y: 8.0});
+    let mut qt = BoundsQuadTree::new(bounds, CalcMethod::Euclidean, 2, 2);
+    let data: [Geometry<f64>; 3] = [
+        Rect::new(coord! {x: 5.0, y: 5.0}, coord! {x: 7.0, y: 6.0}).into(),
+        line(1.0, 6.0, 3.0, 7.0).into(),
+        Point::new(6.0, 1.0).into(),
+    ];
+    for d in data {
+        qt.insert(d).unwrap();
+    }
+
+    let cmp = line(3.0, 3.0, 4.0, 4.0);
+    let (_, d, (p1, p2)) = qt.find_with_points(&cmp).unwrap();
+    assert_eq!((p1, p2), (Point::new(4.0, 4.0), Point::new(5.0, 5.0)));
+    assert_abs_diff_eq!(d, Euclidean::distance(&p1, &p2));
+
+    let knn = qt.knn_with_points(&cmp, 3).unwrap();
+    let sorted = qt.sorted_with_points(&cmp).collect::<Vec<_>>();
+    assert_eq!(knn.len(), 3);
+    let strip = |v: &[(&Geometry<f64>, f64, (Point, Point))]| {
+        v.iter().map(|(_, d, pts)| (*d, *pts)).collect::<Vec<_>>()
+    };
+    assert_eq!(strip(&knn), strip(&sorted));
+    for (_, d, (p1, p2)) in knn {
+        assert_abs_diff_eq!(d, Euclidean::distance(&p1, &p2), epsilon = 1e-12);
+    }
+}
+
+// Distances found by the searches of a tree, so trees with and without
+// QuadTreeSearch share one brute force check
+trait SearchDists<D, C> {
+    fn knn_dists(&self, cmp: &C, k: usize) -> Vec<f64>;
+    fn sorted_dists(&self, cmp: &C) -> Vec<f64>;
+    fn farthest_dists(&self, cmp: &C, k: usize) -> Option<Vec<f64>>;
+}
+
+impl<Q, D> SearchDists<D, Point> for Q
+where
+    Q: QuadTreeSearch<D, f64>,
+    D: AsGeom<f64>,
+{
+    fn knn_dists(&self, cmp: &Point, k: usize) -> Vec<f64> {
+        let knn = self.knn(cmp, k).unwrap();
+        knn.into_iter().map(|(_, d)| d).collect()
+    }
+
+    fn sorted_dists(&self, cmp: &Point) -> Vec<f64> {
+        self.sorted(cmp).map(|(_, d)| d).collect()
+    }
+
+    fn farthest_dists(&self, cmp: &Point, k: usize) -> Option<Vec<f64>> {
+        Some(self.farthest(cmp, k).into_iter().map(|(_, d)| d).collect())
+    }
+}
+
+impl<D> SearchDists<D, Point3> for Octree<D>
+where
+    D: AsGeom3,
+{
+    fn knn_dists(&self, cmp: &Point3, k: usize) -> Vec<f64> {
+        let knn = self.knn(cmp, k).unwrap();
+        knn.into_iter().map(|(_, d)| d).collect()
+    }
+
+    fn sorted_dists(&self, cmp: &Point3) -> Vec<f64> {
+        self.sorted(cmp).map(|(_, d)| d).collect()
+    }
+
+    fn farthest_dists(&self, _cmp: &Point3, _k: usize) -> Option<Vec<f64>> {
+        None
+    }
+}
+
+// Check the closest, sorted and farthest searches of a tree against the
+// distances `metric` measures from the comparator to every datum
+fn assert_searches_match_brute_force<Q, D, C>(
+    qt: &Q,
+    data: &[D],
+    cmp: &C,
+    metric: impl Fn(&C, &D) -> f64,
+) where
+    Q: SearchDists<D, C>,
+{
+    let mut brute = data.iter().map(|d| metric(cmp, d)).collect::<Vec<_>>();
+    brute.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let k = brute.len().min(8);
+
+    let check = |found: Vec<f64>, expected: Vec<f64>| {
+        assert_eq!(found.len(), expected.len());
+        for (f, e) in found.iter().zip(&expected) {
+            assert_relative_eq!(*f, *e, epsilon = 1e-12, max_relative = 1e-12);
+        }
+    };
+    check(qt.knn_dists(cmp, k), brute[..k].to_vec());
+    check(qt.sorted_dists(cmp), brute.clone());
+    if let Some(farthest) = qt.farthest_dists(cmp, k) {
+        check(farthest, brute.iter().rev().take(k).copied().collect());
+    }
+}
+
+#[test]
+fn octree_depth_limits_stack_data_and_keep_searches_exact() {
+    struct Drone {
+        id: u32,
+        position: Point3,
+    }
+
+    impl AsPoint3 for Drone {
+        fn as_point3(&self) -> Point3 {
+            self.position
+        }
+    }
+
+    impl AsGeom3 for Drone {
+        fn as_geom3(&self) -> Geometry3 {
+            Geometry3::Point(self.as_point3())
+        }
+    }
+
+    // A coarse lattice, a swarm hovering at one spot and a tight cluster,
+    // which no depth limit can separate
+    let hover = Point3::new(0.25, 0.25, 0.25);
+    let mut positions = vec![];
+    for id in 0..40u32 {
+        let f = |n: u32| f64::from(n % 10) / 10.0 + 0.05;
+        positions.push(Point3::new(f(id * 3), f(id * 7), f(id * 9 / 4)));
+    }
+    positions.extend((0..12).map(|_| hover));
+    positions.extend((0..8).map(|i| Point3::new(0.7, 0.7, 0.7 + f64::from(i) * 1e-9)));
+
+    let bounds = Cuboid::new(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0));
+    for max_depth in [0, 1, 3, 40] {
+        let mut ot = Octree::new(bounds, max_depth, 2);
+        let mut drones = vec![];
+        for (id, position) in positions.iter().enumerate() {
+            let id = id as u32;
+            ot.insert(Drone {
+                id,
+                position: *position,
+            })
+            .unwrap();
+            drones.push(Drone {
+                id,
+                position: *position,
+            });
+        }
+        assert_eq!(ot.size(), positions.len());
+        assert_eq!(ot.into_iter().count(), positions.len());
+
+        // The swarm drives the tree down to, but not past, the limit
+        let depth = ot
+            .to_string()
+            .lines()
+            .skip(1)
+            .map(|l| (l.len() - l.trim_start().len()) / 4)
+            .max();
+        assert_eq!(depth, Some(max_depth as usize));
+
+        let dist = |cmp: &Point3, d: &Drone| {
+            let p = d.position;
+            ((p.x - cmp.x).powi(2) + (p.y - cmp.y).powi(2) + (p.z - cmp.z).powi(2)).sqrt()
+        };
+        for cmp in [
+            hover,
+            Point3::new(0.7, 0.7, 0.7),
+            Point3::new(0.4, 0.6, 0.5),
+            Point3::new(1.0, 0.0, 1.0),
+        ] {
+            assert_searches_match_brute_force(&ot, &drones, &cmp, dist);
+        }
+
+        // Every drone of the swarm is found, however deep it stacks
+        let mut swarm = ot.knn(&hover, 12).unwrap();
+        swarm.sort_by_key(|(d, _)| d.id);
+        let ids = swarm.iter().map(|(d, _)| d.id).collect::<Vec<_>>();
+        assert_eq!(ids, (40..52).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn cube_qt_searches_are_exact_on_face_seams_and_at_the_poles() {
+    use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+    let mut qt = CubeQuadTree::new(8, 2);
+
+    // Points on the seams between the equatorial faces, on the seams with
+    // the polar faces and at the cube's corners, some nudged either side
+    let mut data = vec![];
+    for seam in [-3.0, -1.0, 1.0, 3.0] {
+        for i in 0..9 {
+            let lat = -1.2 + i as f64 * 0.3;
+            for nudge in [-1e-9, 0.0, 1e-9] {
+                data.push(Point::new(seam * FRAC_PI_4 + nudge, lat));
+            }
+        }
+    }
+    for i in 0..24 {
+        let lng = -PI + i as f64 * PI / 12.0 + 0.01;
+        let lat = lng.cos().abs().max(lng.sin().abs()).atan();
+        for nudge in [-1e-9, 0.0, 1e-9] {
+            data.push(Point::new(lng, lat + nudge));
+            data.push(Point::new(lng, -lat + nudge));
+        }
+    }
+    // Both poles, reached from many longitudes
+    for i in 0..8 {
+        let lng = -PI + i as f64 * PI / 4.0;
+        data.push(Point::new(lng, FRAC_PI_2));
+        data.push(Point::new(lng, -FRAC_PI_2));
+    }
+    for d in &data {
+        qt.insert(*d).unwrap();
+    }
+
+    assert_eq!(qt.size(), data.len());
+    assert_eq!(qt.face_sizes().iter().sum::<usize>(), data.len());
+    assert!(qt.face_sizes().iter().all(|&n| n > 0));
+    assert_eq!(qt.insert(Point::new(0.0, 2.0)), Err(Error::OutOfBounds));
+
+    // Every datum finds itself, whichever face it went to
+    for d in &data {
+        assert_abs_diff_eq!(qt.find(d).unwrap().1, 0.0, epsilon = 1e-12);
+    }
+
+    let corner = (1.0 / 2f64.sqrt()).atan();
+    for cmp in [
+        (0.3, FRAC_PI_2),
+        (2.0, -FRAC_PI_2),
+        (FRAC_PI_4, 0.0),
+        (FRAC_PI_4, corner),
+        (-3.0 * FRAC_PI_4, -corner),
+        (PI, FRAC_PI_4),
+        (-PI + 0.01, -0.7),
+    ] {
+        let cmp = Point::new(cmp.0, cmp.1);
+        assert_searches_match_brute_force(&qt, &data, &cmp, dist_pt_pt);
+    }
+
+    // Searching by a line that runs over the pole
+    let line = Line::new(coord! {x: 0.0, y: 1.3}, coord! {x: PI, y: 1.3});
+    let calc = line.with_calc(CalcMethod::Spherical);
+    let brute = data
+        .iter()
+        .map(|d| calc.dist_geom(&d.as_geom()).unwrap())
+        .fold(f64::INFINITY, f64::min);
+    assert_abs_diff_eq!(qt.find(&line).unwrap().1, brute, epsilon = 1e-12);
+    assert_eq!(
+        qt.find_r(&Point::new(0.0, 0.3), 0.001),
+        Err(Error::NoneInRadius)
+    );
+    assert_eq!(
+        CubeQuadTree::<Point>::default().find(&Point::new(0.0, 0.0)),
+        Err(Error::Empty)
+    );
+}
+
+#[test]
+fn geodesic_searches_are_exact_near_antipodes() {
+    use quadtree::geodesic::math::dist_pt_geom;
+    use std::f64::consts::{FRAC_PI_2, PI};
+
+    let bounds = Rect::new(coord! {x: -PI, y: -FRAC_PI_2}, coord! {x: PI, y: FRAC_PI_2});
+    // Deep enough for leaves to hug the antipodes, where node bounds taken
+    // with the equatorial radius would overshoot the meridional geodesics
+    let mut pt_qt = PointQuadTree::new(bounds, CalcMethod::Geodesic, 16, 1);
+    let mut ls_qt = BoundsQuadTree::new(bounds, CalcMethod::Geodesic, 16, 1);
+
+    // Data crowding the antipodes of the comparators, where geodesics are
+    // longest and least like great circles, on and just off the equator
+    let cmps = [Point::new(0.1, 0.0), Point::new(0.1, 0.05)];
+    let mut pts = vec![];
+    let mut lines = vec![];
+    for cmp in &cmps {
+        let (x0, y0) = (cmp.x() - PI, -cmp.y());
+        for i in 0..30 {
+            let (x, y) = (
+                x0 + (i * 7 % 11) as f64 * 0.002 - 0.01,
+                y0 + (i * 5 % 13) as f64 * 0.002 - 0.012,
+            );
+            pts.push(Point::new(x, y));
+            lines.push(
+                line_string![(x: x, y: y), (x: x + 0.005, y: y + 0.002), (x: x + 0.01, y: y)],
+            );
+        }
+        pts.push(Point::new(x0, y0));
+    }
+    for pt in &pts {
+        pt_qt.insert(*pt).unwrap();
+    }
+    for ls in &lines {
+        ls_qt.insert(ls.clone()).unwrap();
+    }
+
+    let metric = |cmp: &Point, g: &dyn AsGeom<f64>| dist_pt_geom(cmp, &g.as_geom()).unwrap();
+    for cmp in &cmps {
+        assert_searches_match_brute_force(&pt_qt, &pts, cmp, |c, p| metric(c, p));
+        assert_searches_match_brute_force(&ls_qt, &lines, cmp, |c, l| metric(c, l));
+
+        // Distances are in meters, half way round the world
+        let antipode = Point::new(cmp.x() - PI, -cmp.y());
+        let (found, d) = pt_qt.find(cmp).unwrap();
+        assert!(d > 1.9e7);
+        assert_ne!(found, &antipode);
+        assert_eq!(pt_qt.farthest(cmp, 1)[0].0, &antipode);
+        assert_eq!(pt_qt.find_r(cmp, d * 0.999), Err(Error::NoneInRadius));
+    }
+}
+
+#[test]
+fn minkowski_orders_below_one_are_rejected_and_infinity_is_chebyshev() {
+    let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 100.0, y: 100.0});
+    let pts = (0..80)
+        .map(|i| Point::new((i * 37 % 97) as f64, (i * 61 % 89) as f64))
+        .collect::<Vec<_>>();
+    let lines = pts
+        .iter()
+        .map(|p| Line::new(p.0, coord! {x: p.x() + 3.0, y: (p.y() + 7.0).min(100.0)}))
+        .collect::<Vec<_>>();
+
+    // The limits of the order give the named metrics, measured as such
+    for (order, named) in [
+        (1.0, CalcMethod::Manhattan),
+        (f64::INFINITY, CalcMethod::Chebyshev),
+    ] {
+        let mut pt_qt = PointQuadTree::new(bounds, CalcMethod::Minkowski(order), 5, 2);
+        let mut line_qt = BoundsQuadTree::new(bounds, CalcMethod::Minkowski(order), 5, 2);
+        for (pt, line) in pts.iter().zip(&lines) {
+            pt_qt.insert(*pt).unwrap();
+            line_qt.insert(*line).unwrap();
+        }
+
+        let metric = |cmp: &Point, g: &dyn AsGeom<f64>| {
+            let calc = cmp.with_calc(named.clone());
+            calc.dist_geom(&g.as_geom()).unwrap()
+        };
+        for cmp in [Point::new(50.0, 50.0), Point::new(3.0, 97.0)] {
+            assert_searches_match_brute_force(&pt_qt, &pts, &cmp, |c, p| metric(c, p));
+            assert_searches_match_brute_force(&line_qt, &lines, &cmp, |c, l| metric(c, l));
+        }
+    }
+
+    // Orders in between, checked against the formula
+    let mut qt = PointQuadTree::new(bounds, CalcMethod::Minkowski(3.0), 5, 2);
+    for pt in &pts {
+        qt.insert(*pt).unwrap();
+    }
+    let cube_root = |c: &Point, p: &Point| {
+        ((c.x() - p.x()).abs().powi(3) + (c.y() - p.y()).abs().powi(3)).cbrt()
+    };
+    assert_searches_match_brute_force(&qt, &pts, &Point::new(50.0, 50.0), cube_root);
+
+    // Orders below one are not metrics, nor is a missing order
+    for order in [0.5, 0.0, -2.0, f64::NAN] {
+        let mut qt = PointQuadTree::new(bounds, CalcMethod::Minkowski(order), 5, 2);
+        for pt in &pts {
+            qt.insert(*pt).unwrap();
+        }
+
+        let cmp = pts[1];
+        assert_eq!(qt.find(&cmp), Err(Error::InvalidDistance));
+        assert_eq!(qt.knn(&cmp, 3), Err(Error::InvalidDistance));
+        assert_eq!(
+            qt.find_with_points(&cmp).map(|(_, d, _)| d),
+            Err(Error::InvalidDistance)
+        );
+        assert_eq!(qt.sorted(&cmp).count(), 0);
+        assert!(qt.farthest(&cmp, 3).is_empty());
+    }
+}
+
+// Euclidean distances with east-west travel twice as costly as north-south,
+// measured in a space stretched along x
+#[derive(Debug)]
+struct Anisotropic;
+
+impl Anisotropic {
+    fn stretch(geom: &GeometryRef<f64>) -> Result<Geometry<f64>, Error> {
+        let stretch = |c: geo::Coord| coord! {x: 2.0 * c.x, y: c.y};
+        match geom {
+            GeometryRef::Point(p) => Ok(Geometry::Point(p.map_coords(stretch))),
+            GeometryRef::Line(l) => Ok(Geometry::Line(l.map_coords(stretch))),
+            GeometryRef::Rect(r) => Ok(Geometry::Rect(r.map_coords(stretch))),
+            _ => Err(Error::UnsupportedGeometry),
+        }
+    }
+}
+
+impl Metric<f64> for Anisotropic {
+    fn dist_geom(&self, g1: &GeometryRef<f64>, g2: &GeometryRef<f64>) -> Result<f64, Error> {
+        let (g1, g2) = (Anisotropic::stretch(g1)?, Anisotropic::stretch(g2)?);
+        g1.as_geom()
+            .into_calc(CalcMethod::Euclidean)
+            .dist_geom(&g2.as_geom())
+    }
+
+    fn dist_bbox(&self, geom: &GeometryRef<f64>, bbox: &Rect<f64>) -> Result<f64, Error> {
+        self.dist_geom(geom, &bbox.as_geom())
+    }
+}
+
+// Anisotropic distances counting how many are measured, with its node bound
+// either pruning or a trivial zero, and no upper bound
+#[derive(Debug)]
+struct Counted {
+    prune: bool,
+    calls: AtomicUsize,
+}
+
+impl Counted {
+    fn new(prune: bool) -> Arc<Counted> {
+        Arc::new(Counted {
+            prune,
+            calls: AtomicUsize::new(0),
+        })
+    }
+}
+
+impl Metric<f64> for Counted {
+    fn dist_geom(&self, g1: &GeometryRef<f64>, g2: &GeometryRef<f64>) -> Result<f64, Error> {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        Anisotropic.dist_geom(g1, g2)
+    }
+
+    fn dist_bbox(&self, geom: &GeometryRef<f64>, bbox: &Rect<f64>) -> Result<f64, Error> {
+        match self.prune {
+            true => Anisotropic.dist_bbox(geom, bbox),
+            false => Ok(0.0),
+        }
+    }
+}
+
+#[test]
+fn custom_metric_bbox_bound_prunes_searches() {
+    let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 100.0, y: 100.0});
+    let pts = (0..1000)
+        .map(|i| Point::new((i * 37 % 97) as f64, (i * 61 % 89) as f64 + 0.5))
+        .collect::<Vec<_>>();
+    let cmp = Point::new(50.2, 50.1);
+    let mut brute = pts
+        .iter()
+        .map(|p| Anisotropic.dist_geom(&cmp.as_geom(), &p.as_geom()).unwrap())
+        .collect::<Vec<_>>();
+    brute.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let mut calls = vec![];
+    for prune in [true, false] {
+        let metric = Counted::new(prune);
+        let mut qt = PointQuadTree::new(bounds, CalcMethod::Custom(metric.clone()), 6, 4);
+        for pt in &pts {
+            qt.insert(*pt).unwrap();
+        }
+
+        let knn = qt.knn(&cmp, 5).unwrap();
+        let knn = knn.iter().map(|(_, d)| *d).collect::<Vec<_>>();
+        assert_eq!(knn, brute[..5]);
+        calls.push(metric.calls.load(Ordering::Relaxed));
+    }
+
+    // A zero bound is valid but measures every datum
+    assert_eq!(calls[1], pts.len());
+    assert!(calls[0] < pts.len() / 10, "{calls:?}");
+}
+
+#[test]
+fn custom_metric_without_max_dist_bbox_has_no_farthest() {
+    let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 10.0, y: 10.0});
+    let mut qt = PointQuadTree::from_bounds(bounds, CalcMethod::Custom(Counted::new(true)));
+    qt.insert(Point::new(1.0, 1.0)).unwrap();
+    qt.insert(Point::new(9.0, 2.0)).unwrap();
+
+    let cmp = Point::new(2.0, 2.0);
+    assert_eq!(qt.sorted(&cmp).count(), 2);
+    assert_eq!(qt.sorted_desc(&cmp).count(), 0);
+    assert!(qt.farthest(&cmp, 2).is_empty());
+}
+
+// Manhattan distances that refuse to measure anything west of x = 50
+#[derive(Debug)]
+struct Faulty;
+
+impl Metric<f64> for Faulty {
+    fn dist_geom(&self, g1: &GeometryRef<f64>, g2: &GeometryRef<f64>) -> Result<f64, Error> {
+        let (GeometryRef::Point(p1), GeometryRef::Point(p2)) = (g1, g2) else {
+            return Err(Error::UnsupportedGeometry);
+        };
+        match p2.x() < 50.0 {
+            true => Err(Error::InvalidDistance),
+            false => Ok((p1.x() - p2.x()).abs() + (p1.y() - p2.y()).abs()),
+        }
+    }
+
+    fn dist_bbox(&self, geom: &GeometryRef<f64>, bbox: &Rect<f64>) -> Result<f64, Error> {
+        geom.into_calc(CalcMethod::Manhattan).dist_bbox(bbox)
+    }
+}
+
+#[test]
+fn custom_metric_errors_propagate_from_searches() {
+    let bounds = Rect::new(coord! {x: 0.0, y: 0.0}, coord! {x: 100.0, y: 100.0});
+    let mut qt = PointQuadTree::new(bounds, CalcMethod::Custom(Arc::new(Faulty)), 5, 2);
+    let pts = (0..40)
+        .map(|i| Point::new((i * 37 % 97) as f64, (i * 61 % 89) as f64))
+        .collect::<Vec<_>>();
+    for pt in &pts {
+        qt.insert(*pt).unwrap();
+    }
+    let west = pts.iter().filter(|p| p.x() < 50.0).count();
+
+    let cmp = Point::new(90.0, 90.0);
+    assert_eq!(
+        qt.find(&Point::new(10.0, 10.0)),
+        Err(Error::InvalidDistance)
+    );
+    assert_eq!(qt.knn(&cmp, pts.len()), Err(Error::InvalidDistance));
+
+    // Sorting skips what it cannot measure
+    let sorted = qt.sorted(&cmp).collect::<Vec<_>>();
+    assert_eq!(sorted.len(), pts.len() - west);
+    assert!(sorted.iter().all(|(p, _)| p.x() >= 50.0));
+}
+
+#[test]
+fn custom_metric_on_integer_qt_measures_in_f64() {
+    let method = CalcMethod::Custom(Arc::new(Anisotropic));
+    let mut qt = IntQuadTree::new(coord! {x: 0u32, y: 0}, 4, method, 4, 2);
+    let pts = (0..16u32)
+        .map(|i| Point::new(i * 7 % 16, i * 5 % 16))
+        .collect::<Vec<_>>();
+    for pt in &pts {
+        qt.insert(*pt).unwrap();
+    }
+
+    for cmp in [Point::new(7.5f32, 3.25), Point::new(0.0, 14.5)] {
+        let mut brute = pts
+            .iter()
+            .map(|p| {
+                let cmp = Point::new(cmp.x() as f64, cmp.y() as f64);
+                let p = Point::new(p.x() as f64, p.y() as f64);
+                Anisotropic.dist_geom(&cmp.as_geom(), &p.as_geom()).unwrap() as f32
+            })
+            .collect::<Vec<_>>();
+        brute.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let knn = qt.knn(&cmp, 4).unwrap();
+        let knn = knn.iter().map(|(_, d)| *d).collect::<Vec<_>>();
+        assert_eq!(knn, brute[..4]);
+    }
+}